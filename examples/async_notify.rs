@@ -5,7 +5,7 @@ use std::thread;
 use std::time::Duration;
 
 use ssdp::FieldMap;
-use ssdp::header::{HeaderMut, NT, NTS, USN};
+use ssdp::header::{NT, USN};
 use ssdp::message::{NotifyListener, NotifyMessage, Listen, Multicast};
 
 fn main() {
@@ -19,12 +19,8 @@ fn main() {
     thread::sleep(Duration::new(1, 0));
 
     // Create A Test Message
-    let mut message = NotifyMessage::new();
-
-    // Set Some Headers
-    message.set(NTS::ByeBye);
-    message.set(NT(FieldMap::upnp("rootdevice")));
-    message.set(USN(FieldMap::uuid("Hello, This Is Not A UUID!!!"), None));
+    let mut message = NotifyMessage::byebye(NT(FieldMap::upnp("rootdevice")),
+                                            USN(FieldMap::uuid("Hello, This Is Not A UUID!!!"), None));
 
     message.multicast().unwrap();
 
@@ -30,7 +30,7 @@ fn main() {
     // Create Our Search Request
     let mut request = SearchRequest::new();
 
-    // Set Our Desired Headers (Not Verified By The Library)
+    // Set Our Desired Headers (Validated Before Sending)
     request.set(Man);
     request.set(MX(5));
     request.set(ST::All);
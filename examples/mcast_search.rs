@@ -1,16 +1,10 @@
 extern crate ssdp;
 
-use ssdp::header::{HeaderMut, Man, MX, ST};
 use ssdp::message::{SearchRequest, Multicast};
 
 fn main() {
-    // Create Our Search Request
-    let mut request = SearchRequest::new();
-
-    // Set Our Desired Headers (Not Verified By The Library)
-    request.set(Man);
-    request.set(MX(5));
-    request.set(ST::All);
+    // Create Our Search Request (Fills In The MAN, MX, And ST Headers)
+    let mut request = SearchRequest::all().with_mx(5);
 
     // Iterate Over Streaming Responses
     for (msg, src) in request.multicast().unwrap() {
@@ -0,0 +1,180 @@
+//! Aggregation of protocol anomalies per source for device fingerprinting.
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::time::Instant;
+
+use error::SSDPError;
+
+/// Anomalies of a single error kind seen from a single source.
+#[derive(Clone, Debug)]
+pub struct KindAnomalies {
+    /// Number of times the error kind was seen.
+    pub count: u64,
+    /// Raw payload of the first message that produced the error kind.
+    pub sample: Vec<u8>,
+}
+
+/// Anomalies seen from a single source.
+#[derive(Clone, Debug)]
+pub struct SourceAnomalies {
+    /// When the first anomaly was seen from the source.
+    pub first_seen: Instant,
+    /// When the most recent anomaly was seen from the source.
+    pub last_seen: Instant,
+    /// Anomalies keyed by error code, see `SSDPError::code`.
+    pub kinds: BTreeMap<u16, KindAnomalies>,
+}
+
+/// Tracks protocol anomalies per source address.
+///
+/// At most `max_sources` sources are tracked, when a new source is seen while
+/// at capacity the least recently seen source is evicted.
+pub struct AnomalyTracker {
+    max_sources: usize,
+    tick: u64,
+    sources: HashMap<IpAddr, (u64, SourceAnomalies)>,
+}
+
+impl AnomalyTracker {
+    /// Create a new AnomalyTracker tracking at most the given number of sources.
+    pub fn new(max_sources: usize) -> AnomalyTracker {
+        AnomalyTracker {
+            max_sources,
+            tick: 0,
+            sources: HashMap::new(),
+        }
+    }
+
+    /// Record an error produced by a message from the given source.
+    pub fn record(&mut self, src: IpAddr, error: &SSDPError, payload: &[u8]) {
+        if self.max_sources == 0 {
+            return;
+        }
+
+        if !self.sources.contains_key(&src) && self.sources.len() >= self.max_sources {
+            self.evict_least_recent();
+        }
+
+        // Instants may compare equal for back to back records so recency is tracked by a counter
+        self.tick += 1;
+
+        let now = Instant::now();
+        let entry = self.sources.entry(src).or_insert_with(|| {
+            (0,
+             SourceAnomalies {
+                first_seen: now,
+                last_seen: now,
+                kinds: BTreeMap::new(),
+            })
+        });
+        entry.0 = self.tick;
+
+        let source = &mut entry.1;
+        source.last_seen = now;
+
+        let kind = source.kinds.entry(error.code()).or_insert_with(|| {
+            KindAnomalies {
+                count: 0,
+                sample: payload.to_vec(),
+            }
+        });
+        kind.count += 1;
+    }
+
+    /// Anomalies seen from the given source, if it is being tracked.
+    pub fn source(&self, src: &IpAddr) -> Option<&SourceAnomalies> {
+        self.sources.get(src).map(|entry| &entry.1)
+    }
+
+    /// Snapshot of all tracked sources, most recently seen first.
+    pub fn report(&self) -> Vec<(IpAddr, SourceAnomalies)> {
+        let mut sources: Vec<(&IpAddr, &(u64, SourceAnomalies))> = self.sources.iter().collect();
+        sources.sort_by_key(|&(_, entry)| entry.0);
+        sources.reverse();
+
+        sources.into_iter().map(|(addr, entry)| (*addr, entry.1.clone())).collect()
+    }
+
+    /// Remove the source that was seen least recently.
+    fn evict_least_recent(&mut self) {
+        let opt_oldest = self.sources
+            .iter()
+            .min_by_key(|&(_, &(tick, _))| tick)
+            .map(|(addr, _)| *addr);
+
+        if let Some(addr) = opt_oldest {
+            trace!("Evicting anomalies for {}", addr);
+            self.sources.remove(&addr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use error::{SSDPError, SSDPErrorKind};
+    use super::AnomalyTracker;
+
+    fn addr(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    fn missing_host() -> SSDPError {
+        SSDPErrorKind::MissingHeader("Host").into()
+    }
+
+    fn bad_version() -> SSDPError {
+        SSDPErrorKind::InvalidHttpVersion.into()
+    }
+
+    #[test]
+    fn positive_per_source_aggregation() {
+        let mut tracker = AnomalyTracker::new(8);
+
+        tracker.record(addr("192.168.1.1"), &missing_host(), b"first");
+        tracker.record(addr("192.168.1.1"), &missing_host(), b"second");
+        tracker.record(addr("192.168.1.1"), &bad_version(), b"third");
+        tracker.record(addr("192.168.1.2"), &bad_version(), b"fourth");
+        tracker.record(addr("192.168.1.3"), &missing_host(), b"fifth");
+
+        let first = tracker.source(&addr("192.168.1.1")).unwrap();
+        assert_eq!(first.kinds.len(), 2);
+        assert_eq!(first.kinds[&missing_host().code()].count, 2);
+        assert_eq!(first.kinds[&bad_version().code()].count, 1);
+
+        let second = tracker.source(&addr("192.168.1.2")).unwrap();
+        assert_eq!(second.kinds.len(), 1);
+        assert_eq!(second.kinds[&bad_version().code()].count, 1);
+
+        assert_eq!(tracker.report().len(), 3);
+        assert_eq!(tracker.report()[0].0, addr("192.168.1.3"));
+    }
+
+    #[test]
+    fn positive_single_sample_retained() {
+        let mut tracker = AnomalyTracker::new(8);
+
+        tracker.record(addr("192.168.1.1"), &missing_host(), b"first");
+        tracker.record(addr("192.168.1.1"), &missing_host(), b"second");
+
+        let source = tracker.source(&addr("192.168.1.1")).unwrap();
+        assert_eq!(&source.kinds[&missing_host().code()].sample[..], &b"first"[..]);
+    }
+
+    #[test]
+    fn positive_evicts_least_recent_source() {
+        let mut tracker = AnomalyTracker::new(2);
+
+        tracker.record(addr("192.168.1.1"), &missing_host(), b"");
+        tracker.record(addr("192.168.1.2"), &missing_host(), b"");
+        tracker.record(addr("192.168.1.1"), &bad_version(), b"");
+        tracker.record(addr("192.168.1.3"), &missing_host(), b"");
+
+        assert!(tracker.source(&addr("192.168.1.1")).is_some());
+        assert!(tracker.source(&addr("192.168.1.2")).is_none());
+        assert!(tracker.source(&addr("192.168.1.3")).is_some());
+        assert_eq!(tracker.report().len(), 2);
+    }
+}
@@ -0,0 +1,272 @@
+//! Smoke test that exercises the local SSDP stack and reports what works.
+
+use std::fmt::{self, Display, Formatter};
+use std::net::{IpAddr, SocketAddr};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use message::{Config, Listen, SearchListener, SearchRequest, SearchResponse};
+use net;
+use receiver::{ErrorStream, SSDPReceiver};
+
+/// Interval at which pending search responses are polled for.
+const POLL_INTERVAL_MS: u64 = 10;
+
+/// Wait bound sent along with the probe search.
+const PROBE_MX: u8 = 1;
+
+/// Number of parse errors kept for the report, later ones are left out.
+const MAX_PARSE_ERRORS: usize = 5;
+
+/// Outcome of probing a single local interface.
+#[derive(Debug)]
+pub struct InterfaceReport {
+    /// Name of the interface.
+    pub name: String,
+    /// Address of the interface.
+    pub addr: IpAddr,
+    /// Error encountered while sending the probe search, if any.
+    pub send_error: Option<String>,
+    /// Error encountered while listening on the multicast group of this interface, if any.
+    pub listen_error: Option<String>,
+    /// Whether the probe search was received back through the multicast group.
+    pub probe_received: bool,
+    /// Number of search responses received on this interface.
+    pub responses: usize,
+}
+
+/// Structured summary of the state of the local SSDP stack.
+#[derive(Debug)]
+pub struct DoctorReport {
+    /// Error encountered while enumerating the local interfaces, if any.
+    pub enumeration_error: Option<String>,
+    /// Interfaces that were probed with a search request.
+    pub interfaces: Vec<InterfaceReport>,
+    /// SSDP port that was checked for bindability and probed on.
+    pub port: u16,
+    /// Error encountered while binding to the SSDP port, if any.
+    pub bind_error: Option<String>,
+    /// Distinct devices that answered the probe search.
+    pub responders: Vec<SocketAddr>,
+    /// First few packets that failed to parse, by source, while the probe ran.
+    pub parse_errors: Vec<(SocketAddr, String)>,
+}
+
+/// Probe of a single interface that is still collecting.
+struct Probe {
+    addr: IpAddr,
+    listener: Option<SSDPReceiver<SearchRequest>>,
+    search: Option<SSDPReceiver<SearchResponse>>,
+    errors: Vec<ErrorStream>,
+}
+
+/// Exercise the local SSDP stack and report what works.
+///
+/// This will call `doctor_with_config()` with _default_ values.
+pub fn doctor(wait: Duration) -> DoctorReport {
+    doctor_with_config(&Config::new(), wait)
+}
+
+/// Exercise the local SSDP stack on the interfaces and port of the `Config` and report what works.
+///
+/// This enumerates the local interfaces, checks that the SSDP port can be bound to,
+/// and multicasts a single `ssdp:all` search on each interface, collecting responses
+/// for the given duration. The search doubles as the probe of the interface, it is
+/// sent with multicast loop on and the interface listens on the group for it to come
+/// back. Traffic is bounded to one search per interface so it is safe to run on
+/// production networks.
+pub fn doctor_with_config(config: &Config, wait: Duration) -> DoctorReport {
+    let mut report = DoctorReport {
+        enumeration_error: None,
+        interfaces: Vec::new(),
+        port: config.port,
+        bind_error: None,
+        responders: Vec::new(),
+        parse_errors: Vec::new(),
+    };
+
+    report.bind_error = net::bind_reuse(("0.0.0.0", config.port)).err().map(|err| err.to_string());
+
+    let ifaces = match config.selected_interfaces() {
+        Ok(n) => n,
+        Err(err) => {
+            report.enumeration_error = Some(err.to_string());
+            return report;
+        }
+    };
+
+    let send_config = config.clone().set_multicast_loop(true);
+    let request = SearchRequest::all().with_mx(PROBE_MX);
+
+    let mut probes = Vec::with_capacity(ifaces.len());
+    for (name, addr) in ifaces {
+        debug!("Probing interface {} at {}", name, addr);
+        let mut probe = Probe {
+            addr,
+            listener: None,
+            search: None,
+            errors: Vec::new(),
+        };
+
+        // Listen before sending so the probe search can not be missed
+        let listen_config = config.clone().set_interfaces(vec![addr]);
+        let listen_error = match SearchListener::listen_with_config(&listen_config) {
+            Ok(recv) => {
+                probe.errors.extend(recv.take_error_stream());
+                probe.listener = Some(recv);
+                None
+            }
+            Err(err) => Some(err.to_string()),
+        };
+
+        let send_error = match request.multicast_on(addr, &send_config) {
+            Ok(recv) => {
+                probe.errors.extend(recv.take_error_stream());
+                probe.search = Some(recv);
+                None
+            }
+            Err(err) => Some(err.to_string()),
+        };
+
+        report.interfaces.push(InterfaceReport {
+            name,
+            addr,
+            send_error,
+            listen_error,
+            probe_received: false,
+            responses: 0,
+        });
+        probes.push(probe);
+    }
+
+    collect_responses(&mut report, &probes, wait);
+
+    report
+}
+
+/// Poll the probes for the probe search, search responses and parse errors until the wait duration
+/// has elapsed.
+fn collect_responses(report: &mut DoctorReport, probes: &[Probe], wait: Duration) {
+    let deadline = Instant::now() + wait;
+
+    while Instant::now() < deadline {
+        for (iface, probe) in report.interfaces.iter_mut().zip(probes) {
+            if let Some(ref listener) = probe.listener {
+                while let Ok((_, src)) = listener.try_recv() {
+                    iface.probe_received |= src.ip() == probe.addr;
+                }
+            }
+
+            if let Some(ref search) = probe.search {
+                while let Ok((_, src)) = search.try_recv() {
+                    iface.responses += 1;
+
+                    if !report.responders.contains(&src) {
+                        report.responders.push(src);
+                    }
+                }
+            }
+
+            for errors in &probe.errors {
+                while let Ok((err, _, src)) = errors.try_recv() {
+                    if report.parse_errors.len() < MAX_PARSE_ERRORS {
+                        report.parse_errors.push((src, err.to_string()));
+                    }
+                }
+            }
+        }
+
+        thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+    }
+}
+
+impl Display for DoctorReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Interfaces:")?;
+        if let Some(ref err) = self.enumeration_error {
+            writeln!(f, "  enumeration failed: {}", err)?;
+        } else if self.interfaces.is_empty() {
+            writeln!(f, "  no usable interfaces found")?;
+        }
+        for iface in &self.interfaces {
+            match iface.send_error {
+                Some(ref err) => writeln!(f, "  {} ({}): search failed: {}", iface.name, iface.addr, err)?,
+                None => {
+                    writeln!(f,
+                             "  {} ({}): search sent, {} responses",
+                             iface.name,
+                             iface.addr,
+                             iface.responses)?
+                }
+            }
+            match iface.listen_error {
+                Some(ref err) => writeln!(f, "    probe failed: {}", err)?,
+                None if iface.probe_received => writeln!(f, "    probe received")?,
+                None => writeln!(f, "    probe not received")?,
+            }
+        }
+
+        writeln!(f, "Port {}:", self.port)?;
+        match self.bind_error {
+            Some(ref err) => writeln!(f, "  bind failed: {}", err)?,
+            None => writeln!(f, "  bindable")?,
+        }
+
+        writeln!(f, "Devices:")?;
+        if self.responders.is_empty() {
+            writeln!(f, "  no devices answered")?;
+        }
+        for responder in &self.responders {
+            writeln!(f, "  {}", responder)?;
+        }
+
+        writeln!(f, "Parse errors:")?;
+        if self.parse_errors.is_empty() {
+            writeln!(f, "  none")?;
+        }
+        for &(src, ref err) in &self.parse_errors {
+            writeln!(f, "  {}: {}", src, err)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::thread;
+    use std::time::Duration;
+
+    use message::Config;
+    use net::IpVersionMode;
+
+    #[test]
+    fn positive_doctor_report_sections() {
+        let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let config = Config::new()
+            .set_port(port)
+            .set_mode(IpVersionMode::V4Only)
+            .set_interfaces(vec!["127.0.0.1".parse().unwrap()]);
+
+        // Garbage on the group port shows up among the parse errors
+        let garbage = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            UdpSocket::bind("127.0.0.1:0").unwrap().send_to(b"NOTIFY garbage", ("127.0.0.1", port)).unwrap();
+        });
+        let report = super::doctor_with_config(&config, Duration::from_millis(1000));
+        garbage.join().unwrap();
+        let summary = report.to_string();
+
+        assert!(report.enumeration_error.is_none());
+        assert_eq!(report.interfaces.len(), 1);
+        assert!(report.interfaces[0].send_error.is_none());
+        assert!(report.interfaces[0].probe_received);
+        assert_eq!(report.parse_errors.len(), 1);
+        assert!(summary.contains("Interfaces:\n"));
+        assert!(summary.contains("    probe received\n"));
+        assert!(summary.contains(&format!("Port {}:\n", port)));
+        assert!(summary.contains("Devices:\n"));
+        assert!(summary.contains("Parse errors:\n"));
+    }
+}
@@ -54,6 +54,82 @@ error_chain! {
             description("invalid header")
             display("invalid header: '{}': {}", header, msg)
         }
+        /// Interface requested is not present on the local machine.
+        ///
+        /// Interface requested is supplied.
+        InterfaceNotFound(iface:String) {
+            description("interface not found")
+            display("interface not found: '{}'", iface)
+        }
+        /// Local and destination addresses are not of the same IP version.
+        ///
+        /// Local and destination addresses are supplied.
+        IpVersionMismatch(local:String, dst:String) {
+            description("local and destination IP versions differ")
+            display("local address '{}' and destination '{}' are not the same IP version", local, dst)
+        }
+        /// Address would require a DNS lookup while lookups are forbidden.
+        ///
+        /// Address that is not an IP literal is supplied.
+        DnsForbidden(addr:String) {
+            description("DNS resolution forbidden")
+            display("DNS resolution forbidden for non literal address: '{}'", addr)
+        }
+        /// Several headers are missing from the message.
+        ///
+        /// Expected headers are supplied.
+        MissingHeaders(headers:Vec<&'static str>) {
+            description("missing headers")
+            display("missing headers: '{}'", headers.join("', '"))
+        }
+        /// Message could not be sent through any of the local sockets.
+        ///
+        /// Local address of each socket along with its error are supplied.
+        AllSendsFailed(failures:Vec<(net::SocketAddr, SSDPError)>) {
+            description("send failed on all sockets")
+            display("send failed on all sockets: {}", failures.iter()
+                .map(|(addr, err)| format!("{}: {}", addr, err))
+                .collect::<Vec<_>>()
+                .join(", "))
+        }
+        /// Message is too large to be sent in a single datagram.
+        ///
+        /// Serialized length of the message is supplied.
+        MessageTooLarge(len:usize) {
+            description("message too large")
+            display("message of {} bytes is too large to send", len)
+        }
+        /// Fixed source port is already in use by a socket that does not allow reuse.
+        ///
+        /// Source port requested is supplied.
+        SourcePortInUse(port:u16) {
+            description("source port in use")
+            display("source port {} is already in use", port)
+        }
+        /// DSCP value could not be applied to outgoing sockets.
+        ///
+        /// DSCP value requested is supplied.
+        DscpRefused(dscp:u8) {
+            description("DSCP value refused")
+            display("DSCP value {} could not be applied", dscp)
+        }
+        /// Configured address is not a multicast group of the expected IP version.
+        ///
+        /// Configured address is supplied.
+        NotMulticastGroup(addr:String) {
+            description("not a multicast group")
+            display("not a multicast group of the expected IP version: '{}'", addr)
+        }
+        /// Boot instance was moved on but some of its update messages could not be sent.
+        ///
+        /// New boot instance along with the USN of each failed update and its error are supplied.
+        BootIdUpdateFailed(boot_id:u32, failures:Vec<(String, SSDPError)>) {
+            description("boot instance update messages failed")
+            display("moved to boot instance {} but updates failed: {}", boot_id, failures.iter()
+                .map(|(usn, err)| format!("{}: {}", usn, err))
+                .collect::<Vec<_>>()
+                .join(", "))
+        }
     }
 
     foreign_links {
@@ -63,3 +139,206 @@ error_chain! {
         HyperParseError(hyper::error::ParseError);
     }
 }
+
+impl SSDPErrorKind {
+    /// Stable numeric identifier for this kind of error.
+    ///
+    /// Codes are append-only, a code is never reused or reassigned once published:
+    ///
+    /// | Code | Kind                 |
+    /// |------|----------------------|
+    /// | 0    | `Msg`                |
+    /// | 1    | `InvalidHttp`        |
+    /// | 2    | `InvalidHttpVersion` |
+    /// | 3    | `ResponseCode`       |
+    /// | 4    | `InvalidMethod`      |
+    /// | 5    | `InvalidUri`         |
+    /// | 6    | `MissingHeader`      |
+    /// | 7    | `InvalidHeader`      |
+    /// | 8    | `Io`                 |
+    /// | 9    | `AddrParseError`     |
+    /// | 10   | `Hyper`              |
+    /// | 11   | `HyperParseError`    |
+    /// | 12   | `InterfaceNotFound`  |
+    /// | 13   | `IpVersionMismatch`  |
+    /// | 14   | `DnsForbidden`       |
+    /// | 15   | `MissingHeaders`     |
+    /// | 16   | `AllSendsFailed`     |
+    /// | 17   | `MessageTooLarge`    |
+    /// | 18   | `SourcePortInUse`    |
+    /// | 19   | `DscpRefused`        |
+    /// | 20   | `NotMulticastGroup`  |
+    /// | 21   | `BootIdUpdateFailed` |
+    pub fn code(&self) -> u16 {
+        match *self {
+            SSDPErrorKind::Msg(_) => 0,
+            SSDPErrorKind::InvalidHttp(_) => 1,
+            SSDPErrorKind::InvalidHttpVersion => 2,
+            SSDPErrorKind::ResponseCode(_) => 3,
+            SSDPErrorKind::InvalidMethod(_) => 4,
+            SSDPErrorKind::InvalidUri(_) => 5,
+            SSDPErrorKind::MissingHeader(_) => 6,
+            SSDPErrorKind::InvalidHeader(_, _) => 7,
+            SSDPErrorKind::Io(_) => 8,
+            SSDPErrorKind::AddrParseError(_) => 9,
+            SSDPErrorKind::Hyper(_) => 10,
+            SSDPErrorKind::HyperParseError(_) => 11,
+            SSDPErrorKind::InterfaceNotFound(_) => 12,
+            SSDPErrorKind::IpVersionMismatch(_, _) => 13,
+            SSDPErrorKind::DnsForbidden(_) => 14,
+            SSDPErrorKind::MissingHeaders(_) => 15,
+            SSDPErrorKind::AllSendsFailed(_) => 16,
+            SSDPErrorKind::MessageTooLarge(_) => 17,
+            SSDPErrorKind::SourcePortInUse(_) => 18,
+            SSDPErrorKind::DscpRefused(_) => 19,
+            SSDPErrorKind::NotMulticastGroup(_) => 20,
+            SSDPErrorKind::BootIdUpdateFailed(_, _) => 21,
+        }
+    }
+
+    /// Whether retrying the failed operation may succeed.
+    ///
+    /// Only io backed errors (`Io` and io errors wrapped by `Hyper`) are ever
+    /// retryable, and only when their `io::ErrorKind` is one of `Interrupted`,
+    /// `WouldBlock`, `TimedOut`, `ConnectionRefused`, `ConnectionReset` or
+    /// `AddrInUse`. An `AllSendsFailed` is retryable when every one of its
+    /// failures is. Every other error is considered permanent.
+    pub fn is_retryable(&self) -> bool {
+        match *self {
+            SSDPErrorKind::Io(ref err) |
+            SSDPErrorKind::Hyper(hyper::Error::Io(ref err)) => is_transient_io(err.kind()),
+            SSDPErrorKind::AllSendsFailed(ref failures) => {
+                !failures.is_empty() && failures.iter().all(|(_, err)| err.is_retryable())
+            }
+            _ => false,
+        }
+    }
+}
+
+impl SSDPError {
+    /// Stable numeric identifier for the kind of this error.
+    ///
+    /// See `SSDPErrorKind::code` for the registry of codes.
+    pub fn code(&self) -> u16 {
+        self.kind().code()
+    }
+
+    /// Whether retrying the failed operation may succeed.
+    ///
+    /// See `SSDPErrorKind::is_retryable` for the classification.
+    pub fn is_retryable(&self) -> bool {
+        self.kind().is_retryable()
+    }
+}
+
+/// Io error kinds that represent a transient failure.
+const TRANSIENT_IO_KINDS: [io::ErrorKind; 6] = [io::ErrorKind::Interrupted,
+                                                io::ErrorKind::WouldBlock,
+                                                io::ErrorKind::TimedOut,
+                                                io::ErrorKind::ConnectionRefused,
+                                                io::ErrorKind::ConnectionReset,
+                                                io::ErrorKind::AddrInUse];
+
+/// Returns true if the io error kind represents a transient failure.
+fn is_transient_io(kind: io::ErrorKind) -> bool {
+    TRANSIENT_IO_KINDS.contains(&kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::io;
+
+    use hyper;
+
+    use super::{SSDPError, SSDPErrorKind};
+
+    fn all_kinds() -> Vec<SSDPErrorKind> {
+        vec![SSDPErrorKind::Msg(String::new()),
+             SSDPErrorKind::InvalidHttp(Vec::new()),
+             SSDPErrorKind::InvalidHttpVersion,
+             SSDPErrorKind::ResponseCode(404),
+             SSDPErrorKind::InvalidMethod(String::new()),
+             SSDPErrorKind::InvalidUri(String::new()),
+             SSDPErrorKind::MissingHeader("ST"),
+             SSDPErrorKind::InvalidHeader("MX", ""),
+             SSDPErrorKind::Io(io::Error::other("")),
+             SSDPErrorKind::AddrParseError("".parse::<::std::net::IpAddr>().unwrap_err()),
+             SSDPErrorKind::Hyper(hyper::Error::Header),
+             SSDPErrorKind::HyperParseError(hyper::error::ParseError::EmptyHost),
+             SSDPErrorKind::InterfaceNotFound(String::new()),
+             SSDPErrorKind::IpVersionMismatch(String::new(), String::new()),
+             SSDPErrorKind::DnsForbidden(String::new()),
+             SSDPErrorKind::MissingHeaders(Vec::new()),
+             SSDPErrorKind::AllSendsFailed(Vec::new()),
+             SSDPErrorKind::MessageTooLarge(0),
+             SSDPErrorKind::SourcePortInUse(0),
+             SSDPErrorKind::DscpRefused(0),
+             SSDPErrorKind::NotMulticastGroup(String::new()),
+             SSDPErrorKind::BootIdUpdateFailed(0, Vec::new())]
+    }
+
+    #[test]
+    fn positive_unique_codes() {
+        let kinds = all_kinds();
+        let codes = kinds.iter().map(|kind| kind.code()).collect::<HashSet<_>>();
+
+        assert_eq!(codes.len(), kinds.len());
+    }
+
+    #[test]
+    fn positive_error_code_matches_kind() {
+        let error: SSDPError = SSDPErrorKind::MissingHeader("ST").into();
+
+        assert_eq!(error.code(), 6);
+    }
+
+    #[test]
+    fn positive_retryable_io() {
+        for &kind in [io::ErrorKind::Interrupted,
+                      io::ErrorKind::WouldBlock,
+                      io::ErrorKind::TimedOut,
+                      io::ErrorKind::ConnectionRefused,
+                      io::ErrorKind::ConnectionReset,
+                      io::ErrorKind::AddrInUse]
+            .iter() {
+            let error: SSDPError = io::Error::new(kind, "").into();
+            assert!(error.is_retryable(), "{:?} should be retryable", kind);
+
+            let error: SSDPError = hyper::Error::Io(io::Error::new(kind, "")).into();
+            assert!(error.is_retryable(), "{:?} should be retryable through hyper", kind);
+        }
+    }
+
+    #[test]
+    fn negative_retryable_io() {
+        for &kind in [io::ErrorKind::PermissionDenied,
+                      io::ErrorKind::InvalidInput,
+                      io::ErrorKind::AddrNotAvailable,
+                      io::ErrorKind::Other]
+            .iter() {
+            let error: SSDPError = io::Error::new(kind, "").into();
+            assert!(!error.is_retryable(), "{:?} should not be retryable", kind);
+        }
+    }
+
+    #[test]
+    fn positive_retryable_send_failures() {
+        let transient = || -> SSDPError { io::Error::new(io::ErrorKind::WouldBlock, "").into() };
+        let permanent = || -> SSDPError { io::Error::new(io::ErrorKind::PermissionDenied, "").into() };
+        let addr = "192.168.1.1:0".parse::<::std::net::SocketAddr>().unwrap();
+
+        let transient_only = vec![(addr, transient()), (addr, transient())];
+        let with_permanent = vec![(addr, transient()), (addr, permanent())];
+
+        assert!(SSDPErrorKind::AllSendsFailed(transient_only).is_retryable());
+        assert!(!SSDPErrorKind::AllSendsFailed(with_permanent).is_retryable());
+    }
+
+    #[test]
+    fn negative_retryable_parse_errors() {
+        for kind in all_kinds().into_iter().filter(|kind| kind.code() != 8) {
+            assert!(!kind.is_retryable());
+        }
+    }
+}
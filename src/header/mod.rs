@@ -13,6 +13,7 @@ mod bootid;
 mod configid;
 mod man;
 mod mx;
+mod nextbootid;
 mod nt;
 mod nts;
 mod searchport;
@@ -24,6 +25,7 @@ pub use self::bootid::BootID;
 pub use self::configid::ConfigID;
 pub use self::man::Man;
 pub use self::mx::MX;
+pub use self::nextbootid::NextBootID;
 pub use self::nt::NT;
 pub use self::nts::NTS;
 pub use self::searchport::SearchPort;
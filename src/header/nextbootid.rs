@@ -0,0 +1,111 @@
+use std::fmt::{Formatter, Result};
+
+use hyper::error::{self, Error};
+use hyper::header::{HeaderFormat, Header};
+
+const NEXTBOOTID_HEADER_NAME: &'static str = "NEXTBOOTID.UPNP.ORG";
+
+/// Represents a header used to denote the boot instance a root device will move to.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct NextBootID(pub u32);
+
+impl Header for NextBootID {
+    fn header_name() -> &'static str {
+        NEXTBOOTID_HEADER_NAME
+    }
+
+    fn parse_header(raw: &[Vec<u8>]) -> error::Result<Self> {
+        if raw.len() != 1 {
+            return Err(Error::Header);
+        }
+
+        let cow_str = String::from_utf8_lossy(&raw[0][..]);
+
+        // Value needs to be a 31 bit non-negative integer, so convert to i32
+        let value = match i32::from_str_radix(&*cow_str, 10) {
+            Ok(n) => n,
+            Err(_) => return Err(Error::Header),
+        };
+
+        // Check if value is negative, then convert to u32
+        if value.is_negative() {
+            Err(Error::Header)
+        } else {
+            Ok(NextBootID(value as u32))
+        }
+    }
+}
+
+impl HeaderFormat for NextBootID {
+    fn fmt_header(&self, fmt: &mut Formatter) -> Result {
+        try!(fmt.write_fmt(format_args!("{}", self.0)));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::Header;
+
+    use super::NextBootID;
+
+    #[test]
+    fn positive_nextbootid() {
+        let nextbootid_header_value = &[b"1216907400"[..].to_vec()];
+
+        NextBootID::parse_header(nextbootid_header_value).unwrap();
+    }
+
+    #[test]
+    fn positive_leading_zeros() {
+        let nextbootid_header_value = &[b"0000001216907400"[..].to_vec()];
+
+        NextBootID::parse_header(nextbootid_header_value).unwrap();
+    }
+
+    #[test]
+    fn positive_lower_bound() {
+        let nextbootid_header_value = &[b"0"[..].to_vec()];
+
+        NextBootID::parse_header(nextbootid_header_value).unwrap();
+    }
+
+    #[test]
+    fn positive_upper_bound() {
+        let nextbootid_header_value = &[b"2147483647"[..].to_vec()];
+
+        NextBootID::parse_header(nextbootid_header_value).unwrap();
+    }
+
+    #[test]
+    fn positive_negative_zero() {
+        let nextbootid_header_value = &[b"-0"[..].to_vec()];
+
+        NextBootID::parse_header(nextbootid_header_value).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_overflow() {
+        let nextbootid_header_value = &[b"2290649224"[..].to_vec()];
+
+        NextBootID::parse_header(nextbootid_header_value).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_negative_overflow() {
+        let nextbootid_header_value = &[b"-2290649224"[..].to_vec()];
+
+        NextBootID::parse_header(nextbootid_header_value).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_nan() {
+        let nextbootid_header_value = &[b"2290wow649224"[..].to_vec()];
+
+        NextBootID::parse_header(nextbootid_header_value).unwrap();
+    }
+}
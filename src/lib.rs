@@ -1,6 +1,7 @@
 #![allow(unused_features)]
 #![feature(ip)]
 #![recursion_limit = "1024"]
+#![cfg_attr(all(test, feature = "unstable"), feature(test))]
 
 //! An asynchronous abstraction for discovering devices and services on a network.
 //!
@@ -10,14 +11,25 @@
 //! `UPnP` as it is used as the discovery mechanism for that standard.
 
 extern crate hyper;
+extern crate httparse;
 #[macro_use]
 extern crate log;
 extern crate time;
 extern crate get_if_addrs;
 extern crate net2;
+#[cfg(unix)]
+extern crate libc;
+#[cfg(windows)]
+extern crate winapi;
+#[cfg(feature = "stream")]
+extern crate futures;
 #[macro_use]
 extern crate error_chain;
+#[cfg(all(test, feature = "unstable"))]
+extern crate test;
 
+mod anomaly;
+mod doctor;
 mod error;
 mod field;
 mod net;
@@ -26,7 +38,13 @@ mod receiver;
 pub mod header;
 pub mod message;
 
+pub use anomaly::{AnomalyTracker, SourceAnomalies, KindAnomalies};
+pub use doctor::{doctor, doctor_with_config, DoctorReport, InterfaceReport};
 pub use error::{SSDPError, SSDPErrorKind, SSDPResultExt, SSDPResult};
 pub use field::FieldMap;
-pub use receiver::{SSDPReceiver, SSDPIter};
-pub use net::IpVersionMode;
+pub use receiver::{SSDPReceiver, SSDPIter, SSDPRefIter, SSDPLocalIter, SSDPMetaIter, SSDPTryIter,
+                   SSDPUntilIter, DropReason, ErrorStream, FromRawSSDP, MapReceiver, Merger, OverflowPolicy,
+                   RawResult, ReceivedFrom, ReceiverBackend, ReceiverOptions, StatsSnapshot, UniqueReceiver};
+#[cfg(feature = "stream")]
+pub use receiver::SSDPStream;
+pub use net::{DestAddr, IpVersionMode};
@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+use header::{HeaderRef, Location, NTS, Server, USN};
+use message::{self, ReceivedMessage};
+use receiver::{Delivery, Outlet};
+
+/// Number of deltas a subscriber can fall behind by before deltas are dropped for it.
+pub const DEFAULT_SUBSCRIBER_CAPACITY: usize = 64;
+
+/// Device as last advertised through a notify message or a search response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveredDevice {
+    /// Unique service name the device advertised.
+    pub usn: USN,
+    /// Address the latest advertisement was received from.
+    pub src: SocketAddr,
+    /// URL of the description of the device, if advertised.
+    pub location: Option<String>,
+    /// Product tokens of the device, if advertised.
+    pub server: Option<String>,
+    /// Seconds the advertisement is valid for, if advertised.
+    pub max_age: Option<u32>,
+    /// When the advertisement expires, devices without a max-age never expire.
+    pub expires: Option<Instant>,
+}
+
+/// Field of a `DiscoveredDevice` that changed when the device advertised itself again.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DeviceField {
+    Src,
+    Location,
+    Server,
+    MaxAge,
+}
+
+/// Why a device was removed from a `DeviceCache`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RemovalReason {
+    /// The device sent an `ssdp:byebye` notification.
+    ByeBye,
+    /// The max-age of the latest advertisement of the device passed.
+    Expired,
+}
+
+/// Change to the devices of a `DeviceCache`, as published to its subscribers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CacheDelta {
+    /// A device that was not cached advertised itself.
+    Added(DiscoveredDevice),
+    /// A cached device advertised itself with different fields.
+    Updated {
+        usn: USN,
+        changed_fields: Vec<DeviceField>,
+    },
+    /// A cached device was removed.
+    Removed { usn: USN, reason: RemovalReason },
+    /// The subscriber fell behind and this many deltas were dropped for it.
+    ///
+    /// The subscriber should resync from `DeviceCache::snapshot()`.
+    Lagged(usize),
+}
+
+/// Subscriber of a `DeviceCache` along with the number of deltas dropped for it since it last kept up.
+struct Subscriber {
+    outlet: Outlet<CacheDelta>,
+    lagged: usize,
+}
+
+impl Subscriber {
+    /// Pass the delta on to the subscriber, preceded by a `Lagged` delta if deltas were dropped for it.
+    ///
+    /// Returns false once the subscriber hung up.
+    fn publish(&mut self, delta: CacheDelta, closed: &AtomicBool) -> bool {
+        if self.lagged > 0 {
+            match self.outlet.deliver(CacheDelta::Lagged(self.lagged), closed) {
+                Delivery::Sent(_) => self.lagged = 0,
+                Delivery::Dropped => {
+                    self.lagged += 1;
+                    return true;
+                }
+                Delivery::HungUp => return false,
+            }
+        }
+
+        match self.outlet.deliver(delta, closed) {
+            Delivery::Sent(_) => true,
+            Delivery::Dropped => {
+                self.lagged += 1;
+                true
+            }
+            Delivery::HungUp => false,
+        }
+    }
+}
+
+/// Devices discovered through received SSDP messages, keyed by their USN.
+///
+/// Changes to the devices are published to subscribers as `CacheDelta`s, so they do
+/// not have to diff snapshots of the cache. Each subscriber holds a bounded number of
+/// deltas, deltas for a subscriber that is full are dropped and a `Lagged` delta with
+/// the number dropped is sent ahead of the next delta that fits.
+pub struct DeviceCache {
+    devices: HashMap<USN, DiscoveredDevice>,
+    subscribers: Vec<Subscriber>,
+}
+
+impl DeviceCache {
+    /// Create a new empty DeviceCache.
+    pub fn new() -> DeviceCache {
+        DeviceCache {
+            devices: HashMap::new(),
+            subscribers: Vec::new(),
+        }
+    }
+
+    /// Subscribe to the changes to the devices of the cache.
+    ///
+    /// This will call `subscribe_with_capacity()` with `DEFAULT_SUBSCRIBER_CAPACITY`.
+    pub fn subscribe(&mut self) -> Receiver<CacheDelta> {
+        self.subscribe_with_capacity(DEFAULT_SUBSCRIBER_CAPACITY)
+    }
+
+    /// Subscribe to the changes to the devices of the cache, holding at most `capacity` deltas that
+    /// were not read yet.
+    ///
+    /// Only changes made after subscribing are published, start from `snapshot()` for the
+    /// devices that are already cached. Dropping the receiver unsubscribes.
+    pub fn subscribe_with_capacity(&mut self, capacity: usize) -> Receiver<CacheDelta> {
+        let (outlet, recv) = Outlet::dropping_newest(capacity);
+        self.subscribers.push(Subscriber { outlet, lagged: 0 });
+
+        recv
+    }
+
+    /// Cached device with the given USN, if any.
+    pub fn get(&self, usn: &USN) -> Option<&DiscoveredDevice> {
+        self.devices.get(usn)
+    }
+
+    /// Copy of all of the cached devices.
+    pub fn snapshot(&self) -> Vec<DiscoveredDevice> {
+        self.devices.values().cloned().collect()
+    }
+
+    /// Update the cache with a message received from the given source.
+    ///
+    /// Notify messages and search responses add or refresh the device they advertise,
+    /// `ssdp:byebye` notifications remove it. Search requests and messages without a
+    /// `USN` are ignored. Refreshing a device without changing its fields only pushes
+    /// its expiry back and publishes nothing.
+    pub fn insert(&mut self, message: &ReceivedMessage, src: SocketAddr) {
+        let now = Instant::now();
+        let device = match *message {
+            ReceivedMessage::Notify(ref notify) => {
+                if let Some(&NTS::ByeBye) = notify.get::<NTS>() {
+                    if let Some(usn) = notify.get::<USN>() {
+                        self.remove(usn, RemovalReason::ByeBye);
+                    }
+                    return;
+                }
+                discovered_device(notify, src, now)
+            }
+            ReceivedMessage::Response(ref response) => discovered_device(response, src, now),
+            ReceivedMessage::Search(_) => None,
+        };
+
+        let device = match device {
+            Some(n) => n,
+            None => return,
+        };
+
+        let delta = match self.devices.insert(device.usn.clone(), device.clone()) {
+            None => CacheDelta::Added(device),
+            Some(old) => {
+                let changed_fields = changed_fields(&old, &device);
+                if changed_fields.is_empty() {
+                    return;
+                }
+                CacheDelta::Updated {
+                    usn: device.usn,
+                    changed_fields,
+                }
+            }
+        };
+
+        self.publish(delta);
+    }
+
+    /// Remove the devices whose advertisement expired by the given instant.
+    pub fn expire(&mut self, now: Instant) {
+        let expired: Vec<USN> = self.devices
+            .values()
+            .filter(|device| device.expires.is_some_and(|expires| expires <= now))
+            .map(|device| device.usn.clone())
+            .collect();
+
+        for usn in expired {
+            self.remove(&usn, RemovalReason::Expired);
+        }
+    }
+
+    /// Remove the device with the given USN, publishing its removal if it was cached.
+    fn remove(&mut self, usn: &USN, reason: RemovalReason) {
+        if self.devices.remove(usn).is_some() {
+            self.publish(CacheDelta::Removed {
+                usn: usn.clone(),
+                reason,
+            });
+        }
+    }
+
+    /// Pass the delta on to every subscriber, forgetting those that hung up.
+    fn publish(&mut self, delta: CacheDelta) {
+        // Subscriber outlets drop new deltas instead of blocking, so there is nothing to close
+        let closed = AtomicBool::new(false);
+
+        self.subscribers.retain_mut(|subscriber| subscriber.publish(delta.clone(), &closed));
+    }
+}
+
+impl Default for DeviceCache {
+    fn default() -> Self {
+        DeviceCache::new()
+    }
+}
+
+/// Device advertised by the message, if it has a `USN`.
+fn discovered_device<H: HeaderRef>(message: &H, src: SocketAddr, now: Instant) -> Option<DiscoveredDevice> {
+    let max_age = message::max_age(message);
+
+    message.get::<USN>().map(|usn| {
+        DiscoveredDevice {
+            usn: usn.clone(),
+            src,
+            location: message.get::<Location>().map(|location| location.0.clone()),
+            server: message.get::<Server>().map(|server| server.0.clone()),
+            max_age,
+            expires: max_age.map(|max_age| now + Duration::from_secs(u64::from(max_age))),
+        }
+    })
+}
+
+/// Fields that differ between the old and new advertisement of a device.
+fn changed_fields(old: &DiscoveredDevice, new: &DiscoveredDevice) -> Vec<DeviceField> {
+    let mut changed = Vec::new();
+
+    if old.src != new.src {
+        changed.push(DeviceField::Src);
+    }
+    if old.location != new.location {
+        changed.push(DeviceField::Location);
+    }
+    if old.server != new.server {
+        changed.push(DeviceField::Server);
+    }
+    if old.max_age != new.max_age {
+        changed.push(DeviceField::MaxAge);
+    }
+
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::mpsc::Receiver;
+    use std::time::{Duration, Instant};
+
+    use super::{CacheDelta, DeviceCache, DeviceField, DiscoveredDevice, RemovalReason};
+    use header::{NT, USN};
+    use message::{NotifyMessage, ReceivedMessage};
+    use FieldMap;
+
+    fn usn(uuid: &str) -> USN {
+        USN(FieldMap::uuid(uuid), None)
+    }
+
+    fn alive(uuid: &str, location: &str, max_age: u64) -> ReceivedMessage {
+        ReceivedMessage::Notify(NotifyMessage::alive(NT(FieldMap::upnp("rootdevice")),
+                                                     usn(uuid),
+                                                     location,
+                                                     Duration::from_secs(max_age),
+                                                     "test/1.0 UPnP/1.1 test/1.0"))
+    }
+
+    fn byebye(uuid: &str) -> ReceivedMessage {
+        ReceivedMessage::Notify(NotifyMessage::byebye(NT(FieldMap::upnp("rootdevice")), usn(uuid)))
+    }
+
+    fn src() -> SocketAddr {
+        "192.168.1.2:1900".parse().unwrap()
+    }
+
+    fn drain(recv: &Receiver<CacheDelta>) -> Vec<CacheDelta> {
+        recv.try_iter().collect()
+    }
+
+    /// Drain the deltas, clearing the expiry of added devices so they can be compared.
+    fn drain_unexpiring(recv: &Receiver<CacheDelta>) -> Vec<CacheDelta> {
+        drain(recv)
+            .into_iter()
+            .map(|delta| match delta {
+                CacheDelta::Added(device) => CacheDelta::Added(DiscoveredDevice { expires: None, ..device }),
+                delta => delta,
+            })
+            .collect()
+    }
+
+    fn added(uuid: &str) -> CacheDelta {
+        CacheDelta::Added(DiscoveredDevice {
+            usn: usn(uuid),
+            src: src(),
+            location: Some(format!("http://192.168.1.2/{}.xml", uuid)),
+            server: Some("test/1.0 UPnP/1.1 test/1.0".to_owned()),
+            max_age: Some(1800),
+            expires: None,
+        })
+    }
+
+    #[test]
+    fn positive_subscriber_gets_delta_stream() {
+        let mut cache = DeviceCache::new();
+        let recv = cache.subscribe();
+
+        cache.insert(&alive("a", "http://192.168.1.2/a.xml", 1800), src());
+        cache.insert(&alive("b", "http://192.168.1.2/b.xml", 1800), src());
+        cache.insert(&alive("a", "http://192.168.1.2/a.xml", 1800), src());
+        cache.insert(&alive("a", "http://192.168.1.2/new.xml", 900), src());
+        cache.insert(&byebye("b"), src());
+        cache.insert(&byebye("b"), src());
+
+        assert_eq!(drain_unexpiring(&recv),
+                   vec![added("a"),
+                        added("b"),
+                        CacheDelta::Updated {
+                            usn: usn("a"),
+                            changed_fields: vec![DeviceField::Location, DeviceField::MaxAge],
+                        },
+                        CacheDelta::Removed {
+                            usn: usn("b"),
+                            reason: RemovalReason::ByeBye,
+                        }]);
+        assert_eq!(cache.snapshot().len(), 1);
+        assert_eq!(cache.get(&usn("a")).unwrap().location, Some("http://192.168.1.2/new.xml".to_owned()));
+    }
+
+    #[test]
+    fn positive_expire_removes_device() {
+        let mut cache = DeviceCache::new();
+        cache.insert(&alive("short", "http://192.168.1.2/a.xml", 10), src());
+        cache.insert(&alive("long", "http://192.168.1.2/b.xml", 1800), src());
+        let recv = cache.subscribe();
+
+        cache.expire(Instant::now() + Duration::from_secs(60));
+
+        assert_eq!(drain(&recv),
+                   vec![CacheDelta::Removed {
+                            usn: usn("short"),
+                            reason: RemovalReason::Expired,
+                        }]);
+        assert!(cache.get(&usn("long")).is_some());
+    }
+
+    #[test]
+    fn positive_stalled_subscriber_lagged() {
+        let mut cache = DeviceCache::new();
+        let fast = cache.subscribe();
+        let stalled = cache.subscribe_with_capacity(2);
+
+        cache.insert(&alive("a", "http://192.168.1.2/a.xml", 1800), src());
+        cache.insert(&alive("b", "http://192.168.1.2/b.xml", 1800), src());
+        cache.insert(&alive("c", "http://192.168.1.2/c.xml", 1800), src());
+        cache.insert(&byebye("a"), src());
+        assert_eq!(drain_unexpiring(&stalled), vec![added("a"), added("b")]);
+
+        cache.insert(&alive("d", "http://192.168.1.2/d.xml", 1800), src());
+
+        assert_eq!(drain_unexpiring(&stalled), vec![CacheDelta::Lagged(2), added("d")]);
+        assert_eq!(drain_unexpiring(&fast),
+                   vec![added("a"),
+                        added("b"),
+                        added("c"),
+                        CacheDelta::Removed {
+                            usn: usn("a"),
+                            reason: RemovalReason::ByeBye,
+                        },
+                        added("d")]);
+    }
+
+    #[test]
+    fn negative_dropped_subscriber_forgotten() {
+        let mut cache = DeviceCache::new();
+        drop(cache.subscribe());
+
+        cache.insert(&alive("a", "http://192.168.1.2/a.xml", 1800), src());
+
+        assert!(cache.subscribers.is_empty());
+    }
+}
@@ -1,14 +1,28 @@
-use std::net::{SocketAddr, IpAddr};
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::net::UdpSocket;
+use std::time::Duration;
 
 use error::SSDPResult;
-use message::{self, Config};
-use receiver::{SSDPReceiver, FromRawSSDP};
-use net;
+use message::{self, Config, NotifyMessage, NotifyListener, SearchRequest, SearchResponse};
+use message::{Deviation, ParsePolicy, SSDPMessage};
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+use net::IpVersionMode;
+use receiver::{DropReason, SSDPReceiver, FromRawSSDP, RawResult};
 
 
 pub trait Listen {
     type Message: FromRawSSDP + Send + 'static;
 
+    /// Whether a received message should be delivered to the listener, or why it is dropped.
+    ///
+    /// This is invoked on the receiver threads for every message received through
+    /// `listen_with_config()`, with the `Config` the listener was started with. Dropped
+    /// messages are counted by reason in `SSDPReceiver::stats()`.
+    fn accept(_message: &Self::Message, _config: &Config) -> Result<(), DropReason> {
+        Ok(())
+    }
+
     /// Listen for messages on all local network interfaces.
     ///
     /// This will call `listen_with_config()` with _default_ values.
@@ -19,73 +33,419 @@ pub trait Listen {
     /// Listen for messages on all local network interfaces.
     ///
     /// # Notes
-    /// This will _bind_ to each interface, **NOT** to `INADDR_ANY`.
+    /// This will _bind_ a single socket per IP version to `INADDR_ANY` on the multicast port
+    /// and join the multicast group on each interface. Where the platform supports it the
+    /// local address of each message is that of the interface it arrived on, see
+    /// `SSDPReceiver::recv_with_local()`.
+    ///
+    /// If you are on an environment where the network interface will be changing, the
+    /// local interfaces are polled as configured by `Config::set_interface_poll()` and
+    /// the multicast group is joined on new interfaces as they appear.
     ///
-    /// If you are on an environment where the network interface will be changing,
-    /// you will have to stop listening and start listening again,
-    /// or we recommend using `listen_anyaddr_with_config()` instead.
+    /// Listening stops when the receiver is dropped, `SSDPReceiver::close()` stops
+    /// listening and waits until the port is released.
     fn listen_with_config(config: &Config) -> SSDPResult<SSDPReceiver<Self::Message>> {
-        let mut ipv4_sock = None;
-        let mut ipv6_sock = None;
+        let (sockets, joined) = message::join_multicast_sockets(config)?;
 
-        // Generate a list of reused sockets on the standard multicast address.
-        let addrs: Vec<SocketAddr> = try!(message::map_local(|&addr| Ok(Some(addr))));
-
-        for addr in addrs {
-            match addr {
-                SocketAddr::V4(_) => {
-                    let mcast_ip = config.ipv4_addr.parse().unwrap();
-
-                    if ipv4_sock.is_none() {
-                        ipv4_sock = Some(try!(net::bind_reuse(("0.0.0.0", config.port))));
-                    }
+        let recv = receiver::<Self>(sockets, None, config)?;
+        message::watch_memberships(&recv, joined, config)?;
+        Ok(recv)
+    }
 
-                    let ref sock = ipv4_sock.as_ref().unwrap();
+    /// Listen for messages on all local network interfaces until the duration has passed.
+    ///
+    /// This will call `listen_with_config_for()` with _default_ values.
+    fn listen_for(duration: Duration) -> SSDPResult<SSDPReceiver<Self::Message>> {
+        Self::listen_with_config_for(&Default::default(), duration)
+    }
 
-                    debug!("Joining ipv4 multicast {} at iface: {}", mcast_ip, addr);
-                    try!(net::join_multicast(&sock, &addr, &mcast_ip));
-                }
-                SocketAddr::V6(_) => {
-                    let mcast_ip = config.ipv6_addr.parse().unwrap();
+    /// Listen for messages on all local network interfaces until the duration has passed.
+    ///
+    /// The receiver stops delivering messages once the duration has passed, even if no
+    /// message was ever received, so iterating over it ends on its own.
+    fn listen_with_config_for(config: &Config,
+                              duration: Duration)
+                              -> SSDPResult<SSDPReceiver<Self::Message>> {
+        let (sockets, joined) = message::join_multicast_sockets(config)?;
 
-                    if ipv6_sock.is_none() {
-                        ipv6_sock = Some(try!(net::bind_reuse(("::", config.port))));
-                    }
+        let recv = receiver::<Self>(sockets, Some(duration), config)?;
+        message::watch_memberships(&recv, joined, config)?;
+        Ok(recv)
+    }
 
-                    let ref sock = ipv6_sock.as_ref().unwrap();
+    /// Listen for messages on all local network interfaces, including packets that fail to parse.
+    ///
+    /// This will call `listen_raw_with_config()` with _default_ values.
+    fn listen_raw() -> SSDPResult<SSDPReceiver<RawResult<Self::Message>>> {
+        Self::listen_raw_with_config(&Default::default())
+    }
 
-                    debug!("Joining ipv6 multicast {} at iface: {}", mcast_ip, addr);
-                    try!(net::join_multicast(&sock, &addr, &IpAddr::V6(mcast_ip)));
-                }
-            }
-        }
+    /// Listen for messages on all local network interfaces, including packets that fail to parse.
+    ///
+    /// Packets that can not be parsed into a message are delivered as an error along
+    /// with their raw bytes, for debugging interoperability problems. Messages that
+    /// parse are filtered like in `listen_with_config()`.
+    fn listen_raw_with_config(config: &Config) -> SSDPResult<SSDPReceiver<RawResult<Self::Message>>> {
+        let (sockets, joined) = message::join_multicast_sockets(config)?;
 
-        let sockets = vec![ipv4_sock, ipv6_sock]
-            .into_iter()
-            .flat_map(|opt_interface| opt_interface)
-            .collect();
+        let filter_config = config.clone();
+        let accept: fn(&Self::Message, &Config) -> Result<(), DropReason> = Self::accept;
+        let recv = SSDPReceiver::with_options(sockets,
+                                               None,
+                                               config.receiver_options(),
+                                               move |result: &RawResult<Self::Message>| {
+                                                   match *result {
+                                                       Ok(ref msg) => accept(msg, &filter_config),
+                                                       Err(_) => Ok(()),
+                                                   }
+                                               })?;
 
-        Ok(try!(SSDPReceiver::new(sockets, None)))
+        message::watch_memberships(&recv, joined, config)?;
+        Ok(recv)
     }
 
     /// Listen on any interface
     ///
     /// # Important
     ///
-    /// This version of the `listen`()` will _bind_ to `INADDR_ANY` instead of binding to each interface
-    #[cfg(linux)]
+    /// This version of the `listen()` will _bind_ to `INADDR_ANY` without joining on each selected interface.
+    /// The `IPv4` group is joined on the default interface, the `IPv6` group on every
+    /// interface with an `IPv6` address.
+    #[cfg(any(target_os = "linux", target_os = "macos", windows))]
     fn listen_anyaddr_with_config(config: &Config) -> SSDPResult<SSDPReceiver<Self::Message>> {
-        // Ipv4
-        let mcast_ip = config.ipv4_address.parse().unwrap();
-        let ipv4_sock = try!(net::bind_reuse(("0.0.0.0", config.port)));
-        try!(ipv4_sock.join_multicast_v4(&mcast_ip, &"0.0.0.0".parse().unwrap()));
-
-        // Ipv6
-        let mcast_ip = config.ipv6_address.parse().unwrap();
-        let ipv6_sock = try!(net::bind_reuse(("::", config.port)));
-        try!(ipv6_sock.join_multicast_v6(&mcast_ip, 0));
-
-        let sockets = vec![ipv4_sock, ipv6_sock];
-        Ok(try!(SSDPReceiver::new(sockets, None)))
+        config.validate_literal_groups()?;
+        let mut sockets = Vec::new();
+
+        if let IpVersionMode::V4Only | IpVersionMode::Any = config.mode {
+            let mcast_ip: Ipv4Addr = config.ipv4_addr.parse()?;
+            let ipv4_sock = message::bind_listener(("0.0.0.0", config.port), config)?;
+
+            debug!("Joining ipv4 multicast {} at default iface", mcast_ip);
+            ipv4_sock.join_multicast_v4(&mcast_ip, &Ipv4Addr::new(0, 0, 0, 0))?;
+            sockets.push(ipv4_sock);
+        }
+
+        if let IpVersionMode::V6Only | IpVersionMode::Any = config.mode {
+            let mcast_ip: Ipv6Addr = config.ipv6_addr.parse()?;
+            let ipv6_sock = message::bind_listener(("::", config.port), config)?;
+
+            for scope_id in message::ipv6_scope_ids()? {
+                debug!("Joining ipv6 multicast {} at scope id: {}", mcast_ip, scope_id);
+                if let Err(err) = ipv6_sock.join_multicast_v6(&mcast_ip, scope_id) {
+                    warn!("Failed to join ipv6 multicast {} at scope id {}: {}", mcast_ip, scope_id, err);
+                }
+            }
+            sockets.push(ipv6_sock);
+        }
+
+        receiver::<Self>(sockets, None, config)
+    }
+}
+
+/// Listener for every kind of SSDP message.
+///
+/// Notify messages, search requests and search responses are all received on the
+/// same pair of `IPv4` and `IPv6` sockets, for devices that need to both answer
+/// searches and watch the notifications of other devices.
+pub struct SsdpListener;
+
+impl Listen for SsdpListener {
+    type Message = ReceivedMessage;
+
+    fn accept(message: &ReceivedMessage, config: &Config) -> Result<(), DropReason> {
+        match *message {
+            ReceivedMessage::Notify(ref notify) => NotifyListener::accept(notify, config),
+            ReceivedMessage::Search(_) => Ok(()),
+            ReceivedMessage::Response(ref response) => message::accept_response(response, config),
+        }
+    }
+}
+
+/// Message received by an `SsdpListener`.
+#[derive(Debug, Clone)]
+pub enum ReceivedMessage {
+    /// A notify message.
+    Notify(NotifyMessage),
+    /// A search request.
+    Search(SearchRequest),
+    /// A response to a search request.
+    Response(SearchResponse),
+}
+
+impl FromRawSSDP for ReceivedMessage {
+    fn raw_ssdp(bytes: &[u8]) -> SSDPResult<ReceivedMessage> {
+        ReceivedMessage::raw_ssdp_with_policy(bytes, &ParsePolicy::default())
+    }
+
+    fn raw_ssdp_with_policy(bytes: &[u8], policy: &ParsePolicy) -> SSDPResult<ReceivedMessage> {
+        let message = SSDPMessage::raw_ssdp_with_policy(bytes, policy)?;
+
+        message.into_notify()
+            .map(ReceivedMessage::Notify)
+            .or_else(|message| message.into_search().map(ReceivedMessage::Search))
+            .or_else(|message| message.into_response().map(ReceivedMessage::Response))
+            .map_err(|_| "SSDP Message Received Is Of An Unknown Type".into())
+    }
+
+    fn deviations(&self) -> &[Deviation] {
+        match *self {
+            ReceivedMessage::Notify(ref n) => n.deviations(),
+            ReceivedMessage::Search(ref n) => n.deviations(),
+            ReceivedMessage::Response(ref n) => n.deviations(),
+        }
+    }
+}
+
+impl From<NotifyMessage> for ReceivedMessage {
+    fn from(message: NotifyMessage) -> ReceivedMessage {
+        ReceivedMessage::Notify(message)
+    }
+}
+
+impl From<SearchRequest> for ReceivedMessage {
+    fn from(message: SearchRequest) -> ReceivedMessage {
+        ReceivedMessage::Search(message)
+    }
+}
+
+impl From<SearchResponse> for ReceivedMessage {
+    fn from(message: SearchResponse) -> ReceivedMessage {
+        ReceivedMessage::Response(message)
+    }
+}
+
+/// Receiver combining receivers of different message types, built through `Merger`.
+pub type MergedReceiver = SSDPReceiver<ReceivedMessage>;
+
+/// Construct a receiver on the sockets that only delivers messages accepted by the listener.
+fn receiver<L: Listen + ?Sized>(sockets: Vec<UdpSocket>,
+                                time: Option<Duration>,
+                                config: &Config)
+                                -> SSDPResult<SSDPReceiver<L::Message>> {
+    let filter_config = config.clone();
+    let accept: fn(&L::Message, &Config) -> Result<(), DropReason> = L::accept;
+
+    Ok(SSDPReceiver::with_options(sockets,
+                                   time,
+                                   config.receiver_options(),
+                                   move |msg| accept(msg, &filter_config))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, UdpSocket};
+    use std::time::Duration;
+
+    use header::{HeaderRef, NT, ST, USN};
+    use message::{Config, Multicast, NotifyMessage, SearchResponse};
+    use net::IpVersionMode;
+    use receiver::{FromRawSSDP, Merger, SSDPReceiver};
+    use FieldMap;
+    use super::{Listen, MergedReceiver, ReceivedMessage, SsdpListener};
+    use message::NotifyListener;
+
+    const RAW_NOTIFY: &str = "NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nNT: upnp:rootdevice\r\nNTS: \
+                              ssdp:alive\r\nUSN: uuid:device\r\n\r\n";
+    const RAW_SEARCH: &str = "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \
+                              \"ssdp:discover\"\r\nMX: 3\r\nST: ssdp:all\r\n\r\n";
+    const RAW_RESPONSE: &str = "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=1800\r\nEXT:\r\nLOCATION: \
+                                http://192.168.1.1/desc.xml\r\nST: upnp:rootdevice\r\nUSN: \
+                                uuid:device::upnp:rootdevice\r\n\r\n";
+
+    #[test]
+    fn positive_received_notify() {
+        match ReceivedMessage::raw_ssdp(RAW_NOTIFY.as_bytes()).unwrap() {
+            ReceivedMessage::Notify(notify) => {
+                assert_eq!(notify.get::<NT>(), Some(&NT(FieldMap::upnp("rootdevice"))))
+            }
+            message => panic!("Unexpected message {:?}", message),
+        }
+    }
+
+    #[test]
+    fn positive_received_search() {
+        match ReceivedMessage::raw_ssdp(RAW_SEARCH.as_bytes()).unwrap() {
+            ReceivedMessage::Search(_) => (),
+            message => panic!("Unexpected message {:?}", message),
+        }
+    }
+
+    #[test]
+    fn positive_received_response() {
+        match ReceivedMessage::raw_ssdp(RAW_RESPONSE.as_bytes()).unwrap() {
+            ReceivedMessage::Response(response) => {
+                assert_eq!(response.get::<ST>(), Some(&ST::Target(FieldMap::upnp("rootdevice"))))
+            }
+            message => panic!("Unexpected message {:?}", message),
+        }
+    }
+
+    #[test]
+    fn negative_received_garbage() {
+        assert!(ReceivedMessage::raw_ssdp(b"not an ssdp message").is_err());
+    }
+
+    #[test]
+    fn positive_listener_receives_all_kinds() {
+        let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let recv = SsdpListener::listen_with_config(&Config::new().set_port(port)).unwrap();
+
+        let send_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        for raw in &[RAW_NOTIFY, RAW_SEARCH, RAW_RESPONSE] {
+            send_sock.send_to(raw.as_bytes(), ("127.0.0.1", port)).unwrap();
+        }
+
+        let kinds = (0..3)
+            .map(|_| match recv.recv().unwrap().0 {
+                ReceivedMessage::Notify(_) => "notify",
+                ReceivedMessage::Search(_) => "search",
+                ReceivedMessage::Response(_) => "response",
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(kinds, vec!["notify", "search", "response"]);
+    }
+
+    #[test]
+    fn positive_merged_receiver_mixes_types() {
+        let notify_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let response_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let notify_addr = notify_sock.local_addr().unwrap();
+        let response_addr = response_sock.local_addr().unwrap();
+
+        let notify = SSDPReceiver::<NotifyMessage>::new(vec![notify_sock], None).unwrap();
+        let response = SSDPReceiver::<SearchResponse>::new(vec![response_sock], None).unwrap();
+        let merged: MergedReceiver = Merger::new()
+            .add_receiver(notify)
+            .add_receiver(response)
+            .build()
+            .unwrap();
+
+        let send_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        send_sock.send_to(RAW_NOTIFY.as_bytes(), notify_addr).unwrap();
+        send_sock.send_to(RAW_RESPONSE.as_bytes(), response_addr).unwrap();
+
+        let mut kinds = merged.iter_for(Duration::from_millis(500))
+            .map(|(message, _)| match message {
+                ReceivedMessage::Notify(_) => "notify",
+                ReceivedMessage::Search(_) => "search",
+                ReceivedMessage::Response(_) => "response",
+            })
+            .collect::<Vec<_>>();
+        kinds.sort();
+
+        assert_eq!(kinds, vec!["notify", "response"]);
+    }
+
+    #[test]
+    fn positive_listen_large_packet() {
+        let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let config = Config::new().set_port(port).set_max_packet_size(4096);
+        let recv = SsdpListener::listen_with_config(&config).unwrap();
+
+        // Pad the notify past a typical MTU with a long vendor header
+        let raw = RAW_NOTIFY.replace("\r\n\r\n", &format!("\r\nX-VENDOR: {}\r\n\r\n", "a".repeat(3000)));
+        assert!(raw.len() > 3000);
+
+        let send_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        send_sock.send_to(raw.as_bytes(), ("127.0.0.1", port)).unwrap();
+
+        match recv.recv().unwrap().0 {
+            ReceivedMessage::Notify(_) => (),
+            message => panic!("Unexpected message {:?}", message),
+        }
+    }
+
+    #[test]
+    fn positive_listen_read_timeout() {
+        let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let config = Config::new().set_port(port).set_read_timeout(Some(Duration::from_millis(100)));
+        let recv = SsdpListener::listen_with_config(&config).unwrap();
+
+        let send_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        for _ in 0..2 {
+            send_sock.send_to(RAW_NOTIFY.as_bytes(), ("127.0.0.1", port)).unwrap();
+            match recv.recv_timeout(Duration::from_millis(1000)).unwrap().0 {
+                ReceivedMessage::Notify(_) => (),
+                message => panic!("Unexpected message {:?}", message),
+            }
+
+            // Quiet for several read timeouts, the listener keeps going
+            ::std::thread::sleep(Duration::from_millis(350));
+        }
+
+        recv.close_and_join(Duration::from_millis(500)).unwrap();
+    }
+
+    #[test]
+    fn positive_listen_again_after_close() {
+        let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let config = Config::new().set_port(port);
+
+        let recv = SsdpListener::listen_with_config(&config).unwrap();
+        recv.close_and_join(Duration::from_millis(1000)).unwrap();
+        let recv = SsdpListener::listen_with_config(&config).unwrap();
+
+        let send_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        send_sock.send_to(RAW_NOTIFY.as_bytes(), ("127.0.0.1", port)).unwrap();
+
+        match recv.recv().unwrap().0 {
+            ReceivedMessage::Notify(_) => (),
+            message => panic!("Unexpected message {:?}", message),
+        }
+    }
+
+    #[test]
+    fn positive_listen_recv_buffer() {
+        let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let config = Config::new().set_port(port).set_recv_buffer(65536);
+        let recv = SsdpListener::listen_with_config(&config).unwrap();
+
+        let sizes = recv.recv_buffer_sizes().unwrap();
+        assert!(!sizes.is_empty());
+        assert!(sizes.iter().all(|&size| size >= 65536));
+
+        recv.close_and_join(Duration::from_millis(1000)).unwrap();
+        assert!(recv.recv_buffer_sizes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn positive_listen_raw_surfaces_invalid_packets() {
+        let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let recv = NotifyListener::listen_raw_with_config(&Config::new().set_port(port)).unwrap();
+
+        let send_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        send_sock.send_to(RAW_NOTIFY.as_bytes(), ("127.0.0.1", port)).unwrap();
+        send_sock.send_to(b"NOTIFY garbage", ("127.0.0.1", port)).unwrap();
+
+        let (valid, src) = recv.recv().unwrap();
+        assert_eq!(src, send_sock.local_addr().unwrap());
+        assert_eq!(valid.unwrap().get::<NT>(), Some(&NT(FieldMap::upnp("rootdevice"))));
+
+        match recv.recv().unwrap().0 {
+            Err((_, bytes)) => assert_eq!(&bytes[..], &b"NOTIFY garbage"[..]),
+            Ok(message) => panic!("Parsed an invalid packet into {:?}", message),
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn positive_listen_reports_interface_local_addr() {
+        let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let config = Config::new()
+            .set_port(port)
+            .set_mode(IpVersionMode::V4Only)
+            .set_interfaces(vec!["127.0.0.1".parse().unwrap()])
+            .set_multicast_loop(true);
+        let recv = NotifyListener::listen_with_config(&config).unwrap();
+
+        NotifyMessage::byebye(NT(FieldMap::upnp("rootdevice")), USN(FieldMap::uuid("local"), None))
+            .multicast_with_config(&config)
+            .unwrap();
+
+        // Bound to the unspecified address, yet tagged with the interface the group was joined on
+        let (_, src, local) = recv.recv_with_local().unwrap();
+        assert!(src.ip().is_loopback());
+        assert_eq!(local, SocketAddr::from(([127, 0, 0, 1], port)));
     }
 }
@@ -1,32 +1,106 @@
 //! Messaging primitives for discovering devices and services.
 
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
 use std::io;
-use std::net::SocketAddr;
+use std::net::{SocketAddr, IpAddr, Ipv4Addr, Ipv6Addr, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use error::{SSDPError, SSDPResult, SSDPErrorKind};
+use header::{HeaderRef, CacheControl, CacheDirective, MX};
 use net::connector::UdpConnector;
-use net::IpVersionMode;
+use net::packet::DEFAULT_MAX_PCKT_LEN;
+use net::{self, IpVersionMode};
+use receiver::{self, DropReason, ReceiverBackend, ReceiverOptions, SSDPReceiver, DEFAULT_READS_PER_TURN};
 
+mod cache;
 mod notify;
 mod search;
 mod ssdp;
 pub mod listen;
 pub mod multicast;
 
-use get_if_addrs;
+use get_if_addrs::{self, IfAddr};
 
 pub use message::multicast::Multicast;
-pub use message::search::{SearchRequest, SearchResponse, SearchListener};
-pub use message::notify::{NotifyMessage, NotifyListener};
-pub use message::listen::Listen;
+pub use message::ssdp::{SSDPMessage, ParsePolicy, Deviation};
+pub use message::search::{SearchRequest, SearchResponse, SearchListener, PeriodicSearch, SearchResponder,
+                          ResponderTarget, DelayedResponse, TargetSet, ResponseSpec};
+pub use message::notify::{NotifyMessage, NotifyMessageBuilder, NotifyListener, NotifyFilter, NotifyGuard,
+                          Advertiser, DeviceAdvertisement};
+pub use message::listen::{Listen, SsdpListener, ReceivedMessage, MergedReceiver};
+pub use message::cache::{DeviceCache, DiscoveredDevice, DeviceField, CacheDelta, RemovalReason,
+                         DEFAULT_SUBSCRIBER_CAPACITY};
 
 /// Multicast Socket Information
 pub const UPNP_MULTICAST_IPV4_ADDR: &'static str = "239.255.255.250";
 pub const UPNP_MULTICAST_IPV6_LINK_LOCAL_ADDR: &'static str = "FF02::C";
+pub const UPNP_MULTICAST_IPV6_SITE_LOCAL_ADDR: &'static str = "FF05::C";
+pub const UPNP_MULTICAST_IPV6_ORGANIZATION_LOCAL_ADDR: &'static str = "FF08::C";
 pub const UPNP_MULTICAST_PORT: u16 = 1900;
 
 /// Default TTL For Multicast
 pub const UPNP_MULTICAST_TTL: u32 = 2;
 
+/// Default Limit On The Serialized Size Of Outgoing Messages
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 1452;
+
+/// Default Interval Between Polls Of The Local Interfaces While Listening
+pub const DEFAULT_INTERFACE_POLL_SECS: u64 = 10;
+
+/// How long the local interfaces listed by an `InterfaceSet` are reused before listing them again.
+const INTERFACE_CACHE_MS: u64 = 1000;
+
+/// Default Hop Limits For Wider IPv6 Multicast Scopes
+pub const UPNP_MULTICAST_SITE_HOPS: u32 = 8;
+pub const UPNP_MULTICAST_ORGANIZATION_HOPS: u32 = 32;
+
+/// Scope of the `IPv6` multicast group used for SSDP.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Ipv6Scope {
+    /// The link-local group, `FF02::C`.
+    LinkLocal,
+    /// The site-local group, `FF05::C`.
+    SiteLocal,
+    /// The organization-local group, `FF08::C`.
+    OrganizationLocal,
+    /// Some other multicast group.
+    Custom(Ipv6Addr),
+}
+
+impl Ipv6Scope {
+    /// Scope of the given multicast group.
+    pub fn from_addr(addr: Ipv6Addr) -> Ipv6Scope {
+        let scopes = [Ipv6Scope::LinkLocal, Ipv6Scope::SiteLocal, Ipv6Scope::OrganizationLocal];
+
+        scopes.iter().cloned().find(|scope| scope.addr() == addr).unwrap_or(Ipv6Scope::Custom(addr))
+    }
+
+    /// Multicast group of the scope.
+    pub fn addr(&self) -> Ipv6Addr {
+        match *self {
+            Ipv6Scope::LinkLocal => UPNP_MULTICAST_IPV6_LINK_LOCAL_ADDR.parse().unwrap(),
+            Ipv6Scope::SiteLocal => UPNP_MULTICAST_IPV6_SITE_LOCAL_ADDR.parse().unwrap(),
+            Ipv6Scope::OrganizationLocal => UPNP_MULTICAST_IPV6_ORGANIZATION_LOCAL_ADDR.parse().unwrap(),
+            Ipv6Scope::Custom(addr) => addr,
+        }
+    }
+
+    /// Default hop limit for multicasting to the group.
+    ///
+    /// Link-local groups never leave the link, wider groups get more hops the wider their scope.
+    pub fn hop_limit(&self) -> u32 {
+        match net::multicast_scope(&self.addr()) {
+            0..=2 => 1,
+            3..=5 => UPNP_MULTICAST_SITE_HOPS,
+            _ => UPNP_MULTICAST_ORGANIZATION_HOPS,
+        }
+    }
+}
+
 /// Enumerates different types of SSDP messages.
 #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
 pub enum MessageType {
@@ -38,13 +112,88 @@ pub enum MessageType {
     Response,
 }
 
+/// Local interface to listen and send on, see `Config::set_allowed_interfaces`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InterfaceSelector {
+    /// Every address of the interface with the name, such as `eth0`.
+    Name(String),
+    /// The interface address.
+    Addr(IpAddr),
+}
+
+impl InterfaceSelector {
+    /// Whether the interface address is selected.
+    fn matches(&self, iface: &LocalInterface) -> bool {
+        match *self {
+            InterfaceSelector::Name(ref name) => *name == iface.name,
+            InterfaceSelector::Addr(ip) => ip == iface.addr,
+        }
+    }
+}
+
+impl From<IpAddr> for InterfaceSelector {
+    fn from(addr: IpAddr) -> InterfaceSelector {
+        InterfaceSelector::Addr(addr)
+    }
+}
+
+impl From<String> for InterfaceSelector {
+    /// Selects the address if the string is an IP address, and the interface name otherwise.
+    fn from(value: String) -> InterfaceSelector {
+        match value.parse() {
+            Ok(addr) => InterfaceSelector::Addr(addr),
+            Err(_) => InterfaceSelector::Name(value),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for InterfaceSelector {
+    fn from(value: &'a str) -> InterfaceSelector {
+        InterfaceSelector::from(value.to_owned())
+    }
+}
+
+impl fmt::Display for InterfaceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InterfaceSelector::Name(ref name) => f.write_str(name),
+            InterfaceSelector::Addr(ref addr) => write!(f, "{}", addr),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Config {
     pub ipv4_addr: String,
     pub ipv6_addr: String,
     pub port: u16,
-    pub ttl: u32,
+    pub ttl_v4: u32,
+    pub hops_v6: Option<u32>,
     pub mode: IpVersionMode,
+    pub forbid_dns: bool,
+    pub max_age_bounds: Option<(u32, u32)>,
+    pub strict_responses: bool,
+    pub max_message_size: usize,
+    pub max_packet_size: usize,
+    pub read_timeout: Option<Duration>,
+    pub announce_repeats: u8,
+    pub mx: Option<u8>,
+    pub interfaces: Option<Vec<InterfaceSelector>>,
+    pub excluded_interfaces: Vec<String>,
+    pub include_loopback: bool,
+    pub allow_global_ipv6: bool,
+    pub prefer_global_ipv6: bool,
+    pub interface_poll: Option<Duration>,
+    pub parse_policy: ParsePolicy,
+    pub receiver_backend: ReceiverBackend,
+    pub reads_per_turn: usize,
+    pub anomaly_sources: usize,
+    pub multicast_loop: Option<bool>,
+    pub recv_buffer: Option<usize>,
+    pub send_buffer: Option<usize>,
+    pub source_port: u16,
+    pub broadcast_fallback: bool,
+    pub dscp: u8,
 }
 
 impl Config {
@@ -62,20 +211,375 @@ impl Config {
         self
     }
 
+    /// Use the multicast group of the scope as the `IPv6` address.
+    ///
+    /// Multicasts to groups wider than link-local are sent without a scope id and
+    /// with a larger hop limit, see `Ipv6Scope::hop_limit`.
+    pub fn set_ipv6_scope(mut self, value: Ipv6Scope) -> Self {
+        self.ipv6_addr = value.addr().to_string();
+        self
+    }
+
+    /// Scope of the configured `IPv6` address.
+    pub fn ipv6_scope(&self) -> SSDPResult<Ipv6Scope> {
+        Ok(Ipv6Scope::from_addr(self.ipv6_addr.parse()?))
+    }
+
     pub fn set_port(mut self, value: u16) -> Self {
         self.port = value;
         self
     }
 
-    pub fn set_ttl(mut self, value: u32) -> Self {
-        self.ttl = value;
+    /// Multicast ttl of outgoing `IPv4` messages and hop limit of outgoing `IPv6` messages.
+    pub fn set_ttl(self, value: u32) -> Self {
+        self.set_ttl_v4(value).set_hops_v6(value)
+    }
+
+    /// Multicast ttl of outgoing `IPv4` messages.
+    pub fn set_ttl_v4(mut self, value: u32) -> Self {
+        self.ttl_v4 = value;
+        self
+    }
+
+    /// Multicast hop limit of outgoing `IPv6` messages.
+    ///
+    /// Defaults to the hop limit of the group's scope, see `Ipv6Scope::hop_limit`.
+    pub fn set_hops_v6(mut self, value: u32) -> Self {
+        self.hops_v6 = Some(value);
         self
     }
 
+    /// Multicast hop limit of outgoing `IPv6` messages, falling back to the one of the group's scope.
+    pub fn multicast_hops_v6(&self) -> SSDPResult<u32> {
+        match self.hops_v6 {
+            Some(hops) => Ok(hops),
+            None => Ok(self.ipv6_scope()?.hop_limit()),
+        }
+    }
+
     pub fn set_mode(mut self, value: IpVersionMode) -> Self {
         self.mode = value;
         self
     }
+
+    /// Set the mode from its textual form, `v4`, `v6` or `any`, see `IpVersionMode`.
+    pub fn set_mode_str(self, value: &str) -> SSDPResult<Self> {
+        Ok(self.set_mode(value.parse()?))
+    }
+
+    /// Refuse to resolve any configured address that is not an IP literal.
+    pub fn set_forbid_dns(mut self, value: bool) -> Self {
+        self.forbid_dns = value;
+        self
+    }
+
+    /// Only deliver received messages whose `CacheControl` max-age lies within the bounds (inclusive).
+    ///
+    /// Messages without a max-age directive are always delivered. Dropped messages are
+    /// counted as `max_age_out_of_bounds` in `SSDPReceiver::stats()`.
+    pub fn set_max_age_bounds(mut self, min: u32, max: u32) -> Self {
+        self.max_age_bounds = Some((min, max));
+        self
+    }
+
+    /// Drop received search responses that fail `SearchResponse::validate` instead of delivering them.
+    pub fn set_strict_responses(mut self, value: bool) -> Self {
+        self.strict_responses = value;
+        self
+    }
+
+    /// Refuse to send messages that serialize to more than the given number of bytes.
+    ///
+    /// Applies to multicasts and to the `unicast_with_config` sends, other unicast sends
+    /// keep the default limit.
+    pub fn set_max_message_size(mut self, value: usize) -> Self {
+        self.max_message_size = value;
+        self
+    }
+
+    /// Read received packets of up to the given number of bytes in full.
+    ///
+    /// Longer packets are truncated and fail to parse, 2048 bytes by default.
+    pub fn set_max_packet_size(mut self, value: usize) -> Self {
+        self.max_packet_size = value;
+        self
+    }
+
+    /// Wake the receiver threads of listeners and searches up at least once every timeout.
+    ///
+    /// Without a timeout, closing a receiver wakes its threads up directly. With one, closing
+    /// leaves them to notice once the timeout expires, unless the close would give up first.
+    /// Expiry never ends the receiver. A zero duration is treated as no timeout.
+    pub fn set_read_timeout(mut self, value: Option<Duration>) -> Self {
+        self.read_timeout = value;
+        self
+    }
+
+    /// Send each multicast notify message this many times, at least once.
+    ///
+    /// Repeats go out on the same sockets, spaced by random gaps of under 200ms.
+    pub fn set_announce_repeats(mut self, value: u8) -> Self {
+        self.announce_repeats = value;
+        self
+    }
+
+    /// Fill in the `MX` header of search requests that are multicast without one.
+    ///
+    /// Requests that carry an `MX` header keep it. Errors if the value is outside
+    /// the bounds of the header, see `MX::new`.
+    pub fn set_mx(mut self, value: u8) -> SSDPResult<Self> {
+        MX::new(value)?;
+        self.mx = Some(value);
+        Ok(self)
+    }
+
+    /// Only listen and send on the local interfaces with the given addresses.
+    ///
+    /// Same as `set_allowed_interfaces` with addresses only.
+    pub fn set_interfaces(self, value: Vec<IpAddr>) -> Self {
+        self.set_allowed_interfaces(value)
+    }
+
+    /// Only listen and send on the local interfaces with the given names or addresses.
+    ///
+    /// Interfaces in the list are used even if they are loopback interfaces. Ones that are not
+    /// present on the local machine are skipped with a warning, listening or sending fails
+    /// if none of them are present.
+    pub fn set_allowed_interfaces<I, S>(mut self, value: I) -> Self
+        where I: IntoIterator<Item = S>,
+              S: Into<InterfaceSelector>
+    {
+        self.interfaces = Some(value.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Never listen or send on the local interfaces with the given names.
+    ///
+    /// A name ending in `*` excludes every interface whose name starts with the rest, as
+    /// in `veth*`. Interfaces explicitly allowed through `set_allowed_interfaces` win over
+    /// exclusions, so exclusions only apply when no interfaces are allowed explicitly.
+    pub fn set_excluded_interfaces<I, S>(mut self, value: I) -> Self
+        where I: IntoIterator<Item = S>,
+              S: Into<String>
+    {
+        self.excluded_interfaces = value.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Also listen and send on loopback interfaces, which are skipped by default.
+    ///
+    /// Lets a device and control point on the same host, or in containers on its network,
+    /// discover each other.
+    pub fn set_include_loopback(mut self, value: bool) -> Self {
+        self.include_loopback = value;
+        self
+    }
+
+    /// Also listen and send on global `IPv6` addresses of interfaces that have a link-local address.
+    ///
+    /// Global addresses are always used on interfaces without a link-local address.
+    pub fn set_allow_global_ipv6(mut self, value: bool) -> Self {
+        self.allow_global_ipv6 = value;
+        self
+    }
+
+    /// Prefer the global `IPv6` address of interfaces over their link-local address.
+    ///
+    /// Interfaces are listened and sent on through a single `IPv6` address, which is the
+    /// link-local one by default. Has no effect along with `set_allow_global_ipv6`, which
+    /// uses both.
+    pub fn set_prefer_global_ipv6(mut self, value: bool) -> Self {
+        self.prefer_global_ipv6 = value;
+        self
+    }
+
+    /// Poll the local interfaces at this interval while listening, joining the multicast
+    /// group on interfaces that appear and leaving it on ones that go away.
+    ///
+    /// Polling is disabled with `None`.
+    pub fn set_interface_poll(mut self, value: Option<Duration>) -> Self {
+        self.interface_poll = value;
+        self
+    }
+
+    /// Loop outgoing multicasts back to the local host, so local listeners see them.
+    ///
+    /// Applied to the connectors of every multicast, including those of an `Advertiser`,
+    /// and to the sockets of listeners since Windows honors the option on the receiving
+    /// side. Turn it on to test a device and a control point on the same host, or off to
+    /// keep a listener from seeing the multicasts of the same process. Left at the
+    /// platform default unless set, which loops back on Linux, macOS and Windows.
+    pub fn set_multicast_loop(mut self, value: bool) -> Self {
+        self.multicast_loop = Some(value);
+        self
+    }
+
+    /// Size of the receive buffer of every socket, in bytes.
+    ///
+    /// Bursts of responses can overflow the platform default and be dropped. Kernels
+    /// clamp the size, see `SSDPReceiver::recv_buffer_sizes()` for the effective size.
+    pub fn set_recv_buffer(mut self, value: usize) -> Self {
+        self.recv_buffer = Some(value);
+        self
+    }
+
+    /// Size of the send buffer of every socket, in bytes.
+    pub fn set_send_buffer(mut self, value: usize) -> Self {
+        self.send_buffer = Some(value);
+        self
+    }
+
+    /// Send searches and notifies from this local port, 0 picks an ephemeral port.
+    ///
+    /// Applies to multicasts and unicasts alike, the sockets on every interface
+    /// share the port. An error of kind `SSDPErrorKind::SourcePortInUse` is
+    /// returned if a socket that does not allow reuse already has it.
+    pub fn set_source_port(mut self, value: u16) -> Self {
+        self.source_port = value;
+        self
+    }
+
+    /// Also send multicasts to the directed broadcast address of each `IPv4` interface.
+    ///
+    /// For networks that filter the multicast group but let subnet broadcasts through,
+    /// responses arrive on the same receiver.
+    pub fn set_broadcast_fallback(mut self, value: bool) -> Self {
+        self.broadcast_fallback = value;
+        self
+    }
+
+    /// Mark outgoing packets with the DSCP value, which must fit in 6 bits.
+    ///
+    /// Left untouched with 0. An error of kind `SSDPErrorKind::DscpRefused` is returned
+    /// when sending if the platform refuses the value, as Windows does without privileges.
+    pub fn set_dscp(mut self, value: u8) -> Self {
+        self.dscp = value;
+        self
+    }
+
+    /// Parse received messages according to the policy.
+    ///
+    /// Strict by default, see `ParsePolicy` for the deviations that can be tolerated.
+    pub fn set_parse_policy(mut self, value: ParsePolicy) -> Self {
+        self.parse_policy = value;
+        self
+    }
+
+    /// Read from the sockets of listeners and searches through the backend.
+    ///
+    /// A thread per socket by default, see `ReceiverBackend` for polling them from one thread.
+    /// There is no `WSAPoll` support, on Windows `ReceiverBackend::Polled` uses a thread per
+    /// socket as well.
+    pub fn set_receiver_backend(mut self, value: ReceiverBackend) -> Self {
+        self.receiver_backend = value;
+        self
+    }
+
+    /// Read at most this many queued packets from one socket before moving on to the next.
+    ///
+    /// Only the `Polled` backend reads several sockets from one thread, 16 by default.
+    /// See `ReceiverOptions::set_reads_per_turn`.
+    pub fn set_reads_per_turn(mut self, value: usize) -> Self {
+        self.reads_per_turn = value;
+        self
+    }
+
+    /// Track the messages that fail to parse, or that the parse policy tolerated deviations in,
+    /// for up to this many sources, 0 by default.
+    ///
+    /// See `ReceiverOptions::set_anomaly_sources` and `SSDPReceiver::anomalies`.
+    pub fn set_anomaly_sources(mut self, value: usize) -> Self {
+        self.anomaly_sources = value;
+        self
+    }
+
+    /// Options of the receivers of listeners and searches.
+    fn receiver_options(&self) -> ReceiverOptions {
+        ReceiverOptions::new()
+            .set_backend(self.receiver_backend)
+            .set_parse_policy(self.parse_policy)
+            .set_max_packet_size(self.max_packet_size)
+            .set_read_timeout(self.read_timeout)
+            .set_reads_per_turn(self.reads_per_turn)
+            .set_anomaly_sources(self.anomaly_sources)
+    }
+
+    /// Multicast ttl, or hop limit, of sockets bound to an address of the same IP version.
+    fn multicast_ttl(&self, addr: IpAddr) -> SSDPResult<u32> {
+        match addr {
+            IpAddr::V4(_) => Ok(self.ttl_v4),
+            IpAddr::V6(_) => self.multicast_hops_v6(),
+        }
+    }
+
+    /// Check that the configured addresses are multicast groups of their IP version.
+    ///
+    /// Only the addresses of the IP versions enabled by the mode are checked. The `IPv4`
+    /// address may be a host name that is resolved when sending, unless DNS lookups are
+    /// forbidden, the `IPv6` address must be a group of a valid scope. Multicasting checks
+    /// the config before touching the network, an address that is not a group gives an
+    /// error of kind `SSDPErrorKind::NotMulticastGroup`.
+    pub fn validate(&self) -> SSDPResult<()> {
+        self.check_groups(false)
+    }
+
+    /// Same as `validate`, except that the `IPv4` address must be an IP literal.
+    ///
+    /// Listening joins the groups by address, so it checks the config with this instead.
+    fn validate_literal_groups(&self) -> SSDPResult<()> {
+        self.check_groups(true)
+    }
+
+    fn check_groups(&self, literal_only: bool) -> SSDPResult<()> {
+        if let IpVersionMode::V4Only | IpVersionMode::Any = self.mode {
+            self.check_literal(&self.ipv4_addr)?;
+            // Nothing but digits and dots is a mistyped address rather than a host name
+            let dotted = self.ipv4_addr.chars().all(|c| c.is_ascii_digit() || c == '.');
+
+            let is_group = match self.ipv4_addr.parse::<IpAddr>() {
+                Ok(IpAddr::V4(addr)) => addr.is_multicast(),
+                Ok(IpAddr::V6(_)) => false,
+                Err(err) if literal_only || dotted => return Err(err.into()),
+                Err(_) => true,
+            };
+            if !is_group {
+                return Err(SSDPErrorKind::NotMulticastGroup(self.ipv4_addr.clone()).into());
+            }
+        }
+
+        if let IpVersionMode::V6Only | IpVersionMode::Any = self.mode {
+            // Scopes 0 and F are reserved, groups of those scopes can not be joined or sent to
+            let group = self.ipv6_scope()?.addr();
+            if !group.is_multicast() || !(1..=0xE).contains(&net::multicast_scope(&group)) {
+                return Err(SSDPErrorKind::NotMulticastGroup(self.ipv6_addr.clone()).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Names and addresses of the local interfaces that listening and multicasting use.
+    ///
+    /// At most one address per interface and IP version allowed by the mode, see
+    /// `set_allowed_interfaces` and `set_excluded_interfaces` for which are selected.
+    pub fn selected_interfaces(&self) -> io::Result<Vec<(String, IpAddr)>> {
+        map_local(self, |iface| {
+            Ok(if self.mode.allows(&iface.socket_addr()) {
+                Some((iface.name.clone(), iface.addr))
+            } else {
+                None
+            })
+        })
+    }
+
+    /// Check that the address can be used without a DNS lookup, if lookups are forbidden.
+    fn check_literal(&self, addr: &str) -> SSDPResult<()> {
+        if self.forbid_dns && !net::is_literal_addr(addr) {
+            Err(SSDPErrorKind::DnsForbidden(addr.to_owned()).into())
+        } else {
+            Ok(())
+        }
+    }
 }
 
 impl Default for Config {
@@ -84,62 +588,1621 @@ impl Default for Config {
             ipv4_addr: UPNP_MULTICAST_IPV4_ADDR.to_string(),
             ipv6_addr: UPNP_MULTICAST_IPV6_LINK_LOCAL_ADDR.to_string(),
             port: UPNP_MULTICAST_PORT,
-            ttl: UPNP_MULTICAST_TTL,
+            ttl_v4: UPNP_MULTICAST_TTL,
+            hops_v6: None,
             mode: IpVersionMode::Any,
+            forbid_dns: false,
+            max_age_bounds: None,
+            strict_responses: false,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_packet_size: DEFAULT_MAX_PCKT_LEN,
+            read_timeout: None,
+            announce_repeats: 1,
+            mx: None,
+            interfaces: None,
+            excluded_interfaces: Vec::new(),
+            include_loopback: false,
+            allow_global_ipv6: false,
+            prefer_global_ipv6: false,
+            interface_poll: Some(Duration::from_secs(DEFAULT_INTERFACE_POLL_SECS)),
+            parse_policy: ParsePolicy::default(),
+            receiver_backend: ReceiverBackend::default(),
+            reads_per_turn: DEFAULT_READS_PER_TURN,
+            anomaly_sources: 0,
+            multicast_loop: None,
+            recv_buffer: None,
+            send_buffer: None,
+            source_port: 0,
+            broadcast_fallback: false,
+            dscp: 0,
         }
     }
 }
 
-/// Generate `UdpConnector` objects for all local `IPv4` interfaces.
-fn all_local_connectors(multicast_ttl: Option<u32>, filter: &IpVersionMode) -> io::Result<Vec<UdpConnector>> {
-    trace!("Fetching all local connectors");
-    map_local(|&addr| match (filter, addr) {
-        (&IpVersionMode::V4Only, SocketAddr::V4(n)) |
-        (&IpVersionMode::Any, SocketAddr::V4(n)) => {
-            Ok(Some(try!(UdpConnector::new((*n.ip(), 0), multicast_ttl))))
+/// Returns true if the max-age of the message is within the bounds of the `Config`.
+///
+/// Messages without a max-age directive are always within bounds.
+fn max_age_in_bounds<H: HeaderRef>(message: &H, config: &Config) -> bool {
+    let (min, max) = match config.max_age_bounds {
+        Some(bounds) => bounds,
+        None => return true,
+    };
+
+    match max_age(message) {
+        Some(n) if n < min || n > max => {
+            debug!("Dropping message with max-age {} outside of bounds [{}, {}]", n, min, max);
+            false
         }
-        (&IpVersionMode::V6Only, SocketAddr::V6(n)) |
-        (&IpVersionMode::Any, SocketAddr::V6(n)) => Ok(Some(try!(UdpConnector::new(n, multicast_ttl)))),
-        _ => Ok(None),
+        _ => true,
+    }
+}
+
+/// Fails with `DropReason::MaxAgeOutOfBounds` if the max-age of the message is outside of the bounds
+/// of the `Config`.
+fn check_max_age<H: HeaderRef>(message: &H, config: &Config) -> Result<(), DropReason> {
+    if max_age_in_bounds(message, config) {
+        Ok(())
+    } else {
+        Err(DropReason::MaxAgeOutOfBounds)
+    }
+}
+
+/// Whether a received search response should be delivered given the `Config`, or why it is dropped.
+fn accept_response(message: &SearchResponse, config: &Config) -> Result<(), DropReason> {
+    check_max_age(message, config)?;
+
+    if config.strict_responses {
+        if let Err(errors) = message.validate() {
+            debug!("Dropping invalid search response: {:?}", errors);
+            return Err(DropReason::Filtered);
+        }
+    }
+
+    Ok(())
+}
+
+/// The max-age directive of the message, if it has one.
+fn max_age<H: HeaderRef>(message: &H) -> Option<u32> {
+    message.get::<CacheControl>().and_then(|directives| {
+        directives.iter().filter_map(|directive| match *directive {
+            CacheDirective::MaxAge(n) => Some(n),
+            _ => None,
+        }).next()
     })
 }
 
-/// Invoke the closure for every local address found on the system
+/// Random number of milliseconds in the range `[0, max_millis]`.
+fn random_millis(max_millis: u64) -> u64 {
+    if max_millis == 0 {
+        return 0;
+    }
+
+    // The std hasher is seeded randomly, which is plenty for spreading messages out
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(max_millis);
+
+    hasher.finish() % (max_millis + 1)
+}
+
+/// Product tokens identifying this library in the `Server` header of outgoing messages.
+fn default_server() -> String {
+    format!("{} UPnP/1.1 ssdp-rs/{}", env::consts::OS, env!("CARGO_PKG_VERSION"))
+}
+
+/// Aggregate the results of sending a message through a number of local sockets.
 ///
-/// This method filters out _loopback_ and _global_ addresses.
-fn map_local<F, R>(mut f: F) -> io::Result<Vec<R>>
-    where F: FnMut(&SocketAddr) -> io::Result<Option<R>>
+/// Fails with `SSDPErrorKind::AllSendsFailed` only if every send failed,
+/// otherwise individual failures are logged and the send is considered a success.
+fn check_sends<I>(results: I) -> SSDPResult<()>
+    where I: IntoIterator<Item = (SocketAddr, SSDPResult<()>)>
 {
-    let addrs_iter = try!(get_local_addrs());
+    let mut success_count = 0;
+    let mut failures = Vec::new();
 
-    let mut obj_list = Vec::with_capacity(addrs_iter.len());
+    for (addr, result) in results {
+        match result {
+            Ok(()) => success_count += 1,
+            Err(err) => failures.push((addr, err)),
+        }
+    }
 
-    for addr in addrs_iter {
-        trace!("Found {}", addr);
-        match addr {
-            SocketAddr::V4(n) if !n.ip().is_loopback() => {
-                if let Some(x) = try!(f(&addr)) {
-                    obj_list.push(x);
-                }
+    if success_count == 0 && !failures.is_empty() {
+        return Err(SSDPErrorKind::AllSendsFailed(failures).into());
+    }
+
+    // Some routing errors are expected, not all interfaces can reach the destination
+    for (addr, err) in failures {
+        debug!("Send through {} failed: {}", addr, err);
+    }
+
+    Ok(())
+}
+
+/// Bind reused sockets on the multicast port and join the multicast group on every local interface.
+///
+/// Returns the sockets along with the local addresses the group was joined on.
+///
+/// # Notes
+/// This will _bind_ one socket per IP version to `INADDR_ANY`, joining the group on each
+/// interface, the receiver recovers the interface of each packet from its packet info.
+fn join_multicast_sockets(config: &Config) -> SSDPResult<(Vec<UdpSocket>, Vec<SocketAddr>)> {
+    config.validate_literal_groups()?;
+    let mut ipv4_sock = None;
+    let mut ipv6_sock = None;
+
+    // Generate a list of reused sockets on the standard multicast address.
+    let addrs = local_multicast_addrs(config)?;
+
+    let mut joined = Vec::new();
+    let mut failures = Vec::new();
+    for addr in addrs {
+        let sock = match addr {
+            SocketAddr::V4(_) => &mut ipv4_sock,
+            SocketAddr::V6(_) => &mut ipv6_sock,
+        };
+
+        if sock.is_none() {
+            *sock = Some(match addr {
+                SocketAddr::V4(_) => bind_listener(("0.0.0.0", config.port), config)?,
+                SocketAddr::V6(_) => bind_listener(("::", config.port), config)?,
+            });
+        }
+
+        match join_group(sock.as_ref().unwrap(), &addr, config) {
+            Ok(()) => joined.push(addr),
+            Err(err) => failures.push((addr, err)),
+        }
+    }
+
+    // A single interface that can not join should not prevent listening on the others.
+    if joined.is_empty() && !failures.is_empty() {
+        return Err(failures.remove(0).1);
+    }
+    for (addr, err) in failures {
+        warn!("Failed to join multicast at iface {}: {}", addr, err);
+    }
+
+    let sockets = vec![ipv4_sock, ipv6_sock]
+        .into_iter()
+        .flatten()
+        .collect();
+
+    Ok((sockets, joined))
+}
+
+/// Bind a reused socket for listening, with the buffer sizes and multicast loop of the `Config`.
+fn bind_listener<A: ToSocketAddrs>(local_addr: A, config: &Config) -> io::Result<UdpSocket> {
+    let sock = net::bind_reuse(local_addr)?;
+    net::set_buffer_sizes(&sock, config.recv_buffer, config.send_buffer)?;
+    if let Some(on) = config.multicast_loop {
+        net::set_multicast_loop(&sock, on)?;
+    }
+
+    Ok(sock)
+}
+
+/// Local addresses the multicast group is joined on, as selected by the `Config`.
+fn local_multicast_addrs(config: &Config) -> io::Result<Vec<SocketAddr>> {
+    map_local(config, |iface| {
+        trace!("Joining multicast on {}", iface.name);
+        Ok(Some(iface.socket_addr()))
+    })
+}
+
+/// Socket that can join and leave multicast groups.
+trait GroupSocket {
+    /// Whether the socket is an `IPv4` socket.
+    fn is_ipv4(&self) -> bool;
+
+    /// Join the multicast group at the interface with the given address.
+    fn join(&self, iface: &SocketAddr, group: &IpAddr) -> io::Result<()>;
+
+    /// Leave the multicast group at the interface with the given address.
+    fn leave(&self, iface: &SocketAddr, group: &IpAddr) -> io::Result<()>;
+}
+
+impl GroupSocket for UdpSocket {
+    fn is_ipv4(&self) -> bool {
+        self.local_addr().map(|addr| addr.is_ipv4()).unwrap_or(false)
+    }
+
+    fn join(&self, iface: &SocketAddr, group: &IpAddr) -> io::Result<()> {
+        net::join_multicast(self, &net::multicast_iface(iface, interface_index_of(iface.ip())), group)
+    }
+
+    fn leave(&self, iface: &SocketAddr, group: &IpAddr) -> io::Result<()> {
+        net::leave_multicast(self, &net::multicast_iface(iface, interface_index_of(iface.ip())), group)
+    }
+}
+
+/// The multicast group of the address' IP version.
+fn group_addr(addr: &SocketAddr, config: &Config) -> SSDPResult<IpAddr> {
+    Ok(match *addr {
+        SocketAddr::V4(_) => config.ipv4_addr.parse()?,
+        SocketAddr::V6(_) => config.ipv6_addr.parse()?,
+    })
+}
+
+/// Join the multicast group of the address' IP version at the interface with the address.
+fn join_group<S: GroupSocket>(sock: &S, addr: &SocketAddr, config: &Config) -> SSDPResult<()> {
+    let mcast_ip = group_addr(addr, config)?;
+
+    debug!("Joining multicast {} at iface: {}", mcast_ip, addr);
+    Ok(sock.join(addr, &mcast_ip)?)
+}
+
+/// Leave the multicast group of the address' IP version at the interface with the address.
+fn leave_group<S: GroupSocket>(sock: &S, addr: &SocketAddr, config: &Config) -> SSDPResult<()> {
+    let mcast_ip = group_addr(addr, config)?;
+
+    debug!("Leaving multicast {} at iface: {}", mcast_ip, addr);
+    Ok(sock.leave(addr, &mcast_ip)?)
+}
+
+/// Manage the multicast memberships of the listening sockets for the lifetime of the receiver.
+///
+/// Interfaces are polled as configured by `Config::set_interface_poll`, groups are joined
+/// on interfaces that appeared and left on ones that went away. Joins that fail are
+/// retried on the next poll. Every membership is left once the receiver is closed or dropped.
+fn watch_memberships<T>(receiver: &SSDPReceiver<T>,
+                        joined: Vec<SocketAddr>,
+                        config: &Config)
+                        -> SSDPResult<()> {
+    // Taken on shutdown, after which the watcher must not join anything
+    let memberships = Arc::new(Mutex::new(Some(joined)));
+
+    let leave_memberships = memberships.clone();
+    let leave_config = config.clone();
+    receiver::on_close(receiver, move |sockets| {
+        if let Some(joined) = leave_memberships.lock().unwrap().take() {
+            leave_all(sockets, &joined, &leave_config);
+        }
+    });
+
+    let interval = match config.interface_poll {
+        Some(interval) => interval,
+        None => return Ok(()),
+    };
+
+    let config = config.clone();
+    receiver::spawn_watcher(receiver, interval, move |sockets| {
+        let current = match local_multicast_addrs(&config) {
+            Ok(addrs) => addrs,
+            Err(err) => {
+                warn!("Failed to poll local interfaces: {}", err);
+                return;
             }
-            // Filter all loopback and global IPv6 addresses
-            SocketAddr::V6(n) if !n.ip().is_loopback() && !n.ip().is_global() => {
-                if let Some(x) = try!(f(&addr)) {
-                    obj_list.push(x);
-                }
+        };
+
+        if let Some(ref mut known) = *memberships.lock().unwrap() {
+            update_memberships(sockets, known, current, &config);
+        }
+    })?;
+
+    Ok(())
+}
+
+/// Leave the groups on interfaces that are no longer in the current list and join them on new ones.
+fn update_memberships<S: GroupSocket>(sockets: &[S],
+                                      known: &mut Vec<SocketAddr>,
+                                      current: Vec<SocketAddr>,
+                                      config: &Config) {
+    let (added, removed) = diff_addrs(known, &current);
+
+    // The interface is usually gone already, taking the membership with it
+    for addr in &removed {
+        if let Some(sock) = family_socket(sockets, addr) {
+            if let Err(err) = leave_group(sock, addr, config) {
+                debug!("Failed to leave multicast at iface {}: {}", addr, err);
             }
-            _ => (),
         }
     }
 
-    Ok(obj_list)
+    *known = current;
+    for addr in &added {
+        let result = match family_socket(sockets, addr) {
+            Some(sock) => join_group(sock, addr, config),
+            None => {
+                debug!("No listening socket for iface {}", addr);
+                continue;
+            }
+        };
+
+        if let Err(err) = result {
+            warn!("Failed to join multicast at iface {}: {}", addr, err);
+            known.retain(|known_addr| known_addr != addr);
+        }
+    }
+}
+
+/// Leave the groups on all of the interfaces, logging failures.
+fn leave_all<S: GroupSocket>(sockets: &[S], joined: &[SocketAddr], config: &Config) {
+    for addr in joined {
+        if let Some(sock) = family_socket(sockets, addr) {
+            if let Err(err) = leave_group(sock, addr, config) {
+                warn!("Failed to leave multicast at iface {}: {}", addr, err);
+            }
+        }
+    }
+}
+
+/// Addresses that were added to and removed from the old list to get the new one.
+fn diff_addrs(old: &[SocketAddr], new: &[SocketAddr]) -> (Vec<SocketAddr>, Vec<SocketAddr>) {
+    let added = new.iter().filter(|addr| !old.contains(addr)).cloned().collect();
+    let removed = old.iter().filter(|addr| !new.contains(addr)).cloned().collect();
+
+    (added, removed)
+}
+
+/// The socket of the same IP version as the address.
+fn family_socket<'a, S: GroupSocket>(sockets: &'a [S], addr: &SocketAddr) -> Option<&'a S> {
+    sockets.iter().find(|sock| sock.is_ipv4() == addr.is_ipv4())
 }
 
-/// Generate a list of some object R constructed from all local `Ipv4Addr` objects.
+/// Scope ids of all local interfaces with an `IPv6` address.
 ///
-/// If any of the `SocketAddr`'s fail to resolve, this function will not return an error.
-fn get_local_addrs() -> io::Result<Vec<SocketAddr>> {
-    let iface_iter = try!(get_if_addrs::get_if_addrs()).into_iter();
-    Ok(iface_iter.filter_map(|iface| Some(SocketAddr::new(iface.addr.ip(), 0)))
-        .collect())
-}
\ No newline at end of file
+/// Falls back to the default interface, scope id 0, when no interface index can be found.
+fn ipv6_scope_ids() -> io::Result<Vec<u32>> {
+    let mut scope_ids = get_if_addrs::get_if_addrs()?
+        .into_iter()
+        .filter(|iface| iface.addr.ip().is_ipv6())
+        .filter_map(|iface| net::interface_index(&iface.name))
+        .collect::<Vec<_>>();
+
+    scope_ids.sort();
+    scope_ids.dedup();
+
+    if scope_ids.is_empty() {
+        scope_ids.push(0);
+    }
+
+    Ok(scope_ids)
+}
+
+/// Index of the local interface that has the address, if there is one and its index is known.
+fn interface_index_of(addr: IpAddr) -> Option<u32> {
+    let ifaces = match get_if_addrs::get_if_addrs() {
+        Ok(ifaces) => ifaces,
+        Err(err) => {
+            debug!("Failed to list interfaces: {}", err);
+            return None;
+        }
+    };
+
+    ifaces.into_iter()
+        .find(|iface| iface.addr.ip() == addr)
+        .and_then(|iface| net::interface_index(&iface.name))
+}
+
+/// Directed broadcast address of the local interface that has the `IPv4` address.
+fn interface_broadcast(addr: Ipv4Addr) -> io::Result<Option<Ipv4Addr>> {
+    let ifaces = get_if_addrs::get_if_addrs()?;
+
+    Ok(ifaces.into_iter()
+        .filter_map(|iface| match iface.addr {
+            IfAddr::V4(ref n) if n.ip == addr => net::directed_broadcast(n.ip, n.netmask),
+            _ => None,
+        })
+        .next())
+}
+
+/// Generate the connectors to send a unicast message to the destination on.
+///
+/// A single connector on the interface the system routes the destination through,
+/// or connectors on all local interfaces selected by the `Config` if the route can
+/// not be found or goes through an interface the `Config` does not select.
+fn unicast_connectors(dst: &SocketAddr, config: &Config) -> SSDPResult<Vec<UdpConnector>> {
+    unicast_connectors_with(dst, config, net::route_local_addr)
+}
+
+/// Generate the connectors to send a unicast message to the destination on, finding the route with the probe.
+fn unicast_connectors_with<F>(dst: &SocketAddr, config: &Config, probe: F) -> SSDPResult<Vec<UdpConnector>>
+    where F: FnOnce(&SocketAddr) -> io::Result<SocketAddr>
+{
+    let mode = IpVersionMode::from_addr(dst)?;
+    let port_error = |err| source_port_error(err, config.source_port);
+
+    let connectors = match probe(dst) {
+        Ok(local) if route_selected(local, &mode, config)? => {
+            trace!("Routing unicast to {} through {}", dst, local);
+            vec![bind_connector(local, None, None, config.source_port).map_err(port_error)?]
+        }
+        Ok(local) => {
+            debug!("Route to {} goes through unselected {}, sending on all selected interfaces", dst, local);
+            local_connectors(None, None, &mode, config).map_err(port_error)?
+        }
+        Err(err) => {
+            debug!("No route to {}, sending on all interfaces: {}", dst, err);
+            local_connectors(None, None, &mode, config).map_err(port_error)?
+        }
+    };
+
+    for connector in &connectors {
+        configure_connector(connector, config)?;
+    }
+
+    Ok(connectors)
+}
+
+/// Whether the routed local address is on an interface the `Config` selects.
+///
+/// Any route is taken when the `Config` neither lists nor excludes interfaces.
+fn route_selected(local: SocketAddr, mode: &IpVersionMode, config: &Config) -> io::Result<bool> {
+    if config.interfaces.is_none() && config.excluded_interfaces.is_empty() {
+        return Ok(true);
+    }
+
+    Ok(connector_addrs(mode, config)?.iter().any(|addr| addr.ip() == local.ip()))
+}
+
+/// IP versions that the local interfaces selected by the `Config` have, `Any` if they have none or both.
+fn local_mode(config: &Config) -> io::Result<IpVersionMode> {
+    let addrs = connector_addrs(&IpVersionMode::Any, config)?;
+
+    Ok(match (addrs.iter().any(SocketAddr::is_ipv4), addrs.iter().any(SocketAddr::is_ipv6)) {
+        (true, false) => IpVersionMode::V4Only,
+        (false, true) => IpVersionMode::V6Only,
+        _ => IpVersionMode::Any,
+    })
+}
+
+/// Resolve a unicast destination to an address of an IP version the local interfaces have.
+///
+/// A destination that needs a DNS lookup fails with `SSDPErrorKind::DnsForbidden`,
+/// without being looked up, if the config forbids lookups.
+fn resolve_dst<A: net::DestAddr>(dst_addr: A, config: &Config) -> SSDPResult<SocketAddr> {
+    if let Some(host) = dst_addr.host_name() {
+        config.check_literal(host)?;
+    }
+
+    Ok(net::addr_from_trait_with_mode(dst_addr, &local_mode(config)?)?)
+}
+
+/// Generate `UdpConnector` objects for the local interfaces selected by the `Config`.
+fn config_connectors(config: &Config) -> SSDPResult<Vec<UdpConnector>> {
+    let hops_v6 = config.multicast_hops_v6()?;
+    let connectors = local_connectors(Some(config.ttl_v4), Some(hops_v6), &config.mode, config)
+        .map_err(|err| source_port_error(err, config.source_port))?;
+
+    for connector in &connectors {
+        configure_connector(connector, config)?;
+    }
+
+    Ok(connectors)
+}
+
+/// Connectors for the local interfaces selected by a `Config`, kept in step with the interfaces as they
+/// come and go.
+struct InterfaceSet {
+    config: Config,
+    connectors: Vec<UdpConnector>,
+    // Interface address each connector was bound for, with an unspecified port
+    addrs: Vec<SocketAddr>,
+    listed: Instant,
+}
+
+impl InterfaceSet {
+    /// Generate connectors for the local interfaces selected by the `Config`, see `config_connectors`.
+    fn new(config: &Config) -> SSDPResult<InterfaceSet> {
+        let addrs = connector_addrs(&config.mode, config)?;
+        let mut set = InterfaceSet {
+            config: config.clone(),
+            connectors: Vec::with_capacity(addrs.len()),
+            addrs: Vec::with_capacity(addrs.len()),
+            listed: Instant::now(),
+        };
+
+        for addr in addrs {
+            set.connectors.push(set.connector(addr)?);
+            set.addrs.push(addr);
+        }
+
+        Ok(set)
+    }
+
+    fn connectors_mut(&mut self) -> &mut [UdpConnector] {
+        &mut self.connectors
+    }
+
+    fn into_connectors(self) -> Vec<UdpConnector> {
+        self.connectors
+    }
+
+    /// List the local interfaces again and update the connectors to match, returning the added connectors.
+    ///
+    /// Interfaces are listed at most once every `INTERFACE_CACHE_MS`, so this is cheap to call before
+    /// every round.
+    fn refresh(&mut self) -> SSDPResult<&[UdpConnector]> {
+        if self.listed.elapsed() < Duration::from_millis(INTERFACE_CACHE_MS) {
+            return Ok(&[]);
+        }
+
+        let addrs = connector_addrs(&self.config.mode, &self.config)?;
+        self.listed = Instant::now();
+
+        Ok(self.update(addrs))
+    }
+
+    /// Drop the connectors of interfaces missing from the addresses and add connectors for new ones.
+    ///
+    /// Interfaces that a connector could not be bound for are retried on the next update.
+    fn update(&mut self, addrs: Vec<SocketAddr>) -> &[UdpConnector] {
+        let (added, removed) = diff_addrs(&self.addrs, &addrs);
+
+        for addr in &removed {
+            let index = self.addrs
+                .iter()
+                .position(|other| other == addr)
+                .expect("removed address is in the set");
+
+            info!("Interface {} went away, dropping its connector", addr.ip());
+            self.addrs.remove(index);
+            self.connectors.remove(index);
+        }
+
+        let kept = self.connectors.len();
+        for addr in added {
+            match self.connector(addr) {
+                Ok(connector) => {
+                    info!("Interface {} appeared, adding a connector", addr.ip());
+                    self.connectors.push(connector);
+                    self.addrs.push(addr);
+                }
+                Err(err) => warn!("Failed to add a connector for interface {}: {}", addr.ip(), err),
+            }
+        }
+
+        &self.connectors[kept..]
+    }
+
+    fn connector(&self, addr: SocketAddr) -> SSDPResult<UdpConnector> {
+        let config = &self.config;
+        let hops_v6 = config.multicast_hops_v6()?;
+        let connector = bind_connector(addr, Some(config.ttl_v4), Some(hops_v6), config.source_port)
+            .map_err(|err| source_port_error(err, config.source_port))?;
+        configure_connector(&connector, config)?;
+
+        Ok(connector)
+    }
+}
+
+/// Apply the socket options of the `Config` to the connector.
+fn configure_connector(connector: &UdpConnector, config: &Config) -> SSDPResult<()> {
+    connector.set_buffer_sizes(config.recv_buffer, config.send_buffer)?;
+    if let Some(on) = config.multicast_loop {
+        connector.set_multicast_loop(on)?;
+    }
+
+    if config.dscp != 0 {
+        connector.set_dscp(config.dscp).map_err(|err| {
+            debug!("Failed to set DSCP {}: {}", config.dscp, err);
+            SSDPErrorKind::DscpRefused(config.dscp)
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Error for a failure to bind connectors, naming the source port if it was taken.
+fn source_port_error(err: io::Error, source_port: u16) -> SSDPError {
+    if source_port != 0 && err.kind() == io::ErrorKind::AddrInUse {
+        SSDPErrorKind::SourcePortInUse(source_port).into()
+    } else {
+        err.into()
+    }
+}
+
+/// Generate `UdpConnector` objects for the local interfaces selected by the `Config`, see `map_local`.
+///
+/// Connectors share the source port of the `Config` if it is not 0, otherwise each gets an ephemeral port.
+fn local_connectors(ttl_v4: Option<u32>,
+                    hops_v6: Option<u32>,
+                    filter: &IpVersionMode,
+                    config: &Config)
+                    -> io::Result<Vec<UdpConnector>> {
+    trace!("Fetching all local connectors");
+
+    connector_addrs(filter, config)?
+        .into_iter()
+        .map(|addr| bind_connector(addr, ttl_v4, hops_v6, config.source_port))
+        .collect()
+}
+
+/// Addresses of the local interfaces selected by the `Config` that connectors are bound for, see `map_local`.
+fn connector_addrs(filter: &IpVersionMode, config: &Config) -> io::Result<Vec<SocketAddr>> {
+    map_local(config, |iface| match (filter, iface.socket_addr()) {
+        (&IpVersionMode::V4Only, addr @ SocketAddr::V4(_)) |
+        (&IpVersionMode::Any, addr @ SocketAddr::V4(_)) |
+        (&IpVersionMode::V6Only, addr @ SocketAddr::V6(_)) |
+        (&IpVersionMode::Any, addr @ SocketAddr::V6(_)) => {
+            trace!("Binding connector on {}", iface.name);
+            Ok(Some(addr))
+        }
+        _ => Ok(None),
+    })
+}
+
+/// Bind a connector on the interface address, sharing the source port if it is not 0.
+fn bind_connector(mut addr: SocketAddr,
+                  ttl_v4: Option<u32>,
+                  hops_v6: Option<u32>,
+                  source_port: u16)
+                  -> io::Result<UdpConnector> {
+    addr.set_port(source_port);
+    let multicast_ttl = if addr.is_ipv4() { ttl_v4 } else { hops_v6 };
+
+    if source_port == 0 {
+        UdpConnector::new(addr, multicast_ttl)
+    } else {
+        UdpConnector::new_reuse(addr, multicast_ttl)
+    }
+}
+
+/// Generate a single `UdpConnector` bound to the local interface with the given address.
+fn interface_connector(iface: IpAddr, multicast_ttl: Option<u32>) -> SSDPResult<UdpConnector> {
+    let addrs = get_local_addrs()?.iter().map(LocalInterface::socket_addr).collect::<Vec<_>>();
+
+    find_interface_connector(iface, &addrs, multicast_ttl)
+}
+
+/// Generate a `UdpConnector` for the interface address if it is present in the list of local addresses.
+fn find_interface_connector(iface: IpAddr,
+                            addrs: &[SocketAddr],
+                            multicast_ttl: Option<u32>)
+                            -> SSDPResult<UdpConnector> {
+    match addrs.iter().find(|addr| addr.ip() == iface) {
+        Some(addr) => {
+            trace!("Found interface {}", addr);
+            Ok(UdpConnector::new(*addr, multicast_ttl)?)
+        }
+        None => Err(SSDPErrorKind::InterfaceNotFound(iface.to_string()).into()),
+    }
+}
+
+/// Generate a single `UdpConnector` bound to the given local address for sending to the destination.
+///
+/// The socket options of the `Config` are applied to the connector, see `configure_connector`.
+fn source_connector(local: IpAddr,
+                    dst: &SocketAddr,
+                    multicast_ttl: Option<u32>,
+                    config: &Config)
+                    -> SSDPResult<UdpConnector> {
+    if local.is_ipv4() != dst.is_ipv4() {
+        return Err(SSDPErrorKind::IpVersionMismatch(local.to_string(), dst.to_string()).into());
+    }
+
+    let connector = UdpConnector::new(SocketAddr::new(local, 0), multicast_ttl)?;
+    configure_connector(&connector, config)?;
+
+    Ok(connector)
+}
+
+/// Invoke the closure for every local address found on the system
+///
+/// This method filters out _loopback_ (unless included by the `Config`) addresses and the
+/// addresses of excluded interfaces, unless the `Config` gives a list of interfaces in which
+/// case only those are used. Each interface gets at most one address per IP version, see
+/// `dedup_interfaces`.
+fn map_local<F, R>(config: &Config, f: F) -> io::Result<Vec<R>>
+    where F: FnMut(&LocalInterface) -> io::Result<Option<R>>
+{
+    let ifaces = try!(get_local_addrs());
+
+    map_addrs(ifaces, config, f)
+}
+
+/// Invoke the closure for every interface address in the list that passes the filters of `map_local`.
+///
+/// Fails if a list of interfaces is given and none of the addresses are on them.
+fn map_addrs<F, R>(ifaces: Vec<LocalInterface>, config: &Config, mut f: F) -> io::Result<Vec<R>>
+    where F: FnMut(&LocalInterface) -> io::Result<Option<R>>
+{
+    let interfaces = config.interfaces.as_deref();
+    let loopback = config.include_loopback;
+
+    if let Some(selectors) = interfaces {
+        for selector in selectors {
+            if !ifaces.iter().any(|iface| selector.matches(iface)) {
+                warn!("Configured interface {} not found", selector);
+            }
+        }
+    }
+
+    let selected = ifaces.into_iter()
+        .filter(|iface| {
+            trace!("Found {} on {}", iface.addr, iface.name);
+            match interfaces {
+                Some(selectors) => selectors.iter().any(|selector| selector.matches(iface)),
+                None if is_excluded(&iface.name, &config.excluded_interfaces) => {
+                    trace!("Excluded interface {}", iface.name);
+                    false
+                }
+                None => loopback || !iface.addr.is_loopback(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if interfaces.is_some() && selected.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "None Of The Configured Interfaces Were Found"));
+    }
+
+    let mut obj_list = Vec::with_capacity(selected.len());
+    for iface in dedup_interfaces(selected, config.allow_global_ipv6, config.prefer_global_ipv6) {
+        trace!("Using {} on {}", iface.addr, iface.name);
+        if let Some(x) = f(&iface)? {
+            obj_list.push(x);
+        }
+    }
+
+    Ok(obj_list)
+}
+
+/// Keep a single address per interface and IP version, plus a global `IPv6` one if those are allowed.
+///
+/// The first `IPv4` address of an interface is kept. For `IPv6` link-local addresses are
+/// preferred, or global addresses if those are preferred, and otherwise the first one is kept.
+fn dedup_interfaces(ifaces: Vec<LocalInterface>,
+                    allow_global: bool,
+                    prefer_global: bool)
+                    -> Vec<LocalInterface> {
+    let rank = |iface: &LocalInterface| match iface.addr {
+        IpAddr::V4(_) => 0,
+        IpAddr::V6(ref ip) if prefer_global && ip.is_global() => 0,
+        IpAddr::V6(ref ip) if !prefer_global && net::needs_scope_id(ip) => 0,
+        IpAddr::V6(_) => 1,
+    };
+    // Allowed global addresses are kept alongside the address the interface would otherwise get
+    let extra_global = |iface: &LocalInterface| match iface.addr {
+        IpAddr::V6(ref ip) => allow_global && ip.is_global(),
+        IpAddr::V4(_) => false,
+    };
+
+    let mut kept: Vec<LocalInterface> = Vec::with_capacity(ifaces.len());
+    for iface in ifaces {
+        let same = kept.iter().position(|other| {
+            other.name == iface.name && other.addr.is_ipv4() == iface.addr.is_ipv4() &&
+            extra_global(other) == extra_global(&iface)
+        });
+
+        match same {
+            Some(index) if rank(&iface) < rank(&kept[index]) => {
+                trace!("Preferring {} over {} on {}", iface.addr, kept[index].addr, iface.name);
+                kept[index] = iface;
+            }
+            Some(index) => {
+                trace!("Skipping {} in favor of {} on {}", iface.addr, kept[index].addr, iface.name)
+            }
+            None => kept.push(iface),
+        }
+    }
+
+    kept
+}
+
+/// Whether the interface name matches one of the exclusions, which are exact names or prefixes ending in `*`.
+fn is_excluded(name: &str, excluded: &[String]) -> bool {
+    excluded.iter().any(|pattern| if pattern.ends_with('*') {
+        name.starts_with(&pattern[..pattern.len() - 1])
+    } else {
+        name == pattern
+    })
+}
+
+/// Address of a local network interface.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct LocalInterface {
+    name: String,
+    addr: IpAddr,
+}
+
+impl LocalInterface {
+    fn new<S: Into<String>>(name: S, addr: IpAddr) -> LocalInterface {
+        LocalInterface {
+            name: name.into(),
+            addr,
+        }
+    }
+
+    /// Socket address to bind to on the interface, with an unspecified port.
+    fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.addr, 0)
+    }
+}
+
+/// Generate a list of the addresses of all local interfaces.
+fn get_local_addrs() -> io::Result<Vec<LocalInterface>> {
+    let iface_iter = get_if_addrs::get_if_addrs()?.into_iter();
+    Ok(iface_iter.map(|iface| {
+            let addr = iface.addr.ip();
+            LocalInterface::new(iface.name, addr)
+        })
+        .collect())
+}
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io;
+    use std::net::{SocketAddr, IpAddr, UdpSocket};
+    use std::time::Instant;
+
+    use net2::UdpSocketExt;
+
+    use error::{SSDPErrorKind, SSDPResult};
+    use header::{HeaderMut, CacheControl, CacheDirective};
+    use message::NotifyMessage;
+    use net::{self, IpVersionMode};
+    use net::connector::UdpConnector;
+    use receiver::DropReason;
+    use super::{Config, GroupSocket, InterfaceSelector, InterfaceSet, Ipv6Scope, LocalInterface};
+
+    #[test]
+    fn positive_find_interface_connector() {
+        let addrs: Vec<SocketAddr> = vec!["10.255.255.1:0".parse().unwrap(), "127.0.0.1:0".parse().unwrap()];
+        let iface: IpAddr = "127.0.0.1".parse().unwrap();
+
+        let connector = super::find_interface_connector(iface, &addrs, None).unwrap();
+
+        assert_eq!(connector.local_addr().unwrap().ip(), iface);
+    }
+
+    fn failed_send(addr: &str) -> (SocketAddr, SSDPResult<()>) {
+        (addr.parse().unwrap(), Err(io::Error::new(io::ErrorKind::PermissionDenied, "forced failure").into()))
+    }
+
+    #[test]
+    fn positive_check_sends_partial_failure() {
+        let results = vec![failed_send("192.168.1.1:0"), ("192.168.1.2:0".parse().unwrap(), Ok(()))];
+
+        super::check_sends(results).unwrap();
+    }
+
+    #[test]
+    fn negative_check_sends_all_failed() {
+        let results = vec![failed_send("192.168.1.1:0"), failed_send("[fe80::1]:0")];
+
+        match super::check_sends(results).map_err(|err| err.0) {
+            Err(SSDPErrorKind::AllSendsFailed(failures)) => {
+                let addrs = failures.iter().map(|&(addr, _)| addr.to_string()).collect::<Vec<_>>();
+
+                assert_eq!(addrs, vec!["192.168.1.1:0", "[fe80::1]:0"]);
+                assert!(failures.iter().all(|(_, err)| err.to_string().contains("forced failure")));
+            }
+            _ => panic!("Expected AllSendsFailed Error"),
+        }
+    }
+
+    #[test]
+    fn positive_config_connectors_source_port() {
+        let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let interfaces = vec!["127.0.0.1".parse().unwrap(), "192.0.2.2".parse().unwrap()];
+        let config = Config::new().set_source_port(port).set_interfaces(interfaces);
+
+        let connectors = super::config_connectors(&config).unwrap();
+
+        assert!(!connectors.is_empty());
+        assert!(connectors.iter().all(|conn| conn.local_addr().unwrap().port() == port));
+    }
+
+    #[test]
+    fn positive_config_connectors_ttl_per_family() {
+        let interfaces = vec!["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()];
+        let config = Config::new().set_ttl_v4(3).set_hops_v6(5).set_interfaces(interfaces);
+
+        let connectors = super::config_connectors(&config).unwrap();
+
+        assert_eq!(connectors.len(), 2);
+        for connector in &connectors {
+            let sock = connector.try_clone_socket().unwrap();
+            match connector.local_addr().unwrap() {
+                SocketAddr::V4(_) => assert_eq!(sock.multicast_ttl_v4().unwrap(), 3),
+                SocketAddr::V6(_) => assert_eq!(sock.multicast_hops_v6().unwrap(), 5),
+            }
+        }
+    }
+
+    #[test]
+    fn positive_set_ttl_sets_both_families() {
+        let config = Config::new().set_ttl(6);
+
+        assert_eq!(config.ttl_v4, 6);
+        assert_eq!(config.multicast_hops_v6().unwrap(), 6);
+    }
+
+    #[test]
+    fn positive_unicast_connectors_routes_loopback() {
+        let dst = "127.0.0.1:1900".parse().unwrap();
+        let connectors = super::unicast_connectors(&dst, &Config::default()).unwrap();
+
+        assert_eq!(connectors.len(), 1);
+        assert_eq!(connectors[0].local_addr().unwrap().ip(), "127.0.0.1".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn positive_unicast_connectors_probe_fallback() {
+        let dst = "192.168.1.2:1900".parse().unwrap();
+
+        let route = |_: &SocketAddr| Err(io::Error::other(""));
+        let connectors = super::unicast_connectors_with(&dst, &Config::default(), route).unwrap();
+
+        let all = super::local_connectors(None, None, &IpVersionMode::V4Only, &Config::default()).unwrap();
+        assert_eq!(connectors.len(), all.len());
+    }
+
+    #[test]
+    fn positive_unicast_connectors_configured_route() {
+        let config = Config::new().set_interfaces(vec!["127.0.0.1".parse().unwrap()]);
+        let dst = "127.0.0.1:1900".parse().unwrap();
+
+        let connectors = super::unicast_connectors_with(&dst, &config, |_| Ok("127.0.0.1:0".parse().unwrap()))
+            .unwrap();
+
+        assert_eq!(connectors.len(), 1);
+        assert_eq!(connectors[0].local_addr().unwrap().ip(), "127.0.0.1".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn positive_unicast_connectors_dscp() {
+        let config = Config::new().set_interfaces(vec!["127.0.0.1".parse().unwrap()]).set_dscp(46);
+        let dst = "127.0.0.1:1900".parse().unwrap();
+
+        let routed = super::unicast_connectors_with(&dst, &config, |_| Ok("127.0.0.1:0".parse().unwrap()))
+            .unwrap();
+        let fallback = super::unicast_connectors_with(&dst, &config, |_| Err(io::Error::other(""))).unwrap();
+
+        for connector in routed.iter().chain(&fallback) {
+            assert_eq!(net::dscp(&connector.try_clone_socket().unwrap()).unwrap(), 46);
+        }
+    }
+
+    #[test]
+    fn negative_unicast_connectors_unselected_route() {
+        let config = Config::new().set_interfaces(vec!["127.0.0.1".parse().unwrap()]);
+        let dst = "192.0.2.1:1900".parse().unwrap();
+
+        let connectors = super::unicast_connectors_with(&dst, &config, |_| Ok("192.0.2.2:0".parse().unwrap()))
+            .unwrap();
+
+        assert_eq!(connectors.len(), 1);
+        assert_eq!(connectors[0].local_addr().unwrap().ip(), "127.0.0.1".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn positive_set_mode_str() {
+        assert_eq!(Config::new().set_mode_str("V6").unwrap().mode, IpVersionMode::V6Only);
+        assert!(Config::new().set_mode_str("both").is_err());
+    }
+
+    #[test]
+    fn negative_set_mx_out_of_bounds() {
+        assert_eq!(Config::new().set_mx(5).unwrap().mx, Some(5));
+        assert!(Config::new().set_mx(0).is_err());
+        assert!(Config::new().set_mx(121).is_err());
+    }
+
+    #[test]
+    fn positive_multicast_hops_v6_defaults_to_scope() {
+        let link = Config::new();
+        let site = Config::new().set_ipv6_scope(Ipv6Scope::SiteLocal);
+
+        assert_eq!(link.multicast_hops_v6().unwrap(), Ipv6Scope::LinkLocal.hop_limit());
+        assert_eq!(site.multicast_hops_v6().unwrap(), Ipv6Scope::SiteLocal.hop_limit());
+        assert_eq!(site.set_hops_v6(3).multicast_hops_v6().unwrap(), 3);
+    }
+
+    #[test]
+    fn positive_configure_connector_multicast_loop() {
+        for &on in &[true, false] {
+            let v4 = UdpConnector::new("127.0.0.1:0", None).unwrap();
+            super::configure_connector(&v4, &Config::new().set_multicast_loop(on)).unwrap();
+            assert_eq!(v4.try_clone_socket().unwrap().multicast_loop_v4().unwrap(), on);
+
+            let v6 = UdpConnector::new("[::1]:0", None).unwrap();
+            super::configure_connector(&v6, &Config::new().set_multicast_loop(on)).unwrap();
+            assert_eq!(v6.try_clone_socket().unwrap().multicast_loop_v6().unwrap(), on);
+        }
+    }
+
+    #[test]
+    fn positive_bind_listener_multicast_loop() {
+        for &on in &[true, false] {
+            let sock = super::bind_listener("0.0.0.0:0", &Config::new().set_multicast_loop(on)).unwrap();
+
+            assert_eq!(sock.multicast_loop_v4().unwrap(), on);
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn positive_configure_connector_dscp() {
+        let connector = UdpConnector::new("127.0.0.1:0", None).unwrap();
+        let untouched = UdpConnector::new("127.0.0.1:0", None).unwrap();
+
+        super::configure_connector(&connector, &Config::new().set_dscp(46)).unwrap();
+        super::configure_connector(&untouched, &Config::new()).unwrap();
+
+        assert_eq!(net::dscp(&connector.try_clone_socket().unwrap()).unwrap(), 46);
+        assert_eq!(net::dscp(&untouched.try_clone_socket().unwrap()).unwrap(), 0);
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn positive_configure_connector_dscp_attempted() {
+        let connector = UdpConnector::new("127.0.0.1:0", None).unwrap();
+
+        match super::configure_connector(&connector, &Config::new().set_dscp(46)).map_err(|err| err.0) {
+            Ok(()) | Err(SSDPErrorKind::DscpRefused(46)) => (),
+            _ => panic!("Expected DSCP To Be Set Or Refused"),
+        }
+    }
+
+    #[test]
+    fn negative_configure_connector_dscp_out_of_range() {
+        let connector = UdpConnector::new("127.0.0.1:0", None).unwrap();
+
+        match super::configure_connector(&connector, &Config::new().set_dscp(64)).map_err(|err| err.0) {
+            Err(SSDPErrorKind::DscpRefused(64)) => (),
+            _ => panic!("Expected DscpRefused Error"),
+        }
+    }
+
+    #[test]
+    fn negative_config_connectors_source_port_in_use() {
+        let taken = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = taken.local_addr().unwrap().port();
+        let config = Config::new().set_source_port(port).set_interfaces(vec!["127.0.0.1".parse().unwrap()]);
+
+        match super::config_connectors(&config).map(|_| ()).map_err(|err| err.0) {
+            Err(SSDPErrorKind::SourcePortInUse(n)) => assert_eq!(n, port),
+            _ => panic!("Expected SourcePortInUse Error"),
+        }
+    }
+
+    #[test]
+    fn positive_check_sends_nothing_sent() {
+        super::check_sends(Vec::new()).unwrap();
+    }
+
+    fn notify_with_max_age(max_age: Option<u32>) -> NotifyMessage {
+        let mut message = NotifyMessage::new();
+
+        if let Some(n) = max_age {
+            message.set(CacheControl(vec![CacheDirective::MaxAge(n)]));
+        }
+
+        message
+    }
+
+    #[test]
+    fn positive_max_age_within_bounds() {
+        let config = Config::new().set_max_age_bounds(60, 3600);
+
+        assert!(super::max_age_in_bounds(&notify_with_max_age(Some(1800)), &config));
+    }
+
+    #[test]
+    fn negative_max_age_below_floor() {
+        let config = Config::new().set_max_age_bounds(60, 3600);
+
+        assert!(!super::max_age_in_bounds(&notify_with_max_age(Some(10)), &config));
+        assert_eq!(super::check_max_age(&notify_with_max_age(Some(10)), &config),
+                   Err(DropReason::MaxAgeOutOfBounds));
+    }
+
+    #[test]
+    fn negative_max_age_above_ceiling() {
+        let config = Config::new().set_max_age_bounds(60, 3600);
+
+        assert!(!super::max_age_in_bounds(&notify_with_max_age(Some(86400 * 365)), &config));
+    }
+
+    #[test]
+    fn positive_missing_max_age() {
+        let config = Config::new().set_max_age_bounds(60, 3600);
+
+        assert!(super::max_age_in_bounds(&notify_with_max_age(None), &config));
+    }
+
+    #[test]
+    fn positive_max_age_without_bounds() {
+        assert!(super::max_age_in_bounds(&notify_with_max_age(Some(10)), &Config::new()));
+    }
+
+    #[test]
+    fn positive_validate_custom_groups() {
+        Config::new().validate().unwrap();
+        Config::new().set_ipv4_addr("239.1.2.3").set_ipv6_scope(Ipv6Scope::SiteLocal).validate().unwrap();
+        Config::new().set_ipv4_addr("ssdp.example.com").validate().unwrap();
+    }
+
+    #[test]
+    fn negative_validate_bad_v4() {
+        for addr in &["239.255.255", "239.255.255.256"] {
+            match Config::new().set_ipv4_addr(*addr).validate().map_err(|err| err.0) {
+                Err(SSDPErrorKind::AddrParseError(_)) => (),
+                other => panic!("Unexpected result {:?}", other),
+            }
+        }
+        match Config::new().set_ipv6_addr("FF02::C::1").validate().map_err(|err| err.0) {
+            Err(SSDPErrorKind::AddrParseError(_)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn negative_validate_literal_groups_host_name() {
+        let config = Config::new().set_ipv4_addr("ssdp.example.com");
+
+        match config.validate_literal_groups().map_err(|err| err.0) {
+            Err(SSDPErrorKind::AddrParseError(_)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        match config.set_forbid_dns(true).validate().map_err(|err| err.0) {
+            Err(SSDPErrorKind::DnsForbidden(ref name)) => assert_eq!(name, "ssdp.example.com"),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn negative_validate_unicast_group() {
+        for config in &[Config::new().set_ipv4_addr("192.168.1.1"),
+                        Config::new().set_ipv4_addr("FF02::C"),
+                        Config::new().set_ipv6_addr("fe80::1"),
+                        Config::new().set_ipv6_addr("FF00::C"),
+                        Config::new().set_ipv6_addr("FF0F::C")] {
+            match config.validate().map_err(|err| err.0) {
+                Err(SSDPErrorKind::NotMulticastGroup(_)) => (),
+                other => panic!("Unexpected result {:?}", other),
+            }
+        }
+
+        // Groups of a disabled IP version are not checked
+        Config::new().set_ipv4_addr("192.168.1.1").set_mode(IpVersionMode::V6Only).validate().unwrap();
+    }
+
+    #[test]
+    fn positive_check_literal_forbidden() {
+        let config = Config::new().set_forbid_dns(true);
+
+        config.check_literal("239.255.255.250").unwrap();
+        config.check_literal("FF02::C").unwrap();
+    }
+
+    #[test]
+    fn negative_check_literal_forbidden() {
+        let config = Config::new().set_forbid_dns(true);
+
+        match config.check_literal("ssdp.example.com").map_err(|err| err.0) {
+            Err(SSDPErrorKind::DnsForbidden(ref n)) => assert_eq!(n, "ssdp.example.com"),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn positive_check_literal_allowed() {
+        Config::new().check_literal("ssdp.example.com").unwrap();
+    }
+
+    #[test]
+    fn positive_source_connector() {
+        let dst: SocketAddr = "127.0.0.1:1900".parse().unwrap();
+        let local: IpAddr = "127.0.0.1".parse().unwrap();
+
+        let connector = super::source_connector(local, &dst, None, &Config::new()).unwrap();
+
+        assert_eq!(connector.local_addr().unwrap().ip(), local);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn positive_source_connector_dscp() {
+        let dst: SocketAddr = "127.0.0.1:1900".parse().unwrap();
+        let local: IpAddr = "127.0.0.1".parse().unwrap();
+
+        let connector = super::source_connector(local, &dst, None, &Config::new().set_dscp(46)).unwrap();
+
+        assert_eq!(net::dscp(&connector.try_clone_socket().unwrap()).unwrap(), 46);
+    }
+
+    #[test]
+    fn negative_source_connector_version_mismatch() {
+        let dst: SocketAddr = "[::1]:1900".parse().unwrap();
+        let local: IpAddr = "127.0.0.1".parse().unwrap();
+
+        match super::source_connector(local, &dst, None, &Config::new()) {
+            Err(ref err) => {
+                match *err.kind() {
+                    SSDPErrorKind::IpVersionMismatch(..) => (),
+                    ref kind => panic!("Unexpected error kind {:?}", kind),
+                }
+            }
+            Ok(_) => panic!("Bound a connector for a mismatched destination"),
+        }
+    }
+
+    #[test]
+    fn negative_find_interface_connector() {
+        let addrs: Vec<SocketAddr> = vec!["127.0.0.1:0".parse().unwrap()];
+        let iface: IpAddr = "10.255.255.1".parse().unwrap();
+
+        match super::find_interface_connector(iface, &addrs, None) {
+            Err(ref err) => {
+                match *err.kind() {
+                    SSDPErrorKind::InterfaceNotFound(ref n) => assert_eq!(n, "10.255.255.1"),
+                    ref kind => panic!("Unexpected error kind {:?}", kind),
+                }
+            }
+            Ok(_) => panic!("Found an interface that is not present"),
+        }
+    }
+
+    fn iface(name: &str, addr: &str) -> LocalInterface {
+        LocalInterface::new(name, addr.parse().unwrap())
+    }
+
+    fn synthetic_addrs() -> Vec<LocalInterface> {
+        vec![iface("lo", "127.0.0.1"),
+             iface("eth0", "192.168.1.10"),
+             iface("eth1", "10.0.0.10"),
+             iface("eth0", "fe80::1")]
+    }
+
+    /// Addresses of the interfaces selected by `map_addrs`.
+    fn selected_addrs(ifaces: Vec<LocalInterface>, config: &Config) -> Vec<SocketAddr> {
+        super::map_addrs(ifaces, config, |iface| Ok(Some(iface.socket_addr()))).unwrap()
+    }
+
+    #[test]
+    fn positive_map_addrs_default_filter() {
+        let addrs = selected_addrs(synthetic_addrs(), &Config::new());
+
+        assert_eq!(addrs,
+                   vec!["192.168.1.10:0".parse::<SocketAddr>().unwrap(),
+                        "10.0.0.10:0".parse().unwrap(),
+                        "[fe80::1]:0".parse().unwrap()]);
+    }
+
+    fn global_ipv6_addrs(link_local: bool, global: bool) -> Vec<LocalInterface> {
+        let mut addrs = Vec::new();
+        if global {
+            addrs.push(iface("eth0", "2a00:1450::1"));
+        }
+        if link_local {
+            addrs.push(iface("eth0", "fe80::1"));
+        }
+
+        addrs
+    }
+
+    #[test]
+    fn positive_map_addrs_only_global_ipv6() {
+        let addrs = selected_addrs(global_ipv6_addrs(false, true), &Config::new());
+
+        assert_eq!(addrs, vec!["[2a00:1450::1]:0".parse::<SocketAddr>().unwrap()]);
+    }
+
+    #[test]
+    fn positive_map_addrs_only_link_local_ipv6() {
+        let addrs = selected_addrs(global_ipv6_addrs(true, false), &Config::new());
+
+        assert_eq!(addrs, vec!["[fe80::1]:0".parse::<SocketAddr>().unwrap()]);
+    }
+
+    #[test]
+    fn positive_map_addrs_prefers_link_local_ipv6() {
+        let addrs = selected_addrs(global_ipv6_addrs(true, true), &Config::new());
+
+        assert_eq!(addrs, vec!["[fe80::1]:0".parse::<SocketAddr>().unwrap()]);
+    }
+
+    #[test]
+    fn positive_map_addrs_allow_global_ipv6() {
+        let config = Config::new().set_allow_global_ipv6(true);
+
+        let addrs = selected_addrs(global_ipv6_addrs(true, true), &config);
+
+        assert_eq!(addrs,
+                   vec!["[2a00:1450::1]:0".parse::<SocketAddr>().unwrap(), "[fe80::1]:0".parse().unwrap()]);
+    }
+
+    #[test]
+    fn positive_map_addrs_prefer_global_ipv6() {
+        let config = Config::new().set_prefer_global_ipv6(true);
+
+        let addrs = selected_addrs(global_ipv6_addrs(true, true), &config);
+
+        assert_eq!(addrs, vec!["[2a00:1450::1]:0".parse::<SocketAddr>().unwrap()]);
+    }
+
+    #[test]
+    fn positive_dedup_interfaces_first_ipv4() {
+        let ifaces = vec![iface("eth0", "192.168.1.10"),
+                          iface("eth0", "192.168.1.11"),
+                          iface("eth1", "192.168.1.12")];
+
+        assert_eq!(super::dedup_interfaces(ifaces, false, false),
+                   vec![iface("eth0", "192.168.1.10"), iface("eth1", "192.168.1.12")]);
+    }
+
+    #[test]
+    fn positive_dedup_interfaces_prefers_link_local() {
+        let ifaces = vec![iface("eth0", "fd00::2"),
+                          iface("eth0", "192.168.1.10"),
+                          iface("eth0", "fe80::1"),
+                          iface("eth0", "fe80::2"),
+                          iface("eth1", "fd00::3")];
+
+        assert_eq!(super::dedup_interfaces(ifaces, false, false),
+                   vec![iface("eth0", "fe80::1"), iface("eth0", "192.168.1.10"), iface("eth1", "fd00::3")]);
+    }
+
+    #[test]
+    fn positive_dedup_interfaces_prefers_global() {
+        let ifaces = vec![iface("eth0", "fe80::1"), iface("eth0", "fd00::2"), iface("eth0", "2a00:1450::1")];
+
+        assert_eq!(super::dedup_interfaces(ifaces, false, true), vec![iface("eth0", "2a00:1450::1")]);
+    }
+
+    #[test]
+    fn positive_dedup_interfaces_allows_global() {
+        let ifaces = vec![iface("eth0", "fd00::2"),
+                          iface("eth0", "fe80::1"),
+                          iface("eth0", "2a00:1450::1"),
+                          iface("eth0", "2a00:1450::2")];
+
+        assert_eq!(super::dedup_interfaces(ifaces, true, false),
+                   vec![iface("eth0", "fe80::1"), iface("eth0", "2a00:1450::1")]);
+    }
+
+    #[test]
+    fn positive_map_addrs_include_loopback() {
+        let config = Config::new().set_include_loopback(true);
+
+        let addrs = selected_addrs(synthetic_addrs(), &config);
+
+        assert_eq!(addrs.len(), 4);
+        assert_eq!(addrs[0], "127.0.0.1:0".parse::<SocketAddr>().unwrap());
+    }
+
+    #[test]
+    fn positive_map_addrs_interfaces() {
+        let config = Config::new().set_allowed_interfaces(vec!["10.0.0.10", "127.0.0.1"]);
+
+        let addrs = selected_addrs(synthetic_addrs(), &config);
+
+        assert_eq!(addrs,
+                   vec!["127.0.0.1:0".parse::<SocketAddr>().unwrap(), "10.0.0.10:0".parse().unwrap()]);
+    }
+
+    #[test]
+    fn positive_map_addrs_interface_names() {
+        let config = Config::new().set_allowed_interfaces(vec!["eth0", "wlan0"]);
+
+        let addrs = selected_addrs(synthetic_addrs(), &config);
+
+        assert_eq!(addrs,
+                   vec!["192.168.1.10:0".parse::<SocketAddr>().unwrap(), "[fe80::1]:0".parse().unwrap()]);
+    }
+
+    #[test]
+    fn negative_map_addrs_missing_interfaces() {
+        let config = Config::new().set_allowed_interfaces(vec!["172.16.0.1", "wlan0"]);
+
+        let result = super::map_addrs(synthetic_addrs(), &config, |iface| Ok(Some(iface.socket_addr())));
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn positive_is_excluded_glob() {
+        let excluded = vec!["veth*".to_owned(), "docker0".to_owned()];
+
+        assert!(super::is_excluded("veth1a2b", &excluded));
+        assert!(super::is_excluded("veth", &excluded));
+        assert!(super::is_excluded("docker0", &excluded));
+        assert!(!super::is_excluded("docker1", &excluded));
+        assert!(!super::is_excluded("eth0", &excluded));
+        assert!(super::is_excluded("anything", &["*".to_owned()]));
+    }
+
+    #[test]
+    fn positive_map_addrs_excluded_interfaces() {
+        let config = Config::new().set_excluded_interfaces(vec!["eth0", "wlan*"]);
+
+        let addrs = selected_addrs(synthetic_addrs(), &config);
+
+        assert_eq!(addrs, vec!["10.0.0.10:0".parse::<SocketAddr>().unwrap()]);
+    }
+
+    #[test]
+    fn positive_map_addrs_allowed_wins_over_excluded() {
+        let config = Config::new().set_allowed_interfaces(vec!["eth1"]).set_excluded_interfaces(vec!["eth*"]);
+
+        let addrs = selected_addrs(synthetic_addrs(), &config);
+
+        assert_eq!(addrs, vec!["10.0.0.10:0".parse::<SocketAddr>().unwrap()]);
+    }
+
+    #[test]
+    fn positive_config_interfaces() {
+        let ifaces: Vec<IpAddr> = vec!["127.0.0.1".parse().unwrap()];
+        let config = Config::new().set_interfaces(ifaces.clone());
+
+        assert_eq!(config.interfaces, Some(vec![InterfaceSelector::Addr(ifaces[0])]));
+        assert_eq!(Config::new().interfaces, None);
+    }
+
+    #[test]
+    fn positive_interface_selector_from_str() {
+        assert_eq!(InterfaceSelector::from("eth0"), InterfaceSelector::Name("eth0".to_owned()));
+        assert_eq!(InterfaceSelector::from("fe80::1"), InterfaceSelector::Addr("fe80::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn positive_ipv6_scope_addrs() {
+        let link = Config::new();
+        let site = Config::new().set_ipv6_scope(Ipv6Scope::SiteLocal);
+        let organization = Config::new().set_ipv6_scope(Ipv6Scope::OrganizationLocal);
+        let custom = Config::new().set_ipv6_scope(Ipv6Scope::Custom("FF0E::C".parse().unwrap()));
+
+        assert_eq!(link.ipv6_scope().unwrap(), Ipv6Scope::LinkLocal);
+        assert_eq!(site.ipv6_addr, "ff05::c");
+        assert_eq!(site.ipv6_scope().unwrap(), Ipv6Scope::SiteLocal);
+        assert_eq!(organization.ipv6_addr, "ff08::c");
+        assert_eq!(organization.ipv6_scope().unwrap(), Ipv6Scope::OrganizationLocal);
+        assert_eq!(custom.ipv6_scope().unwrap(), Ipv6Scope::Custom("FF0E::C".parse().unwrap()));
+    }
+
+    #[test]
+    fn positive_ipv6_scope_hop_limits() {
+        assert_eq!(Ipv6Scope::LinkLocal.hop_limit(), 1);
+        assert_eq!(Ipv6Scope::SiteLocal.hop_limit(), super::UPNP_MULTICAST_SITE_HOPS);
+        assert_eq!(Ipv6Scope::OrganizationLocal.hop_limit(), super::UPNP_MULTICAST_ORGANIZATION_HOPS);
+        assert_eq!(Ipv6Scope::Custom("FF0E::C".parse().unwrap()).hop_limit(),
+                   super::UPNP_MULTICAST_ORGANIZATION_HOPS);
+        assert!(Ipv6Scope::SiteLocal.hop_limit() > 2);
+    }
+
+    #[test]
+    fn positive_diff_addrs() {
+        let old: Vec<SocketAddr> = vec!["192.168.1.10:0".parse().unwrap(), "[fe80::1%2]:0".parse().unwrap()];
+        let new: Vec<SocketAddr> = vec!["[fe80::1%2]:0".parse().unwrap(), "10.0.0.10:0".parse().unwrap()];
+
+        let (added, removed) = super::diff_addrs(&old, &new);
+
+        assert_eq!(added, vec!["10.0.0.10:0".parse::<SocketAddr>().unwrap()]);
+        assert_eq!(removed, vec!["192.168.1.10:0".parse::<SocketAddr>().unwrap()]);
+    }
+
+    #[test]
+    fn positive_diff_addrs_scope_change() {
+        let old: Vec<SocketAddr> = vec!["[fe80::1%2]:0".parse().unwrap()];
+        let new: Vec<SocketAddr> = vec!["[fe80::1%3]:0".parse().unwrap()];
+
+        let (added, removed) = super::diff_addrs(&old, &new);
+
+        assert_eq!(added, new);
+        assert_eq!(removed, old);
+    }
+
+    #[test]
+    fn positive_diff_addrs_unchanged() {
+        let addrs = synthetic_addrs().iter().map(LocalInterface::socket_addr).collect::<Vec<_>>();
+
+        let (added, removed) = super::diff_addrs(&addrs, &addrs);
+
+        assert!(added.is_empty());
+        assert!(removed.is_empty());
+    }
+
+    fn interface_set(config: &Config) -> InterfaceSet {
+        InterfaceSet {
+            config: config.clone(),
+            connectors: Vec::new(),
+            addrs: Vec::new(),
+            listed: Instant::now(),
+        }
+    }
+
+    fn bound_ips(connectors: &[UdpConnector]) -> Vec<IpAddr> {
+        connectors.iter().map(|conn| conn.local_addr().unwrap().ip()).collect()
+    }
+
+    #[test]
+    fn positive_interface_set_follows_snapshots() {
+        let mut set = interface_set(&Config::new());
+        let first: Vec<SocketAddr> = vec!["127.0.0.1:0".parse().unwrap()];
+        let second: Vec<SocketAddr> = vec!["127.0.0.1:0".parse().unwrap(), "[::1]:0".parse().unwrap()];
+        let third: Vec<SocketAddr> = vec!["[::1]:0".parse().unwrap()];
+
+        assert_eq!(bound_ips(set.update(first.clone())), vec![first[0].ip()]);
+        let kept_port = set.connectors[0].local_addr().unwrap().port();
+
+        assert_eq!(bound_ips(set.update(second)), vec![third[0].ip()]);
+        assert_eq!(set.connectors[0].local_addr().unwrap().port(), kept_port);
+
+        assert!(set.update(third.clone()).is_empty());
+        assert_eq!(bound_ips(&set.connectors), vec![third[0].ip()]);
+        assert_eq!(set.addrs, third);
+    }
+
+    #[test]
+    fn positive_interface_set_retries_failed_bind() {
+        let mut set = interface_set(&Config::new());
+        // Documentation address that is not assigned to any local interface
+        let missing: Vec<SocketAddr> = vec!["192.0.2.1:0".parse().unwrap()];
+
+        assert!(set.update(missing.clone()).is_empty());
+        assert!(set.addrs.is_empty());
+        assert!(set.update(missing).is_empty());
+    }
+
+    #[test]
+    fn positive_interface_set_refresh_cached() {
+        let mut set = interface_set(&Config::new());
+
+        assert!(set.refresh().unwrap().is_empty());
+        assert!(set.connectors.is_empty());
+    }
+
+    /// Group socket recording the joins and leaves issued on it.
+    struct MockGroupSocket {
+        ipv4: bool,
+        calls: RefCell<Vec<(&'static str, SocketAddr)>>,
+    }
+
+    impl MockGroupSocket {
+        fn new(ipv4: bool) -> MockGroupSocket {
+            MockGroupSocket {
+                ipv4,
+                calls: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl GroupSocket for MockGroupSocket {
+        fn is_ipv4(&self) -> bool {
+            self.ipv4
+        }
+
+        fn join(&self, iface: &SocketAddr, _group: &IpAddr) -> io::Result<()> {
+            self.calls.borrow_mut().push(("join", *iface));
+            Ok(())
+        }
+
+        fn leave(&self, iface: &SocketAddr, _group: &IpAddr) -> io::Result<()> {
+            self.calls.borrow_mut().push(("leave", *iface));
+            Err(io::Error::new(io::ErrorKind::AddrNotAvailable, "interface gone"))
+        }
+    }
+
+    #[test]
+    fn positive_update_memberships() {
+        let sockets = [MockGroupSocket::new(true), MockGroupSocket::new(false)];
+        let mut known: Vec<SocketAddr> = vec!["192.168.1.10:0".parse().unwrap(),
+                                              "[fe80::1%2]:0".parse().unwrap()];
+        let current: Vec<SocketAddr> = vec!["[fe80::1%2]:0".parse().unwrap(), "10.0.0.10:0".parse().unwrap()];
+
+        super::update_memberships(&sockets, &mut known, current.clone(), &Config::new());
+
+        assert_eq!(known, current);
+        assert_eq!(*sockets[0].calls.borrow(),
+                   vec![("leave", "192.168.1.10:0".parse().unwrap()),
+                        ("join", "10.0.0.10:0".parse().unwrap())]);
+        assert!(sockets[1].calls.borrow().is_empty());
+    }
+
+    #[test]
+    fn positive_leave_all_tolerates_errors() {
+        let sockets = [MockGroupSocket::new(true), MockGroupSocket::new(false)];
+        let joined: Vec<SocketAddr> = vec!["192.168.1.10:0".parse().unwrap(),
+                                           "10.0.0.10:0".parse().unwrap(),
+                                           "[fe80::1%2]:0".parse().unwrap()];
+
+        super::leave_all(&sockets, &joined, &Config::new());
+
+        assert_eq!(*sockets[0].calls.borrow(),
+                   vec![("leave", joined[0]), ("leave", joined[1])]);
+        assert_eq!(*sockets[1].calls.borrow(), vec![("leave", joined[2])]);
+    }
+}
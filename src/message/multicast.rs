@@ -1,11 +1,15 @@
-use std::net::{SocketAddr, SocketAddrV6};
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use error::SSDPResult;
 use net::connector::UdpConnector;
 use message::{self, Config};
 use message::ssdp::SSDPMessage;
+use net;
 
+pub use net::connector::SEND_TIMEOUT_MS;
 
 pub trait Multicast {
     type Item;
@@ -18,26 +22,180 @@ pub trait Multicast {
 }
 
 pub fn send(message: &SSDPMessage, config: &Config) -> SSDPResult<Vec<UdpConnector>> {
-    let mut connectors = try!(message::all_local_connectors(Some(config.ttl), &config.mode));
-
-    for conn in &mut connectors {
-        match try!(conn.local_addr()) {
-            SocketAddr::V4(n) => {
-                let mcast_addr = (config.ipv4_addr.as_str(), config.port);
-                debug!("Sending ipv4 multicast through {} to {:?}", n, mcast_addr);
-                try!(message.send(conn, &mcast_addr));
-            }
-            SocketAddr::V6(n) => {
-                debug!("Sending Ipv6 multicast through {} to {}:{}", n, config.ipv6_addr, config.port);
-                //try!(message.send(conn, &mcast_addr));
-                try!(message.send(conn,
-                                  &SocketAddrV6::new(try!(FromStr::from_str(config.ipv6_addr.as_str())),
-                                                     config.port,
-                                                     n.flowinfo(),
-                                                     n.scope_id())))
+    let connectors = try!(message::config_connectors(config));
+
+    send_on(message, connectors, config)
+}
+
+/// Send the message to the multicast address on each of the given connectors.
+pub fn send_on(message: &SSDPMessage,
+               mut connectors: Vec<UdpConnector>,
+               config: &Config)
+               -> SSDPResult<Vec<UdpConnector>> {
+    send_through(message, &mut connectors, config)?;
+
+    Ok(connectors)
+}
+
+/// Send the message to the multicast address on each of the given connectors, keeping ownership of them.
+pub fn send_through(message: &SSDPMessage,
+                    connectors: &mut [UdpConnector],
+                    config: &Config)
+                    -> SSDPResult<()> {
+    send_through_cancellable(message, connectors, config, &AtomicBool::new(false)).map(|_| ())
+}
+
+/// Send the message to the multicast address on each of the given connectors until cancelled.
+///
+/// The cancel flag is checked before each connector is sent on, and every send is
+/// bounded by `SEND_TIMEOUT_MS`, so once the flag is set this returns within that
+/// bound. Returns the local addresses of the connectors that were skipped.
+pub fn send_through_cancellable(message: &SSDPMessage,
+                                connectors: &mut [UdpConnector],
+                                config: &Config,
+                                cancel: &AtomicBool)
+                                -> SSDPResult<Vec<SocketAddr>> {
+    config.validate()?;
+
+    let addrs = connectors.iter().map(|conn| conn.local_addr()).collect::<io::Result<Vec<_>>>()?;
+
+    let mut results = Vec::with_capacity(connectors.len());
+    let sent = send_each_until(connectors, cancel, |conn| results.push(send_one(message, conn, config)));
+
+    message::check_sends(addrs.iter().cloned().zip(results))?;
+
+    let skipped = addrs[sent..].to_vec();
+    if !skipped.is_empty() {
+        debug!("Multicast cancelled, skipped {:?}", skipped);
+    }
+
+    Ok(skipped)
+}
+
+/// Run the send for each connector, stopping early once the cancel flag is set.
+///
+/// Returns the number of connectors that were sent on.
+fn send_each_until<C, F>(connectors: &mut [C], cancel: &AtomicBool, mut send: F) -> usize
+    where F: FnMut(&mut C)
+{
+    for (index, conn) in connectors.iter_mut().enumerate() {
+        if cancel.load(Ordering::SeqCst) {
+            return index;
+        }
+
+        send(conn);
+    }
+
+    connectors.len()
+}
+
+/// Send the message to the multicast address on a single connector.
+fn send_one(message: &SSDPMessage, conn: &mut UdpConnector, config: &Config) -> SSDPResult<()> {
+    match try!(conn.local_addr()) {
+        SocketAddr::V4(n) => {
+            let mcast_addr = net::addr_from_trait((config.ipv4_addr.as_str(), config.port))?;
+            debug!("Sending ipv4 multicast through {} to {:?}", n, mcast_addr);
+            let result = message.send_to_addr_limited(conn, mcast_addr, config.max_message_size);
+
+            if config.broadcast_fallback {
+                if let Err(err) = send_broadcast(message, conn, *n.ip(), config) {
+                    warn!("Failed to send broadcast through {}: {}", n, err);
+                }
             }
+            result?;
+        }
+        SocketAddr::V6(n) => {
+            debug!("Sending Ipv6 multicast through {} to {}:{}", n, config.ipv6_addr, config.port);
+            let mcast_ip: Ipv6Addr = try!(FromStr::from_str(config.ipv6_addr.as_str()));
+            let scope_id = if net::needs_scope_id(&mcast_ip) { n.scope_id() } else { 0 };
+            let mcast_addr = SocketAddrV6::new(mcast_ip, config.port, n.flowinfo(), scope_id);
+
+            conn.set_multicast_hops_v6(config.multicast_hops_v6()?)?;
+            message.send_to_addr_limited(conn, SocketAddr::V6(mcast_addr), config.max_message_size)?
         }
     }
 
-    Ok(connectors)
+    Ok(())
+}
+
+/// Send the message to the directed broadcast address of the interface with the local address.
+fn send_broadcast(message: &SSDPMessage,
+                  conn: &mut UdpConnector,
+                  local: Ipv4Addr,
+                  config: &Config)
+                  -> SSDPResult<()> {
+    conn.set_broadcast(true)?;
+
+    match message::interface_broadcast(local)? {
+        Some(broadcast) => {
+            debug!("Sending ipv4 broadcast through {} to {}", local, broadcast);
+            message.send_to_addr_limited(conn, SocketAddr::V4(SocketAddrV4::new(broadcast, config.port)),
+                                         config.max_message_size)
+        }
+        None => {
+            debug!("No broadcast address for iface {}", local);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use net::connector::UdpConnector;
+    use message::{Config, MessageType};
+    use message::ssdp::SSDPMessage;
+
+    #[test]
+    fn positive_send_each_until_uncancelled() {
+        let mut connectors = vec![0, 1, 2];
+        let mut sent = Vec::new();
+
+        let count = super::send_each_until(&mut connectors, &AtomicBool::new(false), |conn| sent.push(*conn));
+
+        assert_eq!(count, 3);
+        assert_eq!(sent, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn positive_send_each_until_cancelled_while_blocked() {
+        let block_time = Duration::from_millis(200);
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let thread_cancel = cancel.clone();
+        let start = Instant::now();
+        let sender = thread::spawn(move || {
+            let mut connectors = vec![0, 1, 2, 3, 4];
+
+            // Every send blocks as if the interface were wedged
+            super::send_each_until(&mut connectors, &thread_cancel, |_| thread::sleep(block_time))
+        });
+
+        thread::sleep(Duration::from_millis(50));
+        cancel.store(true, Ordering::SeqCst);
+
+        let sent = sender.join().unwrap();
+
+        assert_eq!(sent, 1);
+        assert!(start.elapsed() < block_time * 2);
+    }
+
+    #[test]
+    fn positive_broadcast_fallback_enables_broadcast() {
+        let message = SSDPMessage::new(MessageType::Notify);
+
+        for &on in &[false, true] {
+            let mut connector = UdpConnector::new("127.0.0.1:0", None).unwrap();
+            let config = Config::new().set_broadcast_fallback(on);
+
+            // The multicast itself may not be routable from loopback, only the option matters
+            let _ = super::send_one(&message, &mut connector, &config);
+
+            assert_eq!(connector.try_clone_socket().unwrap().broadcast().unwrap(), on);
+        }
+    }
 }
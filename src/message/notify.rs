@@ -1,18 +1,33 @@
 use std::borrow::Cow;
-use std::fmt::Debug;
+use std::cmp;
+use std::convert::TryFrom;
+use std::fmt::{self, Debug};
+use std::io::{self, Write};
+use std::mem;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender, Receiver, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 use hyper::header::{Header, HeaderFormat};
+use hyper::net::{NetworkConnector, NetworkStream};
 
-use error::SSDPResult;
-use header::{HeaderRef, HeaderMut};
-use message::{MessageType, Listen, Config};
-use message::ssdp::SSDPMessage;
+use error::{SSDPResult, SSDPErrorKind};
+use header::{HeaderRef, HeaderMut, BootID, CacheControl, CacheDirective, ConfigID, Location, NextBootID, NT,
+             NTS, SearchPort, SecureLocation, Server, ST, USN};
+use message::{self, MessageType, Listen, Config, InterfaceSet, TargetSet};
+use message::ssdp::{SSDPMessage, ParsePolicy, Deviation};
 use message::multicast::{self, Multicast};
-use receiver::FromRawSSDP;
+use net::connector::UdpConnector;
+use net;
+use receiver::{DropReason, SSDPReceiver, FromRawSSDP};
+use FieldMap;
 
 
 /// Notify message that can be sent via multicast to devices on the network.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct NotifyMessage {
     message: SSDPMessage,
 }
@@ -22,14 +37,317 @@ impl NotifyMessage {
     pub fn new() -> Self {
         NotifyMessage { message: SSDPMessage::new(MessageType::Notify) }
     }
+
+    /// Construct an `ssdp:alive` NotifyMessage announcing the given entity.
+    ///
+    /// The advertisement is valid for `max_age`, rounded down to the second.
+    pub fn alive(nt: NT, usn: USN, location: &str, max_age: Duration, server: &str) -> Self {
+        let max_age = cmp::min(max_age.as_secs(), u64::from(u32::MAX)) as u32;
+
+        let mut notify = NotifyMessage::new();
+        notify.set(nt);
+        notify.set(NTS::Alive);
+        notify.set(usn);
+        notify.set(Location(location.to_owned()));
+        notify.set(CacheControl(vec![CacheDirective::MaxAge(max_age)]));
+        notify.set(Server(server.to_owned()));
+
+        notify
+    }
+
+    /// Construct an `ssdp:byebye` NotifyMessage withdrawing the given entity.
+    pub fn byebye(nt: NT, usn: USN) -> Self {
+        let mut notify = NotifyMessage::new();
+        notify.set(nt);
+        notify.set(NTS::ByeBye);
+        notify.set(usn);
+
+        notify
+    }
+
+    /// Construct an `ssdp:update` NotifyMessage moving the given entity from one boot instance to the next.
+    ///
+    /// Alive messages sent afterwards have to carry the new boot instance.
+    pub fn update_boot(nt: NT, usn: USN, old_boot: BootID, new_boot: BootID) -> Self {
+        NotifyMessage::update(nt, usn, old_boot.0, new_boot.0)
+    }
+
+    /// Construct an `ssdp:update` NotifyMessage moving the given entity to a new boot instance.
+    pub fn update(nt: NT, usn: USN, boot_id: u32, next_boot_id: u32) -> Self {
+        let mut notify = NotifyMessage::new();
+        notify.set(nt);
+        notify.set(NTS::Update);
+        notify.set(usn);
+        notify.set(BootID(boot_id));
+        notify.set(NextBootID(next_boot_id));
+
+        notify
+    }
+
+    /// Send this notify message to a single host.
+    ///
+    /// The message is sent from the interface the system routes the host through.
+    /// If that route can not be found it is sent on all available network
+    /// interfaces instead, which assumes that the network interfaces are operating
+    /// on either different subnets or different ip address ranges.
+    ///
+    /// The `Host` header is set to the destination, replacing any multicast group.
+    /// Destinations that resolve to several addresses are sent to at the first one of
+    /// an IP version that the local interfaces have.
+    pub fn unicast<A: ToSocketAddrs>(&mut self, dst_addr: A) -> SSDPResult<()> {
+        let dst_addr = net::addr_from_trait_with_mode(dst_addr, &message::local_mode(&Config::default())?)?;
+
+        self.unicast_addr(dst_addr)
+    }
+
+    /// Send this notify message to a single host, resolving the destination as the config allows.
+    ///
+    /// Same as `unicast`, except that only the interfaces selected by the config are sent on
+    /// and a host name destination fails with an error of kind `SSDPErrorKind::DnsForbidden`
+    /// when the config forbids DNS lookups.
+    pub fn unicast_with_config<A: net::DestAddr>(&mut self, dst_addr: A, config: &Config) -> SSDPResult<()> {
+        let dst_addr = message::resolve_dst(dst_addr, config)?;
+
+        self.unicast_configured(dst_addr, config)
+    }
+
+    /// Send this notify message to a single host.
+    ///
+    /// Same as `unicast` except that the destination is never resolved through DNS.
+    pub fn unicast_addr(&mut self, dst_addr: SocketAddr) -> SSDPResult<()> {
+        self.unicast_configured(dst_addr, &Config::default())
+    }
+
+    /// Send this notify message to a single host on the interfaces selected by the config.
+    fn unicast_configured(&mut self, dst_addr: SocketAddr, config: &Config) -> SSDPResult<()> {
+        let mut connectors = message::unicast_connectors(&dst_addr, config)?;
+
+        // Send On All Connectors
+        let mut results = Vec::with_capacity(connectors.len());
+        for conn in &mut connectors {
+            results.push((conn.local_addr()?, self.send_unicast(conn, dst_addr, config.max_message_size)));
+        }
+
+        message::check_sends(results)
+    }
+
+    /// Send this notify message to a single host through the given connector, if it fits in `max_size` bytes.
+    fn send_unicast<C, S>(&mut self,
+                          connector: &mut C,
+                          dst_addr: SocketAddr,
+                          max_size: usize)
+                          -> SSDPResult<()>
+        where C: NetworkConnector<Stream = S>,
+              S: Into<Box<NetworkStream + Send>>
+    {
+        self.set_raw(HOST_HEADER_NAME, vec![dst_addr.to_string().into_bytes()]);
+
+        self.message.send_to_addr_limited(connector, dst_addr, max_size)
+    }
+
+    /// Serialize this notify message into the bytes that sending it would put on the wire.
+    pub fn to_bytes(&self) -> SSDPResult<Vec<u8>> {
+        self.message.to_bytes()
+    }
+
+    /// Write the bytes that sending this notify message would put on the wire to the writer.
+    pub fn write_to<W: Write>(&self, dst_writer: &mut W) -> SSDPResult<()> {
+        self.message.write_to(dst_writer)
+    }
+
+    /// Check that all headers required by the notification sub type are present.
+    ///
+    /// On top of the headers checked before multicasting, alive messages need a
+    /// `Server` header and update messages need `BOOTID.UPNP.ORG` and `NEXTBOOTID.UPNP.ORG`.
+    pub fn validate(&self) -> SSDPResult<()> {
+        let mut missing = self.missing_for_multicast();
+
+        match self.get::<NTS>() {
+            Some(&NTS::Alive) => {
+                if self.get::<Server>().is_none() {
+                    missing.push(Server::header_name());
+                }
+            }
+            Some(&NTS::Update) => {
+                if self.get::<BootID>().is_none() {
+                    missing.push(BootID::header_name());
+                }
+                if self.get::<NextBootID>().is_none() {
+                    missing.push(NextBootID::header_name());
+                }
+            }
+            Some(&NTS::ByeBye) | None => (),
+        }
+
+        missing_headers(missing)
+    }
+
+    /// Multicast this notify message without checking that the required headers are present.
+    ///
+    /// The message is repeated as configured by `Config::set_announce_repeats`, and
+    /// succeeds if any of the repeats could be sent.
+    pub fn multicast_unchecked(&self, config: &Config) -> SSDPResult<()> {
+        let mut connectors = message::config_connectors(config)?;
+
+        send_repeated(config.announce_repeats,
+                      || multicast::send_through(&self.message, &mut connectors, config),
+                      thread::sleep)
+    }
+
+    /// Headers that have to be present before the message is multicast.
+    ///
+    /// Without a valid `NTS` header only `NT`, `NTS` and `USN` are checked.
+    fn missing_for_multicast(&self) -> Vec<&'static str> {
+        let mut missing = Vec::new();
+
+        if self.get::<NT>().is_none() {
+            missing.push(NT::header_name());
+        }
+        if self.get::<NTS>().is_none() {
+            missing.push(NTS::header_name());
+        }
+        if self.get::<USN>().is_none() {
+            missing.push(USN::header_name());
+        }
+
+        if let Some(&NTS::Alive) = self.get::<NTS>() {
+            if self.get::<Location>().is_none() && self.get::<SecureLocation>().is_none() {
+                missing.push(Location::header_name());
+            }
+            if self.get::<CacheControl>().is_none() {
+                missing.push(CacheControl::header_name());
+            }
+        }
+
+        missing
+    }
+}
+
+/// Builder for `ssdp:alive` notify messages.
+///
+/// The `Server` header defaults to one identifying this library. `Host` and
+/// `Content-Length` are not set here, they are filled in when the message is sent.
+#[derive(Debug, Clone)]
+pub struct NotifyMessageBuilder {
+    notify: NotifyMessage,
+}
+
+impl NotifyMessageBuilder {
+    /// Construct a new NotifyMessageBuilder for an `ssdp:alive` message.
+    pub fn new() -> NotifyMessageBuilder {
+        let mut notify = NotifyMessage::new();
+        notify.set(NTS::Alive);
+        notify.set(Server(message::default_server()));
+
+        NotifyMessageBuilder { notify }
+    }
+
+    /// Set the notification type.
+    pub fn nt(mut self, nt: NT) -> NotifyMessageBuilder {
+        self.notify.set(nt);
+        self
+    }
+
+    /// Set the unique service name.
+    pub fn usn(mut self, usn: USN) -> NotifyMessageBuilder {
+        self.notify.set(usn);
+        self
+    }
+
+    /// Set the URL of the device description.
+    pub fn location<S: Into<String>>(mut self, location: S) -> NotifyMessageBuilder {
+        self.notify.set(Location(location.into()));
+        self
+    }
+
+    /// Set how long the advertisement stays valid, rounded down to the second.
+    pub fn max_age(mut self, max_age: Duration) -> NotifyMessageBuilder {
+        let max_age = cmp::min(max_age.as_secs(), u64::from(u32::MAX)) as u32;
+
+        self.notify.set(CacheControl(vec![CacheDirective::MaxAge(max_age)]));
+        self
+    }
+
+    /// Set the product tokens of the `Server` header.
+    pub fn server<S: Into<String>>(mut self, server: S) -> NotifyMessageBuilder {
+        self.notify.set(Server(server.into()));
+        self
+    }
+
+    /// Set the boot instance of the root device.
+    pub fn boot_id(mut self, boot_id: u32) -> NotifyMessageBuilder {
+        self.notify.set(BootID(boot_id));
+        self
+    }
+
+    /// Set the configuration of the device description.
+    pub fn config_id(mut self, config_id: u32) -> NotifyMessageBuilder {
+        self.notify.set(ConfigID(config_id));
+        self
+    }
+
+    /// Set the port that unicast search requests should be sent to.
+    pub fn search_port(mut self, search_port: u16) -> NotifyMessageBuilder {
+        self.notify.set(SearchPort(search_port));
+        self
+    }
+
+    /// Build the NotifyMessage, failing if any required header was not set.
+    pub fn build(self) -> SSDPResult<NotifyMessage> {
+        self.notify.validate()?;
+
+        Ok(self.notify)
+    }
+}
+
+impl Default for NotifyMessageBuilder {
+    fn default() -> Self {
+        NotifyMessageBuilder::new()
+    }
+}
+
+/// Run the send the given number of times, at least once, sleeping a random gap between sends.
+///
+/// Only the first error is reported, and only if every send failed.
+fn send_repeated<F, S>(repeats: u8, mut send: F, mut sleep: S) -> SSDPResult<()>
+    where F: FnMut() -> SSDPResult<()>,
+          S: FnMut(Duration)
+{
+    let mut result = send();
+
+    for _ in 1..repeats {
+        sleep(Duration::from_millis(message::random_millis(MAX_REPEAT_GAP_MS - 1)));
+
+        let repeat = send();
+        if result.is_err() {
+            result = repeat.or(result);
+        }
+    }
+
+    result
+}
+
+/// Error listing the missing headers, if there are any.
+fn missing_headers(missing: Vec<&'static str>) -> SSDPResult<()> {
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(SSDPErrorKind::MissingHeaders(missing).into())
+    }
 }
 
 impl Multicast for NotifyMessage {
     type Item = ();
 
+    /// Multicast this notify message.
+    ///
+    /// Fails with `SSDPErrorKind::MissingHeaders` unless `NT`, `NTS` and `USN` are
+    /// present, along with `LOCATION` (or `SECURELOCATION.UPNP.ORG`) and
+    /// `CACHE-CONTROL` for alive messages. Use `multicast_unchecked` to skip this.
     fn multicast_with_config(&self, config: &Config) -> SSDPResult<Self::Item> {
-        multicast::send(&self.message, config)?;
-        Ok(())
+        missing_headers(self.missing_for_multicast())?;
+
+        self.multicast_unchecked(config)
     }
 }
 
@@ -39,18 +357,41 @@ impl Default for NotifyMessage {
     }
 }
 
-impl FromRawSSDP for NotifyMessage {
-    fn raw_ssdp(bytes: &[u8]) -> SSDPResult<NotifyMessage> {
-        let message = try!(SSDPMessage::raw_ssdp(bytes));
+impl TryFrom<SSDPMessage> for NotifyMessage {
+    type Error = SSDPMessage;
 
+    /// Fails with the message itself if it is not a notify message.
+    fn try_from(message: SSDPMessage) -> Result<NotifyMessage, SSDPMessage> {
         if message.message_type() != MessageType::Notify {
-            try!(Err("SSDP Message Received Is Not A NotifyMessage"))
+            Err(message)
         } else {
-            Ok(NotifyMessage { message: message })
+            Ok(NotifyMessage { message })
         }
     }
 }
 
+impl fmt::Display for NotifyMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.message, f)
+    }
+}
+
+impl FromRawSSDP for NotifyMessage {
+    fn raw_ssdp(bytes: &[u8]) -> SSDPResult<NotifyMessage> {
+        NotifyMessage::raw_ssdp_with_policy(bytes, &ParsePolicy::default())
+    }
+
+    fn raw_ssdp_with_policy(bytes: &[u8], policy: &ParsePolicy) -> SSDPResult<NotifyMessage> {
+        let message = SSDPMessage::raw_ssdp_with_policy(bytes, policy)?;
+
+        NotifyMessage::try_from(message).map_err(|_| "SSDP Message Received Is Not A NotifyMessage".into())
+    }
+
+    fn deviations(&self) -> &[Deviation] {
+        self.message.deviations()
+    }
+}
+
 impl HeaderRef for NotifyMessage {
     fn get<H>(&self) -> Option<&H>
         where H: Header + HeaderFormat
@@ -80,14 +421,802 @@ impl HeaderMut for NotifyMessage {
 /// Notify listener that can listen to notify messages sent within the network.
 pub struct NotifyListener;
 
+impl NotifyListener {
+    /// Listen for notify messages matching the filter on all local network interfaces.
+    ///
+    /// This will call `listen_filtered_with_config()` with _default_ values.
+    pub fn listen_filtered(filter: NotifyFilter) -> SSDPResult<SSDPReceiver<NotifyMessage>> {
+        NotifyListener::listen_filtered_with_config(filter, &Default::default())
+    }
+
+    /// Listen for notify messages matching the filter on all local network interfaces.
+    ///
+    /// Messages that do not match are dropped on the receiver threads, see
+    /// `Listen::listen_with_config()` for how the interfaces are bound.
+    pub fn listen_filtered_with_config(filter: NotifyFilter,
+                                       config: &Config)
+                                       -> SSDPResult<SSDPReceiver<NotifyMessage>> {
+        let (sockets, joined) = message::join_multicast_sockets(config)?;
+
+        let filter_config = config.clone();
+        let recv = SSDPReceiver::with_options(sockets,
+                                               None,
+                                               config.receiver_options(),
+                                               move |msg| if filter.matches(msg) {
+                                                   NotifyListener::accept(msg, &filter_config)
+                                               } else {
+                                                   Err(DropReason::Filtered)
+                                               })?;
+        message::watch_memberships(&recv, joined, config)?;
+        Ok(recv)
+    }
+}
+
 impl Listen for NotifyListener {
     type Message = NotifyMessage;
+
+    fn accept(message: &NotifyMessage, config: &Config) -> Result<(), DropReason> {
+        message::check_max_age(message, config)
+    }
+}
+
+/// Filter selecting notify messages by their notification sub type and type.
+///
+/// A filter without any sub types or types matches every message, otherwise a
+/// message has to match one of the sub types and one of the types.
+#[derive(Clone, Debug, Default)]
+pub struct NotifyFilter {
+    nts: Vec<NTS>,
+    targets: Vec<TargetMatch>,
+}
+
+/// How the notification type of a message is matched.
+#[derive(Clone, Debug)]
+enum TargetMatch {
+    Exact(FieldMap),
+    Prefix(String),
+}
+
+impl NotifyFilter {
+    /// Construct a NotifyFilter matching every message.
+    pub fn new() -> NotifyFilter {
+        Default::default()
+    }
+
+    /// Match messages with the given notification sub type.
+    pub fn with_nts(mut self, nts: NTS) -> NotifyFilter {
+        self.nts.push(nts);
+        self
+    }
+
+    /// Match messages with exactly the given notification type.
+    pub fn with_target(mut self, nt: FieldMap) -> NotifyFilter {
+        self.targets.push(TargetMatch::Exact(nt));
+        self
+    }
+
+    /// Match messages whose notification type starts with the given prefix.
+    ///
+    /// A prefix such as `urn:schemas-upnp-org:device:MediaRenderer:` matches every
+    /// version of a device or service type.
+    pub fn with_target_prefix<S: Into<String>>(mut self, prefix: S) -> NotifyFilter {
+        self.targets.push(TargetMatch::Prefix(prefix.into()));
+        self
+    }
+
+    /// Whether the message passes the filter.
+    pub fn matches(&self, notify: &NotifyMessage) -> bool {
+        if !self.nts.is_empty() {
+            match notify.get::<NTS>() {
+                Some(nts) if self.nts.contains(nts) => (),
+                _ => return false,
+            }
+        }
+
+        if self.targets.is_empty() {
+            return true;
+        }
+
+        match notify.get::<NT>() {
+            Some(nt) => {
+                self.targets.iter().any(|target| match *target {
+                    TargetMatch::Exact(ref field) => *field == nt.0,
+                    TargetMatch::Prefix(ref prefix) => nt.0.to_string().starts_with(&prefix[..]),
+                })
+            }
+            None => false,
+        }
+    }
+}
+
+const HOST_HEADER_NAME: &'static str = "Host";
+
+/// Exclusive upper bound on the gap between repeats of a notify message.
+const MAX_REPEAT_GAP_MS: u64 = 200;
+
+/// Upper bound on how long sending a single byebye message on a connector may block.
+const BYEBYE_SEND_TIMEOUT_MS: u64 = 100;
+
+/// Upper bound on how long a `NotifyGuard` keeps sending byebye messages.
+const BYEBYE_TIMEOUT_MS: u64 = 500;
+
+/// Multicasts `ssdp:byebye` messages for a set of entities when dropped.
+///
+/// This makes sure control points forget about the entities when the process
+/// exits, instead of keeping them around until their max-age expires.
+pub struct NotifyGuard {
+    byebye: Vec<NotifyMessage>,
+    config: Config,
+}
+
+impl NotifyGuard {
+    /// Construct a NotifyGuard for the entities with the given notification types and USNs.
+    pub fn new(entities: Vec<(NT, USN)>, config: &Config) -> NotifyGuard {
+        NotifyGuard {
+            byebye: entities.into_iter().map(|(nt, usn)| NotifyMessage::byebye(nt, usn)).collect(),
+            config: config.clone(),
+        }
+    }
+
+    /// Multicast the byebye messages now, dropping the guard afterwards sends nothing.
+    ///
+    /// Sending gives up after `BYEBYE_TIMEOUT_MS` so that teardown can not hang.
+    pub fn shutdown(&mut self) -> SSDPResult<()> {
+        let byebye = mem::take(&mut self.byebye);
+        if byebye.is_empty() {
+            return Ok(());
+        }
+
+        let mut connectors = message::config_connectors(&self.config)?;
+        for conn in &connectors {
+            conn.set_write_timeout(Some(Duration::from_millis(BYEBYE_SEND_TIMEOUT_MS)))?;
+        }
+
+        let deadline = Instant::now() + Duration::from_millis(BYEBYE_TIMEOUT_MS);
+        let mut result = Ok(());
+        for (index, notify) in byebye.iter().enumerate() {
+            if Instant::now() >= deadline {
+                let msg = format!("{} byebye messages not sent before timeout", byebye.len() - index);
+                return Err(io::Error::new(io::ErrorKind::TimedOut, msg).into());
+            }
+
+            if let Err(err) = multicast::send_through(&notify.message, &mut connectors, &self.config) {
+                result = result.and(Err(err));
+            }
+        }
+
+        result
+    }
+}
+
+impl Drop for NotifyGuard {
+    fn drop(&mut self) {
+        if let Err(err) = self.shutdown() {
+            warn!("Failed to send byebye messages: {}", err);
+        }
+    }
+}
+
+/// Complete set of notify messages advertising a root device.
+///
+/// Following the UPnP device architecture a root device with `d` embedded devices
+/// and `k` services is advertised with `3 + 2d + k` messages, see `TargetSet` for
+/// how the notification types and USNs are paired.
+#[derive(Clone, Debug)]
+pub struct DeviceAdvertisement {
+    targets: TargetSet,
+    location: String,
+    max_age: Duration,
+}
+
+impl DeviceAdvertisement {
+    /// Construct a DeviceAdvertisement for the root device with the given UUID and device type.
+    ///
+    /// The device type is the full URN without the `urn:` prefix, for example
+    /// `schemas-upnp-org:device:MediaServer:1`.
+    pub fn new<U, D>(uuid: U, device_type: D, location: &str, max_age: Duration) -> DeviceAdvertisement
+        where U: Into<String>,
+              D: Into<String>
+    {
+        DeviceAdvertisement::from_targets(TargetSet::new(uuid, device_type), location, max_age)
+    }
+
+    /// Construct a DeviceAdvertisement for the devices and services of the given `TargetSet`.
+    pub fn from_targets(targets: TargetSet, location: &str, max_age: Duration) -> DeviceAdvertisement {
+        DeviceAdvertisement {
+            targets,
+            location: location.to_owned(),
+            max_age,
+        }
+    }
+
+    /// Add a device embedded in the root device.
+    pub fn with_embedded_device<U, D>(mut self, uuid: U, device_type: D) -> DeviceAdvertisement
+        where U: Into<String>,
+              D: Into<String>
+    {
+        self.targets = self.targets.with_embedded_device(uuid, device_type);
+        self
+    }
+
+    /// Add a service offered by the root device or one of its embedded devices.
+    pub fn with_service<U, S>(mut self, device_uuid: U, service_type: S) -> DeviceAdvertisement
+        where U: Into<String>,
+              S: Into<String>
+    {
+        self.targets = self.targets.with_service(device_uuid, service_type);
+        self
+    }
+
+    /// The `ssdp:alive` messages announcing the device.
+    pub fn messages(&self) -> Vec<NotifyMessage> {
+        let server = message::default_server();
+
+        self.targets
+            .matches(&ST::All)
+            .into_iter()
+            .map(|spec| NotifyMessage::alive(NT(spec.st), spec.usn, &self.location, self.max_age, &server))
+            .collect()
+    }
+
+    /// The `ssdp:byebye` messages withdrawing the device.
+    pub fn byebye_messages(&self) -> Vec<NotifyMessage> {
+        self.targets
+            .matches(&ST::All)
+            .into_iter()
+            .map(|spec| NotifyMessage::byebye(NT(spec.st), spec.usn))
+            .collect()
+    }
+}
+
+/// Advertises a set of entities on the network from a background thread.
+///
+/// The `ssdp:alive` messages are multicast on creation and then re-multicast at a
+/// random point between a quarter and a half of the max-age, so that they are
+/// refreshed well before they expire. Stopping the advertiser multicasts the
+/// matching `ssdp:byebye` messages.
+pub struct Advertiser {
+    alive: Arc<Mutex<Vec<NotifyMessage>>>,
+    config: Config,
+    stop: Sender<()>,
+    cancel: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    guard: Option<NotifyGuard>,
+}
+
+impl Advertiser {
+    /// Start advertising the given `ssdp:alive` messages.
+    ///
+    /// The smallest max-age of the messages determines how often they are re-sent.
+    /// Errors from the first round are returned, errors from later rounds are logged.
+    pub fn new(messages: Vec<NotifyMessage>, config: &Config) -> SSDPResult<Advertiser> {
+        for notify in &messages {
+            notify.validate()?;
+
+            if notify.get::<NTS>() != Some(&NTS::Alive) {
+                return Err(SSDPErrorKind::InvalidHeader(NTS::header_name(), "expected ssdp:alive").into());
+            }
+        }
+
+        let max_age = match messages.iter().filter_map(message::max_age).min() {
+            Some(n) => Duration::from_secs(u64::from(n)),
+            None => {
+                let name = CacheControl::header_name();
+                return Err(SSDPErrorKind::InvalidHeader(name, "missing max-age").into());
+            }
+        };
+
+        Advertiser::spawn(messages, max_age, config)
+    }
+
+    /// Start advertising every device and service of the given `TargetSet`.
+    pub fn for_targets(targets: &TargetSet,
+                       location: &str,
+                       max_age: Duration,
+                       config: &Config)
+                       -> SSDPResult<Advertiser> {
+        let device = DeviceAdvertisement::from_targets(targets.clone(), location, max_age);
+
+        Advertiser::for_device(&device, config)
+    }
+
+    /// Start advertising the root device along with its embedded devices and services.
+    pub fn for_device(device: &DeviceAdvertisement, config: &Config) -> SSDPResult<Advertiser> {
+        Advertiser::spawn(device.messages(), device.max_age, config)
+    }
+
+    fn spawn(alive: Vec<NotifyMessage>, max_age: Duration, config: &Config) -> SSDPResult<Advertiser> {
+        if max_age == Duration::from_secs(0) {
+            let reason = "max-age must be positive";
+            return Err(SSDPErrorKind::InvalidHeader(CacheControl::header_name(), reason).into());
+        }
+
+        let entities = alive.iter()
+            .filter_map(|notify| match (notify.get::<NT>(), notify.get::<USN>()) {
+                (Some(nt), Some(usn)) => Some((nt.clone(), usn.clone())),
+                _ => None,
+            })
+            .collect();
+        let guard = NotifyGuard::new(entities, config);
+
+        let mut interfaces = InterfaceSet::new(config)?;
+        for notify in &alive {
+            multicast::send_through(&notify.message, interfaces.connectors_mut(), config)?;
+        }
+
+        let (stop_send, stop_recv) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let alive = Arc::new(Mutex::new(alive));
+
+        let thread_alive = alive.clone();
+        let thread_config = config.clone();
+        let thread_cancel = cancel.clone();
+        let thread = thread::spawn(move || {
+            advertise(&thread_alive,
+                      max_age,
+                      &mut interfaces,
+                      &thread_config,
+                      &stop_recv,
+                      &thread_cancel);
+        });
+
+        Ok(Advertiser {
+            alive,
+            config: config.clone(),
+            stop: stop_send,
+            cancel,
+            thread: Some(thread),
+            guard: Some(guard),
+        })
+    }
+
+    /// Move the advertised entities to the next boot instance.
+    ///
+    /// The `ssdp:update` message of every entity is multicast right away, and every
+    /// alive message sent afterwards carries the new `BOOTID.UPNP.ORG`. Messages
+    /// without a boot instance are considered to be on boot instance 0.
+    ///
+    /// The boot instance is moved on even if some updates fail to send, those are
+    /// reported together in an error of kind `SSDPErrorKind::BootIdUpdateFailed`.
+    pub fn bump_boot_id(&self) -> SSDPResult<BootID> {
+        let mut alive = self.alive.lock().unwrap();
+
+        let old_boot = alive.iter()
+            .filter_map(|notify| notify.get::<BootID>().map(|boot| boot.0))
+            .max()
+            .unwrap_or(0);
+        let new_boot = if old_boot >= i32::MAX as u32 { 0 } else { old_boot + 1 };
+
+        let mut failures = Vec::new();
+        match message::config_connectors(&self.config) {
+            Ok(mut connectors) => {
+                for notify in alive.iter() {
+                    if let (Some(nt), Some(usn)) = (notify.get::<NT>(), notify.get::<USN>()) {
+                        let update = NotifyMessage::update_boot(nt.clone(),
+                                                                usn.clone(),
+                                                                BootID(old_boot),
+                                                                BootID(new_boot));
+
+                        let sent = multicast::send_through(&update.message, &mut connectors, &self.config);
+                        if let Err(err) = sent {
+                            let raw_usn = notify.get_raw(USN::header_name()).and_then(|raw| raw.first());
+                            let usn = raw_usn.map(|raw| String::from_utf8_lossy(raw).into_owned());
+
+                            failures.push((usn.unwrap_or_default(), err));
+                        }
+                    }
+                }
+            }
+            Err(err) => failures.push((String::new(), err)),
+        }
+
+        for notify in alive.iter_mut() {
+            notify.set(BootID(new_boot));
+        }
+
+        if failures.is_empty() {
+            Ok(BootID(new_boot))
+        } else {
+            Err(SSDPErrorKind::BootIdUpdateFailed(new_boot, failures).into())
+        }
+    }
+
+    /// Stop advertising, announcing the byebye of every advertised entity.
+    ///
+    /// A round of alive messages that is in progress is abandoned before its next
+    /// interface, and this blocks until the byebye messages have been sent.
+    pub fn stop(&mut self) {
+        self.stop_thread();
+
+        if let Some(mut guard) = self.guard.take() {
+            if let Err(err) = guard.shutdown() {
+                warn!("Failed to send byebye messages: {}", err);
+            }
+        }
+    }
+
+    /// Stop re-announcing without sending byebye messages, returning the guard that will.
+    pub fn into_guard(mut self) -> NotifyGuard {
+        self.stop_thread();
+
+        self.guard.take().expect("advertiser guard is only taken when consumed")
+    }
+
+    fn stop_thread(&mut self) {
+        self.cancel.store(true, Ordering::SeqCst);
+
+        // The background thread may have already exited
+        let _ = self.stop.send(());
+
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                warn!("Advertiser thread panicked");
+            }
+        }
+    }
+}
+
+impl Drop for Advertiser {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Re-send the alive messages on a jittered schedule until a stop signal is received.
+///
+/// The messages stay locked for the whole round so that a boot instance bump can
+/// not be overtaken by alive messages carrying the old boot instance. The interfaces
+/// are refreshed before every round so that interfaces that come up get announced on.
+fn advertise(alive: &Mutex<Vec<NotifyMessage>>,
+             max_age: Duration,
+             interfaces: &mut InterfaceSet,
+             config: &Config,
+             stop: &Receiver<()>,
+             cancel: &AtomicBool) {
+    while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(advertise_interval(max_age)) {
+        if let Err(err) = interfaces.refresh() {
+            warn!("Failed to refresh advertised interfaces: {}", err);
+        }
+
+        send_round(&alive.lock().unwrap(), interfaces.connectors_mut(), config, cancel);
+    }
+}
+
+/// Multicast every message, logging failures so that the next round can retry.
+fn send_round(messages: &[NotifyMessage],
+              connectors: &mut [UdpConnector],
+              config: &Config,
+              cancel: &AtomicBool) {
+    for notify in messages {
+        if cancel.load(Ordering::SeqCst) {
+            return;
+        }
+
+        if let Err(err) = multicast::send_through_cancellable(&notify.message, connectors, config, cancel) {
+            warn!("Advertisement round failed: {}", err);
+        }
+    }
+}
+
+/// Random interval between a quarter and a half of the max-age.
+fn advertise_interval(max_age: Duration) -> Duration {
+    let half_millis = max_age.as_secs() * 500 + u64::from(max_age.subsec_nanos()) / 2_000_000;
+
+    Duration::from_millis(half_millis - message::random_millis(half_millis / 2))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::NotifyMessage;
+    use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+    use std::time::{Duration, Instant};
+
+    use error::SSDPErrorKind;
+    use header::{HeaderRef, HeaderMut, BootID, CacheControl, CacheDirective, Location, NextBootID, NT, NTS,
+                 SecureLocation, Server, USN};
+    use message::{Config, Listen, Multicast, TargetSet, DEFAULT_MAX_MESSAGE_SIZE, UPNP_MULTICAST_IPV4_ADDR};
+    use message::ssdp::mocks::MockConnector;
+    use net::IpVersionMode;
     use receiver::FromRawSSDP;
+    use FieldMap;
+    use super::{NotifyMessage, NotifyMessageBuilder, NotifyListener, NotifyFilter, NotifyGuard, Advertiser,
+                DeviceAdvertisement};
+
+    fn round_trip(notify: &NotifyMessage) -> NotifyMessage {
+        let mut connector = MockConnector::new();
+        notify.message.send(&mut connector, "127.0.0.1:1900").unwrap();
+
+        NotifyMessage::raw_ssdp(&connector.sent_bytes()[..]).unwrap()
+    }
+
+    fn nt() -> NT {
+        NT(FieldMap::upnp("rootdevice"))
+    }
+
+    fn usn() -> USN {
+        USN(FieldMap::uuid("2f402f80-da50-11e1-9b23-00178809ea66"), Some(FieldMap::upnp("rootdevice")))
+    }
+
+    #[test]
+    fn positive_alive_round_trip() {
+        let notify = NotifyMessage::alive(nt(),
+                                          usn(),
+                                          "http://192.168.1.1/desc.xml",
+                                          Duration::from_secs(1800),
+                                          "linux UPnP/1.1 test/1.0");
+        notify.validate().unwrap();
+
+        let received = round_trip(&notify);
+        received.validate().unwrap();
+        assert_eq!(received.get::<NTS>(), Some(&NTS::Alive));
+        assert_eq!(received.get::<NT>(), Some(&nt()));
+        assert_eq!(received.get::<USN>(), Some(&usn()));
+        assert_eq!(received.get::<Location>(), Some(&Location("http://192.168.1.1/desc.xml".to_owned())));
+        assert_eq!(received.get::<CacheControl>(), Some(&CacheControl(vec![CacheDirective::MaxAge(1800)])));
+        assert_eq!(received.get::<Server>(), Some(&Server("linux UPnP/1.1 test/1.0".to_owned())));
+    }
+
+    #[test]
+    fn positive_byebye_round_trip() {
+        let notify = NotifyMessage::byebye(nt(), usn());
+        notify.validate().unwrap();
+
+        let received = round_trip(&notify);
+        received.validate().unwrap();
+        assert_eq!(received.get::<NTS>(), Some(&NTS::ByeBye));
+        assert_eq!(received.get::<NT>(), Some(&nt()));
+        assert_eq!(received.get::<USN>(), Some(&usn()));
+    }
+
+    #[test]
+    fn positive_update_round_trip() {
+        let notify = NotifyMessage::update(nt(), usn(), 1, 2);
+        notify.validate().unwrap();
+
+        let received = round_trip(&notify);
+        received.validate().unwrap();
+        assert_eq!(received.get::<NTS>(), Some(&NTS::Update));
+        assert_eq!(received.get::<BootID>(), Some(&BootID(1)));
+        assert_eq!(received.get::<NextBootID>(), Some(&NextBootID(2)));
+    }
+
+    #[test]
+    fn positive_unicast_host_is_destination() {
+        let mut notify = NotifyMessage::byebye(nt(), usn());
+        notify.set_raw("Host", vec![b"239.255.255.250:1900".to_vec()]);
+        let mut connector = MockConnector::new();
+
+        let dst = "192.168.1.20:1900".parse().unwrap();
+        notify.send_unicast(&mut connector, dst, DEFAULT_MAX_MESSAGE_SIZE).unwrap();
+
+        let sent = String::from_utf8(connector.sent_bytes()).unwrap();
+        assert!(sent.starts_with("NOTIFY * HTTP/1.1\r\n"));
+        assert_eq!(sent.matches("Host: ").count(), 1);
+        assert!(sent.contains("Host: 192.168.1.20:1900\r\n"));
+    }
+
+    #[test]
+    fn negative_unicast_with_config_dns_forbidden() {
+        let config = Config::new().set_forbid_dns(true);
+        let result = NotifyMessage::byebye(nt(), usn()).unicast_with_config("ssdp.invalid:1900", &config);
+
+        match result.map_err(|err| err.0) {
+            Err(SSDPErrorKind::DnsForbidden(ref n)) => assert_eq!(n, "ssdp.invalid:1900"),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn negative_unicast_over_max_message_size() {
+        let mut notify = NotifyMessage::byebye(nt(), usn());
+        let mut connector = MockConnector::new();
+
+        let dst = "192.168.1.20:1900".parse().unwrap();
+        match notify.send_unicast(&mut connector, dst, 16).map_err(|err| err.0) {
+            Err(SSDPErrorKind::MessageTooLarge(_)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert!(connector.sent_bytes().is_empty());
+    }
+
+    #[test]
+    fn positive_unicast_ipv6_host() {
+        let mut notify = NotifyMessage::byebye(nt(), usn());
+        let mut connector = MockConnector::new();
+
+        let dst = "[fd00::20]:1900".parse().unwrap();
+        notify.send_unicast(&mut connector, dst, DEFAULT_MAX_MESSAGE_SIZE).unwrap();
+
+        let received = NotifyMessage::raw_ssdp(&connector.sent_bytes()[..]).unwrap();
+        assert_eq!(&received.get_raw("Host").unwrap()[0][..], &b"[fd00::20]:1900"[..]);
+    }
+
+    #[test]
+    fn positive_builder_matches_reference() {
+        let reference = "NOTIFY * HTTP/1.1\r\n\
+                         Host: 239.255.255.250:1900\r\n\
+                         NTS: ssdp:alive\r\n\
+                         Server: linux UPnP/1.1 test/1.0\r\n\
+                         NT: upnp:rootdevice\r\n\
+                         USN: uuid:2f402f80-da50-11e1-9b23-00178809ea66::upnp:rootdevice\r\n\
+                         Location: http://192.168.1.1/desc.xml\r\n\
+                         Cache-Control: max-age=1800\r\n\
+                         BOOTID.UPNP.ORG: 1\r\n\
+                         CONFIGID.UPNP.ORG: 2\r\n\
+                         SEARCHPORT.UPNP.ORG: 49152\r\n\
+                         Content-Length: 0\r\n\
+                         \r\n";
+
+        let notify = NotifyMessageBuilder::new()
+            .server("linux UPnP/1.1 test/1.0")
+            .nt(nt())
+            .usn(usn())
+            .location("http://192.168.1.1/desc.xml")
+            .max_age(Duration::from_secs(1800))
+            .boot_id(1)
+            .config_id(2)
+            .search_port(49152)
+            .build()
+            .unwrap();
+
+        let mut connector = MockConnector::new();
+        notify.message.send(&mut connector, "239.255.255.250:1900").unwrap();
+        let sent = String::from_utf8(connector.sent_bytes()).unwrap();
+
+        let sent_lines = sent.split("\r\n").collect::<Vec<_>>();
+        let reference_lines = reference.split("\r\n").collect::<Vec<_>>();
+        assert_eq!(sent_lines.len(), reference_lines.len());
+        for (sent_line, reference_line) in sent_lines.iter().zip(reference_lines.iter()) {
+            assert_eq!(sent_line, reference_line);
+        }
+    }
+
+    #[test]
+    fn negative_builder_missing_headers() {
+        match NotifyMessageBuilder::new().nt(nt()).usn(usn()).build().map_err(|err| err.0) {
+            Err(SSDPErrorKind::MissingHeaders(missing)) => {
+                assert_eq!(missing, vec!["Location", "Cache-Control"])
+            }
+            _ => panic!("Expected MissingHeaders Error"),
+        }
+    }
+
+    #[test]
+    fn negative_validate_alive_missing_headers() {
+        let mut notify = NotifyMessage::new();
+        notify.set(NTS::Alive);
+        notify.set(nt());
+
+        match notify.validate().map_err(|err| err.0) {
+            Err(SSDPErrorKind::MissingHeaders(missing)) => {
+                assert_eq!(missing, vec!["USN", "Location", "Cache-Control", "Server"])
+            }
+            _ => panic!("Expected MissingHeaders Error"),
+        }
+    }
+
+    fn assert_multicast_missing(notify: &NotifyMessage, expected: &[&str]) {
+        // An unroutable group makes sure nothing reaches the network if validation is skipped
+        let config = Config::new().set_ipv4_addr("0.0.0.0").set_mode(IpVersionMode::V4Only);
+
+        match notify.multicast_with_config(&config).map_err(|err| err.0) {
+            Err(SSDPErrorKind::MissingHeaders(missing)) => assert_eq!(&missing[..], expected),
+            _ => panic!("Expected MissingHeaders Error"),
+        }
+    }
+
+    #[test]
+    fn negative_multicast_empty() {
+        assert_multicast_missing(&NotifyMessage::new(), &["NT", "NTS", "USN"]);
+    }
+
+    #[test]
+    fn negative_multicast_missing_nt() {
+        let mut notify = NotifyMessage::byebye(nt(), usn());
+        notify.set_raw("NT", vec![]);
+
+        assert_multicast_missing(&notify, &["NT"]);
+    }
+
+    #[test]
+    fn negative_multicast_missing_usn() {
+        let mut notify = NotifyMessage::new();
+        notify.set(nt());
+        notify.set(NTS::ByeBye);
+
+        assert_multicast_missing(&notify, &["USN"]);
+    }
+
+    #[test]
+    fn negative_multicast_alive_missing_location_and_cache_control() {
+        let mut notify = NotifyMessage::byebye(nt(), usn());
+        notify.set(NTS::Alive);
+
+        assert_multicast_missing(&notify, &["Location", "Cache-Control"]);
+    }
+
+    #[test]
+    fn negative_multicast_alive_missing_cache_control() {
+        let mut notify = NotifyMessage::byebye(nt(), usn());
+        notify.set(NTS::Alive);
+        notify.set(SecureLocation("https://192.168.1.1/desc.xml".to_owned()));
+
+        assert_multicast_missing(&notify, &["Cache-Control"]);
+    }
+
+    #[test]
+    fn negative_multicast_alive_missing_location() {
+        let mut notify = NotifyMessage::byebye(nt(), usn());
+        notify.set(NTS::Alive);
+        notify.set(CacheControl(vec![CacheDirective::MaxAge(1800)]));
+
+        assert_multicast_missing(&notify, &["Location"]);
+    }
+
+    #[test]
+    fn positive_send_repeated_within_gap() {
+        let notify = NotifyMessage::byebye(nt(), usn());
+        let mut connector = MockConnector::new();
+        let mut gaps = Vec::new();
+
+        super::send_repeated(3,
+                             || notify.message.send(&mut connector, "127.0.0.1:1900"),
+                             |gap| gaps.push(gap))
+            .unwrap();
+
+        let sent = String::from_utf8(connector.sent_bytes()).unwrap();
+        assert_eq!(sent.matches("NOTIFY * HTTP/1.1").count(), 3);
+        assert_eq!(gaps.len(), 2);
+        assert!(gaps.iter().all(|&gap| gap < Duration::from_millis(super::MAX_REPEAT_GAP_MS)));
+    }
+
+    #[test]
+    fn positive_send_repeated_at_least_once() {
+        let mut sends = 0;
+
+        super::send_repeated(0, || { sends += 1; Ok(()) }, |_| panic!("Unexpected Sleep")).unwrap();
+        assert_eq!(sends, 1);
+    }
+
+    #[test]
+    fn positive_send_repeated_aggregates_errors() {
+        let mut sends = 0;
+        let result = super::send_repeated(3,
+                                          || {
+                                              sends += 1;
+                                              Err(SSDPErrorKind::MissingHeader("NT").into())
+                                          },
+                                          |_| ());
+
+        match result.map_err(|err| err.0) {
+            Err(SSDPErrorKind::MissingHeader("NT")) => (),
+            _ => panic!("Expected A Single MissingHeader Error"),
+        }
+        assert_eq!(sends, 3);
+
+        let mut sends = 0;
+        let result = super::send_repeated(2,
+                                          || {
+                                              sends += 1;
+                                              if sends == 1 {
+                                                  Err(SSDPErrorKind::MissingHeader("NT").into())
+                                              } else {
+                                                  Ok(())
+                                              }
+                                          },
+                                          |_| ());
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn negative_validate_missing_nts() {
+        let mut notify = NotifyMessage::byebye(nt(), usn());
+        notify.set_raw("NTS", vec![b"ssdp:unknown".to_vec()]);
+
+        match notify.validate().map_err(|err| err.0) {
+            Err(SSDPErrorKind::MissingHeaders(missing)) => assert_eq!(missing, vec!["NTS"]),
+            _ => panic!("Expected MissingHeaders Error"),
+        }
+    }
 
     #[test]
     fn positive_notify_message_type() {
@@ -111,4 +1240,306 @@ mod tests {
 
         NotifyMessage::raw_ssdp(raw_message.as_bytes()).unwrap();
     }
+
+    fn nt_usn_pairs(messages: &[NotifyMessage]) -> Vec<(String, String)> {
+        messages.iter()
+            .map(|notify| {
+                (notify.get::<NT>().unwrap().0.to_string(), {
+                    let usn = notify.get::<USN>().unwrap();
+                    match usn.1 {
+                        Some(ref suffix) => format!("{}::{}", usn.0, suffix),
+                        None => usn.0.to_string(),
+                    }
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn positive_advertiser_bump_boot_id() {
+        let (recv_sock, config) = loopback_receiver();
+        let notify = NotifyMessageBuilder::new()
+            .nt(nt())
+            .usn(usn())
+            .location("http://127.0.0.1/desc.xml")
+            .max_age(Duration::from_secs(1))
+            .boot_id(5)
+            .build()
+            .unwrap();
+        let mut advertiser = Advertiser::spawn(vec![notify], Duration::from_millis(200), &config).unwrap();
+
+        let mut buf = [0u8; 1500];
+        let mut recv_notify = || {
+            let (len, _) = recv_sock.recv_from(&mut buf).unwrap();
+            let notify = NotifyMessage::raw_ssdp(&buf[..len]).unwrap();
+
+            (*notify.get::<NTS>().unwrap(),
+             notify.get::<BootID>().cloned(),
+             notify.get::<NextBootID>().cloned())
+        };
+
+        assert_eq!(recv_notify(), (NTS::Alive, Some(BootID(5)), None));
+        assert_eq!(advertiser.bump_boot_id().unwrap(), BootID(6));
+
+        // Rounds sent before the bump may still be queued up ahead of the update
+        let mut next = recv_notify();
+        while next == (NTS::Alive, Some(BootID(5)), None) {
+            next = recv_notify();
+        }
+        assert_eq!(next, (NTS::Update, Some(BootID(5)), Some(NextBootID(6))));
+        assert_eq!(recv_notify(), (NTS::Alive, Some(BootID(6)), None));
+        assert_eq!(recv_notify(), (NTS::Alive, Some(BootID(6)), None));
+
+        advertiser.stop();
+    }
+
+    #[test]
+    fn negative_advertiser_bump_boot_id_send_failure() {
+        let (_recv_sock, config) = loopback_receiver();
+        let build = |uuid: &str| {
+            NotifyMessageBuilder::new()
+                .nt(nt())
+                .usn(USN(FieldMap::uuid(uuid), None))
+                .location("http://127.0.0.1/desc.xml")
+                .max_age(Duration::from_secs(60))
+                .boot_id(5)
+                .build()
+                .unwrap()
+        };
+        let notifies = vec![build("first"), build("second")];
+        let mut advertiser = Advertiser::spawn(notifies, Duration::from_secs(60), &config).unwrap();
+
+        // Too small for any update message, every send fails
+        advertiser.config = config.set_max_message_size(16);
+        match advertiser.bump_boot_id().map_err(|err| err.0) {
+            Err(SSDPErrorKind::BootIdUpdateFailed(6, ref failures)) => {
+                let usns = failures.iter().map(|(usn, _)| &usn[..]).collect::<Vec<_>>();
+                assert_eq!(usns, vec!["uuid:first", "uuid:second"]);
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+        let alive = advertiser.alive.lock().unwrap().clone();
+        assert!(alive.iter().all(|notify| notify.get::<BootID>() == Some(&BootID(6))));
+
+        advertiser.stop();
+    }
+
+    #[test]
+    fn positive_update_boot() {
+        let notify = NotifyMessage::update_boot(nt(), usn(), BootID(1), BootID(2));
+
+        notify.validate().unwrap();
+        assert_eq!(notify.get::<BootID>(), Some(&BootID(1)));
+        assert_eq!(notify.get::<NextBootID>(), Some(&NextBootID(2)));
+    }
+
+    #[test]
+    fn positive_device_advertisement_messages() {
+        let device = DeviceAdvertisement::new("root", "schemas-upnp-org:device:MediaServer:1",
+                                              "http://192.168.1.1/desc.xml", Duration::from_secs(1800))
+            .with_embedded_device("embedded", "schemas-upnp-org:device:Basic:1")
+            .with_service("root", "schemas-upnp-org:service:ContentDirectory:1")
+            .with_service("embedded", "schemas-upnp-org:service:Dimming:1");
+
+        let expected = vec![("upnp:rootdevice", "uuid:root::upnp:rootdevice"),
+                            ("uuid:root", "uuid:root"),
+                            ("urn:schemas-upnp-org:device:MediaServer:1",
+                             "uuid:root::urn:schemas-upnp-org:device:MediaServer:1"),
+                            ("uuid:embedded", "uuid:embedded"),
+                            ("urn:schemas-upnp-org:device:Basic:1",
+                             "uuid:embedded::urn:schemas-upnp-org:device:Basic:1"),
+                            ("urn:schemas-upnp-org:service:ContentDirectory:1",
+                             "uuid:root::urn:schemas-upnp-org:service:ContentDirectory:1"),
+                            ("urn:schemas-upnp-org:service:Dimming:1",
+                             "uuid:embedded::urn:schemas-upnp-org:service:Dimming:1")]
+            .into_iter()
+            .map(|(nt, usn)| (nt.to_owned(), usn.to_owned()))
+            .collect::<Vec<_>>();
+
+        let alive = device.messages();
+        assert_eq!(alive.len(), 7);
+        assert_eq!(nt_usn_pairs(&alive), expected);
+        for notify in &alive {
+            notify.validate().unwrap();
+            assert_eq!(notify.get::<NTS>(), Some(&NTS::Alive));
+        }
+
+        let byebye = device.byebye_messages();
+        assert_eq!(nt_usn_pairs(&byebye), expected);
+        for notify in &byebye {
+            notify.validate().unwrap();
+            assert_eq!(notify.get::<NTS>(), Some(&NTS::ByeBye));
+        }
+    }
+
+    #[test]
+    fn positive_advertise_interval_within_half_max_age() {
+        for _ in 0..100 {
+            let interval = super::advertise_interval(Duration::from_secs(1800));
+
+            assert!(interval >= Duration::from_secs(450) && interval <= Duration::from_secs(900));
+        }
+    }
+
+    #[test]
+    fn negative_advertiser_byebye_message() {
+        let result = Advertiser::new(vec![NotifyMessage::byebye(nt(), usn())], &Config::new());
+        match result.map(|_| ()).map_err(|err| err.0) {
+            Err(SSDPErrorKind::InvalidHeader("NTS", _)) => (),
+            _ => panic!("Expected InvalidHeader Error"),
+        }
+    }
+
+    #[test]
+    fn positive_advertiser_rounds_and_byebye() {
+        let (recv_sock, config) = loopback_receiver();
+        let targets = TargetSet::new("2f402f80-da50-11e1-9b23-00178809ea66",
+                                     "schemas-upnp-org:device:Basic:1");
+        let mut advertiser = Advertiser::for_targets(&targets, "http://127.0.0.1/desc.xml",
+                                                     Duration::from_millis(400), &config).unwrap();
+
+        let mut buf = [0u8; 1500];
+        let mut alive_count = 0;
+        while alive_count < 6 {
+            let (len, _) = recv_sock.recv_from(&mut buf).unwrap();
+            let notify = NotifyMessage::raw_ssdp(&buf[..len]).unwrap();
+
+            assert_eq!(notify.get::<NTS>(), Some(&NTS::Alive));
+            alive_count += 1;
+        }
+
+        advertiser.stop();
+
+        let mut byebye_count = 0;
+        while let Ok((len, _)) = recv_sock.recv_from(&mut buf) {
+            let notify = NotifyMessage::raw_ssdp(&buf[..len]).unwrap();
+
+            if notify.get::<NTS>() == Some(&NTS::ByeBye) {
+                byebye_count += 1;
+                if byebye_count == 3 {
+                    break;
+                }
+            }
+        }
+        assert_eq!(byebye_count, 3);
+    }
+
+    fn loopback_receiver() -> (UdpSocket, Config) {
+        let loopback = Ipv4Addr::new(127, 0, 0, 1);
+        let recv_sock = UdpSocket::bind("0.0.0.0:0").unwrap();
+        recv_sock.join_multicast_v4(&UPNP_MULTICAST_IPV4_ADDR.parse().unwrap(), &loopback).unwrap();
+        recv_sock.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let config = Config::new()
+            .set_port(recv_sock.local_addr().unwrap().port())
+            .set_mode(IpVersionMode::V4Only)
+            .set_interfaces(vec![IpAddr::V4(loopback)])
+            .set_include_loopback(true)
+            .set_multicast_loop(true);
+
+        (recv_sock, config)
+    }
+
+    #[test]
+    fn positive_guard_byebye_on_drop() {
+        let (recv_sock, config) = loopback_receiver();
+        drop(NotifyGuard::new(vec![(nt(), usn())], &config));
+
+        let mut buf = [0u8; 1500];
+        let (len, _) = recv_sock.recv_from(&mut buf).unwrap();
+        let notify = NotifyMessage::raw_ssdp(&buf[..len]).unwrap();
+
+        assert_eq!(notify.get::<NTS>(), Some(&NTS::ByeBye));
+        assert_eq!(notify.get::<USN>(), Some(&usn()));
+    }
+
+    #[test]
+    fn positive_guard_shutdown_once() {
+        let (recv_sock, config) = loopback_receiver();
+        let mut guard = NotifyGuard::new(vec![(nt(), usn())], &config);
+        guard.shutdown().unwrap();
+        guard.shutdown().unwrap();
+        drop(guard);
+
+        let mut buf = [0u8; 1500];
+        recv_sock.recv_from(&mut buf).unwrap();
+
+        recv_sock.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        assert!(recv_sock.recv_from(&mut buf).is_err());
+    }
+
+    fn raw_notify(nts: &str, nt: &str) -> String {
+        format!("NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nNT: {}\r\nNTS: {}\r\nUSN: \
+                 uuid:device\r\n\r\n",
+                nt,
+                nts)
+    }
+
+    #[test]
+    fn positive_filter_matches() {
+        let filter = NotifyFilter::new()
+            .with_nts(NTS::Alive)
+            .with_nts(NTS::ByeBye)
+            .with_target(FieldMap::upnp("rootdevice"))
+            .with_target_prefix("urn:schemas-upnp-org:device:MediaRenderer:");
+        let matches = |nts, nt| {
+            filter.matches(&NotifyMessage::raw_ssdp(raw_notify(nts, nt).as_bytes()).unwrap())
+        };
+
+        assert!(matches("ssdp:alive", "upnp:rootdevice"));
+        assert!(matches("ssdp:byebye", "urn:schemas-upnp-org:device:MediaRenderer:1"));
+        assert!(matches("ssdp:alive", "urn:schemas-upnp-org:device:MediaRenderer:2"));
+        assert!(!matches("ssdp:update", "upnp:rootdevice"));
+        assert!(!matches("ssdp:alive", "urn:schemas-upnp-org:device:MediaServer:1"));
+        assert!(!matches("ssdp:alive", "uuid:device"));
+
+        assert!(NotifyFilter::new().matches(&NotifyMessage::new()));
+        assert!(!filter.matches(&NotifyMessage::new()));
+    }
+
+    #[test]
+    fn positive_listener_close_releases_port() {
+        let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let recv = NotifyListener::listen_with_config(&Config::new().set_port(port)).unwrap();
+
+        recv.close_and_join(Duration::from_millis(1000)).unwrap();
+
+        UdpSocket::bind(("0.0.0.0", port)).unwrap();
+    }
+
+    #[test]
+    fn positive_listen_filtered_drops_non_matching() {
+        let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let filter = NotifyFilter::new()
+            .with_nts(NTS::Alive)
+            .with_target_prefix("urn:schemas-upnp-org:device:MediaRenderer:");
+        let config = Config::new().set_port(port);
+        let recv = NotifyListener::listen_filtered_with_config(filter, &config).unwrap();
+
+        let payloads = [raw_notify("ssdp:alive", "upnp:rootdevice"),
+                        raw_notify("ssdp:alive", "urn:schemas-upnp-org:device:MediaRenderer:1"),
+                        raw_notify("ssdp:byebye", "urn:schemas-upnp-org:device:MediaRenderer:1"),
+                        raw_notify("ssdp:alive", "urn:schemas-upnp-org:device:MediaServer:1"),
+                        raw_notify("ssdp:alive", "urn:schemas-upnp-org:device:MediaRenderer:2")];
+        let send_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        for payload in &payloads {
+            send_sock.send_to(payload.as_bytes(), ("127.0.0.1", port)).unwrap();
+        }
+
+        let (first, _) = recv.recv().unwrap();
+        let (second, _) = recv.recv().unwrap();
+        assert_eq!(first.get::<NT>(), Some(&NT(FieldMap::urn("schemas-upnp-org:device:MediaRenderer:1"))));
+        assert_eq!(second.get::<NT>(), Some(&NT(FieldMap::urn("schemas-upnp-org:device:MediaRenderer:2"))));
+        assert!(recv.try_recv().is_err());
+    }
+
+    #[test]
+    fn positive_listen_for_ends_without_messages() {
+        let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let start = Instant::now();
+        let config = Config::new().set_port(port);
+        let recv = NotifyListener::listen_with_config_for(&config, Duration::from_millis(200)).unwrap();
+
+        assert_eq!(recv.into_iter().count(), 0);
+        assert!(start.elapsed() < Duration::from_millis(1000));
+    }
 }
@@ -1,18 +1,29 @@
 use std::borrow::Cow;
-use std::fmt::Debug;
-use std::net::ToSocketAddrs;
-use std::time::Duration;
-use std::io;
+use std::cmp;
+use std::convert::TryFrom;
+use std::fmt::{self, Debug};
+use std::net::{ToSocketAddrs, SocketAddr, IpAddr, UdpSocket};
+use std::time::{Duration, Instant};
+use std::io::{self, Write};
+use std::thread::{self, JoinHandle};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender, Receiver, RecvTimeoutError};
 
-use hyper::header::{Header, HeaderFormat};
+use hyper::header::{Header, HeaderFormat, ContentLength, Date, HttpDate, UserAgent};
+use hyper::version::HttpVersion;
+use time;
 
-use error::SSDPResult;
-use header::{HeaderRef, HeaderMut, MX};
-use message::{self, MessageType, Listen, Config};
-use message::ssdp::SSDPMessage;
+use error::{SSDPResult, SSDPErrorKind};
+use header::{HeaderRef, HeaderMut, CacheControl, CacheDirective, Location, Man, MX, SearchPort, Server, ST,
+             USN};
+use message::{self, MessageType, Listen, Config, InterfaceSet, accept_response};
+use message::ssdp::{SSDPMessage, ParsePolicy, Deviation};
 use message::multicast::{self, Multicast};
-use receiver::{SSDPReceiver, FromRawSSDP};
+use net::connector::UdpConnector;
+use receiver::{self, SSDPReceiver, FromRawSSDP, SocketAdder};
 use net;
+use FieldMap;
 
 
 /// Overhead to add to device response times to account for transport time.
@@ -21,8 +32,23 @@ const NETWORK_TIMEOUT_OVERHEAD: u8 = 1;
 /// Devices are required to respond within 1 second of receiving unicast message.
 const DEFAULT_UNICAST_TIMEOUT: u8 = 1 + NETWORK_TIMEOUT_OVERHEAD;
 
+/// Wait bound used by search requests constructed for a specific target.
+const DEFAULT_SEARCH_MX: u8 = 3;
+
+/// Upper bound on the response delay, larger MX values are treated as this value.
+const MAX_RESPONSE_DELAY_SECS: u8 = 5;
+
+/// Most responses a `SearchResponder` holds on to while they wait out their delay.
+const MAX_PENDING_RESPONSES: usize = 256;
+
+/// Search target that all root devices respond to.
+const ROOT_DEVICE_TARGET: &'static str = "rootdevice";
+
+/// Header required in responses for backwards compatibility with UPnP 1.0.
+const EXT_HEADER_NAME: &'static str = "EXT";
+
 /// Search request that can be sent via unicast or multicast to devices on the network.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct SearchRequest {
     message: SSDPMessage,
 }
@@ -33,26 +59,259 @@ impl SearchRequest {
         SearchRequest { message: SSDPMessage::new(MessageType::Search) }
     }
 
+    /// Construct a new SearchRequest for the given search target.
+    ///
+    /// The `Man` header and a default `MX` header are filled in so the request
+    /// can be multicast as is.
+    pub fn for_target(target: ST) -> SearchRequest {
+        let mut request = SearchRequest::new();
+
+        request.set(Man);
+        request.set(MX(DEFAULT_SEARCH_MX));
+        request.set(target);
+
+        request
+    }
+
+    /// Construct a new SearchRequest for all devices and services (`ssdp:all`).
+    pub fn all() -> SearchRequest {
+        SearchRequest::for_target(ST::All)
+    }
+
+    /// Set the maximum time, in seconds, that devices should wait before responding.
+    ///
+    /// The value is not checked against the bounds of the `MX` header, see `MX::new`.
+    pub fn with_mx(mut self, wait_bound: u8) -> SearchRequest {
+        self.set(MX(wait_bound));
+        self
+    }
+
+    /// Set the user agent that is sent along with this request.
+    pub fn with_user_agent<S: Into<String>>(mut self, agent: S) -> SearchRequest {
+        self.set(UserAgent(agent.into()));
+        self
+    }
+
+    /// Serialize this search request into the bytes that sending it would put on the wire.
+    pub fn to_bytes(&self) -> SSDPResult<Vec<u8>> {
+        self.message.to_bytes()
+    }
+
+    /// Write the bytes that sending this search request would put on the wire to the writer.
+    pub fn write_to<W: Write>(&self, dst_writer: &mut W) -> SSDPResult<()> {
+        self.message.write_to(dst_writer)
+    }
+
+    /// Check that the headers required for a multicast search are present.
+    ///
+    /// All of the `Man`, `MX` and `ST` headers that are missing (or could not be
+    /// parsed) are reported together in a single `SSDPErrorKind::MissingHeaders`.
+    pub fn validate(&self) -> SSDPResult<()> {
+        let mut missing = Vec::new();
+
+        if self.get::<Man>().is_none() {
+            missing.push(Man::header_name());
+        }
+        if self.get::<MX>().is_none() {
+            missing.push(MX::header_name());
+        }
+        if self.get::<ST>().is_none() {
+            missing.push(ST::header_name());
+        }
+
+        match self.get::<ContentLength>() {
+            Some(&ContentLength(n)) if n != 0 => {
+                warn!("Search request has a Content-Length of {}, it will be sent as 0", n)
+            }
+            _ => (),
+        }
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(SSDPErrorKind::MissingHeaders(missing).into())
+        }
+    }
+
     /// Send this search request to a single host.
     ///
-    /// Currently this sends the unicast message on all available network
-    /// interfaces. This assumes that the network interfaces are operating
+    /// The message is sent from the interface the system routes the host through.
+    /// If that route can not be found it is sent on all available network
+    /// interfaces instead, which assumes that the network interfaces are operating
     /// on either different subnets or different ip address ranges.
+    ///
+    /// Prefer `unicast_from` when the local interface that can reach the host
+    /// is known, as without a route devices see one request per interface.
+    ///
+    /// Destinations that resolve to several addresses are sent to at the first one of
+    /// an IP version that the local interfaces have.
     pub fn unicast<A: ToSocketAddrs>(&mut self, dst_addr: A) -> SSDPResult<SSDPReceiver<SearchResponse>> {
-        let mode = try!(net::IpVersionMode::from_addr(&dst_addr));
-        let mut connectors = try!(message::all_local_connectors(None, &mode));
+        let dst_addr = net::addr_from_trait_with_mode(dst_addr, &message::local_mode(&Config::default())?)?;
+
+        self.unicast_addr(dst_addr)
+    }
+
+    /// Send this search request to a single host.
+    ///
+    /// Same as `unicast` except that the destination is never resolved through DNS.
+    pub fn unicast_addr(&mut self, dst_addr: SocketAddr) -> SSDPResult<SSDPReceiver<SearchResponse>> {
+        self.unicast_configured(dst_addr, &Config::default())
+    }
+
+    /// Send this search request to a single host, resolving the destination as the config allows.
+    ///
+    /// Same as `unicast`, except that only the interfaces selected by the config are sent on
+    /// and a host name destination fails with an error of kind `SSDPErrorKind::DnsForbidden`
+    /// when the config forbids DNS lookups.
+    pub fn unicast_with_config<A: net::DestAddr>(&mut self,
+                                                 dst_addr: A,
+                                                 config: &Config)
+                                                 -> SSDPResult<SSDPReceiver<SearchResponse>> {
+        let dst_addr = message::resolve_dst(dst_addr, config)?;
+
+        self.unicast_configured(dst_addr, config)
+    }
+
+    /// Send this search request to a single host on the interfaces selected by the config.
+    fn unicast_configured(&mut self,
+                          dst_addr: SocketAddr,
+                          config: &Config)
+                          -> SSDPResult<SSDPReceiver<SearchResponse>> {
+        let raw_connectors = self.unicast_sockets(dst_addr, config)?;
+
+        let opt_timeout = opt_unicast_timeout(self.get::<MX>());
+
+        let filter_config = config.clone();
+        Ok(SSDPReceiver::with_options(raw_connectors,
+                                       opt_timeout,
+                                       config.receiver_options(),
+                                       move |msg| accept_response(msg, &filter_config))?)
+    }
+
+    /// Send this search request to a single host, collecting responses for the given duration.
+    ///
+    /// The `MX` header is left untouched, a zero duration means responses are
+    /// collected until the receiver is dropped.
+    pub fn unicast_with_timeout<A: ToSocketAddrs>(&mut self,
+                                                  dst_addr: A,
+                                                  timeout: Duration)
+                                                  -> SSDPResult<SSDPReceiver<SearchResponse>> {
+        let dst_addr = net::addr_from_trait_with_mode(dst_addr, &message::local_mode(&Config::default())?)?;
+        let raw_connectors = self.unicast_sockets(dst_addr, &Config::default())?;
+
+        Ok(SSDPReceiver::new(raw_connectors, override_timeout(timeout))?)
+    }
+
+    /// Send this search request to a single host on all connectors, returning the underlying sockets.
+    fn unicast_sockets(&mut self, dst_addr: SocketAddr, config: &Config) -> SSDPResult<Vec<UdpSocket>> {
+        let mut connectors = message::unicast_connectors(&dst_addr, config)?;
 
         // Send On All Connectors
         for connector in &mut connectors {
-            try!(self.message.send(connector, &dst_addr));
+            try!(self.message.send_to_addr_limited(connector, dst_addr, config.max_message_size));
         }
 
         let mut raw_connectors = Vec::with_capacity(connectors.len());
         raw_connectors.extend(connectors.into_iter().map(|conn| conn.deconstruct()));
 
+        Ok(raw_connectors)
+    }
+
+    /// Send this search request to the multicast address, collecting responses for the given duration.
+    ///
+    /// The `MX` header is left untouched, so devices can be asked to respond
+    /// quickly while late responders are still collected. A zero duration means
+    /// responses are collected until the receiver is dropped.
+    pub fn multicast_with_timeout(&self,
+                                  config: &Config,
+                                  timeout: Duration)
+                                  -> SSDPResult<SSDPReceiver<SearchResponse>> {
+        let raw_connectors = self.with_config_mx(config).multicast_sockets(config)?;
+
+        let filter_config = config.clone();
+        Ok(SSDPReceiver::with_options(raw_connectors,
+                                       override_timeout(timeout),
+                                       config.receiver_options(),
+                                       move |msg| {
+                                           accept_response(msg, &filter_config)
+                                       })?)
+    }
+
+    /// Send this search request to the multicast address on all connectors, returning the underlying sockets.
+    fn multicast_sockets(&self, config: &Config) -> SSDPResult<Vec<UdpSocket>> {
+        self.validate()?;
+        let connectors = multicast::send(&self.message, config)?;
+
+        let mut raw_connectors = Vec::with_capacity(connectors.len());
+        raw_connectors.extend(connectors.into_iter().map(|conn| conn.deconstruct()));
+
+        Ok(raw_connectors)
+    }
+
+    /// This search request with the `MX` header filled in from the config, if it has none.
+    fn with_config_mx(&self, config: &Config) -> Cow<'_, SearchRequest> {
+        match config.mx {
+            Some(mx) if self.get::<MX>().is_none() => Cow::Owned(self.clone().with_mx(mx)),
+            _ => Cow::Borrowed(self),
+        }
+    }
+
+    /// Send this search request to the device that sent the given response.
+    ///
+    /// The request is sent to the port advertised in the `SearchPort` header of the
+    /// response, or the standard SSDP port if the header is absent or invalid.
+    pub fn unicast_to_device(&mut self,
+                             response: &SearchResponse,
+                             responder: IpAddr)
+                             -> SSDPResult<SSDPReceiver<SearchResponse>> {
+        self.unicast_addr(device_search_addr(response, responder))
+    }
+
+    /// Send this search request to a single host from the given local address.
+    ///
+    /// Exactly one socket is bound to the local address, the request is sent
+    /// once and the returned receiver listens on that socket alone.
+    pub fn unicast_from<A: ToSocketAddrs>(&mut self,
+                                          local: IpAddr,
+                                          dst_addr: A)
+                                          -> SSDPResult<SSDPReceiver<SearchResponse>> {
+        let mode = net::IpVersionMode::from_addr((local, 0))?;
+        let dst_addr = net::addr_from_trait_with_mode(dst_addr, &mode)?;
+        let mut connector = message::source_connector(local, &dst_addr, None, &Config::default())?;
+
+        self.message.send_to_addr(&mut connector, dst_addr)?;
+
         let opt_timeout = opt_unicast_timeout(self.get::<MX>());
 
-        Ok(try!(SSDPReceiver::new(raw_connectors, opt_timeout)))
+        Ok(SSDPReceiver::new(vec![connector.deconstruct()], opt_timeout)?)
+    }
+
+    /// Send this search request to the multicast address on a single local interface.
+    ///
+    /// The interface is identified by one of its local addresses, an error of kind
+    /// `SSDPErrorKind::InterfaceNotFound` is returned if no interface has that address.
+    pub fn multicast_on<A: Into<IpAddr>>(&self,
+                                         iface_addr: A,
+                                         config: &Config)
+                                         -> SSDPResult<SSDPReceiver<SearchResponse>> {
+        let request = self.with_config_mx(config);
+        request.validate()?;
+        let mcast_timeout = multicast_timeout(request.get::<MX>())?;
+
+        let iface_addr = iface_addr.into();
+        let connector = message::interface_connector(iface_addr, Some(config.multicast_ttl(iface_addr)?))?;
+        message::configure_connector(&connector, config)?;
+        let connectors = multicast::send_on(&request.message, vec![connector], config)?;
+
+        let raw_connectors = connectors.into_iter().map(|conn| conn.deconstruct()).collect();
+
+        let filter_config = config.clone();
+        Ok(SSDPReceiver::with_options(raw_connectors,
+                                       Some(mcast_timeout),
+                                       config.receiver_options(),
+                                       move |msg| {
+                                           accept_response(msg, &filter_config)
+                                       })?)
     }
 }
 
@@ -60,16 +319,469 @@ impl Multicast for SearchRequest {
     type Item = SSDPReceiver<SearchResponse>;
 
     fn multicast_with_config(&self, config: &Config) -> SSDPResult<Self::Item> {
-        let connectors = multicast::send(&self.message, config)?;
+        // The timeout follows whichever MX ends up on the wire
+        let request = self.with_config_mx(config);
+        let raw_connectors = request.multicast_sockets(config)?;
 
-        let mcast_timeout = try!(multicast_timeout(self.get::<MX>()));
-        let mut raw_connectors = Vec::with_capacity(connectors.len());
-        raw_connectors.extend(connectors.into_iter().map(|conn| conn.deconstruct()));
+        let mcast_timeout = try!(multicast_timeout(request.get::<MX>()));
+
+        let filter_config = config.clone();
+        Ok(SSDPReceiver::with_options(raw_connectors,
+                                       Some(mcast_timeout),
+                                       config.receiver_options(),
+                                       move |msg| {
+                                           accept_response(msg, &filter_config)
+                                       })?)
+    }
+}
+
+/// Search request that is re-multicast on a fixed interval from a background thread.
+///
+/// Rounds are sent through the same sockets, so responses from all rounds are merged
+/// into a single receiver that stays open until the search is stopped. The interfaces
+/// are refreshed before every round, interfaces that come up are searched and received
+/// on from then on.
+pub struct PeriodicSearch {
+    stop: Sender<()>,
+    cancel: Arc<AtomicBool>,
+    receiver: SSDPReceiver<SearchResponse>,
+}
+
+impl PeriodicSearch {
+    /// Multicast the search request now and then once every interval.
+    ///
+    /// Errors from the first round are returned, errors from later rounds are logged.
+    pub fn new(request: &SearchRequest, interval: Duration, config: &Config) -> SSDPResult<PeriodicSearch> {
+        let request = request.with_config_mx(config);
+        request.validate()?;
+
+        let mut interfaces = InterfaceSet::new(config)?;
+        multicast::send_through(&request.message, interfaces.connectors_mut(), config)?;
+
+        let raw_connectors = interfaces.connectors_mut()
+            .iter()
+            .map(|conn| conn.try_clone_socket())
+            .collect::<io::Result<_>>()?;
+        let filter_config = config.clone();
+        let receiver = SSDPReceiver::with_options(raw_connectors,
+                                                   None,
+                                                   config.receiver_options(),
+                                                   move |msg| {
+                                                       message::check_max_age(msg, &filter_config)
+                                                   })?;
+        let adder = receiver::socket_adder(&receiver);
+
+        let (stop_send, stop_recv) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let message = request.message.clone();
+        let config = config.clone();
+
+        let thread_cancel = cancel.clone();
+        thread::spawn(move || {
+            schedule_rounds(interval, &stop_recv, || {
+                match interfaces.refresh() {
+                    Ok(added) => receive_on(&adder, added),
+                    Err(err) => warn!("Failed to refresh periodic search interfaces: {}", err),
+                }
+
+                let connectors = interfaces.connectors_mut();
+                let result =
+                    multicast::send_through_cancellable(&message, connectors, &config, &thread_cancel);
+
+                if let Err(err) = result {
+                    warn!("Periodic search round failed: {}", err);
+                }
+            });
+
+            wake_receivers(interfaces.into_connectors());
+        });
+
+        Ok(PeriodicSearch {
+            stop: stop_send,
+            cancel,
+            receiver,
+        })
+    }
+
+    /// Receiver for the responses to all rounds of the search.
+    pub fn receiver(&self) -> &SSDPReceiver<SearchResponse> {
+        &self.receiver
+    }
+
+    /// Stop sending search rounds.
+    ///
+    /// A round that is in progress is abandoned before its next interface, so the
+    /// background thread exits within twice `multicast::SEND_TIMEOUT_MS`. Responses
+    /// that were already received can still be read, after which the receiver closes.
+    pub fn stop(&self) {
+        self.cancel.store(true, Ordering::SeqCst);
+
+        // The background thread may have already exited
+        let _ = self.stop.send(());
+    }
+}
+
+/// Address that unicast search requests for the device that sent the response should be sent to.
+fn device_search_addr(response: &SearchResponse, responder: IpAddr) -> SocketAddr {
+    let port = match response.get::<SearchPort>() {
+        Some(&SearchPort(n)) => n,
+        None => {
+            if let Some(raw) = response.get_raw(SearchPort::header_name()) {
+                warn!("Ignoring invalid {} header {:?} from {}",
+                      SearchPort::header_name(),
+                      raw,
+                      responder);
+            }
+
+            message::UPNP_MULTICAST_PORT
+        }
+    };
+
+    SocketAddr::new(responder, port)
+}
+
+/// Run a round every interval until a stop signal is received or the stop sender hangs up.
+fn schedule_rounds<F: FnMut()>(interval: Duration, stop: &Receiver<()>, mut round: F) {
+    while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(interval) {
+        round();
+    }
+}
+
+/// Start receiving responses on the connectors as well.
+fn receive_on(adder: &SocketAdder, connectors: &[UdpConnector]) {
+    for conn in connectors {
+        if let Err(err) = conn.try_clone_socket().and_then(|sock| adder.add(sock)) {
+            warn!("Failed to receive periodic search responses on a new interface: {}", err);
+        }
+    }
+}
+
+/// Unblock the receiver threads reading from the sockets so that they exit.
+fn wake_receivers(connectors: Vec<UdpConnector>) {
+    for sock in connectors.into_iter().map(|conn| conn.deconstruct()) {
+        let result = sock.set_read_timeout(Some(Duration::from_millis(1)))
+            .and_then(|_| sock.local_addr())
+            .and_then(|addr| sock.send_to(&[], addr));
+
+        if let Err(err) = result {
+            warn!("Failed to wake periodic search receiver: {}", err);
+        }
+    }
+}
 
-        Ok(try!(SSDPReceiver::new(raw_connectors, Some(mcast_timeout))))
+/// Target that a `SearchResponder` answers searches for.
+#[derive(Clone, Debug)]
+pub struct ResponderTarget {
+    /// Search target that is answered, searches for `ssdp:all` are answered as well.
+    pub st: FieldMap,
+    /// Unique service name sent in responses for this target.
+    pub usn: USN,
+    /// URL of the device description sent in responses for this target.
+    pub location: String,
+    /// How long responses for this target stay valid.
+    pub max_age: Duration,
+}
+
+impl ResponderTarget {
+    /// Construct a new ResponderTarget.
+    pub fn new<S: Into<String>>(st: FieldMap, usn: USN, location: S, max_age: Duration) -> ResponderTarget {
+        ResponderTarget {
+            st,
+            usn,
+            location: location.into(),
+            max_age,
+        }
+    }
+}
+
+/// Search target and unique service name pair that a device responds with.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ResponseSpec {
+    /// Search target of the response.
+    pub st: FieldMap,
+    /// Unique service name of the response.
+    pub usn: USN,
+}
+
+impl ResponseSpec {
+    fn new(st: FieldMap, usn: USN) -> ResponseSpec {
+        ResponseSpec { st, usn }
+    }
+}
+
+/// Registry of the devices and services offered by a root device.
+///
+/// Search targets are expanded into responses following the UPnP device
+/// architecture: the root device has three, each embedded device two, and each
+/// service one. Search targets are matched exactly, including their version.
+#[derive(Clone, Debug)]
+pub struct TargetSet {
+    uuid: String,
+    device_type: String,
+    embedded: Vec<(String, String)>,
+    services: Vec<(String, String)>,
+}
+
+impl TargetSet {
+    /// Construct a TargetSet for the root device with the given UUID and device type.
+    ///
+    /// The device type is the full URN without the `urn:` prefix, for example
+    /// `schemas-upnp-org:device:MediaServer:1`.
+    pub fn new<U, D>(uuid: U, device_type: D) -> TargetSet
+        where U: Into<String>,
+              D: Into<String>
+    {
+        TargetSet {
+            uuid: uuid.into(),
+            device_type: device_type.into(),
+            embedded: Vec::new(),
+            services: Vec::new(),
+        }
+    }
+
+    /// Add a device embedded in the root device.
+    pub fn with_embedded_device<U, D>(mut self, uuid: U, device_type: D) -> TargetSet
+        where U: Into<String>,
+              D: Into<String>
+    {
+        self.embedded.push((uuid.into(), device_type.into()));
+        self
+    }
+
+    /// Add a service offered by the root device or one of its embedded devices.
+    pub fn with_service<U, S>(mut self, device_uuid: U, service_type: S) -> TargetSet
+        where U: Into<String>,
+              S: Into<String>
+    {
+        self.services.push((device_uuid.into(), service_type.into()));
+        self
+    }
+
+    /// Responses that should be sent for a search with the given target.
+    pub fn matches(&self, st: &ST) -> Vec<ResponseSpec> {
+        let specs = self.all_specs();
+
+        match *st {
+            ST::All => specs,
+            ST::Target(ref target) => specs.into_iter().filter(|spec| spec.st == *target).collect(),
+        }
+    }
+
+    /// Every response the device would send for an `ssdp:all` search.
+    fn all_specs(&self) -> Vec<ResponseSpec> {
+        let mut specs = Vec::with_capacity(3 + self.embedded.len() * 2 + self.services.len());
+
+        let root_usn = USN(FieldMap::uuid(&self.uuid[..]), Some(FieldMap::upnp(ROOT_DEVICE_TARGET)));
+        specs.push(ResponseSpec::new(FieldMap::upnp(ROOT_DEVICE_TARGET), root_usn));
+        specs.extend(device_specs(&self.uuid, &self.device_type));
+
+        for (uuid, device_type) in &self.embedded {
+            specs.extend(device_specs(uuid, device_type));
+        }
+
+        for (uuid, service_type) in &self.services {
+            let service_usn = USN(FieldMap::uuid(&uuid[..]), Some(FieldMap::urn(&service_type[..])));
+            specs.push(ResponseSpec::new(FieldMap::urn(&service_type[..]), service_usn));
+        }
+
+        specs
+    }
+
+    /// Targets for a `SearchResponder` answering on behalf of the device.
+    pub fn responder_targets(&self, location: &str, max_age: Duration) -> Vec<ResponderTarget> {
+        self.all_specs()
+            .into_iter()
+            .map(|spec| ResponderTarget::new(spec.st, spec.usn, location, max_age))
+            .collect()
+    }
+}
+
+/// The two responses every device, root or embedded, is found by.
+fn device_specs(uuid: &str, device_type: &str) -> Vec<ResponseSpec> {
+    vec![ResponseSpec::new(FieldMap::uuid(uuid), USN(FieldMap::uuid(uuid), None)),
+         ResponseSpec::new(FieldMap::urn(device_type),
+                           USN(FieldMap::uuid(uuid), Some(FieldMap::urn(device_type))))]
+}
+
+/// Target along with whether or not it is currently being answered.
+struct ResponderEntry {
+    target: ResponderTarget,
+    enabled: bool,
+}
+
+/// Answers search requests for a set of targets from a background thread.
+///
+/// Responses are sent back to the source address of the request after a random
+/// delay within the `MX` of the request. The responder stops when `stop()` is
+/// called or when it is dropped.
+pub struct SearchResponder {
+    entries: Arc<Mutex<Vec<ResponderEntry>>>,
+    recv: Arc<SSDPReceiver<SearchRequest>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl SearchResponder {
+    /// Start answering search requests for the given targets on the multicast group.
+    ///
+    /// Responses are sent from the interface the request arrived on, as configured. At most
+    /// 256 responses wait out their delay at once, responses to further requests are dropped.
+    pub fn new(targets: Vec<ResponderTarget>, config: &Config) -> SSDPResult<SearchResponder> {
+        let recv = Arc::new(SearchListener::listen_with_config(config)?);
+
+        let entries = targets.into_iter()
+            .map(|target| {
+                ResponderEntry {
+                    target,
+                    enabled: true,
+                }
+            })
+            .collect();
+        let entries = Arc::new(Mutex::new(entries));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread_recv = recv.clone();
+        let thread_entries = entries.clone();
+        let thread_stop = stop.clone();
+        let thread_config = config.clone();
+        thread::spawn(move || {
+            respond_to_searches(&thread_recv, &thread_entries, &thread_stop, &thread_config)
+        });
+
+        Ok(SearchResponder {
+            entries,
+            recv,
+            stop,
+        })
+    }
+
+    /// Enable or disable answering searches for the given target.
+    ///
+    /// Returns false if the responder has no such target.
+    pub fn set_enabled(&self, st: &FieldMap, enabled: bool) -> bool {
+        let mut entries = self.entries.lock().unwrap();
+        let mut found = false;
+
+        for entry in entries.iter_mut().filter(|entry| entry.target.st == *st) {
+            entry.enabled = enabled;
+            found = true;
+        }
+
+        found
+    }
+
+    /// Stop answering search requests.
+    ///
+    /// Closing the listener wakes the background thread. Responses that are still waiting
+    /// out their delay are not sent, and due responses are abandoned between sends, so the
+    /// thread exits within twice `multicast::SEND_TIMEOUT_MS`.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.recv.close();
+    }
+}
+
+impl Drop for SearchResponder {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Answer search requests from the receiver until the responder is stopped.
+///
+/// Blocks on the receiver until the next response is due, or until a request arrives
+/// if none are pending. Closing the receiver wakes the thread, which then exits.
+fn respond_to_searches(recv: &SSDPReceiver<SearchRequest>,
+                       entries: &Mutex<Vec<ResponderEntry>>,
+                       stop: &AtomicBool,
+                       config: &Config) {
+    let mut pending: Vec<(Instant, SearchResponse, SocketAddr, IpAddr)> = Vec::new();
+
+    while !stop.load(Ordering::SeqCst) {
+        let received = match pending.iter().map(|&(send_at, _, _, _)| send_at).min() {
+            Some(next) => recv.recv_timeout_meta(next.saturating_duration_since(Instant::now())),
+            None => recv.recv_meta().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match received {
+            Ok(received) => {
+                let (request, src, local) = (received.msg, received.src, received.local.ip());
+                let send_at = Instant::now() + random_delay(request.get::<MX>());
+                let mut responses = matching_responses(&request, &entries.lock().unwrap());
+
+                let room = MAX_PENDING_RESPONSES.saturating_sub(pending.len());
+                if responses.len() > room {
+                    debug!("Dropping {} responses to search from {}, too many are pending",
+                           responses.len() - room,
+                           src);
+                    responses.truncate(room);
+                }
+
+                debug!("Scheduling {} responses to search from {}", responses.len(), src);
+                pending.extend(responses.into_iter().map(|response| (send_at, response, src, local)));
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        let now = Instant::now();
+        let (due, waiting): (Vec<_>, Vec<_>) = pending.into_iter()
+            .partition(|&(send_at, _, _, _)| send_at <= now);
+        pending = waiting;
+
+        for (_, mut response, dst, local) in due {
+            if stop.load(Ordering::SeqCst) {
+                return;
+            }
+
+            // Answer from the interface the search arrived on, where the platform reports it
+            let result = if local.is_unspecified() {
+                response.unicast_configured(dst, config)
+            } else {
+                response.unicast_from_configured(local, dst, config)
+            };
+            if let Err(err) = result {
+                warn!("Failed to send search response to {}: {}", dst, err);
+            }
+        }
     }
 }
 
+/// Build a response for each enabled entry that the search request targets.
+fn matching_responses(request: &SearchRequest, entries: &[ResponderEntry]) -> Vec<SearchResponse> {
+    if let Err(err) = request.validate() {
+        debug!("Ignoring invalid search request: {}", err);
+        return Vec::new();
+    }
+
+    let st = request.get::<ST>();
+
+    entries.iter()
+        .filter(|entry| entry.enabled)
+        .filter(|entry| match st {
+            Some(ST::All) => true,
+            Some(ST::Target(n)) => *n == entry.target.st,
+            None => false,
+        })
+        .filter_map(|entry| {
+            let target = &entry.target;
+
+            SearchResponse::reply_to(request, target.usn.clone(), &target.location, target.max_age)
+                .map(|mut response| {
+                    response.set(ST::Target(target.st.clone()));
+                    response
+                })
+                .ok()
+        })
+        .collect()
+}
+
+/// Random delay within the wait bound of a search request.
+fn random_delay(mx: Option<&MX>) -> Duration {
+    let max_secs = mx.map_or(0, |&MX(n)| cmp::min(n, MAX_RESPONSE_DELAY_SECS));
+
+    Duration::from_millis(message::random_millis(u64::from(max_secs) * 1000))
+}
+
 impl Default for SearchRequest {
     fn default() -> Self {
         SearchRequest::new()
@@ -80,7 +792,16 @@ impl Default for SearchRequest {
 fn multicast_timeout(mx: Option<&MX>) -> SSDPResult<Duration> {
     match mx {
         Some(&MX(n)) => Ok(Duration::new((n + NETWORK_TIMEOUT_OVERHEAD) as u64, 0)),
-        None => try!(Err("Multicast Searches Require An MX Header")),
+        None => Err(SSDPErrorKind::MissingHeader(MX::header_name()).into()),
+    }
+}
+
+/// Get the receiver timeout for an explicitly requested duration, where zero means no timeout.
+fn override_timeout(timeout: Duration) -> Option<Duration> {
+    if timeout == Duration::new(0, 0) {
+        None
+    } else {
+        Some(timeout)
     }
 }
 
@@ -92,18 +813,41 @@ fn opt_unicast_timeout(mx: Option<&MX>) -> Option<Duration> {
     }
 }
 
-impl FromRawSSDP for SearchRequest {
-    fn raw_ssdp(bytes: &[u8]) -> SSDPResult<SearchRequest> {
-        let message = try!(SSDPMessage::raw_ssdp(bytes));
+impl TryFrom<SSDPMessage> for SearchRequest {
+    type Error = SSDPMessage;
 
+    /// Fails with the message itself if it is not a search message.
+    fn try_from(message: SSDPMessage) -> Result<SearchRequest, SSDPMessage> {
         if message.message_type() != MessageType::Search {
-            try!(Err("SSDP Message Received Is Not A SearchRequest"))
+            Err(message)
         } else {
-            Ok(SearchRequest { message: message })
+            Ok(SearchRequest { message })
         }
     }
 }
 
+impl fmt::Display for SearchRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.message, f)
+    }
+}
+
+impl FromRawSSDP for SearchRequest {
+    fn raw_ssdp(bytes: &[u8]) -> SSDPResult<SearchRequest> {
+        SearchRequest::raw_ssdp_with_policy(bytes, &ParsePolicy::default())
+    }
+
+    fn raw_ssdp_with_policy(bytes: &[u8], policy: &ParsePolicy) -> SSDPResult<SearchRequest> {
+        let message = SSDPMessage::raw_ssdp_with_policy(bytes, policy)?;
+
+        SearchRequest::try_from(message).map_err(|_| "SSDP Message Received Is Not A SearchRequest".into())
+    }
+
+    fn deviations(&self) -> &[Deviation] {
+        self.message.deviations()
+    }
+}
+
 impl HeaderRef for SearchRequest {
     fn get<H>(&self) -> Option<&H>
         where H: Header + HeaderFormat
@@ -131,7 +875,7 @@ impl HeaderMut for SearchRequest {
 }
 
 /// Search response that can be received or sent via unicast to devices on the network.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct SearchResponse {
     message: SSDPMessage,
 }
@@ -142,59 +886,296 @@ impl SearchResponse {
         SearchResponse { message: SSDPMessage::new(MessageType::Response) }
     }
 
+    /// Construct a SearchResponse answering the given search request.
+    ///
+    /// The `ST` of the request is echoed back as is, expanding `ssdp:all` into one
+    /// response per target is left to the caller. Returns an error of kind
+    /// `SSDPErrorKind::MissingHeader` if the request has no `ST` header.
+    pub fn reply_to(request: &SearchRequest,
+                    usn: USN,
+                    location: &str,
+                    max_age: Duration)
+                    -> SSDPResult<SearchResponse> {
+        let st = match request.get::<ST>() {
+            Some(n) => n.clone(),
+            None => return Err(SSDPErrorKind::MissingHeader(ST::header_name()).into()),
+        };
+        let max_age = cmp::min(max_age.as_secs(), u64::from(u32::MAX)) as u32;
+
+        let mut response = SearchResponse::new();
+        response.set(CacheControl(vec![CacheDirective::MaxAge(max_age)]));
+        response.set(Date(HttpDate(time::now_utc())));
+        response.set_raw(EXT_HEADER_NAME, vec![Vec::new()]);
+        response.set(Location(location.to_owned()));
+        response.set(Server(message::default_server()));
+        response.set(st);
+        response.set(usn);
+        response.set(ContentLength(0));
+
+        Ok(response)
+    }
+
     /// Send this search response to a single host.
     ///
-    /// Currently this sends the unicast message on all available network
-    /// interfaces. This assumes that the network interfaces are operating
+    /// The message is sent from the interface the system routes the host through.
+    /// If that route can not be found it is sent on all available network
+    /// interfaces instead, which assumes that the network interfaces are operating
     /// on either different subnets or different ip address ranges.
+    ///
+    /// Prefer `unicast_from` when the interface the search was received on is
+    /// known, as without a route requesters see one response per interface.
+    ///
+    /// Destinations that resolve to several addresses are sent to at the first one of
+    /// an IP version that the local interfaces have.
     pub fn unicast<A: ToSocketAddrs>(&mut self, dst_addr: A) -> SSDPResult<()> {
-        let mode = try!(net::IpVersionMode::from_addr(&dst_addr));
-        let mut connectors = try!(message::all_local_connectors(None, &mode));
+        let dst_addr = net::addr_from_trait_with_mode(dst_addr, &message::local_mode(&Config::default())?)?;
 
-        let mut success_count = 0;
-        let mut error_count = 0;
-        // Send On All Connectors
-        for conn in &mut connectors {
-            // Some routing errors are expected, not all interfaces can find the target addresses
-            match self.message.send(conn, &dst_addr) {
-                Ok(_) => success_count += 1,
-                Err(_) => error_count += 1,
-            }
-        }
+        self.unicast_addr(dst_addr)
+    }
 
-        if success_count == 0 && error_count > 0 {
-            try!(Err(io::Error::last_os_error()));
-        }
+    /// Send this search response to a single host, resolving the destination as the config allows.
+    ///
+    /// Same as `unicast`, except that only the interfaces selected by the config are sent on
+    /// and a host name destination fails with an error of kind `SSDPErrorKind::DnsForbidden`
+    /// when the config forbids DNS lookups.
+    pub fn unicast_with_config<A: net::DestAddr>(&mut self, dst_addr: A, config: &Config) -> SSDPResult<()> {
+        let dst_addr = message::resolve_dst(dst_addr, config)?;
 
-        Ok(())
+        self.unicast_configured(dst_addr, config)
     }
-}
 
-impl Default for SearchResponse {
-    fn default() -> Self {
-        SearchResponse::new()
+    /// HTTP version the search response was received with.
+    ///
+    /// Only differs from HTTP/1.1 when parsed under `ParsePolicy::set_accept_http10_responses()`.
+    pub fn version(&self) -> HttpVersion {
+        self.message.version()
     }
-}
 
-/// Search listener that can listen for search messages sent within the network.
-pub struct SearchListener;
+    /// Serialize this search response into the bytes that sending it would put on the wire.
+    pub fn to_bytes(&self) -> SSDPResult<Vec<u8>> {
+        self.message.to_bytes()
+    }
 
-impl Listen for SearchListener {
-    type Message = SearchResponse;
-}
+    /// Write the bytes that sending this search response would put on the wire to the writer.
+    pub fn write_to<W: Write>(&self, dst_writer: &mut W) -> SSDPResult<()> {
+        self.message.write_to(dst_writer)
+    }
 
-impl FromRawSSDP for SearchResponse {
-    fn raw_ssdp(bytes: &[u8]) -> SSDPResult<SearchResponse> {
-        let message = try!(SSDPMessage::raw_ssdp(bytes));
+    /// Check that all headers required in a search response are present and valid.
+    ///
+    /// Every missing or invalid header is reported, `Date` is optional but must be
+    /// valid when present.
+    pub fn validate(&self) -> Result<(), Vec<SSDPErrorKind>> {
+        let mut errors = Vec::new();
+
+        match self.get::<CacheControl>() {
+            Some(directives) => {
+                let mut max_ages = directives.iter().filter_map(|directive| match *directive {
+                    CacheDirective::MaxAge(n) => Some(n),
+                    _ => None,
+                });
+
+                if max_ages.next().is_none() {
+                    errors.push(SSDPErrorKind::InvalidHeader(CacheControl::header_name(), "missing max-age"));
+                }
+            }
+            None => errors.push(missing_or_invalid::<CacheControl, _>(self)),
+        }
+        if self.get::<Date>().is_none() && self.get_raw(Date::header_name()).is_some() {
+            errors.push(missing_or_invalid::<Date, _>(self));
+        }
+        if self.get_raw(EXT_HEADER_NAME).is_none() {
+            errors.push(SSDPErrorKind::MissingHeader(EXT_HEADER_NAME));
+        }
+        if self.get::<Location>().is_none() {
+            errors.push(missing_or_invalid::<Location, _>(self));
+        }
+        if self.get::<Server>().is_none() {
+            errors.push(missing_or_invalid::<Server, _>(self));
+        }
+        if self.get::<ST>().is_none() {
+            errors.push(missing_or_invalid::<ST, _>(self));
+        }
+        if self.get::<USN>().is_none() {
+            errors.push(missing_or_invalid::<USN, _>(self));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Send this search response to a single host from the given local address.
+    ///
+    /// Exactly one socket is bound to the local address, so the requester sees a
+    /// single response whose source matches the interface it was answered from.
+    pub fn unicast_from(&mut self, local: IpAddr, dst_addr: SocketAddr) -> SSDPResult<()> {
+        self.unicast_from_configured(local, dst_addr, &Config::default())
+    }
+
+    /// Send this search response to a single host from the given local address, as configured.
+    fn unicast_from_configured(&mut self,
+                               local: IpAddr,
+                               dst_addr: SocketAddr,
+                               config: &Config)
+                               -> SSDPResult<()> {
+        let mut connector = message::source_connector(local, &dst_addr, None, config)?;
+
+        self.message.send_to_addr_limited(&mut connector, dst_addr, config.max_message_size)
+    }
+
+    /// Send this search response to a single host after a random delay within the `MX` of the request.
+    ///
+    /// The delay is capped at 5 seconds and waited out on a worker thread, so any
+    /// number of responses to different requesters can be pending at once. The response
+    /// is sent once, from the interface the system routes the host through. Dropping the
+    /// returned handle does not cancel the response.
+    pub fn unicast_delayed<A: ToSocketAddrs>(&mut self, dst_addr: A, mx: &MX) -> SSDPResult<DelayedResponse> {
+        let dst_addr = net::addr_from_trait_with_mode(dst_addr, &message::local_mode(&Config::default())?)?;
+        let local = net::route_local_addr(&dst_addr)?;
+
+        Ok(send_after(self.clone(), local.ip(), dst_addr, random_delay(Some(mx))))
+    }
+
+    /// Send this search response to a single host.
+    ///
+    /// Same as `unicast` except that the destination is never resolved through DNS.
+    pub fn unicast_addr(&mut self, dst_addr: SocketAddr) -> SSDPResult<()> {
+        self.unicast_configured(dst_addr, &Config::default())
+    }
+
+    /// Send this search response to a single host on the interfaces selected by the config.
+    fn unicast_configured(&mut self, dst_addr: SocketAddr, config: &Config) -> SSDPResult<()> {
+        let mut connectors = message::unicast_connectors(&dst_addr, config)?;
+
+        // Send On All Connectors
+        let mut results = Vec::with_capacity(connectors.len());
+        for conn in &mut connectors {
+            let result = self.message.send_to_addr_limited(conn, dst_addr, config.max_message_size);
+            results.push((conn.local_addr()?, result));
+        }
+
+        message::check_sends(results)
+    }
+}
+
+/// Error for a header that could not be retrieved, depending on whether it is present at all.
+fn missing_or_invalid<H, M>(message: &M) -> SSDPErrorKind
+    where H: Header + HeaderFormat,
+          M: HeaderRef
+{
+    if message.get_raw(H::header_name()).is_some() {
+        SSDPErrorKind::InvalidHeader(H::header_name(), "unparseable value")
+    } else {
+        SSDPErrorKind::MissingHeader(H::header_name())
+    }
+}
+
+/// Handle to a search response waiting out its delay on a worker thread.
+pub struct DelayedResponse {
+    cancel: Sender<()>,
+    thread: JoinHandle<SSDPResult<bool>>,
+}
+
+impl DelayedResponse {
+    /// Cancel the response if it has not been sent yet.
+    pub fn cancel(&self) {
+        // The worker thread may have already exited
+        let _ = self.cancel.send(());
+    }
+
+    /// Wait for the response to be sent, returning false if it was cancelled instead.
+    pub fn join(self) -> SSDPResult<bool> {
+        match self.thread.join() {
+            Ok(result) => result,
+            Err(_) => Err("Delayed Search Response Thread Panicked".into()),
+        }
+    }
+}
+
+/// Send the response from the local address to the destination once the delay has passed, unless
+/// cancelled first.
+fn send_after(mut response: SearchResponse,
+              local: IpAddr,
+              dst_addr: SocketAddr,
+              delay: Duration)
+              -> DelayedResponse {
+    let (cancel_send, cancel_recv) = mpsc::channel();
+
+    let thread = thread::spawn(move || {
+        let deadline = Instant::now() + delay;
+        match cancel_recv.recv_timeout(delay) {
+            Ok(()) => return Ok(false),
+            Err(RecvTimeoutError::Timeout) => (),
+            // The handle was dropped without cancelling, wait out the rest of the delay
+            Err(RecvTimeoutError::Disconnected) => {
+                thread::sleep(deadline.saturating_duration_since(Instant::now()))
+            }
+        }
+
+        trace!("Sending search response to {} after {:?}", dst_addr, delay);
+        response.unicast_from(local, dst_addr).map(|_| true)
+    });
+
+    DelayedResponse {
+        cancel: cancel_send,
+        thread,
+    }
+}
+
+impl Default for SearchResponse {
+    fn default() -> Self {
+        SearchResponse::new()
+    }
+}
+
+/// Search listener that can listen for search messages sent within the network.
+///
+/// Each request is delivered along with the address of the requester, which is
+/// where a `SearchResponse` should be unicast back to.
+pub struct SearchListener;
+
+impl Listen for SearchListener {
+    type Message = SearchRequest;
+}
+
+impl TryFrom<SSDPMessage> for SearchResponse {
+    type Error = SSDPMessage;
 
+    /// Fails with the message itself if it is not a response message.
+    fn try_from(message: SSDPMessage) -> Result<SearchResponse, SSDPMessage> {
         if message.message_type() != MessageType::Response {
-            try!(Err("SSDP Message Received Is Not A SearchResponse"))
+            Err(message)
         } else {
-            Ok(SearchResponse { message: message })
+            Ok(SearchResponse { message })
         }
     }
 }
 
+impl fmt::Display for SearchResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.message, f)
+    }
+}
+
+impl FromRawSSDP for SearchResponse {
+    fn raw_ssdp(bytes: &[u8]) -> SSDPResult<SearchResponse> {
+        SearchResponse::raw_ssdp_with_policy(bytes, &ParsePolicy::default())
+    }
+
+    fn raw_ssdp_with_policy(bytes: &[u8], policy: &ParsePolicy) -> SSDPResult<SearchResponse> {
+        let message = SSDPMessage::raw_ssdp_with_policy(bytes, policy)?;
+
+        SearchResponse::try_from(message).map_err(|_| "SSDP Message Received Is Not A SearchResponse".into())
+    }
+
+    fn deviations(&self) -> &[Deviation] {
+        self.message.deviations()
+    }
+}
+
 impl HeaderRef for SearchResponse {
     fn get<H>(&self) -> Option<&H>
         where H: Header + HeaderFormat
@@ -223,7 +1204,724 @@ impl HeaderMut for SearchResponse {
 
 #[cfg(test)]
 mod tests {
-    use header::MX;
+    use std::collections::HashSet;
+    use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::{self, TryRecvError};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use hyper::header::{ContentLength, Date};
+    use hyper::version::HttpVersion;
+
+    use error::SSDPErrorKind;
+    use header::{HeaderRef, HeaderMut, CacheControl, CacheDirective, Location, Man, MX, Server, ST, USN};
+    use message::{Config, Listen, Multicast, ParsePolicy, UPNP_MULTICAST_IPV4_ADDR};
+    use message::ssdp::mocks::MockConnector;
+    use net::connector::UdpConnector;
+    use net::IpVersionMode;
+    use receiver::{DropReason, SSDPReceiver, FromRawSSDP};
+    use FieldMap;
+    use super::{SearchRequest, SearchResponse, SearchListener, SearchResponder, ResponderTarget, ResponseSpec,
+                TargetSet};
+
+    #[test]
+    fn positive_periodic_rounds_share_receiver() {
+        let request = SearchRequest::all();
+        let mut connector = MockConnector::new();
+
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let recv = SSDPReceiver::<SearchResponse>::new(vec![recv_sock], None).unwrap();
+
+        let (stop_send, stop_recv) = mpsc::channel();
+        let mut rounds = 0;
+        super::schedule_rounds(Duration::from_millis(10), &stop_recv, || {
+            request.message.send(&mut connector, "127.0.0.1:1900").unwrap();
+
+            rounds += 1;
+            if rounds == 2 {
+                stop_send.send(()).unwrap();
+            }
+        });
+
+        let sent = String::from_utf8(connector.sent_bytes()).unwrap();
+        assert_eq!(rounds, 2);
+        assert_eq!(sent.matches("M-SEARCH").count(), 2);
+
+        match recv.try_recv() {
+            Err(TryRecvError::Empty) => (),
+            _ => panic!("Receiver Closed Between Rounds"),
+        }
+    }
+
+    #[test]
+    fn positive_periodic_wake_closes_receiver() {
+        let connector = UdpConnector::new("127.0.0.1:0", None).unwrap();
+        let recv_sock = connector.try_clone_socket().unwrap();
+        let recv = SSDPReceiver::<SearchResponse>::new(vec![recv_sock], None).unwrap();
+
+        super::wake_receivers(vec![connector]);
+
+        assert!(recv.recv().is_err());
+    }
+
+    fn response_with_raw(name: &str, value: &[u8]) -> SearchResponse {
+        let mut response = SearchResponse::new();
+        response.set_raw(name.to_owned(), vec![value.to_vec()]);
+
+        response
+    }
+
+    #[test]
+    fn positive_device_search_port() {
+        let mut response = response_with_raw("SEARCHPORT.UPNP.ORG", b"50000");
+        response.set_raw("TCPPORT.UPNP.ORG", vec![b"60000".to_vec()]);
+        let responder = "192.168.1.1".parse().unwrap();
+
+        assert_eq!(super::device_search_addr(&response, responder),
+                   "192.168.1.1:50000".parse().unwrap());
+    }
+
+    #[test]
+    fn positive_device_default_port() {
+        let response = response_with_raw("TCPPORT.UPNP.ORG", b"60000");
+        let responder = "192.168.1.1".parse().unwrap();
+
+        assert_eq!(super::device_search_addr(&response, responder),
+                   "192.168.1.1:1900".parse().unwrap());
+    }
+
+    #[test]
+    fn negative_device_invalid_port() {
+        let response = response_with_raw("SEARCHPORT.UPNP.ORG", b"80");
+        let responder = "192.168.1.1".parse().unwrap();
+
+        assert_eq!(super::device_search_addr(&response, responder),
+                   "192.168.1.1:1900".parse().unwrap());
+    }
+
+    fn reply_usn() -> USN {
+        USN(FieldMap::uuid("device"), Some(FieldMap::upnp("rootdevice")))
+    }
+
+    #[test]
+    fn positive_reply_to_round_trip() {
+        let request = SearchRequest::for_target(ST::Target(FieldMap::upnp("rootdevice")));
+        let response = SearchResponse::reply_to(&request,
+                                                reply_usn(),
+                                                "http://192.168.1.1/desc.xml",
+                                                Duration::from_secs(1800))
+            .unwrap();
+
+        let mut connector = MockConnector::new();
+        response.message.send(&mut connector, "127.0.0.1:1900").unwrap();
+        let parsed = SearchResponse::raw_ssdp(&connector.sent_bytes()[..]).unwrap();
+
+        assert_eq!(parsed.get::<ST>(), request.get::<ST>());
+        assert_eq!(parsed.get::<USN>(), Some(&reply_usn()));
+        assert_eq!(parsed.get::<Location>(), Some(&Location("http://192.168.1.1/desc.xml".to_owned())));
+        assert_eq!(parsed.get::<CacheControl>(),
+                   Some(&CacheControl(vec![CacheDirective::MaxAge(1800)])));
+        assert_eq!(parsed.get::<ContentLength>(), Some(&ContentLength(0)));
+        assert!(parsed.get::<Date>().is_some());
+        assert!(parsed.get::<Server>().is_some());
+        assert!(parsed.get_raw("EXT").is_some());
+    }
+
+    #[test]
+    fn negative_reply_to_missing_st() {
+        let request = SearchRequest::new();
+        let result = SearchResponse::reply_to(&request,
+                                              reply_usn(),
+                                              "http://192.168.1.1/desc.xml",
+                                              Duration::from_secs(1800));
+
+        match result.map_err(|err| err.0) {
+            Err(SSDPErrorKind::MissingHeader("ST")) => (),
+            _ => panic!("Expected MissingHeader Error"),
+        }
+    }
+
+    fn responder_entry(st: FieldMap, enabled: bool) -> super::ResponderEntry {
+        super::ResponderEntry {
+            target: ResponderTarget::new(st.clone(),
+                                         USN(FieldMap::uuid("device"), Some(st)),
+                                         "http://192.168.1.1/desc.xml",
+                                         Duration::from_secs(1800)),
+            enabled,
+        }
+    }
+
+    fn response_targets(request: &SearchRequest, entries: &[super::ResponderEntry]) -> Vec<ST> {
+        super::matching_responses(request, entries)
+            .iter()
+            .map(|response| response.get::<ST>().unwrap().clone())
+            .collect()
+    }
+
+    #[test]
+    fn positive_responder_matches_target() {
+        let entries = vec![responder_entry(FieldMap::upnp("rootdevice"), true),
+                           responder_entry(FieldMap::uuid("device"), true)];
+        let request = SearchRequest::for_target(ST::Target(FieldMap::uuid("device")));
+
+        assert_eq!(response_targets(&request, &entries),
+                   vec![ST::Target(FieldMap::uuid("device"))]);
+    }
+
+    #[test]
+    fn positive_responder_matches_all() {
+        let entries = vec![responder_entry(FieldMap::upnp("rootdevice"), true),
+                           responder_entry(FieldMap::uuid("device"), true),
+                           responder_entry(FieldMap::urn("schemas-upnp-org:service:Dimming:1"), false)];
+
+        assert_eq!(response_targets(&SearchRequest::all(), &entries),
+                   vec![ST::Target(FieldMap::upnp("rootdevice")), ST::Target(FieldMap::uuid("device"))]);
+    }
+
+    #[test]
+    fn negative_responder_disabled_target() {
+        let entries = vec![responder_entry(FieldMap::upnp("rootdevice"), false)];
+        let request = SearchRequest::for_target(ST::Target(FieldMap::upnp("rootdevice")));
+
+        assert!(response_targets(&request, &entries).is_empty());
+    }
+
+    #[test]
+    fn negative_responder_invalid_request() {
+        let entries = vec![responder_entry(FieldMap::upnp("rootdevice"), true)];
+        let mut request = SearchRequest::new();
+        request.set(ST::All);
+
+        assert!(response_targets(&request, &entries).is_empty());
+    }
+
+    #[test]
+    fn positive_random_delay_within_mx() {
+        for _ in 0..100 {
+            assert!(super::random_delay(Some(&MX(1))) <= Duration::from_secs(1));
+            assert!(super::random_delay(Some(&MX(120))) <= Duration::from_secs(5));
+        }
+
+        assert_eq!(super::random_delay(None), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn positive_responder_answers_loopback_search() {
+        let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let config = Config::new().set_port(port);
+        let target = ResponderTarget::new(FieldMap::upnp("rootdevice"),
+                                          USN(FieldMap::uuid("device"), Some(FieldMap::upnp("rootdevice"))),
+                                          "http://127.0.0.1/desc.xml",
+                                          Duration::from_secs(1800));
+        let _responder = SearchResponder::new(vec![target], &config).unwrap();
+
+        let mut request = SearchRequest::for_target(ST::Target(FieldMap::upnp("rootdevice"))).with_mx(1);
+        let recv = request.unicast(("127.0.0.1", port)).unwrap();
+
+        let usns = recv.into_iter().map(|(response, _)| response.get::<USN>().cloned()).collect::<Vec<_>>();
+
+        assert!(!usns.is_empty());
+        assert!(usns.iter().all(|usn| usn.as_ref().map(|usn| &usn.0) == Some(&FieldMap::uuid("device"))));
+    }
+
+    #[test]
+    fn negative_responder_over_max_message_size() {
+        let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let config = Config::new().set_port(port).set_max_message_size(64);
+        let target = ResponderTarget::new(FieldMap::upnp("rootdevice"),
+                                          USN(FieldMap::uuid("device"), Some(FieldMap::upnp("rootdevice"))),
+                                          "http://127.0.0.1/desc.xml",
+                                          Duration::from_secs(1800));
+        let _responder = SearchResponder::new(vec![target], &config).unwrap();
+
+        let mut request = SearchRequest::for_target(ST::Target(FieldMap::upnp("rootdevice"))).with_mx(1);
+        let recv = request.unicast(("127.0.0.1", port)).unwrap();
+
+        // The responder sends with its own config, which has no room for the response
+        assert_eq!(recv.into_iter().count(), 0);
+    }
+
+    #[test]
+    fn positive_responder_close_wakes_thread() {
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let recv = Arc::new(SSDPReceiver::<SearchRequest>::new(vec![sock], None).unwrap());
+        let entries = Arc::new(Mutex::new(vec![responder_entry(FieldMap::upnp("rootdevice"), true)]));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (done_send, done) = mpsc::channel();
+
+        let (thread_recv, thread_stop) = (recv.clone(), stop.clone());
+        thread::spawn(move || {
+            super::respond_to_searches(&thread_recv, &entries, &thread_stop, &Config::new());
+            done_send.send(()).unwrap();
+        });
+
+        thread::sleep(Duration::from_millis(50));
+        stop.store(true, Ordering::SeqCst);
+        recv.close_and_join(Duration::from_millis(1000)).unwrap();
+
+        done.recv_timeout(Duration::from_millis(500)).unwrap();
+    }
+
+    #[test]
+    fn positive_unicast_delayed_within_mx() {
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        recv_sock.set_read_timeout(Some(Duration::from_secs(3))).unwrap();
+        let recv_addr = recv_sock.local_addr().unwrap();
+
+        let start = Instant::now();
+        let delayed = SearchResponse::new().unicast_delayed(recv_addr, &MX(1)).unwrap();
+
+        let mut buf = [0u8; 1500];
+        recv_sock.recv_from(&mut buf).unwrap();
+
+        assert!(start.elapsed() < Duration::from_millis(1500));
+        assert!(delayed.join().unwrap());
+    }
+
+    #[test]
+    fn positive_send_after_dropped_handle() {
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        recv_sock.set_read_timeout(Some(Duration::from_secs(3))).unwrap();
+        let recv_addr = recv_sock.local_addr().unwrap();
+
+        let start = Instant::now();
+        drop(super::send_after(SearchResponse::new(),
+                               "127.0.0.1".parse().unwrap(),
+                               recv_addr,
+                               Duration::from_millis(200)));
+
+        let mut buf = [0u8; 1500];
+        let (_, src) = recv_sock.recv_from(&mut buf).unwrap();
+
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(src.ip(), recv_addr.ip());
+    }
+
+    #[test]
+    fn positive_send_after_cancelled() {
+        let delayed = super::send_after(SearchResponse::new(),
+                                        "127.0.0.1".parse().unwrap(),
+                                        "127.0.0.1:1900".parse().unwrap(),
+                                        Duration::from_secs(10));
+
+        let start = Instant::now();
+        delayed.cancel();
+
+        assert!(!delayed.join().unwrap());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    fn valid_response() -> SearchResponse {
+        let request = SearchRequest::for_target(ST::Target(FieldMap::upnp("rootdevice")));
+
+        SearchResponse::reply_to(&request,
+                                 reply_usn(),
+                                 "http://192.168.1.1/desc.xml",
+                                 Duration::from_secs(1800))
+            .unwrap()
+    }
+
+    #[test]
+    fn positive_validate_response() {
+        let response = valid_response();
+
+        assert!(response.validate().is_ok());
+        assert!(super::accept_response(&response, &Config::new().set_strict_responses(true)).is_ok());
+    }
+
+    #[test]
+    fn negative_validate_response_missing_usn() {
+        let raw = "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=1800\r\nEXT:\r\nLOCATION: \
+                   http://192.168.1.1/desc.xml\r\nSERVER: os/1 UPnP/1.1 product/1\r\nST: \
+                   upnp:rootdevice\r\n\r\n";
+        let response = SearchResponse::raw_ssdp(raw.as_bytes()).unwrap();
+
+        match response.validate() {
+            Err(ref errors) if errors.len() == 1 => {
+                match errors[0] {
+                    SSDPErrorKind::MissingHeader("USN") => (),
+                    _ => panic!("Expected Missing USN Header"),
+                }
+            }
+            _ => panic!("Expected A Single Error"),
+        }
+        assert!(super::accept_response(&response, &Config::new()).is_ok());
+        assert_eq!(super::accept_response(&response, &Config::new().set_strict_responses(true)),
+                   Err(DropReason::Filtered));
+    }
+
+    #[test]
+    fn negative_unicast_with_config_strict_responses() {
+        let device = UdpSocket::bind("127.0.0.1:0").unwrap();
+        device.set_read_timeout(Some(Duration::from_millis(1000))).unwrap();
+        let config = Config::new()
+            .set_strict_responses(true)
+            .set_interfaces(vec!["127.0.0.1".parse().unwrap()]);
+        let recv = SearchRequest::all().unicast_with_config(device.local_addr().unwrap(), &config).unwrap();
+
+        let (_, requester) = device.recv_from(&mut [0u8; 1500]).unwrap();
+        let missing_usn = "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=1800\r\nEXT:\r\nLOCATION: \
+                           http://192.168.1.1/desc.xml\r\nSERVER: os/1 UPnP/1.1 product/1\r\nST: \
+                           upnp:rootdevice\r\n\r\n";
+        device.send_to(missing_usn.as_bytes(), requester).unwrap();
+        device.send_to(&valid_response().to_bytes().unwrap(), requester).unwrap();
+
+        let (response, _) = recv.recv_timeout(Duration::from_millis(1000)).unwrap();
+        assert!(response.get::<USN>().is_some());
+        assert!(recv.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+
+    #[test]
+    fn negative_validate_response_bad_cache_control() {
+        let raw = "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=forever\r\nEXT:\r\nLOCATION: \
+                   http://192.168.1.1/desc.xml\r\nSERVER: os/1 UPnP/1.1 product/1\r\nST: \
+                   upnp:rootdevice\r\nUSN: uuid:device::upnp:rootdevice\r\n\r\n";
+        let response = SearchResponse::raw_ssdp(raw.as_bytes()).unwrap();
+
+        match response.validate() {
+            Err(ref errors) if errors.len() == 1 => {
+                match errors[0] {
+                    SSDPErrorKind::InvalidHeader("Cache-Control", _) => (),
+                    _ => panic!("Expected Invalid Cache-Control Header"),
+                }
+            }
+            _ => panic!("Expected A Single Error"),
+        }
+    }
+
+    #[test]
+    fn positive_display_response() {
+        let raw = "HTTP/1.1 200 OK\r\nCache-Control: max-age=1800\r\nEXT:\r\nst: upnp:rootdevice\r\nUSN: \
+                   uuid:device::upnp:rootdevice\r\n\r\n";
+        let response = SearchResponse::raw_ssdp(raw.as_bytes()).unwrap();
+
+        let expected = "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=1800\r\nEXT: \r\nST: \
+                        upnp:rootdevice\r\nUSN: uuid:device::upnp:rootdevice\r\n\r\n";
+        assert_eq!(response.to_string(), expected);
+    }
+
+    #[test]
+    // Hyper caches typed headers in cells, hashing only reads the raw values which never change
+    #[allow(clippy::mutable_key_type)]
+    fn positive_duplicate_responses_deduplicated() {
+        let first = "HTTP/1.1 200 OK\r\nST: upnp:rootdevice\r\nUSN: \
+                     uuid:device::upnp:rootdevice\r\nEXT:\r\n\r\n";
+        let second = "HTTP/1.1 200 OK\r\next:\r\nusn: uuid:device::upnp:rootdevice\r\nst: \
+                      upnp:rootdevice\r\n\r\n";
+        let other = "HTTP/1.1 200 OK\r\nST: upnp:rootdevice\r\nUSN: \
+                     uuid:other::upnp:rootdevice\r\nEXT:\r\n\r\n";
+
+        let responses = [first, second, other]
+            .iter()
+            .map(|raw| SearchResponse::raw_ssdp(raw.as_bytes()).unwrap())
+            .collect::<HashSet<_>>();
+
+        assert_eq!(responses.len(), 2);
+    }
+
+    #[test]
+    fn positive_http10_response_lenient() {
+        let raw = "HTTP/1.0 200 OK\r\nCACHE-CONTROL: max-age=1800\r\nEXT:\r\nLOCATION: \
+                   http://192.168.1.1/desc.xml\r\nST: upnp:rootdevice\r\nUSN: \
+                   uuid:device::upnp:rootdevice\r\n\r\n";
+        let policy = ParsePolicy::new().set_accept_http10_responses(true);
+        let response = SearchResponse::raw_ssdp_with_policy(raw.as_bytes(), &policy).unwrap();
+
+        assert_eq!(response.version(), HttpVersion::Http10);
+        assert_eq!(&response.get_raw("ST").unwrap()[0][..], &b"upnp:rootdevice"[..]);
+    }
+
+    #[test]
+    fn negative_http10_response_default_policy() {
+        let raw = "HTTP/1.0 200 OK\r\nCACHE-CONTROL: max-age=1800\r\nEXT:\r\nLOCATION: \
+                   http://192.168.1.1/desc.xml\r\nST: upnp:rootdevice\r\nUSN: \
+                   uuid:device::upnp:rootdevice\r\n\r\n";
+
+        assert!(SearchResponse::raw_ssdp(raw.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn negative_http10_request_lenient() {
+        let raw = "M-SEARCH * HTTP/1.0\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: \
+                   1\r\nST: upnp:rootdevice\r\n\r\n";
+        let policy = ParsePolicy::new().set_accept_http10_responses(true);
+
+        assert!(SearchRequest::raw_ssdp_with_policy(raw.as_bytes(), &policy).is_err());
+    }
+
+    #[test]
+    fn positive_listener_yields_requests() {
+        let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let recv = SearchListener::listen_with_config(&Config::new().set_port(port)).unwrap();
+
+        let send_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let raw = "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: \
+                   1\r\nST: upnp:rootdevice\r\n\r\n";
+        send_sock.send_to(raw.as_bytes(), ("127.0.0.1", port)).unwrap();
+
+        let (request, src) = recv.recv().unwrap();
+
+        assert_eq!(request.get::<ST>(), Some(&ST::Target(FieldMap::upnp("rootdevice"))));
+        assert_eq!(src, send_sock.local_addr().unwrap());
+    }
+
+    #[test]
+    fn positive_response_unicast_from() {
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        recv_sock.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        let recv_addr = recv_sock.local_addr().unwrap();
+
+        SearchResponse::new().unicast_from("127.0.0.1".parse().unwrap(), recv_addr).unwrap();
+
+        let mut buf = [0u8; 1500];
+        let (_, src) = recv_sock.recv_from(&mut buf).unwrap();
+
+        assert_eq!(src.ip(), "127.0.0.1".parse::<IpAddr>().unwrap());
+        assert!(recv_sock.recv_from(&mut buf).is_err());
+    }
+
+    #[test]
+    fn positive_response_unicast_with_config_literal() {
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        recv_sock.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        let dst = recv_sock.local_addr().unwrap().to_string();
+        let config = Config::new().set_forbid_dns(true);
+
+        SearchResponse::new().unicast_with_config(dst.as_str(), &config).unwrap();
+
+        let mut buf = [0u8; 1500];
+        recv_sock.recv_from(&mut buf).unwrap();
+    }
+
+    #[test]
+    fn positive_response_unicast_with_config_source_port() {
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        recv_sock.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let config = Config::new().set_source_port(port).set_interfaces(vec!["127.0.0.1".parse().unwrap()]);
+
+        SearchResponse::new().unicast_with_config(recv_sock.local_addr().unwrap(), &config).unwrap();
+
+        let mut buf = [0u8; 1500];
+        let (_, src) = recv_sock.recv_from(&mut buf).unwrap();
+
+        assert_eq!(src.port(), port);
+    }
+
+    #[test]
+    fn negative_unicast_with_config_over_max_message_size() {
+        let config = Config::new().set_max_message_size(16);
+
+        match SearchRequest::all().unicast_with_config("127.0.0.1:1900", &config).map_err(|err| err.0) {
+            Err(SSDPErrorKind::MessageTooLarge(_)) => (),
+            other => panic!("Unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn negative_unicast_with_config_dns_forbidden() {
+        let config = Config::new().set_forbid_dns(true);
+
+        match SearchRequest::all().unicast_with_config("ssdp.invalid:1900", &config).map_err(|err| err.0) {
+            Err(SSDPErrorKind::DnsForbidden(ref n)) => assert_eq!(n, "ssdp.invalid:1900"),
+            other => panic!("Unexpected result {:?}", other.map(|_| ())),
+        }
+        let result = SearchResponse::new().unicast_with_config(("ssdp.invalid", 1900), &config);
+        match result.map_err(|err| err.0) {
+            Err(SSDPErrorKind::DnsForbidden(ref n)) => assert_eq!(n, "ssdp.invalid"),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn negative_response_unicast_from_version_mismatch() {
+        let dst = "127.0.0.1:1900".parse().unwrap();
+        let result = SearchResponse::new().unicast_from("::1".parse().unwrap(), dst);
+
+        match result.map_err(|err| err.0) {
+            Err(SSDPErrorKind::IpVersionMismatch(..)) => (),
+            _ => panic!("Expected IpVersionMismatch Error"),
+        }
+    }
+
+    const ROOT_UUID: &'static str = "2fac1234-31f8-11b4-a222-08002b34c003";
+    const EMBEDDED_UUID: &'static str = "9ab0c000-f668-11de-9976-00a0de98381a";
+    const ROOT_DEVICE: &'static str = "schemas-upnp-org:device:MediaServer:1";
+    const EMBEDDED_DEVICE: &'static str = "schemas-upnp-org:device:Printer:1";
+    const ROOT_SERVICE: &'static str = "schemas-upnp-org:service:ContentDirectory:1";
+    const EMBEDDED_SERVICE: &'static str = "schemas-upnp-org:service:PrintBasic:1";
+
+    fn target_set() -> TargetSet {
+        TargetSet::new(ROOT_UUID, ROOT_DEVICE)
+            .with_embedded_device(EMBEDDED_UUID, EMBEDDED_DEVICE)
+            .with_service(ROOT_UUID, ROOT_SERVICE)
+            .with_service(EMBEDDED_UUID, EMBEDDED_SERVICE)
+    }
+
+    fn spec(st: FieldMap, uuid: &str, suffix: Option<FieldMap>) -> ResponseSpec {
+        ResponseSpec {
+            st,
+            usn: USN(FieldMap::uuid(uuid), suffix),
+        }
+    }
+
+    #[test]
+    fn positive_target_set_all() {
+        let expected = vec![spec(FieldMap::upnp("rootdevice"), ROOT_UUID, Some(FieldMap::upnp("rootdevice"))),
+                            spec(FieldMap::uuid(ROOT_UUID), ROOT_UUID, None),
+                            spec(FieldMap::urn(ROOT_DEVICE), ROOT_UUID, Some(FieldMap::urn(ROOT_DEVICE))),
+                            spec(FieldMap::uuid(EMBEDDED_UUID), EMBEDDED_UUID, None),
+                            spec(FieldMap::urn(EMBEDDED_DEVICE),
+                                 EMBEDDED_UUID,
+                                 Some(FieldMap::urn(EMBEDDED_DEVICE))),
+                            spec(FieldMap::urn(ROOT_SERVICE), ROOT_UUID, Some(FieldMap::urn(ROOT_SERVICE))),
+                            spec(FieldMap::urn(EMBEDDED_SERVICE),
+                                 EMBEDDED_UUID,
+                                 Some(FieldMap::urn(EMBEDDED_SERVICE)))];
+
+        assert_eq!(target_set().matches(&ST::All), expected);
+    }
+
+    #[test]
+    fn positive_target_set_root_device() {
+        assert_eq!(target_set().matches(&ST::Target(FieldMap::upnp("rootdevice"))),
+                   vec![spec(FieldMap::upnp("rootdevice"), ROOT_UUID, Some(FieldMap::upnp("rootdevice")))]);
+    }
+
+    #[test]
+    fn positive_target_set_device_uuids() {
+        assert_eq!(target_set().matches(&ST::Target(FieldMap::uuid(ROOT_UUID))),
+                   vec![spec(FieldMap::uuid(ROOT_UUID), ROOT_UUID, None)]);
+        assert_eq!(target_set().matches(&ST::Target(FieldMap::uuid(EMBEDDED_UUID))),
+                   vec![spec(FieldMap::uuid(EMBEDDED_UUID), EMBEDDED_UUID, None)]);
+    }
+
+    #[test]
+    fn positive_target_set_device_types() {
+        assert_eq!(target_set().matches(&ST::Target(FieldMap::urn(ROOT_DEVICE))),
+                   vec![spec(FieldMap::urn(ROOT_DEVICE), ROOT_UUID, Some(FieldMap::urn(ROOT_DEVICE)))]);
+        assert_eq!(target_set().matches(&ST::Target(FieldMap::urn(EMBEDDED_DEVICE))),
+                   vec![spec(FieldMap::urn(EMBEDDED_DEVICE),
+                             EMBEDDED_UUID,
+                             Some(FieldMap::urn(EMBEDDED_DEVICE)))]);
+    }
+
+    #[test]
+    fn positive_target_set_service_types() {
+        assert_eq!(target_set().matches(&ST::Target(FieldMap::urn(ROOT_SERVICE))),
+                   vec![spec(FieldMap::urn(ROOT_SERVICE), ROOT_UUID, Some(FieldMap::urn(ROOT_SERVICE)))]);
+        assert_eq!(target_set().matches(&ST::Target(FieldMap::urn(EMBEDDED_SERVICE))),
+                   vec![spec(FieldMap::urn(EMBEDDED_SERVICE),
+                             EMBEDDED_UUID,
+                             Some(FieldMap::urn(EMBEDDED_SERVICE)))]);
+    }
+
+    #[test]
+    fn negative_target_set_unoffered() {
+        let set = target_set();
+
+        assert!(set.matches(&ST::Target(FieldMap::urn("schemas-upnp-org:service:Dimming:1"))).is_empty());
+        assert!(set.matches(&ST::Target(FieldMap::urn("schemas-upnp-org:device:MediaServer:2"))).is_empty());
+        assert!(set.matches(&ST::Target(FieldMap::uuid("00000000-0000-0000-0000-000000000000"))).is_empty());
+    }
+
+    #[test]
+    fn positive_target_set_responder_targets() {
+        let targets = target_set()
+            .responder_targets("http://192.168.1.1/desc.xml", Duration::from_secs(1800));
+
+        assert_eq!(targets.len(), 7);
+        assert!(targets.iter().all(|target| target.location == "http://192.168.1.1/desc.xml"));
+    }
+
+    #[test]
+    fn positive_for_target_headers() {
+        let request = SearchRequest::all();
+
+        assert_eq!(request.get::<Man>(), Some(&Man));
+        assert_eq!(request.get::<MX>(), Some(&MX(3)));
+        assert_eq!(request.get::<ST>(), Some(&ST::All));
+    }
+
+    fn missing_headers(request: &SearchRequest) -> Vec<&'static str> {
+        match request.validate().map_err(|err| err.0) {
+            Err(SSDPErrorKind::MissingHeaders(headers)) => headers,
+            other => panic!("Unexpected validation result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn positive_validate() {
+        SearchRequest::all().validate().unwrap();
+    }
+
+    #[test]
+    fn negative_validate_missing_st() {
+        let mut request = SearchRequest::new();
+        request.set(Man);
+        request.set(MX(3));
+
+        assert_eq!(missing_headers(&request), vec!["ST"]);
+    }
+
+    #[test]
+    fn negative_validate_missing_all() {
+        assert_eq!(missing_headers(&SearchRequest::new()), vec!["MAN", "MX", "ST"]);
+    }
+
+    /// Multicast the request to a loopback socket, returning the `MX` header it was sent with.
+    fn multicast_mx(request: &SearchRequest, config: Config) -> Option<MX> {
+        let loopback = Ipv4Addr::new(127, 0, 0, 1);
+        let recv_sock = UdpSocket::bind("0.0.0.0:0").unwrap();
+        recv_sock.join_multicast_v4(&UPNP_MULTICAST_IPV4_ADDR.parse().unwrap(), &loopback).unwrap();
+        recv_sock.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let config = config.set_port(recv_sock.local_addr().unwrap().port())
+            .set_mode(IpVersionMode::V4Only)
+            .set_interfaces(vec![IpAddr::V4(loopback)])
+            .set_include_loopback(true)
+            .set_multicast_loop(true);
+
+        let _responses = request.multicast_with_config(&config).unwrap();
+
+        let mut buf = [0u8; 1500];
+        let (len, _) = recv_sock.recv_from(&mut buf).unwrap();
+        SearchRequest::raw_ssdp(&buf[..len]).unwrap().get::<MX>().cloned()
+    }
+
+    #[test]
+    fn positive_multicast_config_mx_when_absent() {
+        let mut request = SearchRequest::new();
+        request.set(Man);
+        request.set(ST::All);
+
+        assert!(request.multicast_with_config(&Config::new().set_mode(IpVersionMode::V4Only)).is_err());
+        assert_eq!(multicast_mx(&request, Config::new().set_mx(7).unwrap()), Some(MX(7)));
+    }
+
+    #[test]
+    fn positive_multicast_explicit_mx_wins() {
+        let request = SearchRequest::all().with_mx(2);
+
+        assert_eq!(multicast_mx(&request, Config::new().set_mx(7).unwrap()), Some(MX(2)));
+    }
+
+    #[test]
+    fn positive_for_target_serialized() {
+        let request = SearchRequest::all().with_mx(5).with_user_agent("OS/1.0 UPnP/1.1 ssdp-rs/0.7");
+        let mut connector = MockConnector::new();
+
+        request.message.send(&mut connector, ("127.0.0.1", 0)).unwrap();
+
+        let sent_message = String::from_utf8(connector.sent_bytes()).unwrap();
+
+        assert!(sent_message.starts_with("M-SEARCH * HTTP/1.1\r\n"));
+        assert!(sent_message.contains("MAN: \"ssdp:discover\"\r\n"));
+        assert!(sent_message.contains("MX: 5\r\n"));
+        assert!(sent_message.contains("ST: ssdp:all\r\n"));
+        assert!(sent_message.contains("User-Agent: OS/1.0 UPnP/1.1 ssdp-rs/0.7\r\n"));
+    }
 
     #[test]
     fn positive_multicast_timeout() {
@@ -240,6 +1938,34 @@ mod tests {
         super::opt_unicast_timeout(None).unwrap();
     }
 
+    #[test]
+    fn positive_override_timeout() {
+        assert_eq!(super::override_timeout(Duration::from_secs(10)),
+                   Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn positive_zero_override_timeout() {
+        assert_eq!(super::override_timeout(Duration::new(0, 0)), None);
+    }
+
+    #[test]
+    fn positive_multicast_with_timeout_overrides_mx() {
+        let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let config = Config::new()
+            .set_port(port)
+            .set_mode(IpVersionMode::V4Only)
+            .set_interfaces(vec!["127.0.0.1".parse().unwrap()]);
+        let request = SearchRequest::all().with_mx(5);
+
+        let start = Instant::now();
+        let recv = request.multicast_with_timeout(&config, Duration::from_millis(300)).unwrap();
+
+        assert_eq!(recv.into_iter().count(), 0);
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
     #[test]
     #[should_panic]
     fn negative_multicast_timeout() {
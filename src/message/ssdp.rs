@@ -1,24 +1,19 @@
 use std::borrow::{Cow, ToOwned};
-use std::fmt::Debug;
-use std::io::Write;
+use std::convert::TryFrom;
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
 use std::net::{ToSocketAddrs, SocketAddr};
 
-use hyper::Url;
-use hyper::buffer::BufReader;
-use hyper::client::request::Request;
-use hyper::header::{Headers, Header, HeaderFormat, ContentLength, Host};
-use hyper::http::RawStatus;
-use hyper::http::h1::{self, Incoming};
-use hyper::method::Method;
+use hyper::header::{Headers, Header, HeaderFormat, ContentLength, Date, HttpDate, Host};
 use hyper::net::{NetworkConnector, NetworkStream};
-use hyper::server::response::Response;
-use hyper::status::StatusCode;
-use hyper::uri::RequestUri;
 use hyper::version::HttpVersion;
+use httparse;
+use time;
 
-use {SSDPResult, SSDPErrorKind};
+use {SSDPResult, SSDPError, SSDPErrorKind};
 use header::{HeaderRef, HeaderMut};
-use message::MessageType;
+use message::{MessageType, NotifyMessage, SearchRequest, SearchResponse, DEFAULT_MAX_MESSAGE_SIZE};
 use net;
 use receiver::FromRawSSDP;
 
@@ -26,18 +21,104 @@ use receiver::FromRawSSDP;
 /// Only Valid `SearchResponse` Code
 const VALID_RESPONSE_CODE: u16 = 200;
 
-/// Appended To Destination Socket Addresses For URLs
-const BASE_HOST_URL: &'static str = "http://";
+/// Largest message that fits in a single datagram on a minimum MTU IPv6 link.
+const SOFT_MAX_MESSAGE_SIZE: usize = 1232;
+
+/// Most headers a received message may have.
+const MAX_HEADERS: usize = 100;
 
 /// Case-Sensitive Method Names
 const NOTIFY_METHOD: &'static str = "NOTIFY";
 const SEARCH_METHOD: &'static str = "M-SEARCH";
 
+/// Leniency towards non-conforming messages when parsing.
+///
+/// The default policy is strict, each leniency has to be opted into.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParsePolicy {
+    pub require_host: bool,
+    pub accept_http10_responses: bool,
+    pub accept_root_uri: bool,
+}
+
+impl ParsePolicy {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Accept requests without a `Host` header when set to false.
+    ///
+    /// Some embedded devices leave it out, accepted messages are logged at debug level.
+    pub fn set_require_host(mut self, value: bool) -> Self {
+        self.require_host = value;
+        self
+    }
+
+    /// Accept search responses with an HTTP/1.0 status line when set to true.
+    ///
+    /// Some old devices reply to searches with `HTTP/1.0 200 OK`, requests are always
+    /// required to be HTTP/1.1. See `SSDPMessage::version()` for the version received.
+    pub fn set_accept_http10_responses(mut self, value: bool) -> Self {
+        self.accept_http10_responses = value;
+        self
+    }
+
+    /// Treat a `/` request URI like `*` when set to true.
+    ///
+    /// Some smart TVs and older Windows stacks send `NOTIFY / HTTP/1.1`, any other path
+    /// is still rejected with `SSDPErrorKind::InvalidUri`.
+    pub fn set_accept_root_uri(mut self, value: bool) -> Self {
+        self.accept_root_uri = value;
+        self
+    }
+}
+
+impl Default for ParsePolicy {
+    fn default() -> Self {
+        ParsePolicy {
+            require_host: true,
+            accept_http10_responses: false,
+            accept_root_uri: false,
+        }
+    }
+}
+
+/// Deviation from the standard that a `ParsePolicy` tolerated in a received message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Deviation {
+    /// The request had no valid `Host` header.
+    MissingHost,
+    /// The search response had an HTTP/1.0 status line.
+    Http10Response,
+    /// The request URI was `/` instead of `*`.
+    RootUri,
+}
+
+impl Deviation {
+    /// Error that the message would have failed to parse with under the default policy.
+    pub fn error(&self) -> SSDPError {
+        match *self {
+            Deviation::MissingHost => SSDPErrorKind::MissingHeader(Host::header_name()).into(),
+            Deviation::Http10Response => SSDPErrorKind::InvalidHttpVersion.into(),
+            Deviation::RootUri => SSDPErrorKind::InvalidUri("/".to_owned()).into(),
+        }
+    }
+}
+
 /// Represents an SSDP method combined with both SSDP and HTTP headers.
+///
+/// Messages are equal, and hash the same, when their methods and their normalized headers
+/// are equal. Normalized headers ignore the order headers appear in and the case of their
+/// names, while their raw values are compared byte for byte, in the order they appear for
+/// repeated headers. The HTTP version is not taken into account.
+///
+/// Messages are parsed from raw bytes through `FromRawSSDP`.
 #[derive(Debug, Clone)]
 pub struct SSDPMessage {
     method: MessageType,
     headers: Headers,
+    version: HttpVersion,
+    deviations: Vec<Deviation>,
 }
 
 impl SSDPMessage {
@@ -46,6 +127,8 @@ impl SSDPMessage {
         SSDPMessage {
             method: message_type,
             headers: Headers::new(),
+            version: HttpVersion::Http11,
+            deviations: Vec::new(),
         }
     }
 
@@ -54,6 +137,62 @@ impl SSDPMessage {
         self.method
     }
 
+    /// Get the HTTP version the message was received with.
+    ///
+    /// Messages constructed locally are always HTTP/1.1, which is also the version they are sent with.
+    pub fn version(&self) -> HttpVersion {
+        self.version
+    }
+
+    /// Deviations from the standard that the parse policy tolerated when the message was received.
+    ///
+    /// Always empty for messages constructed locally or parsed with the default policy.
+    pub fn deviations(&self) -> &[Deviation] {
+        &self.deviations
+    }
+
+    /// Convert into a `NotifyMessage`, giving the message back if it is not a notify message.
+    pub fn into_notify(self) -> Result<NotifyMessage, SSDPMessage> {
+        NotifyMessage::try_from(self)
+    }
+
+    /// Convert into a `SearchRequest`, giving the message back if it is not a search request.
+    pub fn into_search(self) -> Result<SearchRequest, SSDPMessage> {
+        SearchRequest::try_from(self)
+    }
+
+    /// Convert into a `SearchResponse`, giving the message back if it is not a search response.
+    pub fn into_response(self) -> Result<SearchResponse, SSDPMessage> {
+        SearchResponse::try_from(self)
+    }
+
+    /// Serialize this message into the bytes that sending it would put on the wire.
+    ///
+    /// See `write_to` for how the serialized message differs from the message itself.
+    pub fn to_bytes(&self) -> SSDPResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes)?;
+
+        Ok(bytes)
+    }
+
+    /// Write the bytes that sending this message would put on the wire to the writer.
+    ///
+    /// Like when sending, a `Content-Length` of 0 is added as well as a `Date` for
+    /// responses without one. Requests are only given a `Host` header if the message
+    /// has one, since sending fills it in from the destination.
+    pub fn write_to<W: Write>(&self, dst_writer: &mut W) -> SSDPResult<()> {
+        let bytes = match self.method {
+            MessageType::Notify => request_bytes(NOTIFY_METHOD, &request_headers(&self.headers)),
+            MessageType::Search => request_bytes(SEARCH_METHOD, &request_headers(&self.headers)),
+            MessageType::Response => response_bytes(&response_headers(&self.headers)),
+        };
+
+        dst_writer.write_all(&bytes)?;
+
+        Ok(())
+    }
+
     /// Send this request to the given destination address using the given connector.
     ///
     /// The host header field will be taken care of by the underlying library.
@@ -62,84 +201,213 @@ impl SSDPMessage {
               S: Into<Box<NetworkStream + Send>>
     {
         let dst_sock_addr = try!(net::addr_from_trait(dst_addr));
+
+        self.send_to_addr(connector, dst_sock_addr)
+    }
+
+    /// Send this request to the given destination socket address using the given connector.
+    ///
+    /// Unlike `send`, this never performs a DNS lookup. Messages are limited to
+    /// `DEFAULT_MAX_MESSAGE_SIZE` bytes, see `send_to_addr_limited` for other limits.
+    pub fn send_to_addr<C, S>(&self, connector: &mut C, dst_sock_addr: SocketAddr) -> SSDPResult<()>
+        where C: NetworkConnector<Stream = S>,
+              S: Into<Box<NetworkStream + Send>>
+    {
+        self.send_to_addr_limited(connector, dst_sock_addr, DEFAULT_MAX_MESSAGE_SIZE)
+    }
+
+    /// Send this request to the given destination socket address if it serializes to at most
+    /// `max_size` bytes.
+    ///
+    /// Returns an error of kind `SSDPErrorKind::MessageTooLarge` without sending
+    /// anything if the serialized message is larger.
+    pub fn send_to_addr_limited<C, S>(&self,
+                                      connector: &mut C,
+                                      dst_sock_addr: SocketAddr,
+                                      max_size: usize)
+                                      -> SSDPResult<()>
+        where C: NetworkConnector<Stream = S>,
+              S: Into<Box<NetworkStream + Send>>
+    {
         match self.method {
             MessageType::Notify => {
                 trace!("Notify to: {:?}", dst_sock_addr);
-                send_request(NOTIFY_METHOD, &self.headers, connector, dst_sock_addr)
+                send_request(NOTIFY_METHOD, &self.headers, connector, dst_sock_addr, max_size)
             }
             MessageType::Search => {
                 trace!("Sending search request...");
-                send_request(SEARCH_METHOD, &self.headers, connector, dst_sock_addr)
+                send_request(SEARCH_METHOD, &self.headers, connector, dst_sock_addr, max_size)
             }
             MessageType::Response => {
                 trace!("Sending response to: {:?}", dst_sock_addr);
-                // This might need fixing for IPV6, passing down the IP loses the scope information
-                let dst_ip_string = dst_sock_addr.ip().to_string();
-                let dst_port = dst_sock_addr.port();
-
-                let net_stream = try!(connector.connect(&dst_ip_string[..], dst_port, "")).into();
-
-                send_response(&self.headers, net_stream)
+                send_response(&self.headers, connector, dst_sock_addr, max_size)
             }
         }
     }
 }
 
-#[allow(unused)]
 /// Send a request using the connector with the supplied method and headers.
+///
+/// A `Host` header for the destination is added up front, unless the headers already have one.
 fn send_request<C, S>(method: &str,
                       headers: &Headers,
                       connector: &mut C,
-                      dst_addr: SocketAddr)
+                      dst_addr: SocketAddr,
+                      max_size: usize)
                       -> SSDPResult<()>
     where C: NetworkConnector<Stream = S>,
           S: Into<Box<NetworkStream + Send>>
 {
-    trace!("Trying to parse url...");
-    let url = try!(url_from_addr(dst_addr));
-    trace!("Url: {}", url);
+    let mut temp_headers = Headers::new();
+    if !headers.has::<Host>() {
+        temp_headers.set(host_from_addr(dst_addr));
+    }
+    copy_headers(&request_headers(headers), &mut temp_headers);
 
-    let mut request = try!(Request::with_connector(Method::Extension(method.to_owned()), url, connector));
+    let bytes = request_bytes(method, &temp_headers);
+    check_size(bytes.len(), max_size)?;
 
-    trace!("Copying headers...");
-    copy_headers(headers, request.headers_mut());
-    trace!("Setting length");
-    request.headers_mut().set(ContentLength(0));
+    write_message(&bytes, connector, dst_addr)
+}
 
-    // Send Will Always Fail Within The UdpConnector Which Is Intended So That
-    // Hyper Does Not Block For A Response Since We Are Handling That Ourselves.
+/// Send an Ok response using the connector with the supplied headers.
+fn send_response<C, S>(headers: &Headers,
+                       connector: &mut C,
+                       dst_addr: SocketAddr,
+                       max_size: usize)
+                       -> SSDPResult<()>
+    where C: NetworkConnector<Stream = S>,
+          S: Into<Box<NetworkStream + Send>>
+{
+    let bytes = response_bytes(&response_headers(headers));
+    check_size(bytes.len(), max_size)?;
 
-    trace!("actual .send ...");
-    try!(request.start()).send();
+    write_message(&bytes, connector, dst_addr)
+}
+
+/// Write the serialized message to a stream for the destination, sending it as a single datagram.
+fn write_message<C, S>(bytes: &[u8], connector: &mut C, dst_addr: SocketAddr) -> SSDPResult<()>
+    where C: NetworkConnector<Stream = S>,
+          S: Into<Box<NetworkStream + Send>>
+{
+    // The scope id of link-local IPv6 destinations is passed down as a zone on the host
+    let dst_host = net::connect_host(&dst_addr);
+    let stream = connector.connect(&dst_host[..], dst_addr.port(), "http")?;
+    let mut net_stream: Box<NetworkStream + Send> = stream.into();
+
+    net_stream.write_all(bytes)?;
+    net_stream.flush()?;
 
     Ok(())
 }
 
-/// Send an Ok response on the Writer with the supplied headers.
-fn send_response<W>(headers: &Headers, mut dst_writer: W) -> SSDPResult<()>
+/// Copy of the request headers with the headers added when sending.
+fn request_headers(headers: &Headers) -> Headers {
+    let mut request_headers = Headers::new();
+
+    copy_headers(headers, &mut request_headers);
+    request_headers.set(ContentLength(0));
+
+    request_headers
+}
+
+/// Copy of the response headers with the headers added when sending.
+fn response_headers(headers: &Headers) -> Headers {
+    let mut response_headers = Headers::new();
+
+    copy_headers(headers, &mut response_headers);
+    response_headers.set(ContentLength(0));
+
+    // Responses are required to carry a Date, a caller provided one is left untouched
+    if !response_headers.has::<Date>() {
+        response_headers.set(Date(HttpDate(time::now_utc())));
+    }
+
+    response_headers
+}
+
+/// Serialize a request with the method and headers, using the `*` request uri.
+fn request_bytes(method: &str, headers: &Headers) -> Vec<u8> {
+    head_bytes(&format!("{} * {}", method, HttpVersion::Http11), headers, false)
+}
+
+/// Serialize an Ok response with the headers.
+fn response_bytes(headers: &Headers) -> Vec<u8> {
+    head_bytes(&response_line(HttpVersion::Http11), headers, false)
+}
+
+/// Status line of an Ok response with the version.
+fn response_line(version: HttpVersion) -> String {
+    format!("{} {} OK", version, VALID_RESPONSE_CODE)
+}
+
+/// Serialize the start line followed by the headers and the terminating blank line.
+///
+/// Repeated headers are written one line per value. Names are written in upper
+/// case if `upper_names` is set, otherwise in the case they were set with. Raw
+/// values are written byte for byte, even if they are not valid UTF-8.
+fn head_bytes(start_line: &str, headers: &Headers, upper_names: bool) -> Vec<u8> {
+    let mut head = Vec::new();
+
+    // Writing to a Vec can not fail
+    write_head(&mut head, start_line, headers, upper_names).unwrap();
+
+    head
+}
+
+/// Write the start line followed by the headers and the terminating blank line.
+fn write_head<W>(dst: &mut W, start_line: &str, headers: &Headers, upper_names: bool) -> io::Result<()>
     where W: Write
 {
-    let mut temp_headers = Headers::new();
+    write!(dst, "{}\r\n", start_line)?;
 
-    copy_headers(headers, &mut temp_headers);
-    temp_headers.set(ContentLength(0));
+    for view in headers.iter() {
+        let name = if upper_names {
+            Cow::Owned(view.name().to_ascii_uppercase())
+        } else {
+            Cow::Borrowed(view.name())
+        };
+
+        match headers.get_raw(view.name()) {
+            Some(values) => {
+                for value in values {
+                    write!(dst, "{}: ", name)?;
+                    dst.write_all(value)?;
+                    dst.write_all(b"\r\n")?;
+                }
+            }
+            None => write!(dst, "{}: {}\r\n", name, view.value_string())?,
+        }
+    }
 
-    let mut response = Response::new(&mut dst_writer as &mut Write, &mut temp_headers);
-    *response.status_mut() = StatusCode::Ok;
+    dst.write_all(b"\r\n")
+}
 
-    // Have to make sure response is destroyed here for lifetime issues with temp_headers
-    try!(try!(response.start()).end());
+/// Check the serialized size of a message against the limit, warning when it may not fit all links.
+fn check_size(size: usize, max_size: usize) -> SSDPResult<()> {
+    if size > max_size {
+        return Err(SSDPErrorKind::MessageTooLarge(size).into());
+    }
+
+    if size > SOFT_MAX_MESSAGE_SIZE {
+        warn!("Sending {} byte message which may not fit in a single datagram on all networks",
+              size);
+    }
 
     Ok(())
 }
 
-/// Convert the given address to a Url with a base of "udp://".
-fn url_from_addr(addr: SocketAddr) -> SSDPResult<Url> {
-    let str_url = BASE_HOST_URL.chars()
-        .chain(addr.to_string()[..].chars())
-        .collect::<String>();
+/// Host header for the destination address, with `IPv6` addresses enclosed in brackets.
+fn host_from_addr(addr: SocketAddr) -> Host {
+    let hostname = match addr {
+        SocketAddr::V4(n) => n.ip().to_string(),
+        SocketAddr::V6(n) => format!("[{}]", n.ip()),
+    };
 
-    Ok(try!(Url::parse(&str_url[..])))
+    Host {
+        hostname: hostname,
+        port: Some(addr.port()),
+    }
 }
 
 /// Copy the headers from the source header to the destination header.
@@ -149,9 +417,69 @@ fn copy_headers(src_headers: &Headers, dst_headers: &mut Headers) {
 
     // TODO: See if there is a way around calling to_owned() since set_raw
     // requires a Cow<'static, _> and we only have access to Cow<'a, _>.
+    // Raw values are copied line for line so repeated headers are not folded together.
     let iter = src_headers.iter();
     for view in iter {
-        dst_headers.set_raw(Cow::Owned(view.name().to_owned()), vec![view.value_string().into_bytes()]);
+        let raw = match src_headers.get_raw(view.name()) {
+            Some(n) => n.to_vec(),
+            None => vec![view.value_string().into_bytes()],
+        };
+
+        dst_headers.set_raw(Cow::Owned(view.name().to_owned()), raw);
+    }
+}
+
+impl fmt::Display for SSDPMessage {
+    /// Format the message as it appears on the wire, with header names in upper case.
+    ///
+    /// Unlike `to_bytes`, no headers are added and the version is the one the message
+    /// was received with. Header values that are not valid UTF-8 are written lossily.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let start_line = match self.method {
+            MessageType::Notify => format!("{} * {}", NOTIFY_METHOD, self.version),
+            MessageType::Search => format!("{} * {}", SEARCH_METHOD, self.version),
+            MessageType::Response => response_line(self.version),
+        };
+
+        f.write_str(&String::from_utf8_lossy(&head_bytes(&start_line, &self.headers, true)))
+    }
+}
+
+impl SSDPMessage {
+    /// Header names in lower case along with their raw values, sorted by name.
+    ///
+    /// Equality and hashing go by these alone. The headers cache typed values through interior
+    /// mutability, which leaves the raw values untouched, so messages are stable hash keys.
+    fn normalized_headers(&self) -> Vec<(String, Vec<Vec<u8>>)> {
+        let mut headers = self.headers
+            .iter()
+            .map(|view| {
+                let raw = match self.headers.get_raw(view.name()) {
+                    Some(n) => n.to_vec(),
+                    None => vec![view.value_string().into_bytes()],
+                };
+
+                (view.name().to_ascii_lowercase(), raw)
+            })
+            .collect::<Vec<_>>();
+
+        headers.sort();
+        headers
+    }
+}
+
+impl PartialEq for SSDPMessage {
+    fn eq(&self, other: &SSDPMessage) -> bool {
+        self.method == other.method && self.normalized_headers() == other.normalized_headers()
+    }
+}
+
+impl Eq for SSDPMessage {}
+
+impl Hash for SSDPMessage {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.method.hash(state);
+        self.normalized_headers().hash(state);
     }
 }
 
@@ -183,28 +511,23 @@ impl HeaderMut for SSDPMessage {
 
 impl FromRawSSDP for SSDPMessage {
     fn raw_ssdp(bytes: &[u8]) -> SSDPResult<SSDPMessage> {
-        let mut buf_reader = BufReader::new(bytes);
-
-        if let Ok(parts) = h1::parse_request(&mut buf_reader) {
-            let message_result = message_from_request(parts);
+        SSDPMessage::raw_ssdp_with_policy(bytes, &ParsePolicy::default())
+    }
 
-            log_message_result(&message_result, bytes);
-            message_result
+    fn raw_ssdp_with_policy(bytes: &[u8], policy: &ParsePolicy) -> SSDPResult<SSDPMessage> {
+        // Only responses start with the version, so the status or request line is parsed exactly once
+        let message_result = if bytes.starts_with(b"HTTP/") {
+            message_from_response(bytes, policy)
         } else {
-            match h1::parse_response(&mut buf_reader) {
-                Ok(parts) => {
-                    let message_result = message_from_response(parts);
+            message_from_request(bytes, policy)
+        };
 
-                    log_message_result(&message_result, bytes);
-                    message_result
-                },
-                Err(err) => {
-                    debug!("Failed parsing http response: {}, data: {}", err, String::from_utf8_lossy(bytes));
+        log_message_result(&message_result, bytes);
+        message_result
+    }
 
-                    Err(SSDPErrorKind::InvalidHttp(bytes.to_owned()).into())
-                }
-            }
-        } 
+    fn deviations(&self) -> &[Deviation] {
+        SSDPMessage::deviations(self)
     }
 }
 
@@ -216,52 +539,118 @@ fn log_message_result(result: &SSDPResult<SSDPMessage>, message: &[u8]) {
     }
 }
 
-/// Attempts to construct an `SSDPMessage` from the given request pieces.
-fn message_from_request(parts: Incoming<(Method, RequestUri)>) -> SSDPResult<SSDPMessage> {
-    let headers = parts.headers;
+/// Attempts to construct an `SSDPMessage` from the bytes of a request.
+fn message_from_request(bytes: &[u8], policy: &ParsePolicy) -> SSDPResult<SSDPMessage> {
+    let mut raw_headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut request = httparse::Request::new(&mut raw_headers);
+    check_complete(request.parse(bytes), bytes)?;
 
-    try!(validate_http_version(parts.version));
-    try!(validate_http_host(&headers));
+    let headers = headers_from_raw(request.headers);
+    let version = http_version(request.version);
 
-    match parts.subject {
-        (Method::Extension(n), RequestUri::Star) => {
-            match &n[..] {
-                NOTIFY_METHOD => {
-                    Ok(SSDPMessage {
-                        method: MessageType::Notify,
-                        headers: headers,
-                    })
-                }
-                SEARCH_METHOD => {
-                    Ok(SSDPMessage {
-                        method: MessageType::Search,
-                        headers: headers,
-                    })
-                }
-                _ => Err(SSDPErrorKind::InvalidMethod(n).into()),
-            }
+    let mut deviations = Vec::new();
+
+    try!(validate_http_version(version));
+    match validate_http_host(&headers) {
+        Err(ref err) if !policy.require_host => {
+            debug!("Accepting message despite {}", err);
+            deviations.push(Deviation::MissingHost);
+        }
+        result => result?,
+    }
+
+    let path = request.path.unwrap_or("");
+    if path != "*" {
+        if !(policy.accept_root_uri && path == "/") {
+            return Err(SSDPErrorKind::InvalidUri(path.to_owned()).into());
         }
-        (n, RequestUri::Star) => Err(SSDPErrorKind::InvalidMethod(n.to_string()).into()),
-        (_, RequestUri::AbsolutePath(n)) |
-        (_, RequestUri::Authority(n)) => Err(SSDPErrorKind::InvalidUri(n).into()),
-        (_, RequestUri::AbsoluteUri(n)) => Err(SSDPErrorKind::InvalidUri(n.into_string()).into()),
+        debug!("Accepting message with request uri '/'");
+        deviations.push(Deviation::RootUri);
     }
+
+    let method = match request.method.unwrap_or("") {
+        NOTIFY_METHOD => MessageType::Notify,
+        SEARCH_METHOD => MessageType::Search,
+        n => return Err(SSDPErrorKind::InvalidMethod(n.to_owned()).into()),
+    };
+
+    Ok(SSDPMessage {
+        method: method,
+        headers: headers,
+        version: version,
+        deviations: deviations,
+    })
 }
 
-/// Attempts to construct an `SSDPMessage` from the given response pieces.
-fn message_from_response(parts: Incoming<RawStatus>) -> SSDPResult<SSDPMessage> {
-    let RawStatus(status_code, _) = parts.subject;
-    let headers = parts.headers;
+/// Attempts to construct an `SSDPMessage` from the bytes of a response.
+fn message_from_response(bytes: &[u8], policy: &ParsePolicy) -> SSDPResult<SSDPMessage> {
+    let mut raw_headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut response = httparse::Response::new(&mut raw_headers);
+    check_complete(response.parse(bytes), bytes)?;
 
-    try!(validate_http_version(parts.version));
-    try!(validate_response_code(status_code));
+    let headers = headers_from_raw(response.headers);
+    let version = http_version(response.version);
+
+    let mut deviations = Vec::new();
+
+    if version == HttpVersion::Http10 && policy.accept_http10_responses {
+        debug!("Accepting {} search response", version);
+        deviations.push(Deviation::Http10Response);
+    } else {
+        try!(validate_http_version(version));
+    }
+    try!(validate_response_code(response.code.unwrap_or(0)));
 
     Ok(SSDPMessage {
         method: MessageType::Response,
         headers: headers,
+        version: version,
+        deviations: deviations,
     })
 }
 
+/// Check that the bytes held a complete HTTP head.
+fn check_complete(status: httparse::Result<usize>, bytes: &[u8]) -> SSDPResult<()> {
+    match status {
+        Ok(httparse::Status::Complete(_)) => Ok(()),
+        Ok(httparse::Status::Partial) => {
+            debug!("Failed parsing http message: incomplete, data: {}", String::from_utf8_lossy(bytes));
+
+            Err(SSDPErrorKind::InvalidHttp(bytes.to_owned()).into())
+        }
+        Err(err) => {
+            debug!("Failed parsing http message: {}, data: {}", err, String::from_utf8_lossy(bytes));
+
+            Err(SSDPErrorKind::InvalidHttp(bytes.to_owned()).into())
+        }
+    }
+}
+
+/// Collect the parsed headers, keeping repeated headers as separate raw values.
+///
+/// Names and values are copied out of the packet buffer since the message outlives it,
+/// but values are stored raw and a typed header is only parsed once it is asked for.
+fn headers_from_raw(raw_headers: &[httparse::Header]) -> Headers {
+    let mut headers = Headers::new();
+
+    for header in raw_headers {
+        let trim = header.value.iter().rev().take_while(|&&byte| byte == b' ').count();
+        let value = &header.value[..header.value.len() - trim];
+
+        headers.append_raw(header.name.to_owned(), value.to_vec());
+    }
+
+    headers
+}
+
+/// HTTP version of the minor version parsed from an HTTP/1.x message.
+fn http_version(minor: Option<u8>) -> HttpVersion {
+    match minor {
+        Some(0) => HttpVersion::Http10,
+        _ => HttpVersion::Http11,
+    }
+}
+
 /// Validate the HTTP version for an SSDP message.
 fn validate_http_version(version: HttpVersion) -> SSDPResult<()> {
     if version != HttpVersion::Http11 {
@@ -294,7 +683,7 @@ fn validate_response_code(code: u16) -> SSDPResult<()> {
 }
 
 #[cfg(test)]
-mod mocks {
+pub mod mocks {
     use std::cell::RefCell;
     use std::io::{self, Read, Write, ErrorKind};
     use std::net::SocketAddr;
@@ -306,28 +695,50 @@ mod mocks {
 
     pub struct MockConnector {
         pub receivers: RefCell<Vec<Receiver<Vec<u8>>>>,
+        pub hosts: RefCell<Vec<String>>,
     }
 
     impl MockConnector {
         pub fn new() -> MockConnector {
-            MockConnector { receivers: RefCell::new(Vec::new()) }
+            MockConnector {
+                receivers: RefCell::new(Vec::new()),
+                hosts: RefCell::new(Vec::new()),
+            }
+        }
+
+        /// Join everything written to all streams handed out by this connector.
+        pub fn sent_bytes(&self) -> Vec<u8> {
+            let mut buffer = Vec::new();
+
+            for recv in self.receivers.borrow().iter() {
+                for recv_buf in recv.try_iter() {
+                    buffer.extend(&recv_buf[..])
+                }
+            }
+
+            buffer
         }
     }
 
     impl NetworkConnector for MockConnector {
         type Stream = MockStream;
 
-        fn connect(&self, _: &str, _: u16, _: &str) -> error::Result<Self::Stream> {
+        fn connect(&self, host: &str, _: u16, _: &str) -> error::Result<Self::Stream> {
             let (send, recv) = mpsc::channel();
 
+            self.hosts.borrow_mut().push(host.to_owned());
             self.receivers.borrow_mut().push(recv);
 
-            Ok(MockStream { sender: send })
+            Ok(MockStream {
+                sender: send,
+                buf: Vec::new(),
+            })
         }
     }
 
     pub struct MockStream {
         sender: Sender<Vec<u8>>,
+        buf: Vec<u8>,
     }
 
     impl NetworkStream for MockStream {
@@ -350,25 +761,14 @@ mod mocks {
 
     impl Write for MockStream {
         fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-            // Hyper will generate a request with a /, we need to intercept that.
-            let mut buffer = vec![0u8; buf.len()];
-
-            let mut found = false;
-            for (src, dst) in buf.iter().zip(buffer.iter_mut()) {
-                if *src == b'/' && !found && buf[0] != b'H' {
-                    *dst = b'*';
-                    found = true;
-                } else {
-                    *dst = *src;
-                }
-            }
-
-            self.sender.send(buffer).unwrap();
+            self.buf.extend_from_slice(buf);
 
             Ok(buf.len())
         }
 
         fn flush(&mut self) -> io::Result<()> {
+            self.sender.send(self.buf.split_off(0)).unwrap();
+
             Ok(())
         }
     }
@@ -377,11 +777,18 @@ mod mocks {
 #[cfg(test)]
 mod tests {
     mod send {
+        use std::net::{SocketAddr, SocketAddrV6};
         use std::sync::mpsc::Receiver;
 
+        use hyper::header::{Date, HttpDate};
+        use time;
+
         use super::super::mocks::MockConnector;
         use super::super::SSDPMessage;
+        use error::SSDPErrorKind;
+        use header::{HeaderRef, HeaderMut};
         use message::MessageType;
+        use receiver::FromRawSSDP;
 
         fn join_buffers(recv_list: &[Receiver<Vec<u8>>]) -> Vec<u8> {
             let mut buffer = Vec::new();
@@ -431,6 +838,120 @@ mod tests {
             assert_eq!(&sent_message[..15], "HTTP/1.1 200 OK");
         }
 
+        #[test]
+        fn positive_slashes_in_headers_intact() {
+            let mut message = SSDPMessage::new(MessageType::Notify);
+            message.set_raw("Location", vec![b"http://192.168.1.1/desc.xml".to_vec()]);
+            message.set_raw("NT", vec![b"urn:schemas-upnp-org:device:Basic:1".to_vec()]);
+            let mut connector = MockConnector::new();
+
+            message.send(&mut connector, ("127.0.0.1", 1900)).unwrap();
+
+            let sent_message = String::from_utf8(join_buffers(&*connector.receivers.borrow())).unwrap();
+            let expected = "NOTIFY * HTTP/1.1\r\nHost: 127.0.0.1:1900\r\nLocation: \
+                            http://192.168.1.1/desc.xml\r\nNT: urn:schemas-upnp-org:device:Basic:1\r\n\
+                            Content-Length: 0\r\n\r\n";
+
+            assert_eq!(sent_message, expected);
+        }
+
+        #[test]
+        fn positive_sent_request_matches_bytes() {
+            let mut message = SSDPMessage::new(MessageType::Search);
+            message.set_raw("Host", vec![b"239.255.255.250:1900".to_vec()]);
+            message.set_raw("ST", vec![b"urn:schemas-upnp-org:service:Printer/1".to_vec()]);
+            let mut connector = MockConnector::new();
+
+            message.send(&mut connector, ("127.0.0.1", 0)).unwrap();
+
+            assert_eq!(join_buffers(&connector.receivers.borrow()), message.to_bytes().unwrap());
+        }
+
+        #[test]
+        fn positive_response_keeps_scope_id() {
+            let message = SSDPMessage::new(MessageType::Response);
+            let mut connector = MockConnector::new();
+            let dst_addr = SocketAddrV6::new("fe80::1".parse().unwrap(), 1900, 0, 3);
+
+            message.send_to_addr(&mut connector, SocketAddr::V6(dst_addr)).unwrap();
+
+            assert_eq!(&connector.hosts.borrow()[..], &["fe80::1%3".to_owned()]);
+        }
+
+        #[test]
+        fn positive_response_date_stamped() {
+            let message = SSDPMessage::new(MessageType::Response);
+            let mut connector = MockConnector::new();
+
+            message.send(&mut connector, ("127.0.0.1", 0)).unwrap();
+
+            let sent = SSDPMessage::raw_ssdp(&connector.sent_bytes()[..]).unwrap();
+
+            assert!(sent.get::<Date>().is_some());
+        }
+
+        #[test]
+        fn positive_response_date_preserved() {
+            let mut message = SSDPMessage::new(MessageType::Response);
+            message.set(Date(HttpDate(time::at_utc(time::Timespec::new(1_500_000_000, 0)))));
+            let mut connector = MockConnector::new();
+
+            message.send(&mut connector, ("127.0.0.1", 0)).unwrap();
+
+            let sent = SSDPMessage::raw_ssdp(&connector.sent_bytes()[..]).unwrap();
+
+            assert_eq!(&sent.get_raw("Date").unwrap()[0][..], &b"Fri, 14 Jul 2017 02:40:00 GMT"[..]);
+        }
+
+        fn vendor_message(value_len: usize) -> SSDPMessage {
+            let mut message = SSDPMessage::new(MessageType::Notify);
+            message.set_raw("X-Vendor", vec![vec![b'a'; value_len]]);
+
+            message
+        }
+
+        fn sent_len(message: &SSDPMessage) -> usize {
+            let mut connector = MockConnector::new();
+            message.send_to_addr_limited(&mut connector, "127.0.0.1:0".parse().unwrap(), usize::MAX).unwrap();
+
+            connector.sent_bytes().len()
+        }
+
+        #[test]
+        fn negative_message_too_large() {
+            let message = vendor_message(2000);
+            let mut connector = MockConnector::new();
+
+            match message.send(&mut connector, ("127.0.0.1", 0)).map_err(|err| err.0) {
+                Err(SSDPErrorKind::MessageTooLarge(n)) => assert_eq!(n, sent_len(&message)),
+                _ => panic!("Expected MessageTooLarge Error"),
+            }
+            assert!(connector.sent_bytes().is_empty());
+        }
+
+        #[test]
+        fn positive_message_at_size_limit() {
+            let message = vendor_message(100);
+            let size = sent_len(&message);
+            let mut connector = MockConnector::new();
+
+            let dst = "127.0.0.1:0".parse().unwrap();
+            message.send_to_addr_limited(&mut connector, dst, size).unwrap();
+            assert!(message.send_to_addr_limited(&mut connector, dst, size - 1).is_err());
+        }
+
+        #[test]
+        fn negative_response_too_large() {
+            let mut message = SSDPMessage::new(MessageType::Response);
+            message.set_raw("X-Vendor", vec![vec![b'a'; 2000]]);
+            let mut connector = MockConnector::new();
+
+            match message.send(&mut connector, ("127.0.0.1", 0)).map_err(|err| err.0) {
+                Err(SSDPErrorKind::MessageTooLarge(_)) => (),
+                _ => panic!("Expected MessageTooLarge Error"),
+            }
+        }
+
         #[test]
         fn positive_host_header() {
             let message = SSDPMessage::new(MessageType::Search);
@@ -445,10 +966,114 @@ mod tests {
     }
 
     mod parse {
-        use super::super::SSDPMessage;
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        use super::super::{Deviation, SSDPMessage, ParsePolicy};
+        use super::super::mocks::MockConnector;
+        use error::SSDPErrorKind;
         use header::HeaderRef;
+        use message::MessageType;
         use receiver::FromRawSSDP;
 
+        const HOST_LAST_MESSAGE: &'static str = "NOTIFY * HTTP/1.1\r\nNT: upnp:rootdevice\r\nNTS: \
+                                                 ssdp:alive\r\nUSN: uuid:device\r\nNTS: \
+                                                 ssdp:alive\r\nHOST: 239.255.255.250:1900\r\n\r\n";
+
+        #[test]
+        fn positive_host_last() {
+            let message = SSDPMessage::raw_ssdp(HOST_LAST_MESSAGE.as_bytes()).unwrap();
+
+            assert_eq!(&message.get_raw("Host").unwrap()[0][..], &b"239.255.255.250:1900"[..]);
+            assert_eq!(message.get_raw("NTS").unwrap().len(), 2);
+        }
+
+        #[test]
+        fn positive_send_preserves_order() {
+            let message = SSDPMessage::raw_ssdp(HOST_LAST_MESSAGE.as_bytes()).unwrap();
+            let mut connector = MockConnector::new();
+
+            message.send(&mut connector, ("127.0.0.1", 0)).unwrap();
+
+            let sent_message = String::from_utf8(connector.sent_bytes()).unwrap();
+            let expected = "NT: upnp:rootdevice\r\nNTS: ssdp:alive\r\nNTS: ssdp:alive\r\nUSN: \
+                            uuid:device\r\nHOST: 239.255.255.250:1900\r\n";
+
+            assert!(sent_message.contains(expected));
+        }
+
+        #[test]
+        fn positive_reordered_headers_equal() {
+            let reordered = "NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nNTS: ssdp:alive\r\nNTS: \
+                             ssdp:alive\r\nUSN: uuid:device\r\nNT: upnp:rootdevice\r\n\r\n";
+
+            let host_last = SSDPMessage::raw_ssdp(HOST_LAST_MESSAGE.as_bytes()).unwrap();
+            let host_first = SSDPMessage::raw_ssdp(reordered.as_bytes()).unwrap();
+
+            for name in &["Host", "NT", "NTS", "USN"] {
+                assert_eq!(host_last.get_raw(name), host_first.get_raw(name));
+            }
+        }
+
+        #[test]
+        fn positive_reordered_messages_equal() {
+            let reordered = "NOTIFY * HTTP/1.1\r\nhost: 239.255.255.250:1900\r\nNTS: ssdp:alive\r\nnts: \
+                             ssdp:alive\r\nUsn: uuid:device\r\nNT: upnp:rootdevice\r\n\r\n";
+
+            let host_last = SSDPMessage::raw_ssdp(HOST_LAST_MESSAGE.as_bytes()).unwrap();
+            let host_first = SSDPMessage::raw_ssdp(reordered.as_bytes()).unwrap();
+
+            assert_eq!(host_last, host_first);
+            assert_eq!(hash_of(&host_last), hash_of(&host_first));
+        }
+
+        #[test]
+        fn negative_different_values_not_equal() {
+            let other_usn = "NOTIFY * HTTP/1.1\r\nNT: upnp:rootdevice\r\nNTS: ssdp:alive\r\nUSN: \
+                             uuid:other\r\nNTS: ssdp:alive\r\nHOST: 239.255.255.250:1900\r\n\r\n";
+
+            let message = SSDPMessage::raw_ssdp(HOST_LAST_MESSAGE.as_bytes()).unwrap();
+            let other = SSDPMessage::raw_ssdp(other_usn.as_bytes()).unwrap();
+
+            assert!(message != other);
+        }
+
+        fn hash_of(message: &SSDPMessage) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            message.hash(&mut hasher);
+
+            hasher.finish()
+        }
+
+        #[test]
+        fn positive_trailing_spaces_trimmed() {
+            let raw_message = "NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900  \r\nNT: \
+                               upnp:rootdevice \r\n\r\n";
+            let message = SSDPMessage::raw_ssdp(raw_message.as_bytes()).unwrap();
+
+            assert_eq!(&message.get_raw("NT").unwrap()[0][..], &b"upnp:rootdevice"[..]);
+        }
+
+        #[test]
+        fn negative_incomplete_message() {
+            let raw_message = "NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\n";
+
+            match SSDPMessage::raw_ssdp(raw_message.as_bytes()).map_err(|err| err.0) {
+                Err(SSDPErrorKind::InvalidHttp(_)) => (),
+                _ => panic!("Expected Invalid HTTP"),
+            }
+        }
+
+        #[test]
+        fn negative_unknown_method() {
+            let raw_message = "SUBSCRIBE * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\n\r\n";
+
+            match SSDPMessage::raw_ssdp(raw_message.as_bytes()).map_err(|err| err.0) {
+                Err(SSDPErrorKind::InvalidMethod(ref method)) if method == "SUBSCRIBE" => (),
+                _ => panic!("Expected Invalid Method"),
+            }
+        }
+
         #[test]
         fn positive_valid_http() {
             let raw_message = "NOTIFY * HTTP/1.1\r\nHOST: 192.168.1.1\r\n\r\n";
@@ -480,6 +1105,14 @@ mod tests {
             SSDPMessage::raw_ssdp(raw_message.as_bytes()).unwrap();
         }
 
+        #[test]
+        #[should_panic]
+        fn negative_invalid_host() {
+            let raw_message = "NOTIFY * HTTP/1.1\r\nHOST: [FF02::C:1900\r\n\r\n";
+
+            SSDPMessage::raw_ssdp(raw_message.as_bytes()).unwrap();
+        }
+
         #[test]
         #[should_panic]
         fn negative_path_included() {
@@ -487,5 +1120,418 @@ mod tests {
 
             SSDPMessage::raw_ssdp(raw_message.as_bytes()).unwrap();
         }
+
+        #[test]
+        fn positive_no_host_lenient() {
+            let raw_message = "NOTIFY * HTTP/1.1\r\nNT: upnp:rootdevice\r\nUSN: uuid:device\r\n\r\n";
+            let policy = ParsePolicy::new().set_require_host(false);
+
+            let message = SSDPMessage::raw_ssdp_with_policy(raw_message.as_bytes(), &policy).unwrap();
+
+            assert_eq!(message.message_type(), MessageType::Notify);
+            assert!(message.get_raw("Host").is_none());
+            assert_eq!(&message.get_raw("NT").unwrap()[0][..], &b"upnp:rootdevice"[..]);
+            assert_eq!(&message.get_raw("USN").unwrap()[0][..], &b"uuid:device"[..]);
+            assert_eq!(message.deviations(), &[Deviation::MissingHost]);
+        }
+
+        #[test]
+        fn negative_no_host_default_policy() {
+            let raw_message = "NOTIFY * HTTP/1.1\r\nNT: upnp:rootdevice\r\n\r\n";
+
+            let policy = ParsePolicy::default();
+            assert!(SSDPMessage::raw_ssdp_with_policy(raw_message.as_bytes(), &policy).is_err());
+        }
+
+        #[test]
+        fn positive_root_uri_quirks() {
+            let policy = ParsePolicy::new().set_accept_root_uri(true);
+
+            for raw_message in &["NOTIFY / HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\n\r\n",
+                                 "M-SEARCH / HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\n\r\n"] {
+                let message = SSDPMessage::raw_ssdp_with_policy(raw_message.as_bytes(), &policy).unwrap();
+
+                assert_eq!(message.deviations(), &[Deviation::RootUri]);
+            }
+        }
+
+        #[test]
+        fn negative_root_uri_default_policy() {
+            let raw_message = "NOTIFY / HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\n\r\n";
+
+            assert!(SSDPMessage::raw_ssdp(raw_message.as_bytes()).is_err());
+        }
+
+        #[test]
+        fn negative_other_path_quirks() {
+            let raw_message = "NOTIFY /notify HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\n\r\n";
+            let policy = ParsePolicy::new().set_accept_root_uri(true);
+
+            assert!(SSDPMessage::raw_ssdp_with_policy(raw_message.as_bytes(), &policy).is_err());
+        }
+
+        #[test]
+        fn negative_path_included_lenient() {
+            let raw_message = "NOTIFY / HTTP/1.1\r\n\r\n";
+            let policy = ParsePolicy::new().set_require_host(false);
+
+            assert!(SSDPMessage::raw_ssdp_with_policy(raw_message.as_bytes(), &policy).is_err());
+        }
+    }
+
+    mod bytes {
+        use hyper::header::{ContentLength, Date, HttpDate};
+        use time;
+
+        use super::super::SSDPMessage;
+        use super::super::mocks::MockConnector;
+        use header::{HeaderMut, NT, NTS, ST, USN};
+        use message::MessageType;
+        use receiver::FromRawSSDP;
+        use FieldMap;
+
+        fn notify() -> SSDPMessage {
+            let mut message = SSDPMessage::new(MessageType::Notify);
+            message.set_raw("Host", vec![b"239.255.255.250:1900".to_vec()]);
+            message.set(NT(FieldMap::upnp("rootdevice")));
+            message.set(NTS::Alive);
+            message.set(USN(FieldMap::uuid("device"), None));
+
+            message
+        }
+
+        #[test]
+        fn positive_request_bytes() {
+            let bytes = notify().to_bytes().unwrap();
+
+            assert_eq!(String::from_utf8(bytes).unwrap(),
+                       "NOTIFY * HTTP/1.1\r\nHost: 239.255.255.250:1900\r\nNT: upnp:rootdevice\r\nNTS: \
+                        ssdp:alive\r\nUSN: uuid:device\r\nContent-Length: 0\r\n\r\n");
+        }
+
+        #[test]
+        fn positive_request_bytes_raw_value() {
+            let mut message = notify();
+            message.set_raw("X-Vendor", vec![b"caf\xe9".to_vec()]);
+
+            let bytes = message.to_bytes().unwrap();
+            assert!(bytes.windows(14).any(|line| line == b"X-Vendor: caf\xe9"));
+        }
+
+        #[test]
+        fn positive_request_bytes_match_sent() {
+            let message = notify();
+            let mut connector = MockConnector::new();
+
+            message.send(&mut connector, "239.255.255.250:1900").unwrap();
+
+            assert_eq!(message.to_bytes().unwrap(), connector.sent_bytes());
+        }
+
+        #[test]
+        fn positive_request_round_trip() {
+            let mut message = notify();
+            message.set(ContentLength(0));
+
+            let parsed = SSDPMessage::raw_ssdp(&message.to_bytes().unwrap()).unwrap();
+
+            assert_eq!(parsed, message);
+        }
+
+        #[test]
+        fn positive_response_round_trip() {
+            let mut message = SSDPMessage::new(MessageType::Response);
+            message.set(ST::All);
+            message.set(USN(FieldMap::uuid("device"), None));
+            message.set(ContentLength(0));
+            message.set(Date(HttpDate(time::now_utc())));
+
+            let parsed = SSDPMessage::raw_ssdp(&message.to_bytes().unwrap()).unwrap();
+
+            assert_eq!(parsed, message);
+        }
+
+        #[test]
+        fn positive_response_bytes_dated() {
+            let message = SSDPMessage::new(MessageType::Response);
+
+            let bytes = String::from_utf8(message.to_bytes().unwrap()).unwrap();
+
+            assert!(bytes.starts_with("HTTP/1.1 200 OK\r\n"));
+            assert!(bytes.contains("Date: "));
+            assert!(bytes.contains("Content-Length: 0\r\n"));
+            assert!(bytes.ends_with("\r\n\r\n"));
+        }
+    }
+
+    mod downcast {
+        use super::super::SSDPMessage;
+        use message::MessageType;
+        use receiver::FromRawSSDP;
+
+        const NOTIFY: &'static str = "NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nNT: \
+                                      upnp:rootdevice\r\nNTS: ssdp:alive\r\nUSN: uuid:device\r\n\r\n";
+        const SEARCH: &'static str = "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \
+                                      \"ssdp:discover\"\r\nMX: 3\r\nST: ssdp:all\r\n\r\n";
+        const RESPONSE: &'static str = "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=1800\r\nEXT:\r\nST: \
+                                        upnp:rootdevice\r\nUSN: uuid:device::upnp:rootdevice\r\n\r\n";
+
+        #[test]
+        fn positive_into_notify() {
+            let message = SSDPMessage::raw_ssdp(NOTIFY.as_bytes()).unwrap();
+
+            assert_eq!(message.message_type(), MessageType::Notify);
+            message.into_notify().unwrap();
+        }
+
+        #[test]
+        fn positive_into_search() {
+            let message = SSDPMessage::raw_ssdp(SEARCH.as_bytes()).unwrap();
+
+            assert_eq!(message.message_type(), MessageType::Search);
+            message.into_search().unwrap();
+        }
+
+        #[test]
+        fn positive_into_response() {
+            let message = SSDPMessage::raw_ssdp(RESPONSE.as_bytes()).unwrap();
+
+            assert_eq!(message.message_type(), MessageType::Response);
+            message.into_response().unwrap();
+        }
+
+        #[test]
+        fn negative_into_wrong_type() {
+            let message = SSDPMessage::raw_ssdp(NOTIFY.as_bytes()).unwrap();
+
+            let message = message.into_search().unwrap_err();
+            let message = message.into_response().unwrap_err();
+            assert_eq!(message.message_type(), MessageType::Notify);
+        }
+    }
+
+    mod display {
+        use super::super::{SSDPMessage, ParsePolicy};
+        use receiver::FromRawSSDP;
+
+        #[test]
+        fn positive_display_notify() {
+            let raw_message = "NOTIFY * HTTP/1.1\r\nHost: 239.255.255.250:1900\r\nnt: \
+                               upnp:rootdevice\r\nNTS: ssdp:alive\r\nUsn: \
+                               uuid:device::upnp:rootdevice\r\n\r\n";
+            let message = SSDPMessage::raw_ssdp(raw_message.as_bytes()).unwrap();
+
+            let expected = "NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nNT: upnp:rootdevice\r\nNTS: \
+                            ssdp:alive\r\nUSN: uuid:device::upnp:rootdevice\r\n\r\n";
+            assert_eq!(message.to_string(), expected);
+        }
+
+        #[test]
+        fn positive_display_repeated_headers() {
+            let raw_message = "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nST: \
+                               ssdp:all\r\nX-Vendor: one\r\nX-Vendor: two\r\n\r\n";
+            let message = SSDPMessage::raw_ssdp(raw_message.as_bytes()).unwrap();
+
+            let expected = "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nST: ssdp:all\r\nX-VENDOR: \
+                            one\r\nX-VENDOR: two\r\n\r\n";
+            assert_eq!(message.to_string(), expected);
+        }
+
+        #[test]
+        fn positive_display_received_version() {
+            let raw_message = "HTTP/1.0 200 OK\r\nExt:\r\nST: upnp:rootdevice\r\n\r\n";
+            let policy = ParsePolicy::new().set_accept_http10_responses(true);
+            let message = SSDPMessage::raw_ssdp_with_policy(raw_message.as_bytes(), &policy).unwrap();
+
+            assert_eq!(message.to_string(), "HTTP/1.0 200 OK\r\nEXT: \r\nST: upnp:rootdevice\r\n\r\n");
+        }
+    }
+
+    mod golden {
+        use std::env;
+        use std::fs::File;
+        use std::io::{Read, Write};
+        use std::net::SocketAddr;
+        use std::path::PathBuf;
+
+        use hyper::header::{CacheControl, CacheDirective, Date, HttpDate, Location, Server, UserAgent};
+        use time;
+
+        use super::super::SSDPMessage;
+        use super::super::mocks::MockConnector;
+        use header::{HeaderMut, BootID, ConfigID, Man, MX, NT, NTS, SearchPort, ST, USN};
+        use message::MessageType;
+        use FieldMap;
+
+        /// Set to rewrite the golden files with the current output instead of comparing against them.
+        const REGENERATE_VAR: &'static str = "SSDP_REGENERATE_GOLDEN";
+
+        fn check_golden(name: &str, message: &SSDPMessage, dst_addr: &str) {
+            let dst_addr: SocketAddr = dst_addr.parse().unwrap();
+            let mut connector = MockConnector::new();
+
+            message.send_to_addr(&mut connector, dst_addr).unwrap();
+            let actual = connector.sent_bytes();
+
+            let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            path.push("tests");
+            path.push("golden");
+            path.push(format!("{}.txt", name));
+
+            if env::var_os(REGENERATE_VAR).is_some() {
+                File::create(&path).unwrap().write_all(&actual[..]).unwrap();
+                return;
+            }
+
+            let mut expected = Vec::new();
+            File::open(&path)
+                .and_then(|mut file| file.read_to_end(&mut expected))
+                .unwrap_or_else(|err| panic!("Failed To Read Golden File {}: {}", path.display(), err));
+
+            assert!(actual == expected,
+                    "Serialization Of {} Drifted From Golden File, Got:\n{}",
+                    name,
+                    String::from_utf8_lossy(&actual[..]));
+        }
+
+        fn notify(nts: NTS) -> SSDPMessage {
+            let mut message = SSDPMessage::new(MessageType::Notify);
+
+            message.set(NT(FieldMap::upnp("rootdevice")));
+            message.set(nts);
+            message.set(USN(FieldMap::uuid("2fac1234-31f8-11b4-a222-08002b34c003"),
+                            Some(FieldMap::upnp("rootdevice"))));
+            message.set(Location("http://192.168.1.10:8080/description.xml".to_owned()));
+            message.set(CacheControl(vec![CacheDirective::MaxAge(1800)]));
+            message.set(Server("Linux/3.14 UPnP/1.1 golden/1.0".to_owned()));
+            message.set(BootID(7));
+            message.set(ConfigID(1));
+
+            message
+        }
+
+        fn search_all_headers() -> SSDPMessage {
+            let mut message = SSDPMessage::new(MessageType::Search);
+
+            message.set(Man);
+            message.set(MX(3));
+            message.set(ST::Target(FieldMap::urn("schemas-upnp-org:device:MediaServer:1")));
+            message.set(UserAgent("Linux/3.14 UPnP/1.1 golden/1.0".to_owned()));
+
+            message
+        }
+
+        #[test]
+        fn golden_search_ipv4() {
+            check_golden("search_ipv4", &search_all_headers(), "239.255.255.250:1900");
+        }
+
+        #[test]
+        fn golden_search_ipv6() {
+            check_golden("search_ipv6", &search_all_headers(), "[ff02::c]:1900");
+        }
+
+        #[test]
+        fn golden_search_custom_port() {
+            check_golden("search_custom_port", &search_all_headers(), "192.168.1.10:50000");
+        }
+
+        #[test]
+        fn golden_notify_alive() {
+            check_golden("notify_alive", &notify(NTS::Alive), "239.255.255.250:1900");
+        }
+
+        #[test]
+        fn golden_notify_byebye() {
+            check_golden("notify_byebye", &notify(NTS::ByeBye), "[ff02::c]:1900");
+        }
+
+        #[test]
+        fn golden_notify_update() {
+            let mut message = notify(NTS::Update);
+            message.set(SearchPort(50000));
+
+            check_golden("notify_update", &message, "239.255.255.250:1900");
+        }
+
+        #[test]
+        fn golden_response_date_ext() {
+            let mut message = SSDPMessage::new(MessageType::Response);
+
+            message.set(CacheControl(vec![CacheDirective::MaxAge(1800)]));
+            message.set(Date(HttpDate(time::at_utc(time::Timespec::new(1_500_000_000, 0)))));
+            message.set_raw("EXT", vec![Vec::new()]);
+            message.set(Location("http://192.168.1.10:8080/description.xml".to_owned()));
+            message.set(ST::All);
+            message.set(USN(FieldMap::uuid("2fac1234-31f8-11b4-a222-08002b34c003"), None));
+
+            check_golden("response_date_ext", &message, "192.168.1.20:50000");
+        }
+
+        #[test]
+        fn golden_vendor_headers() {
+            let mut message = search_all_headers();
+
+            message.set_raw("x-AcMe-DEVICE", vec![b"Gadget".to_vec()]);
+            message.set_raw("01-NLS", vec![b"1a2b3c".to_vec()]);
+
+            check_golden("vendor_headers", &message, "[fe80::1]:1900");
+        }
+    }
+}
+
+#[cfg(all(test, feature = "unstable"))]
+mod bench {
+    use hyper::buffer::BufReader;
+    use hyper::header::Host;
+    use hyper::http::h1;
+    use test::{self, Bencher};
+
+    use super::SSDPMessage;
+    use receiver::FromRawSSDP;
+
+    const NOTIFY: &'static str = "NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nCACHE-CONTROL: \
+                                  max-age=1800\r\nLOCATION: http://192.168.1.1:49152/desc.xml\r\nNT: \
+                                  upnp:rootdevice\r\nNTS: ssdp:alive\r\nSERVER: Linux/4.4 UPnP/1.1 \
+                                  product/1.0\r\nUSN: \
+                                  uuid:2fac1234-31f8-11b4-a222-08002b34c003::upnp:rootdevice\r\n\r\n";
+
+    const RESPONSE: &'static str = "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=1800\r\nDATE: Sun, 06 Nov 1994 \
+                                    08:49:37 GMT\r\nEXT:\r\nLOCATION: http://192.168.1.1:49152/desc.xml\r\n\
+                                    SERVER: Linux/4.4 UPnP/1.1 product/1.0\r\nST: upnp:rootdevice\r\nUSN: \
+                                    uuid:2fac1234-31f8-11b4-a222-08002b34c003::upnp:rootdevice\r\n\r\n";
+
+    /// Parse the way messages were parsed before, trying hyper's request parser then its response parser.
+    fn hyper_h1_parse(bytes: &[u8]) -> bool {
+        let mut buf_reader = BufReader::new(bytes);
+
+        match h1::parse_request(&mut buf_reader) {
+            Ok(parts) => parts.headers.get::<Host>().is_some(),
+            Err(_) => h1::parse_response(&mut buf_reader).is_ok(),
+        }
+    }
+
+    #[bench]
+    fn parse_notify(b: &mut Bencher) {
+        b.bytes = NOTIFY.len() as u64;
+        b.iter(|| SSDPMessage::raw_ssdp(test::black_box(NOTIFY.as_bytes())).unwrap());
+    }
+
+    #[bench]
+    fn parse_response(b: &mut Bencher) {
+        b.bytes = RESPONSE.len() as u64;
+        b.iter(|| SSDPMessage::raw_ssdp(test::black_box(RESPONSE.as_bytes())).unwrap());
+    }
+
+    #[bench]
+    fn baseline_hyper_h1_notify(b: &mut Bencher) {
+        b.bytes = NOTIFY.len() as u64;
+        b.iter(|| assert!(hyper_h1_parse(test::black_box(NOTIFY.as_bytes()))));
+    }
+
+    #[bench]
+    fn baseline_hyper_h1_response(b: &mut Bencher) {
+        b.bytes = RESPONSE.len() as u64;
+        b.iter(|| assert!(hyper_h1_parse(test::black_box(RESPONSE.as_bytes()))));
     }
 }
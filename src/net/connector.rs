@@ -1,30 +1,65 @@
 use std::io;
 use std::net::{UdpSocket, ToSocketAddrs, SocketAddr, SocketAddrV4, SocketAddrV6};
 use std::str::FromStr;
+use std::time::Duration;
 
 use hyper::error;
 use hyper::net::NetworkConnector;
+use net2::UdpSocketExt;
 
 use net::sender::UdpSender;
 use net;
 
+/// Upper bound on how long sending a single message on a connector may block.
+pub const SEND_TIMEOUT_MS: u64 = 1000;
+
 /// A `UdpConnector` allows Hyper to obtain `NetworkStream` objects over `UdpSockets`
 /// so that Http messages created by Hyper can be sent over UDP instead of TCP.
 pub struct UdpConnector(UdpSocket);
 
 impl UdpConnector {
     /// Create a new UdpConnector that will be bound to the given local address.
-    pub fn new<A: ToSocketAddrs>(local_addr: A, _: Option<u32>) -> io::Result<UdpConnector> {
+    ///
+    /// The multicast ttl, if any, is applied with the option matching the address
+    /// family, the ttl for `IPv4` and the hop limit for `IPv6`.
+    pub fn new<A: ToSocketAddrs>(local_addr: A, multicast_ttl: Option<u32>) -> io::Result<UdpConnector> {
         let addr = try!(net::addr_from_trait(local_addr));
         debug!("Attempting to connect to {}", addr);
 
-        let udp = try!(UdpSocket::bind(addr));
+        UdpConnector::from_socket(try!(UdpSocket::bind(addr)), multicast_ttl)
+    }
+
+    /// Create a new UdpConnector bound to the given local address with `SO_REUSEADDR` set.
+    ///
+    /// Connectors on several interfaces, or several connectors on one interface, can
+    /// then share a fixed port.
+    pub fn new_reuse<A>(local_addr: A, multicast_ttl: Option<u32>) -> io::Result<UdpConnector>
+        where A: ToSocketAddrs
+    {
+        let addr = net::addr_from_trait(local_addr)?;
+        debug!("Attempting to connect to {} with reuse", addr);
+
+        UdpConnector::from_socket(net::bind_reuse(addr)?, multicast_ttl)
+    }
+
+    fn from_socket(udp: UdpSocket, multicast_ttl: Option<u32>) -> io::Result<UdpConnector> {
+        let addr = udp.local_addr()?;
+
+        // A wedged interface should never block a send, and with it shutdown, indefinitely
+        udp.set_write_timeout(Some(Duration::from_millis(SEND_TIMEOUT_MS)))?;
 
-        // TODO: This throws an invalid argument error
-        // if let Some(n) = multicast_ttl {
-        //     trace!("Setting ttl to {}", n);
-        //     try!(udp.set_multicast_ttl_v4(n));
-        // }
+        if let Some(n) = multicast_ttl {
+            trace!("Setting multicast ttl to {}", n);
+            match addr {
+                SocketAddr::V4(_) => udp.set_multicast_ttl_v4(n)?,
+                SocketAddr::V6(_) => udp.set_multicast_hops_v6(n)?,
+            }
+        }
+
+        // Multicast would otherwise follow the default route rather than leave through this interface
+        if let Err(err) = net::set_multicast_if(&udp, &addr) {
+            warn!("Failed to set the multicast interface of {}: {}", addr, err);
+        }
 
         Ok(UdpConnector(udp))
     }
@@ -33,6 +68,43 @@ impl UdpConnector {
         self.0.local_addr()
     }
 
+    /// Bound how long sending on the connector may block, replacing `SEND_TIMEOUT_MS`.
+    pub fn set_write_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.0.set_write_timeout(dur)
+    }
+
+    /// Set the hop limit of outgoing `IPv6` multicast packets.
+    pub fn set_multicast_hops_v6(&self, hops: u32) -> io::Result<()> {
+        self.0.set_multicast_hops_v6(hops)
+    }
+
+    /// Set whether outgoing multicast packets are looped back to the local host.
+    ///
+    /// The option matching the address family of the connector is set.
+    pub fn set_multicast_loop(&self, on: bool) -> io::Result<()> {
+        net::set_multicast_loop(&self.0, on)
+    }
+
+    /// Set whether the connector may send to broadcast addresses.
+    pub fn set_broadcast(&self, on: bool) -> io::Result<()> {
+        self.0.set_broadcast(on)
+    }
+
+    /// Mark outgoing packets with the DSCP value, see `net::set_dscp`.
+    pub fn set_dscp(&self, dscp: u8) -> io::Result<()> {
+        net::set_dscp(&self.0, dscp)
+    }
+
+    /// Set the receive and send buffer sizes of the connector, see `net::set_buffer_sizes`.
+    pub fn set_buffer_sizes(&self, recv: Option<usize>, send: Option<usize>) -> io::Result<()> {
+        net::set_buffer_sizes(&self.0, recv, send)
+    }
+
+    /// Create an independently owned handle to the underlying UdpSocket.
+    pub fn try_clone_socket(&self) -> io::Result<UdpSocket> {
+        self.0.try_clone()
+    }
+
     /// Destroy the UdpConnector and return the underlying UdpSocket.
     pub fn deconstruct(self) -> UdpSocket {
         self.0
@@ -52,16 +124,13 @@ impl NetworkConnector for UdpConnector {
                                                  port))
             }
             SocketAddr::V6(n) => {
-                let mut addr: SocketAddrV6 = if host.find('[') == Some(0) &&
-                                                host.rfind(']') == Some(host.len() - 1) {
-                    try!(FromStr::from_str(format!("{}:{}", host, port).as_str())
-                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err)))
-                } else {
-                    try!(FromStr::from_str(format!("[{}]:{}", host, port).as_str())
-                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err)))
-                };
-                addr.set_flowinfo(n.flowinfo());
-                addr.set_scope_id(n.scope_id());
+                let (ip, opt_scope_id) = net::parse_scoped_ipv6(host)?;
+
+                let mut addr = SocketAddrV6::new(ip, port, n.flowinfo(), 0);
+                // A zone on the host is the scope of the destination, which beats that of the local address
+                if net::needs_scope_id(addr.ip()) {
+                    addr.set_scope_id(opt_scope_id.unwrap_or_else(|| n.scope_id()));
+                }
                 SocketAddr::V6(addr)
             }
         };
@@ -69,3 +138,85 @@ impl NetworkConnector for UdpConnector {
         Ok(UdpSender::new(udp_sock, sock_addr))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use hyper::net::{NetworkConnector, NetworkStream};
+    use net2::UdpSocketExt;
+
+    use net;
+    use super::UdpConnector;
+
+    #[test]
+    #[cfg(unix)]
+    fn positive_multicast_if_follows_local_addr() {
+        let connector = UdpConnector::new("127.0.0.1:0", None).unwrap();
+
+        let sock = connector.try_clone_socket().unwrap();
+        assert_eq!(net::multicast_if_v4(&sock).unwrap(), "127.0.0.1".parse::<Ipv4Addr>().unwrap());
+    }
+
+    #[test]
+    fn positive_reuse_shares_port() {
+        let first = UdpConnector::new_reuse("127.0.0.1:0", None).unwrap();
+        let port = first.local_addr().unwrap().port();
+
+        let second = UdpConnector::new_reuse(("127.0.0.1", port), None).unwrap();
+
+        assert_eq!(second.local_addr().unwrap().port(), port);
+    }
+
+    #[test]
+    fn positive_multicast_ttl_v4_applied() {
+        let connector = UdpConnector::new("127.0.0.1:0", Some(4)).unwrap();
+
+        assert_eq!(connector.try_clone_socket().unwrap().multicast_ttl_v4().unwrap(), 4);
+    }
+
+    #[test]
+    fn positive_multicast_loop_round_trip() {
+        for &on in &[false, true] {
+            let connector = UdpConnector::new("127.0.0.1:0", None).unwrap();
+            connector.set_multicast_loop(on).unwrap();
+            assert_eq!(connector.try_clone_socket().unwrap().multicast_loop_v4().unwrap(), on);
+
+            let connector = UdpConnector::new("[::1]:0", None).unwrap();
+            connector.set_multicast_loop(on).unwrap();
+            assert_eq!(connector.try_clone_socket().unwrap().multicast_loop_v6().unwrap(), on);
+        }
+    }
+
+    #[test]
+    fn positive_multicast_ttl_v6_applied() {
+        let connector = UdpConnector::new("[::1]:0", Some(4)).unwrap();
+
+        assert_eq!(connector.try_clone_socket().unwrap().multicast_hops_v6().unwrap(), 4);
+    }
+
+    #[test]
+    fn positive_connect_keeps_host_scope_id() {
+        let connector = UdpConnector::new("[::1]:0", None).unwrap();
+
+        let mut sender = connector.connect("fe80::1%7", 1900, "").unwrap();
+
+        match sender.peer_addr().unwrap() {
+            SocketAddr::V6(n) => {
+                assert_eq!(n.ip(), &"fe80::1".parse::<::std::net::Ipv6Addr>().unwrap());
+                assert_eq!(n.port(), 1900);
+                assert_eq!(n.scope_id(), 7);
+            }
+            SocketAddr::V4(_) => panic!("Expected An IPv6 Destination"),
+        }
+    }
+
+    #[test]
+    fn positive_connect_bracketed_host() {
+        let connector = UdpConnector::new("[::1]:0", None).unwrap();
+
+        let mut sender = connector.connect("[::1]", 1900, "").unwrap();
+
+        assert_eq!(sender.peer_addr().unwrap(), "[::1]:1900".parse().unwrap());
+    }
+}
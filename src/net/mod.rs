@@ -3,19 +3,42 @@
 //! This module deals with primitives for working with external libraries to write
 //! data to UDP sockets as a stream, and read data from UDP sockets as packets.
 
+#[cfg(unix)]
+use std::ffi::CString;
+#[cfg(unix)]
+use std::cmp;
+#[cfg(target_os = "linux")]
+use std::collections::HashMap;
+use std::fmt;
 use std::io::{self, ErrorKind};
 use std::net::{ToSocketAddrs, UdpSocket};
-use std::net::{SocketAddr, IpAddr};
+use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6, IpAddr, Ipv4Addr, Ipv6Addr};
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+use std::str::FromStr;
+#[cfg(target_os = "linux")]
+use std::sync::Mutex;
+#[cfg(unix)]
+use std::time::Duration;
+#[cfg(target_os = "linux")]
+use std::time::Instant;
 
 #[cfg(not(windows))]
 use net2::unix::UnixUdpBuilderExt;
-use net2::UdpBuilder;
+use net2::{UdpBuilder, UdpSocketExt};
+#[cfg(target_os = "linux")]
+use get_if_addrs;
+
+use error::{SSDPError, SSDPResult};
 
 pub mod connector;
 pub mod packet;
 pub mod sender;
 
-#[derive(Copy, Clone)]
+/// IP versions that messages are sent and received over.
+///
+/// Parsed from and displayed as `v4`, `v6` or `any`, parsing ignores case.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum IpVersionMode {
     V4Only,
     V6Only,
@@ -29,6 +52,38 @@ impl IpVersionMode {
             SocketAddr::V6(_) => Ok(IpVersionMode::V6Only),
         }
     }
+
+    /// Whether the address is of an IP version allowed by the mode.
+    pub fn allows(&self, addr: &SocketAddr) -> bool {
+        match *self {
+            IpVersionMode::V4Only => addr.is_ipv4(),
+            IpVersionMode::V6Only => addr.is_ipv6(),
+            IpVersionMode::Any => true,
+        }
+    }
+}
+
+impl FromStr for IpVersionMode {
+    type Err = SSDPError;
+
+    fn from_str(s: &str) -> SSDPResult<IpVersionMode> {
+        match s.to_ascii_lowercase().as_str() {
+            "v4" => Ok(IpVersionMode::V4Only),
+            "v6" => Ok(IpVersionMode::V6Only),
+            "any" => Ok(IpVersionMode::Any),
+            _ => Err(format!("Invalid IP Version Mode: '{}'", s).into()),
+        }
+    }
+}
+
+impl fmt::Display for IpVersionMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            IpVersionMode::V4Only => "v4",
+            IpVersionMode::V6Only => "v6",
+            IpVersionMode::Any => "any",
+        })
+    }
 }
 
 /// Accept a type implementing `ToSocketAddrs` and tries to extract the first address.
@@ -41,6 +96,183 @@ pub fn addr_from_trait<A: ToSocketAddrs>(addr: A) -> io::Result<SocketAddr> {
     }
 }
 
+/// Accept a type implementing `ToSocketAddrs` and extract the first address of an IP version allowed by
+/// the mode.
+///
+/// Same as `addr_from_trait` for `IpVersionMode::Any`. Otherwise fails with `NotFound`, naming the
+/// resolved addresses, if none of them are of the requested IP version.
+pub fn addr_from_trait_with_mode<A: ToSocketAddrs>(addr: A, mode: &IpVersionMode) -> io::Result<SocketAddr> {
+    if let IpVersionMode::Any = *mode {
+        return addr_from_trait(addr);
+    }
+
+    let resolved = addr.to_socket_addrs()?.collect::<Vec<_>>();
+    match resolved.iter().find(|sock_addr| mode.allows(sock_addr)) {
+        Some(n) => Ok(*n),
+        None => {
+            let msg = format!("No {} Address Found Among {:?}", mode, resolved);
+            Err(io::Error::new(ErrorKind::NotFound, msg))
+        }
+    }
+}
+
+/// Local address the system would send to the destination from.
+///
+/// Found by connecting a throwaway socket to the destination, which picks a route
+/// without sending anything.
+pub fn route_local_addr(dst: &SocketAddr) -> io::Result<SocketAddr> {
+    let unspecified = match *dst {
+        SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0),
+        SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)), 0),
+    };
+
+    let probe = UdpSocket::bind(unspecified)?;
+    probe.connect(dst)?;
+
+    let mut local = probe.local_addr()?;
+    if local.ip().is_unspecified() {
+        return Err(io::Error::new(ErrorKind::NotFound, "No Route To The Destination"));
+    }
+    local.set_port(0);
+
+    Ok(local)
+}
+
+/// Returns true if the address is an IP literal that can be used without a DNS lookup.
+///
+/// Accepts bare IPv4 and IPv6 addresses as well as socket addresses with a port,
+/// where IPv6 addresses with a port must be enclosed in brackets.
+pub fn is_literal_addr(addr: &str) -> bool {
+    addr.parse::<IpAddr>().is_ok() || addr.parse::<SocketAddr>().is_ok()
+}
+
+/// Destination address that tells whether resolving it would need a DNS lookup.
+///
+/// Implemented for the types the standard library implements `ToSocketAddrs` for,
+/// so lookups can be refused before `to_socket_addrs` performs them.
+pub trait DestAddr: ToSocketAddrs {
+    /// Host name that resolving the destination looks up, `None` for IP literals.
+    fn host_name(&self) -> Option<&str>;
+}
+
+macro_rules! literal_dest_addr {
+    ($($ty:ty),*) => {
+        $(impl DestAddr for $ty {
+            fn host_name(&self) -> Option<&str> {
+                None
+            }
+        })*
+    }
+}
+
+literal_dest_addr!(SocketAddr, SocketAddrV4, SocketAddrV6, (IpAddr, u16), (Ipv4Addr, u16), (Ipv6Addr, u16));
+
+impl DestAddr for str {
+    fn host_name(&self) -> Option<&str> {
+        if is_literal_addr(self) { None } else { Some(self) }
+    }
+}
+
+impl DestAddr for String {
+    fn host_name(&self) -> Option<&str> {
+        self.as_str().host_name()
+    }
+}
+
+impl DestAddr for (&str, u16) {
+    fn host_name(&self) -> Option<&str> {
+        host_part(self.0)
+    }
+}
+
+impl DestAddr for (String, u16) {
+    fn host_name(&self) -> Option<&str> {
+        host_part(&self.0)
+    }
+}
+
+/// Host part of a host and port pair, `None` if it is an IP literal.
+fn host_part(host: &str) -> Option<&str> {
+    if host.parse::<IpAddr>().is_ok() { None } else { Some(host) }
+}
+
+impl<T: DestAddr + ?Sized> DestAddr for &T {
+    fn host_name(&self) -> Option<&str> {
+        (**self).host_name()
+    }
+}
+
+/// Returns true if the `IPv6` address is only meaningful along with the scope id of an interface.
+///
+/// This holds for link-local unicast addresses and for multicast addresses with a scope
+/// of at most link-local.
+pub fn needs_scope_id(addr: &Ipv6Addr) -> bool {
+    if addr.is_multicast() {
+        multicast_scope(addr) <= 2
+    } else {
+        addr.segments()[0] & 0xFFC0 == 0xFE80
+    }
+}
+
+/// Host of the socket address as handed to a `NetworkConnector`.
+///
+/// The scope id of `IPv6` addresses that need one is kept as a numeric zone, as in `fe80::1%2`.
+pub fn connect_host(addr: &SocketAddr) -> String {
+    match *addr {
+        SocketAddr::V6(ref n) if n.scope_id() != 0 && needs_scope_id(n.ip()) => {
+            format!("{}%{}", n.ip(), n.scope_id())
+        }
+        ref n => n.ip().to_string(),
+    }
+}
+
+/// Parse an `IPv6` host, optionally enclosed in brackets, along with the scope id of its zone.
+///
+/// The zone is either a scope id or the name of a local interface.
+pub fn parse_scoped_ipv6(host: &str) -> io::Result<(Ipv6Addr, Option<u32>)> {
+    let host = if host.starts_with('[') && host.ends_with(']') {
+        &host[1..host.len() - 1]
+    } else {
+        host
+    };
+    let invalid = || io::Error::new(ErrorKind::InvalidInput, format!("Invalid IPv6 Host: {}", host));
+
+    let (ip, zone) = match host.find('%') {
+        Some(index) => (&host[..index], Some(&host[index + 1..])),
+        None => (host, None),
+    };
+    let ip = ip.parse::<Ipv6Addr>().map_err(|_| invalid())?;
+
+    let scope_id = match zone {
+        Some(zone) => {
+            let scope_id = zone.parse::<u32>().ok().or_else(|| interface_index(zone));
+            Some(scope_id.ok_or_else(invalid)?)
+        }
+        None => None,
+    };
+
+    Ok((ip, scope_id))
+}
+
+/// The scope field of an `IPv6` multicast address, 2 for link-local, 5 for site-local and 8 for
+/// organization-local.
+pub fn multicast_scope(addr: &Ipv6Addr) -> u8 {
+    (addr.segments()[0] & 0x000F) as u8
+}
+
+/// Directed broadcast address of the subnet with the address and netmask.
+///
+/// Subnets of one or two addresses, such as point-to-point links (RFC 3021), have no broadcast address.
+pub fn directed_broadcast(addr: Ipv4Addr, netmask: Ipv4Addr) -> Option<Ipv4Addr> {
+    let mask = u32::from(netmask);
+
+    if mask.count_ones() >= 31 {
+        None
+    } else {
+        Some(Ipv4Addr::from(u32::from(addr) | !mask))
+    }
+}
+
 /// Bind to a `UdpSocket`, setting `SO_REUSEADDR` on the underlying socket before binding.
 pub fn bind_reuse<A: ToSocketAddrs>(local_addr: A) -> io::Result<UdpSocket> {
     let local_addr = try!(addr_from_trait(local_addr));
@@ -54,6 +286,364 @@ pub fn bind_reuse<A: ToSocketAddrs>(local_addr: A) -> io::Result<UdpSocket> {
     builder.bind(local_addr)
 }
 
+/// Set the receive and send buffer sizes of the socket, leaving sizes that are `None` alone.
+///
+/// Kernels clamp and adjust the sizes, the effective sizes are logged at debug level.
+pub fn set_buffer_sizes(sock: &UdpSocket, recv: Option<usize>, send: Option<usize>) -> io::Result<()> {
+    if let Some(size) = recv {
+        sock.set_recv_buffer_size(size)?;
+        debug!("Requested {} byte receive buffer, got {}", size, sock.recv_buffer_size()?);
+    }
+
+    if let Some(size) = send {
+        sock.set_send_buffer_size(size)?;
+        debug!("Requested {} byte send buffer, got {}", size, sock.send_buffer_size()?);
+    }
+
+    Ok(())
+}
+
+/// Set whether multicasts sent by the local host are looped back to it, for the address family of the socket.
+///
+/// Most platforms apply the option to the sending socket, Windows to the receiving one.
+pub fn set_multicast_loop(sock: &UdpSocket, on: bool) -> io::Result<()> {
+    match sock.local_addr()? {
+        SocketAddr::V4(_) => sock.set_multicast_loop_v4(on),
+        SocketAddr::V6(_) => sock.set_multicast_loop_v6(on),
+    }
+}
+
+/// Largest value that fits in the 6 bit DSCP field.
+pub const MAX_DSCP: u8 = 63;
+
+/// Mark outgoing packets of the socket with the DSCP value, through `IP_TOS` or `IPV6_TCLASS`.
+#[cfg(unix)]
+pub fn set_dscp(sock: &UdpSocket, dscp: u8) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    if dscp > MAX_DSCP {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "DSCP Value Does Not Fit In 6 Bits"));
+    }
+
+    let (level, option) = match sock.local_addr()? {
+        SocketAddr::V4(_) => (::libc::IPPROTO_IP, ::libc::IP_TOS),
+        SocketAddr::V6(_) => (::libc::IPPROTO_IPV6, ::libc::IPV6_TCLASS),
+    };
+    // DSCP is the upper 6 bits of the traffic class, the lower 2 are used for ECN
+    let value = ::libc::c_int::from(dscp << 2);
+
+    let result = unsafe {
+        ::libc::setsockopt(sock.as_raw_fd(),
+                           level,
+                           option,
+                           &value as *const _ as *const ::libc::c_void,
+                           ::std::mem::size_of::<::libc::c_int>() as ::libc::socklen_t)
+    };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Mark outgoing packets of the socket with the DSCP value.
+///
+/// Windows may accept the option and still send packets unmarked unless its QoS policy allows it.
+#[cfg(windows)]
+pub fn set_dscp(sock: &UdpSocket, dscp: u8) -> io::Result<()> {
+    use std::os::windows::io::AsRawSocket;
+    use winapi::shared::ws2def::{IPPROTO_IP, IPPROTO_IPV6};
+    use winapi::shared::ws2ipdef::{IP_TOS, IPV6_TCLASS};
+    use winapi::um::winsock2;
+
+    if dscp > MAX_DSCP {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "DSCP Value Does Not Fit In 6 Bits"));
+    }
+
+    let (level, option) = match sock.local_addr()? {
+        SocketAddr::V4(_) => (IPPROTO_IP as i32, IP_TOS),
+        SocketAddr::V6(_) => (IPPROTO_IPV6 as i32, IPV6_TCLASS),
+    };
+    // DSCP is the upper 6 bits of the traffic class, the lower 2 are used for ECN
+    let value = i32::from(dscp << 2);
+
+    let result = unsafe {
+        winsock2::setsockopt(sock.as_raw_socket() as winsock2::SOCKET,
+                             level,
+                             option,
+                             &value as *const _ as *const _,
+                             ::std::mem::size_of::<i32>() as i32)
+    };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Mark outgoing packets of the socket with the DSCP value.
+///
+/// Marking packets is not available on this platform.
+#[cfg(not(any(unix, windows)))]
+pub fn set_dscp(_sock: &UdpSocket, _dscp: u8) -> io::Result<()> {
+    Err(io::Error::new(ErrorKind::Other, "Setting DSCP Is Not Supported"))
+}
+
+/// Have the socket pass along where each packet arrived, through `IP_PKTINFO` or `IPV6_RECVPKTINFO`.
+///
+/// See `recv_from_local` for reading the local address of a packet.
+#[cfg(target_os = "linux")]
+pub fn set_recv_local(sock: &UdpSocket) -> io::Result<()> {
+    let (level, option) = match sock.local_addr()? {
+        SocketAddr::V4(_) => (::libc::IPPROTO_IP, ::libc::IP_PKTINFO),
+        SocketAddr::V6(_) => (::libc::IPPROTO_IPV6, ::libc::IPV6_RECVPKTINFO),
+    };
+    let value: ::libc::c_int = 1;
+
+    let result = unsafe {
+        ::libc::setsockopt(sock.as_raw_fd(),
+                           level,
+                           option,
+                           &value as *const _ as *const ::libc::c_void,
+                           ::std::mem::size_of::<::libc::c_int>() as ::libc::socklen_t)
+    };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Have the socket pass along where each packet arrived.
+///
+/// Packet info is not available on this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn set_recv_local(_sock: &UdpSocket) -> io::Result<()> {
+    Err(io::Error::new(ErrorKind::Other, "Receiving Packet Info Is Not Supported"))
+}
+
+/// Shut down the read half of the socket, waking any thread blocked reading from or polling it.
+///
+/// Linux reports `ENOTCONN` for sockets that are not connected but shuts them down all the same.
+#[cfg(target_os = "linux")]
+pub fn shutdown_read(sock: &UdpSocket) -> io::Result<()> {
+    let result = unsafe { ::libc::shutdown(sock.as_raw_fd(), ::libc::SHUT_RD) };
+
+    match io::Error::last_os_error() {
+        _ if result == 0 => Ok(()),
+        ref err if err.kind() == ErrorKind::NotConnected => Ok(()),
+        err => Err(err),
+    }
+}
+
+/// Shut down the read half of the socket.
+///
+/// Shutting down unconnected sockets is not reliable on this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn shutdown_read(_sock: &UdpSocket) -> io::Result<()> {
+    Err(io::Error::new(ErrorKind::Other, "Shutting Down Sockets Is Not Supported"))
+}
+
+/// Receive a packet into the buffer, along with its source and the local address it arrived on.
+///
+/// The local address is only known for sockets that `set_recv_local` succeeded on. Packets
+/// sent to a multicast group are reported as arriving on an address of the interface that
+/// received them.
+#[cfg(target_os = "linux")]
+pub fn recv_from_local(sock: &UdpSocket, buf: &mut [u8]) -> io::Result<(usize, SocketAddr, Option<IpAddr>)> {
+    use std::mem;
+    use std::ptr;
+
+    let mut src: ::libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut iov = ::libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut ::libc::c_void,
+        iov_len: buf.len(),
+    };
+    // Aligned room for a single in_pktinfo or in6_pktinfo control message
+    let mut control = [0u64; 8];
+
+    let mut msg: ::libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_name = &mut src as *mut _ as *mut ::libc::c_void;
+    msg.msg_namelen = mem::size_of::<::libc::sockaddr_storage>() as ::libc::socklen_t;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut ::libc::c_void;
+    msg.msg_controllen = mem::size_of_val(&control) as _;
+
+    let size = unsafe { ::libc::recvmsg(sock.as_raw_fd(), &mut msg, 0) };
+    if size < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let src = match i32::from(src.ss_family) {
+        ::libc::AF_INET => {
+            let src = unsafe { ptr::read(&src as *const _ as *const ::libc::sockaddr_in) };
+            SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::from(u32::from_be(src.sin_addr.s_addr)),
+                                             u16::from_be(src.sin_port)))
+        }
+        ::libc::AF_INET6 => {
+            let src = unsafe { ptr::read(&src as *const _ as *const ::libc::sockaddr_in6) };
+            SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::from(src.sin6_addr.s6_addr),
+                                             u16::from_be(src.sin6_port),
+                                             src.sin6_flowinfo,
+                                             src.sin6_scope_id))
+        }
+        _ => {
+            return Err(io::Error::new(ErrorKind::InvalidData, "Packet Received From Unknown Address Family"))
+        }
+    };
+
+    let mut local = None;
+    let mut cmsg = unsafe { ::libc::CMSG_FIRSTHDR(&msg) };
+    while !cmsg.is_null() {
+        let (level, kind, data) = unsafe { ((*cmsg).cmsg_level, (*cmsg).cmsg_type, ::libc::CMSG_DATA(cmsg)) };
+
+        if (level, kind) == (::libc::IPPROTO_IP, ::libc::IP_PKTINFO) {
+            // The specific destination is the interface address, not the group, for multicast packets
+            let info = unsafe { ptr::read_unaligned(data as *const ::libc::in_pktinfo) };
+            local = Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(info.ipi_spec_dst.s_addr))));
+        } else if (level, kind) == (::libc::IPPROTO_IPV6, ::libc::IPV6_PKTINFO) {
+            let info = unsafe { ptr::read_unaligned(data as *const ::libc::in6_pktinfo) };
+            let dst = Ipv6Addr::from(info.ipi6_addr.s6_addr);
+            local = if dst.is_multicast() {
+                interface_ipv6_addr(info.ipi6_ifindex).map(IpAddr::V6)
+            } else {
+                Some(IpAddr::V6(dst))
+            };
+        }
+
+        cmsg = unsafe { ::libc::CMSG_NXTHDR(&msg, cmsg) };
+    }
+
+    Ok((size as usize, src, local))
+}
+
+/// Receive a packet into the buffer, along with its source and the local address it arrived on.
+///
+/// Packet info is not available on this platform, the local address is never known.
+#[cfg(not(target_os = "linux"))]
+pub fn recv_from_local(sock: &UdpSocket, buf: &mut [u8]) -> io::Result<(usize, SocketAddr, Option<IpAddr>)> {
+    sock.recv_from(buf).map(|(size, src)| (size, src, None))
+}
+
+/// How long the listed `IPv6` addresses of the interfaces are used before they are listed again.
+#[cfg(target_os = "linux")]
+const INTERFACE_ADDRS_MS: u64 = 1000;
+
+/// How long after listing the addresses an interface missing from them has them listed again.
+#[cfg(target_os = "linux")]
+const INTERFACE_RELIST_MS: u64 = 100;
+
+/// `IPv6` addresses of the local interfaces by index, shared by every socket.
+#[cfg(target_os = "linux")]
+static INTERFACE_ADDRS: Mutex<Option<InterfaceAddrs>> = Mutex::new(None);
+
+#[cfg(target_os = "linux")]
+struct InterfaceAddrs {
+    addrs: HashMap<u32, Ipv6Addr>,
+    listed: Instant,
+}
+
+/// First `IPv6` address of the interface with the given index.
+///
+/// The addresses of all interfaces are listed at most once every `INTERFACE_ADDRS_MS`, or
+/// `INTERFACE_RELIST_MS` if the index is missing, so this is cheap to call for every packet.
+#[cfg(target_os = "linux")]
+fn interface_ipv6_addr(index: u32) -> Option<Ipv6Addr> {
+    let mut cached = INTERFACE_ADDRS.lock().unwrap();
+
+    let stale = match *cached {
+        Some(ref cache) => {
+            let age = cache.listed.elapsed();
+            age >= Duration::from_millis(INTERFACE_ADDRS_MS) ||
+            (!cache.addrs.contains_key(&index) && age >= Duration::from_millis(INTERFACE_RELIST_MS))
+        }
+        None => true,
+    };
+    if stale {
+        match list_interface_ipv6_addrs() {
+            Ok(addrs) => {
+                *cached = Some(InterfaceAddrs {
+                    addrs,
+                    listed: Instant::now(),
+                })
+            }
+            Err(err) => debug!("Failed to list interfaces for index {}: {}", index, err),
+        }
+    }
+
+    cached.as_ref().and_then(|cache| cache.addrs.get(&index).cloned())
+}
+
+/// First `IPv6` address of every local interface that has one, by interface index.
+#[cfg(target_os = "linux")]
+fn list_interface_ipv6_addrs() -> io::Result<HashMap<u32, Ipv6Addr>> {
+    let mut addrs = HashMap::new();
+
+    for iface in get_if_addrs::get_if_addrs()? {
+        if let (IpAddr::V6(addr), Some(index)) = (iface.ip(), interface_index(&iface.name)) {
+            addrs.entry(index).or_insert(addr);
+        }
+    }
+
+    Ok(addrs)
+}
+
+/// DSCP value outgoing packets of the socket are marked with.
+#[cfg(all(unix, test))]
+pub fn dscp(sock: &UdpSocket) -> io::Result<u8> {
+    use std::os::unix::io::AsRawFd;
+
+    let (level, option) = match sock.local_addr()? {
+        SocketAddr::V4(_) => (::libc::IPPROTO_IP, ::libc::IP_TOS),
+        SocketAddr::V6(_) => (::libc::IPPROTO_IPV6, ::libc::IPV6_TCLASS),
+    };
+    let mut value: ::libc::c_int = 0;
+    let mut len = ::std::mem::size_of::<::libc::c_int>() as ::libc::socklen_t;
+
+    let result = unsafe {
+        ::libc::getsockopt(sock.as_raw_fd(),
+                           level,
+                           option,
+                           &mut value as *mut _ as *mut ::libc::c_void,
+                           &mut len)
+    };
+
+    if result == 0 {
+        Ok((value >> 2) as u8)
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Interface outgoing `IPv4` multicast of the socket leaves through.
+///
+/// Read directly since the getter of net2 zero initializes a reference.
+#[cfg(all(unix, test))]
+pub fn multicast_if_v4(sock: &UdpSocket) -> io::Result<Ipv4Addr> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut value = ::libc::in_addr { s_addr: 0 };
+    let mut len = ::std::mem::size_of::<::libc::in_addr>() as ::libc::socklen_t;
+
+    let result = unsafe {
+        ::libc::getsockopt(sock.as_raw_fd(),
+                           ::libc::IPPROTO_IP,
+                           ::libc::IP_MULTICAST_IF,
+                           &mut value as *mut _ as *mut ::libc::c_void,
+                           &mut len)
+    };
+
+    if result == 0 {
+        Ok(Ipv4Addr::from(u32::from_be(value.s_addr)))
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
 #[cfg(windows)]
 fn reuse_port(builder: &UdpBuilder) -> io::Result<()> {
     // Allow wildcards + specific to not overlap
@@ -70,11 +660,66 @@ fn reuse_port(builder: &UdpBuilder) -> io::Result<()> {
     Ok(())
 }
 
+/// Local interface a multicast group is joined or left on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MulticastIface {
+    /// Interface with the given `IPv4` address.
+    ByAddr(Ipv4Addr),
+    /// Interface with the given index, 0 lets the system pick one.
+    ByIndex(u32),
+}
+
+/// Interface to join a multicast group on for the local address, preferring the interface index.
+///
+/// `IPv4` groups are only joined by index on Linux, elsewhere and when no index is known
+/// the address is used. `IPv6` addresses fall back to their scope id.
+pub fn multicast_iface(addr: &SocketAddr, index: Option<u32>) -> MulticastIface {
+    match (*addr, index) {
+        (SocketAddr::V4(_), Some(n)) if cfg!(target_os = "linux") => MulticastIface::ByIndex(n),
+        (SocketAddr::V4(n), _) => MulticastIface::ByAddr(*n.ip()),
+        (SocketAddr::V6(n), _) if n.scope_id() != 0 => MulticastIface::ByIndex(n.scope_id()),
+        (SocketAddr::V6(_), Some(n)) => MulticastIface::ByIndex(n),
+        (SocketAddr::V6(_), None) => MulticastIface::ByIndex(0),
+    }
+}
+
+/// Socket that outgoing multicast can be directed out of a specific interface on.
+pub trait MulticastIfSocket {
+    /// Send outgoing `IPv4` multicast out of the interface with the given address.
+    fn set_multicast_if_v4(&self, iface: &Ipv4Addr) -> io::Result<()>;
+
+    /// Send outgoing `IPv6` multicast out of the interface with the given index, 0 lets the system pick one.
+    fn set_multicast_if_v6(&self, index: u32) -> io::Result<()>;
+}
+
+impl MulticastIfSocket for UdpSocket {
+    fn set_multicast_if_v4(&self, iface: &Ipv4Addr) -> io::Result<()> {
+        UdpSocketExt::set_multicast_if_v4(self, iface)
+    }
+
+    fn set_multicast_if_v6(&self, index: u32) -> io::Result<()> {
+        UdpSocketExt::set_multicast_if_v6(self, index)
+    }
+}
+
+/// Send outgoing multicast out of the interface the local address belongs to.
+///
+/// Binding to the address of an interface does not direct multicast out of it on every
+/// platform, Windows for one uses the default route. `IPv6` addresses without a scope id
+/// leave the choice of interface to the system.
+pub fn set_multicast_if<S: MulticastIfSocket>(sock: &S, local: &SocketAddr) -> io::Result<()> {
+    match multicast_iface(local, None) {
+        MulticastIface::ByAddr(ref ip) => sock.set_multicast_if_v4(ip),
+        MulticastIface::ByIndex(index) => sock.set_multicast_if_v6(index),
+    }
+}
+
 /// Join a multicast address on the current `UdpSocket`.
-pub fn join_multicast(sock: &UdpSocket, iface: &SocketAddr, mcast_addr: &IpAddr) -> io::Result<()> {
-    match (iface, mcast_addr) {
-        (&SocketAddr::V4(ref i), &IpAddr::V4(ref m)) => sock.join_multicast_v4(m, i.ip()),
-        (&SocketAddr::V6(ref i), &IpAddr::V6(ref m)) => sock.join_multicast_v6(m, i.scope_id()),
+pub fn join_multicast(sock: &UdpSocket, iface: &MulticastIface, mcast_addr: &IpAddr) -> io::Result<()> {
+    match (*iface, mcast_addr) {
+        (MulticastIface::ByAddr(ref i), IpAddr::V4(m)) => sock.join_multicast_v4(m, i),
+        (MulticastIface::ByIndex(i), IpAddr::V4(m)) => set_membership_v4_index(sock, m, i, true),
+        (MulticastIface::ByIndex(i), IpAddr::V6(m)) => sock.join_multicast_v6(m, i),
         _ => {
             Err(io::Error::new(ErrorKind::InvalidInput,
                                "Multicast And Interface Addresses Are Not The Same Version"))
@@ -82,12 +727,107 @@ pub fn join_multicast(sock: &UdpSocket, iface: &SocketAddr, mcast_addr: &IpAddr)
     }
 }
 
+/// Join or leave the `IPv4` multicast group on the interface with the given index.
+#[cfg(target_os = "linux")]
+fn set_membership_v4_index(sock: &UdpSocket,
+                           mcast_addr: &Ipv4Addr,
+                           index: u32,
+                           join: bool)
+                           -> io::Result<()> {
+    let mreq = ::libc::ip_mreqn {
+        imr_multiaddr: ::libc::in_addr { s_addr: u32::from(*mcast_addr).to_be() },
+        imr_address: ::libc::in_addr { s_addr: 0 },
+        imr_ifindex: index as ::libc::c_int,
+    };
+    let option = if join { ::libc::IP_ADD_MEMBERSHIP } else { ::libc::IP_DROP_MEMBERSHIP };
+
+    let result = unsafe {
+        ::libc::setsockopt(sock.as_raw_fd(),
+                           ::libc::IPPROTO_IP,
+                           option,
+                           &mreq as *const _ as *const ::libc::c_void,
+                           ::std::mem::size_of::<::libc::ip_mreqn>() as ::libc::socklen_t)
+    };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Join or leave the `IPv4` multicast group on the interface with the given index.
+///
+/// Joining by index is not available on this platform.
+#[cfg(not(target_os = "linux"))]
+fn set_membership_v4_index(_sock: &UdpSocket,
+                           _mcast_addr: &Ipv4Addr,
+                           _index: u32,
+                           _join: bool)
+                           -> io::Result<()> {
+    Err(io::Error::new(ErrorKind::InvalidInput, "Joining IPv4 Multicast By Interface Index Is Not Supported"))
+}
+
+/// Index of the network interface with the given name, used as the scope id of IPv6 addresses.
+#[cfg(unix)]
+pub fn interface_index(name: &str) -> Option<u32> {
+    let name = match CString::new(name) {
+        Ok(n) => n,
+        Err(_) => return None,
+    };
+
+    match unsafe { ::libc::if_nametoindex(name.as_ptr()) } {
+        0 => None,
+        n => Some(n),
+    }
+}
+
+/// Index of the network interface with the given name, used as the scope id of IPv6 addresses.
+///
+/// Interface indices are not available on this platform.
+#[cfg(not(unix))]
+pub fn interface_index(_name: &str) -> Option<u32> {
+    None
+}
+
+/// Wait until at least one of the sockets is readable, or the timeout passes.
+///
+/// Returns whether each socket is readable, all false on timeout. Sockets that hung up, errored
+/// or are not open count as readable so that reading from them surfaces the error.
+#[cfg(unix)]
+pub fn poll_readable(socks: &[&UdpSocket], timeout: Option<Duration>) -> io::Result<Vec<bool>> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut fds = socks.iter()
+        .map(|sock| {
+            ::libc::pollfd {
+                fd: sock.as_raw_fd(),
+                events: ::libc::POLLIN,
+                revents: 0,
+            }
+        })
+        .collect::<Vec<_>>();
+    // Round up so a sub-millisecond remainder does not turn into a busy loop
+    let timeout_ms = timeout.map_or(-1, |time| {
+        let ms = time.as_secs().saturating_mul(1000) + u64::from(time.subsec_nanos().div_ceil(1_000_000));
+        cmp::min(ms, ::libc::c_int::MAX as u64) as ::libc::c_int
+    });
+
+    let res = unsafe { ::libc::poll(fds.as_mut_ptr(), fds.len() as ::libc::nfds_t, timeout_ms) };
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let ready = ::libc::POLLIN | ::libc::POLLERR | ::libc::POLLHUP | ::libc::POLLNVAL;
+    Ok(fds.iter().map(|fd| fd.revents & ready != 0).collect())
+}
+
 /// Leave a multicast address on the current `UdpSocket`.
-#[allow(dead_code)] // TODO: call this from somewhere?
-pub fn leave_multicast(sock: &UdpSocket, iface_addr: &SocketAddr, mcast_addr: &SocketAddr) -> io::Result<()> {
-    match (iface_addr, mcast_addr) {
-        (&SocketAddr::V4(ref i), &SocketAddr::V4(ref m)) => sock.leave_multicast_v4(m.ip(), i.ip()),
-        (&SocketAddr::V6(ref i), &SocketAddr::V6(ref m)) => sock.leave_multicast_v6(m.ip(), i.scope_id()),
+pub fn leave_multicast(sock: &UdpSocket, iface: &MulticastIface, mcast_addr: &IpAddr) -> io::Result<()> {
+    match (*iface, mcast_addr) {
+        (MulticastIface::ByAddr(ref i), IpAddr::V4(m)) => sock.leave_multicast_v4(m, i),
+        (MulticastIface::ByIndex(i), IpAddr::V4(m)) => set_membership_v4_index(sock, m, i, false),
+        (MulticastIface::ByIndex(i), IpAddr::V6(m)) => sock.leave_multicast_v6(m, i),
         _ => {
             Err(io::Error::new(ErrorKind::InvalidInput,
                                "Multicast And Interface Addresses Are Not The Same Version"))
@@ -97,12 +837,326 @@ pub fn leave_multicast(sock: &UdpSocket, iface_addr: &SocketAddr, mcast_addr: &S
 
 #[cfg(test)]
 mod tests {
+    use std::cell::RefCell;
+    use std::io;
+    use std::net::{Ipv4Addr, SocketAddr, SocketAddrV6, ToSocketAddrs, UdpSocket};
+    #[cfg(unix)]
+    use std::time::Duration;
+    use std::vec;
+
+    use net2::UdpSocketExt;
+
+    use super::{DestAddr, IpVersionMode, MulticastIface, MulticastIfSocket};
 
     #[test]
     fn positive_addr_from_trait() {
         super::addr_from_trait("192.168.0.1:0").unwrap();
     }
 
+    /// Destination resolving to a fixed list of addresses, as a host name with several records would.
+    #[derive(Debug)]
+    struct MultiAddr(Vec<SocketAddr>);
+
+    impl ToSocketAddrs for MultiAddr {
+        type Iter = vec::IntoIter<SocketAddr>;
+
+        fn to_socket_addrs(&self) -> io::Result<Self::Iter> {
+            Ok(self.0.clone().into_iter())
+        }
+    }
+
+    fn multi_addr() -> MultiAddr {
+        MultiAddr(vec!["[fe80::1]:1900".parse().unwrap(),
+                       "192.168.1.2:1900".parse().unwrap(),
+                       "[fe80::2]:1900".parse().unwrap()])
+    }
+
+    #[test]
+    fn positive_addr_from_trait_with_mode() {
+        let addr = |mode| super::addr_from_trait_with_mode(multi_addr(), &mode).unwrap();
+
+        assert_eq!(addr(IpVersionMode::V4Only), "192.168.1.2:1900".parse::<SocketAddr>().unwrap());
+        assert_eq!(addr(IpVersionMode::V6Only), "[fe80::1]:1900".parse::<SocketAddr>().unwrap());
+        assert_eq!(addr(IpVersionMode::Any), "[fe80::1]:1900".parse::<SocketAddr>().unwrap());
+    }
+
+    #[test]
+    fn negative_addr_from_trait_with_mode_missing_family() {
+        let dst = MultiAddr(vec!["192.168.1.2:1900".parse().unwrap()]);
+
+        let err = super::addr_from_trait_with_mode(dst, &IpVersionMode::V6Only).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("192.168.1.2:1900"));
+    }
+
+    #[test]
+    fn positive_route_local_addr_loopback() {
+        let local = super::route_local_addr(&"127.0.0.1:1900".parse().unwrap()).unwrap();
+
+        assert_eq!(local, "127.0.0.1:0".parse::<SocketAddr>().unwrap());
+    }
+
+    #[test]
+    fn positive_ip_version_mode_round_trip() {
+        for &mode in &[IpVersionMode::V4Only, IpVersionMode::V6Only, IpVersionMode::Any] {
+            assert_eq!(mode.to_string().parse::<IpVersionMode>().unwrap(), mode);
+        }
+    }
+
+    #[test]
+    fn positive_ip_version_mode_ignores_case() {
+        assert_eq!("V4".parse::<IpVersionMode>().unwrap(), IpVersionMode::V4Only);
+        assert_eq!("Any".parse::<IpVersionMode>().unwrap(), IpVersionMode::Any);
+    }
+
+    #[test]
+    fn negative_ip_version_mode_junk() {
+        assert!("ipv4".parse::<IpVersionMode>().is_err());
+        assert!("".parse::<IpVersionMode>().is_err());
+    }
+
+    #[test]
+    fn positive_literal_ipv4() {
+        assert!(super::is_literal_addr("192.168.0.1"));
+        assert!(super::is_literal_addr("192.168.0.1:1900"));
+    }
+
+    #[test]
+    fn positive_literal_ipv6() {
+        assert!(super::is_literal_addr("FF02::C"));
+        assert!(super::is_literal_addr("[fe80::1]:1900"));
+    }
+
+    #[test]
+    fn negative_literal_hostname() {
+        assert!(!super::is_literal_addr("localhost"));
+        assert!(!super::is_literal_addr("mydevice.local:1900"));
+        assert!(!super::is_literal_addr("fe80::1:1900:"));
+    }
+
+    #[test]
+    fn positive_dest_addr_host_name() {
+        assert_eq!("mydevice.local:1900".host_name(), Some("mydevice.local:1900"));
+        assert_eq!(("mydevice.local", 1900).host_name(), Some("mydevice.local"));
+        assert_eq!(("mydevice.local".to_owned(), 1900).host_name(), Some("mydevice.local"));
+    }
+
+    #[test]
+    fn negative_dest_addr_host_name_literal() {
+        let addr: SocketAddr = "192.168.0.1:1900".parse().unwrap();
+
+        assert_eq!("[fe80::1]:1900".host_name(), None);
+        assert_eq!(("192.168.0.1", 1900).host_name(), None);
+        assert_eq!(addr.host_name(), None);
+        assert_eq!((addr.ip(), 1900).host_name(), None);
+    }
+
+    #[test]
+    fn positive_needs_scope_id() {
+        assert!(super::needs_scope_id(&"FF02::C".parse().unwrap()));
+        assert!(super::needs_scope_id(&"fe80::1".parse().unwrap()));
+        assert!(!super::needs_scope_id(&"FF05::C".parse().unwrap()));
+        assert!(!super::needs_scope_id(&"FF08::C".parse().unwrap()));
+        assert!(!super::needs_scope_id(&"2a00:1450::1".parse().unwrap()));
+        assert!(!super::needs_scope_id(&"fd00::2".parse().unwrap()));
+    }
+
+    #[test]
+    fn positive_connect_host_keeps_scope_id() {
+        let scoped = SocketAddr::V6(SocketAddrV6::new("fe80::1".parse().unwrap(), 1900, 0, 3));
+        let global = SocketAddr::V6(SocketAddrV6::new("FF05::C".parse().unwrap(), 1900, 0, 3));
+
+        assert_eq!(super::connect_host(&scoped), "fe80::1%3");
+        assert_eq!(super::connect_host(&global), "ff05::c");
+        assert_eq!(super::connect_host(&"192.168.0.1:1900".parse().unwrap()), "192.168.0.1");
+    }
+
+    #[test]
+    fn positive_parse_scoped_ipv6() {
+        let fe80 = "fe80::1".parse().unwrap();
+
+        assert_eq!(super::parse_scoped_ipv6("fe80::1%3").unwrap(), (fe80, Some(3)));
+        assert_eq!(super::parse_scoped_ipv6("[fe80::1%3]").unwrap(), (fe80, Some(3)));
+        assert_eq!(super::parse_scoped_ipv6("[fe80::1]").unwrap(), (fe80, None));
+    }
+
+    #[test]
+    fn negative_parse_scoped_ipv6() {
+        assert!(super::parse_scoped_ipv6("192.168.0.1").is_err());
+        assert!(super::parse_scoped_ipv6("fe80::1%").is_err());
+        assert!(super::parse_scoped_ipv6("fe80::1%no-such-interface").is_err());
+    }
+
+    #[test]
+    fn positive_set_buffer_sizes() {
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        super::set_buffer_sizes(&sock, Some(65536), Some(32768)).unwrap();
+
+        // Linux doubles the requested size to account for bookkeeping
+        assert!(sock.recv_buffer_size().unwrap() >= 65536);
+        assert!(sock.send_buffer_size().unwrap() >= 32768);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn positive_set_dscp_round_trip() {
+        for addr in &["127.0.0.1:0", "[::1]:0"] {
+            let sock = UdpSocket::bind(addr).unwrap();
+
+            super::set_dscp(&sock, 46).unwrap();
+
+            assert_eq!(super::dscp(&sock).unwrap(), 46);
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn positive_poll_readable() {
+        let idle = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let busy = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let timeout = Some(Duration::from_millis(50));
+
+        assert_eq!(super::poll_readable(&[&idle, &busy], timeout).unwrap(), vec![false, false]);
+
+        idle.send_to(b"ping", busy.local_addr().unwrap()).unwrap();
+        assert_eq!(super::poll_readable(&[&idle, &busy], timeout).unwrap(), vec![false, true]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn positive_interface_ipv6_addr_cached() {
+        let index = super::interface_index("lo").unwrap();
+        let expected = super::list_interface_ipv6_addrs().unwrap().get(&index).cloned();
+
+        assert_eq!(super::interface_ipv6_addr(index), expected);
+
+        let cached = super::INTERFACE_ADDRS.lock().unwrap();
+        assert_eq!(cached.as_ref().unwrap().addrs.get(&index).cloned(), expected);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn positive_shutdown_read_wakes_reader() {
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let reader = sock.try_clone().unwrap();
+        reader.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let thread = ::std::thread::spawn(move || reader.recv_from(&mut [0u8; 16]).map(|(len, _)| len));
+        ::std::thread::sleep(Duration::from_millis(50));
+        let start = ::std::time::Instant::now();
+        super::shutdown_read(&sock).unwrap();
+        let _ = thread.join().unwrap();
+
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn negative_set_dscp_out_of_range() {
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        assert!(super::set_dscp(&sock, super::MAX_DSCP + 1).is_err());
+    }
+
+    #[test]
+    fn positive_directed_broadcast() {
+        let broadcast = |addr: &str, netmask: &str| {
+            let broadcast = super::directed_broadcast(addr.parse().unwrap(), netmask.parse().unwrap());
+            broadcast.map(|ip| ip.to_string())
+        };
+
+        assert_eq!(broadcast("192.168.1.20", "255.255.255.0").unwrap(), "192.168.1.255");
+        assert_eq!(broadcast("10.1.2.3", "255.0.0.0").unwrap(), "10.255.255.255");
+        assert_eq!(broadcast("172.16.5.4", "255.255.240.0").unwrap(), "172.16.15.255");
+        assert_eq!(broadcast("192.168.1.20", "255.255.255.252").unwrap(), "192.168.1.23");
+    }
+
+    #[test]
+    fn negative_directed_broadcast_single_address() {
+        assert_eq!(super::directed_broadcast("10.0.0.1".parse().unwrap(), "255.255.255.255".parse().unwrap()),
+                   None);
+    }
+
+    #[test]
+    fn negative_directed_broadcast_point_to_point() {
+        assert_eq!(super::directed_broadcast("10.0.0.1".parse().unwrap(), "255.255.255.254".parse().unwrap()),
+                   None);
+    }
+
+    #[test]
+    fn positive_multicast_iface_v4() {
+        let addr = "192.168.0.1:0".parse().unwrap();
+        let by_addr = MulticastIface::ByAddr("192.168.0.1".parse().unwrap());
+
+        assert_eq!(super::multicast_iface(&addr, None), by_addr);
+        if cfg!(target_os = "linux") {
+            assert_eq!(super::multicast_iface(&addr, Some(3)), MulticastIface::ByIndex(3));
+        } else {
+            assert_eq!(super::multicast_iface(&addr, Some(3)), by_addr);
+        }
+    }
+
+    /// Socket recording the multicast interface options set on it.
+    #[derive(Default)]
+    struct MockIfSocket {
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl MulticastIfSocket for MockIfSocket {
+        fn set_multicast_if_v4(&self, iface: &Ipv4Addr) -> io::Result<()> {
+            self.calls.borrow_mut().push(format!("v4 {}", iface));
+            Ok(())
+        }
+
+        fn set_multicast_if_v6(&self, index: u32) -> io::Result<()> {
+            self.calls.borrow_mut().push(format!("v6 {}", index));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn positive_set_multicast_if_per_family() {
+        let sock = MockIfSocket::default();
+
+        for addr in &["192.168.0.1:0", "[fe80::1%2]:0", "[2001:db8::1]:0"] {
+            super::set_multicast_if(&sock, &addr.parse().unwrap()).unwrap();
+        }
+
+        assert_eq!(*sock.calls.borrow(), vec!["v4 192.168.0.1", "v6 2", "v6 0"]);
+    }
+
+    #[test]
+    fn positive_multicast_iface_v6() {
+        let scoped = SocketAddr::V6(SocketAddrV6::new("fe80::1".parse().unwrap(), 0, 0, 2));
+        let unscoped = "[fe80::1]:0".parse().unwrap();
+
+        assert_eq!(super::multicast_iface(&scoped, Some(3)), MulticastIface::ByIndex(2));
+        assert_eq!(super::multicast_iface(&unscoped, Some(3)), MulticastIface::ByIndex(3));
+        assert_eq!(super::multicast_iface(&unscoped, None), MulticastIface::ByIndex(0));
+    }
+
+    #[test]
+    fn negative_join_multicast_version_mismatch() {
+        let sock = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let iface = MulticastIface::ByAddr("127.0.0.1".parse().unwrap());
+
+        assert!(super::join_multicast(&sock, &iface, &"FF02::C".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn positive_join_multicast_v4_loopback_index() {
+        let sock = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let iface = MulticastIface::ByIndex(super::interface_index("lo").unwrap());
+        let group = "239.255.255.250".parse().unwrap();
+
+        super::join_multicast(&sock, &iface, &group).unwrap();
+        // Joining twice on the same interface is refused, so the first join took effect
+        assert!(super::join_multicast(&sock, &iface, &group).is_err());
+        super::leave_multicast(&sock, &iface, &group).unwrap();
+    }
+
     #[test]
     #[should_panic]
     fn negative_addr_from_trait() {
@@ -1,44 +1,130 @@
-use std::io::{self, Error, ErrorKind};
+use std::cmp;
+use std::io::{self, Error};
 use std::net::{UdpSocket, SocketAddr};
 use std::fmt;
+use std::time::Duration;
 
-/// Maximum length for packets received on a `PacketReceiver`.
-pub const MAX_PCKT_LEN: usize = 1500;
+use net;
+
+/// Default maximum length for packets received on a `PacketReceiver`.
+///
+/// Above the typical MTU of a standard router, so that packets with long headers
+/// sent over jumbo-frame networks are still received in full.
+pub const DEFAULT_MAX_PCKT_LEN: usize = 2048;
 
 /// A `PacketReceiver` that abstracts over a network socket and reads full packets
-/// from the connection. Packets received from this connection are assumed to
-/// be no larger than what the typical MTU would be on a standard router.
+/// from the connection. Packets longer than the buffer size are truncated.
 ///
-/// See `net::packet::MAX_PCKT_LEN`.
-pub struct PacketReceiver(UdpSocket);
+/// See `net::packet::DEFAULT_MAX_PCKT_LEN`.
+pub struct PacketReceiver {
+    udp: UdpSocket,
+    max_len: usize,
+    idle_timeout: Option<Duration>,
+    // Packets carry the local address they arrived on, the socket is bound to the unspecified address
+    recv_local: bool,
+    // Local address of the socket, looked up once rather than for every packet
+    local: Option<SocketAddr>,
+}
 
 impl PacketReceiver {
-    /// Create a new PacketReceiver from the given UdpSocket.
-    pub fn new(udp: UdpSocket) -> PacketReceiver {
-        PacketReceiver(udp)
+    /// Create a new PacketReceiver from the given UdpSocket, reading packets of up to `len` bytes in full.
+    ///
+    /// Sockets bound to the unspecified address are asked for the local address of each
+    /// packet, where the platform supports it.
+    pub fn with_buffer_size(udp: UdpSocket, len: usize) -> PacketReceiver {
+        let local = udp.local_addr().ok();
+        let recv_local = match local {
+            Some(addr) if addr.ip().is_unspecified() => {
+                match net::set_recv_local(&udp) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        debug!("Packets on {} will not carry their local address: {}", addr, err);
+                        false
+                    }
+                }
+            }
+            _ => false,
+        };
+
+        PacketReceiver {
+            udp,
+            max_len: len,
+            idle_timeout: None,
+            recv_local,
+            local,
+        }
     }
 
-    /// Receive a packet from the underlying connection.
-    pub fn recv_pckt(&self) -> io::Result<(Vec<u8>, SocketAddr)> {
-        let mut pckt_buf = vec![0u8; MAX_PCKT_LEN];
+    /// Wake up at least once every timeout while waiting on packets, without giving up.
+    pub fn with_idle_timeout(mut self, timeout: Option<Duration>) -> PacketReceiver {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Longest time to wait on packets before checking back in, if any.
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
 
-        let (size, addr) = try!(self.0.recv_from(&mut pckt_buf));
+    /// Set the read timeout of the underlying connection.
+    pub fn set_read_timeout(&self, time: Option<Duration>) -> io::Result<()> {
+        self.udp.set_read_timeout(time)
+    }
+
+    /// Underlying connection, for waiting on it to become readable.
+    pub fn socket(&self) -> &UdpSocket {
+        &self.udp
+    }
+
+    /// Local address of the underlying connection.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self.local {
+            Some(local) => Ok(local),
+            None => self.udp.local_addr(),
+        }
+    }
+
+    /// Receive a packet from the underlying connection, along with its source and the local address it
+    /// arrived on.
+    ///
+    /// Packets longer than the buffer size are truncated to it, which is logged. The local
+    /// address is that of the underlying connection, unless it is bound to the unspecified
+    /// address and the address the packet arrived on is known.
+    pub fn recv_pckt(&self) -> io::Result<(Vec<u8>, SocketAddr, SocketAddr)> {
+        // One byte over the limit, receiving it means the packet did not fit
+        let mut pckt_buf = vec![0u8; self.max_len + 1];
+
+        let (size, addr, local_ip) = if self.recv_local {
+            net::recv_from_local(&self.udp, &mut pckt_buf)?
+        } else {
+            let (size, addr) = try!(self.udp.recv_from(&mut pckt_buf));
+            (size, addr, None)
+        };
+        let local = self.local_addr()?;
+        let local = local_ip.map_or(local, |ip| SocketAddr::new(ip, local.port()));
 
         // Check For Something That SHOULD NEVER Occur.
         if size > pckt_buf.len() {
-            Err(Error::new(ErrorKind::Other, "UdpSocket Reported Receive Length Greater Than Buffer"))
+            Err(Error::other("UdpSocket Reported Receive Length Greater Than Buffer"))
         } else {
+            if size > self.max_len {
+                warn!("Packet from {} on {} was truncated to {} bytes, raise the maximum packet size to \
+                       receive it",
+                      addr,
+                      self,
+                      self.max_len);
+            }
             // `truncate` does not reallocate the vec's backing storage
-            pckt_buf.truncate(size);
+            pckt_buf.truncate(cmp::min(size, self.max_len));
 
-            Ok((pckt_buf, addr))
+            Ok((pckt_buf, addr, local))
         }
     }
 }
 
 impl fmt::Display for PacketReceiver {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.0.local_addr() {
+        match self.udp.local_addr() {
             Ok(addr) => write!(f, "{}", addr),
             Err(err) => write!(f, "{}", err),
         }
@@ -7,7 +7,7 @@ use hyper::net::NetworkStream;
 /// trait.
 ///
 /// Note that reading from this stream will generate an error, this object is
-/// used for intercepting Http messages from Hyper and sending them out via Udp.
+/// used for sending serialized Http messages out via Udp.
 /// The response(s) from client(s) are to be handled by some other object that
 /// has a cloned handle to our internal `UdpSocket` handle.
 pub struct UdpSender {
@@ -48,20 +48,7 @@ impl Read for UdpSender {
 
 impl Write for UdpSender {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        // Hyper will generate a request with a /, we need to intercept that.
-        let mut buffer = vec![0u8; buf.len()];
-
-        let mut found = false;
-        for (src, dst) in buf.iter().zip(buffer.iter_mut()) {
-            if *src == b'/' && !found && buf[0] != b'H' {
-                *dst = b'*';
-                found = true;
-            } else {
-                *dst = *src;
-            }
-        }
-
-        self.buf.append(&mut buffer);
+        self.buf.extend_from_slice(buf);
 
         Ok(buf.len())
     }
@@ -1,18 +1,542 @@
 //! Primitives for non-blocking SSDP message receiving.
 
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
 use std::io;
+use std::panic::{self, AssertUnwindSafe};
 use std::result::Result;
-use std::thread;
-use std::sync::mpsc::{self, Receiver, Sender, TryRecvError, RecvError, Iter};
-use std::net::{UdpSocket, SocketAddr};
-use std::time::Duration;
+use std::thread::{self, JoinHandle};
+use std::sync::{Arc, Mutex, TryLockError, Weak};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError, TrySendError, RecvError,
+                      RecvTimeoutError};
+use std::net::{UdpSocket, SocketAddr, IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant, SystemTime};
+#[cfg(feature = "stream")]
+use std::pin::Pin;
+#[cfg(feature = "stream")]
+use std::task::{Context, Poll};
 
-use SSDPResult;
-use net::packet::PacketReceiver;
+#[cfg(feature = "stream")]
+use futures::Stream;
+#[cfg(feature = "stream")]
+use futures::channel::mpsc::{UnboundedReceiver, UnboundedSender};
+use net2::UdpSocketExt;
+
+use {SSDPError, SSDPResult};
+use anomaly::{AnomalyTracker, SourceAnomalies};
+use message::{Deviation, ParsePolicy};
+use net;
+use net::packet::{PacketReceiver, DEFAULT_MAX_PCKT_LEN};
 
 /// Trait for constructing an object from some serialized SSDP message.
 pub trait FromRawSSDP: Sized {
     fn raw_ssdp(bytes: &[u8]) -> SSDPResult<Self>;
+
+    /// Construct the object, tolerating the deviations from the standard that the policy allows.
+    ///
+    /// Defaults to `raw_ssdp`, ignoring the policy.
+    fn raw_ssdp_with_policy(bytes: &[u8], _policy: &ParsePolicy) -> SSDPResult<Self> {
+        Self::raw_ssdp(bytes)
+    }
+
+    /// Construct the object from a packet sent from the source address, tolerating the
+    /// deviations from the standard that the policy allows. This is the only method receivers call.
+    ///
+    /// Overriding this is optional, it defaults to `raw_ssdp_with_policy`, ignoring the source.
+    fn raw_ssdp_from_with_policy(bytes: &[u8], _src: SocketAddr, policy: &ParsePolicy) -> SSDPResult<Self> {
+        Self::raw_ssdp_with_policy(bytes, policy)
+    }
+
+    /// Deviations from the standard that the policy tolerated when the object was constructed.
+    ///
+    /// Receivers record these with the anomalies of the source. Defaults to none.
+    fn deviations(&self) -> &[Deviation] {
+        &[]
+    }
+}
+
+/// Result of parsing a packet, carrying the error along with the raw bytes of packets that failed to parse.
+pub type RawResult<T> = Result<T, (SSDPError, Vec<u8>)>;
+
+impl<T: FromRawSSDP> FromRawSSDP for RawResult<T> {
+    fn raw_ssdp(bytes: &[u8]) -> SSDPResult<RawResult<T>> {
+        RawResult::raw_ssdp_with_policy(bytes, &ParsePolicy::default())
+    }
+
+    fn raw_ssdp_with_policy(bytes: &[u8], policy: &ParsePolicy) -> SSDPResult<RawResult<T>> {
+        // Bytes are only copied for packets that failed to parse
+        Ok(T::raw_ssdp_with_policy(bytes, policy).map_err(|err| (err, bytes.to_vec())))
+    }
+
+    fn raw_ssdp_from_with_policy(bytes: &[u8],
+                                 src: SocketAddr,
+                                 policy: &ParsePolicy)
+                                 -> SSDPResult<RawResult<T>> {
+        Ok(T::raw_ssdp_from_with_policy(bytes, src, policy).map_err(|err| (err, bytes.to_vec())))
+    }
+
+    fn deviations(&self) -> &[Deviation] {
+        match *self {
+            Ok(ref n) => n.deviations(),
+            Err(_) => &[],
+        }
+    }
+}
+
+/// Predicate deciding whether a constructed object is delivered through the receiver.
+///
+/// Objects that are not delivered are counted under the reason the predicate gives.
+type Filter<T> = Arc<Fn(&T) -> Result<(), DropReason> + Send + Sync>;
+
+/// Why a filter dropped an object that was constructed from a packet.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DropReason {
+    /// Rejected by the filter for a reason not listed separately.
+    Filtered,
+    /// The max-age of the message was outside of the bounds of `Config::set_max_age_bounds()`.
+    MaxAgeOutOfBounds,
+}
+
+/// Cleanup run with the receiving sockets once the receiver shuts down.
+type CloseHook = Box<FnOnce(&[UdpSocket]) + Send>;
+
+/// Hooks to run once the receiver shuts down, taken by whoever runs them first.
+type SharedCleanup = Arc<Mutex<Option<Cleanup>>>;
+
+/// Handles needed to stop the receiver threads, taken by whoever closes the receiver.
+type SharedShutdown = Arc<Mutex<Option<Shutdown>>>;
+
+/// Spawns a receiver thread for a socket added after the receiver was constructed.
+type Spawner = Box<FnMut(UdpSocket) -> io::Result<Vec<JoinHandle<()>>> + Send>;
+
+/// Upper bound on how long `SSDPReceiver::close` waits for the receiver threads to exit.
+const CLOSE_TIMEOUT_MS: u64 = 1000;
+
+/// How long a blocked sender waits between checks for the receiver being closed.
+const CLOSE_POLL_MS: u64 = 10;
+
+/// Object received along with where and when its packet was received.
+#[derive(Clone, Debug)]
+pub struct ReceivedFrom<T> {
+    /// Object constructed from the packet.
+    pub msg: T,
+    /// Source address of the packet.
+    pub src: SocketAddr,
+    /// Local address the packet was read from, see `SSDPReceiver::recv_with_local`.
+    pub local: SocketAddr,
+    /// When the packet was read from the socket, not when the object was read from the receiver.
+    pub received_at: Instant,
+    /// Wall clock time the packet was read from the socket.
+    pub received_at_wall: SystemTime,
+}
+
+/// Item sent from the receiver threads.
+type Received<T> = ReceivedFrom<T>;
+
+/// Packet that failed to parse: the error, the raw bytes of the packet and its source.
+type ParseFailure = (SSDPError, Vec<u8>, SocketAddr);
+
+/// Number of queued packets the `Polled` backend reads from one socket before moving on to the next.
+pub const DEFAULT_READS_PER_TURN: usize = 16;
+
+/// How often threads forwarding the values of a receiver check whether they should stop.
+const FORWARD_POLL_MS: u64 = 100;
+
+/// Number of parse failures the error stream holds before the oldest ones are dropped.
+const ERROR_STREAM_CAPACITY: usize = 64;
+
+/// Receiving half of the channel, shared so that receiver threads can drop the oldest item.
+type SharedReceiver<T> = Arc<Mutex<Receiver<Received<T>>>>;
+
+/// Packets of a receiver that failed to parse, see `SSDPReceiver::take_error_stream`.
+///
+/// Holds the latest failures that were not read yet, the oldest failure is dropped to
+/// make room for a new one while the stream is full.
+pub struct ErrorStream {
+    recv: Arc<Mutex<Receiver<ParseFailure>>>,
+}
+
+impl ErrorStream {
+    /// Non-blocking method that attempts to read a failure from the stream.
+    pub fn try_recv(&self) -> Result<(SSDPError, Vec<u8>, SocketAddr), TryRecvError> {
+        self.recv.lock().unwrap().try_recv()
+    }
+
+    /// Blocking method that reads a failure from the stream until one is available.
+    pub fn recv(&self) -> Result<(SSDPError, Vec<u8>, SocketAddr), RecvError> {
+        self.recv.lock().unwrap().recv()
+    }
+
+    /// Blocking method that reads a failure from the stream, giving up after the timeout.
+    pub fn recv_timeout(&self,
+                        timeout: Duration)
+                        -> Result<(SSDPError, Vec<u8>, SocketAddr), RecvTimeoutError> {
+        self.recv.lock().unwrap().recv_timeout(timeout)
+    }
+}
+
+/// Counters shared between a receiver and its threads.
+///
+/// Updated with relaxed ordering, the counters are only ever read as a snapshot.
+#[derive(Default)]
+struct ReceiverStats {
+    packets: AtomicUsize,
+    bytes: AtomicUsize,
+    parse_failures: AtomicUsize,
+    delivered: AtomicUsize,
+    dropped: AtomicUsize,
+    paused_drops: AtomicUsize,
+    recv_errors: AtomicUsize,
+    filtered: AtomicUsize,
+    max_age_out_of_bounds: AtomicUsize,
+    // Packets read from each socket, registered by the thread reading from it
+    socket_reads: Mutex<Vec<(SocketAddr, Arc<AtomicUsize>)>>,
+    // Parse failures and tolerated deviations by source, if the options asked to track them
+    anomalies: Option<Mutex<AnomalyTracker>>,
+}
+
+impl ReceiverStats {
+    fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            packets: self.packets.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            parse_failures: self.parse_failures.load(Ordering::Relaxed),
+            delivered: self.delivered.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            paused_drops: self.paused_drops.load(Ordering::Relaxed),
+            recv_errors: self.recv_errors.load(Ordering::Relaxed),
+            filtered: self.filtered.load(Ordering::Relaxed),
+            max_age_out_of_bounds: self.max_age_out_of_bounds.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Count an object that the filter dropped for the reason.
+    fn filter_drop(&self, reason: DropReason) {
+        let counter = match reason {
+            DropReason::Filtered => &self.filtered,
+            DropReason::MaxAgeOutOfBounds => &self.max_age_out_of_bounds,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counter of the packets read from the socket at the local address.
+    fn socket_reads(&self, local: SocketAddr) -> Arc<AtomicUsize> {
+        let reads = Arc::new(AtomicUsize::new(0));
+        self.socket_reads.lock().unwrap().push((local, reads.clone()));
+
+        reads
+    }
+
+    /// Count a packet that failed to parse, recording it with the anomalies of its source if tracked.
+    fn parse_failure(&self, src: SocketAddr, err: &SSDPError, payload: &[u8]) {
+        self.parse_failures.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(ref anomalies) = self.anomalies {
+            anomalies.lock().unwrap().record(src.ip(), err, payload);
+        }
+    }
+
+    /// Record the deviations the parse policy tolerated with the anomalies of the source if tracked.
+    fn deviations(&self, src: SocketAddr, deviations: &[Deviation], payload: &[u8]) {
+        if let (Some(anomalies), false) = (self.anomalies.as_ref(), deviations.is_empty()) {
+            let mut anomalies = anomalies.lock().unwrap();
+
+            for deviation in deviations {
+                anomalies.record(src.ip(), &deviation.error(), payload);
+            }
+        }
+    }
+
+    /// Count a failed read from the socket of the receiver.
+    fn recv_error(&self, recv: &PacketReceiver, err: &io::Error) {
+        debug!("Failed to read from receiver at {}: {}", recv, err);
+        self.recv_errors.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Counters of a receiver at one point in time, see `SSDPReceiver::stats`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    /// Packets read from the sockets.
+    pub packets: usize,
+    /// Total size of the packets read from the sockets, in bytes.
+    pub bytes: usize,
+    /// Packets that could not be parsed.
+    pub parse_failures: usize,
+    /// Objects passed on to the channel, including ones later dropped by `DropOldest`.
+    pub delivered: usize,
+    /// Objects dropped because a receiver constructed with `with_capacity` was full.
+    pub dropped: usize,
+    /// Packets discarded unparsed because the receiver was paused.
+    pub paused_drops: usize,
+    /// Reads from the sockets that failed with an error other than a timeout.
+    pub recv_errors: usize,
+    /// Objects dropped by the filter with `DropReason::Filtered`.
+    pub filtered: usize,
+    /// Messages dropped by the filter with `DropReason::MaxAgeOutOfBounds`.
+    pub max_age_out_of_bounds: usize,
+}
+
+/// What the receiver threads do with a new item while a bounded receiver is full.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drop the new item.
+    DropNewest,
+    /// Drop the oldest item that was not read yet to make room for the new one.
+    DropOldest,
+    /// Wait for room, holding off reading from the socket until the receiver is read from or closed.
+    Block,
+}
+
+/// How a receiver reads from its sockets.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ReceiverBackend {
+    /// One thread per socket, each blocking on a read from its socket.
+    #[default]
+    Threaded,
+    /// A single thread waiting on all of the sockets at once with `poll(2)`.
+    ///
+    /// Falls back to `Threaded` on platforms other than unix. Sockets added to the
+    /// receiver after it was constructed are polled on a thread of their own.
+    Polled,
+}
+
+/// Transformation applied to values as they are read, skipping values it returns `None` for.
+type MapFn<T, U> = Box<FnMut(T, SocketAddr) -> Option<U> + Send>;
+
+/// How a receiver reads packets from its sockets and holds on to the objects constructed from them.
+///
+/// Passed to `SSDPReceiver::with_options`, listeners and searches take theirs from their `Config`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ReceiverOptions {
+    backend: ReceiverBackend,
+    policy: ParsePolicy,
+    bound: Option<(usize, OverflowPolicy)>,
+    max_packet: usize,
+    read_timeout: Option<Duration>,
+    reads_per_turn: usize,
+    anomaly_sources: usize,
+}
+
+impl ReceiverOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Read from the sockets through the backend, see `ReceiverBackend`.
+    pub fn set_backend(mut self, value: ReceiverBackend) -> Self {
+        self.backend = value;
+        self
+    }
+
+    /// Tolerate the deviations from the standard allowed by the policy when constructing objects.
+    pub fn set_parse_policy(mut self, value: ParsePolicy) -> Self {
+        self.policy = value;
+        self
+    }
+
+    /// Hold at most `capacity` unread objects, see `SSDPReceiver::with_capacity`.
+    pub fn set_capacity(mut self, capacity: usize, overflow: OverflowPolicy) -> Self {
+        self.bound = Some((capacity, overflow));
+        self
+    }
+
+    /// Read packets of up to the given number of bytes in full.
+    ///
+    /// Longer packets are truncated and will most likely fail to parse, 2048 bytes by default.
+    pub fn set_max_packet_size(mut self, value: usize) -> Self {
+        self.max_packet = value;
+        self
+    }
+
+    /// Wake the receiver threads up at least once every timeout.
+    ///
+    /// Reads give up after the timeout, upon which the threads check whether the receiver
+    /// was closed and go back to waiting, so closing does not wake them up unless it would
+    /// give up first. Unlike the duration of the receiver, expiry of the read timeout never
+    /// ends the receiver. A zero timeout is treated as none.
+    pub fn set_read_timeout(mut self, value: Option<Duration>) -> Self {
+        self.read_timeout = value.filter(|timeout| *timeout > Duration::from_secs(0));
+        self
+    }
+
+    /// Read at most this many queued packets from one socket before moving on to the next.
+    ///
+    /// Keeps a flooded socket from starving the others of the `Polled` backend, which reads
+    /// the sockets in turns until all of them are drained. 16 by default, 0 is treated as 1.
+    /// The `Threaded` backend reads each socket on its own thread and ignores it.
+    pub fn set_reads_per_turn(mut self, value: usize) -> Self {
+        self.reads_per_turn = cmp::max(value, 1);
+        self
+    }
+
+    /// Track the packets that fail to parse, along with the deviations the parse policy tolerated,
+    /// for up to this many sources, see `SSDPReceiver::anomalies`.
+    ///
+    /// The least recently seen source is evicted when a new one shows up while at capacity.
+    /// 0 by default, which tracks none.
+    pub fn set_anomaly_sources(mut self, value: usize) -> Self {
+        self.anomaly_sources = value;
+        self
+    }
+
+    fn packet_receiver(&self, sock: UdpSocket) -> PacketReceiver {
+        PacketReceiver::with_buffer_size(sock, self.max_packet).with_idle_timeout(self.read_timeout)
+    }
+}
+
+impl Default for ReceiverOptions {
+    fn default() -> ReceiverOptions {
+        ReceiverOptions {
+            backend: ReceiverBackend::default(),
+            policy: ParsePolicy::default(),
+            bound: None,
+            max_packet: DEFAULT_MAX_PCKT_LEN,
+            read_timeout: None,
+            reads_per_turn: DEFAULT_READS_PER_TURN,
+            anomaly_sources: 0,
+        }
+    }
+}
+
+/// Spawns the threads reading from the sockets for one of the backends.
+type SpawnFn<T> = fn(Vec<PacketReceiver>,
+                     Sinks<T>,
+                     Option<Filter<T>>,
+                     ReceiverOptions,
+                     &Arc<AtomicBool>,
+                     Vec<ThreadExit>,
+                     Option<Instant>)
+                     -> io::Result<Vec<JoinHandle<()>>>;
+
+/// Sending half of a channel along with what to do when it is full.
+pub enum Outlet<I> {
+    Unbounded(Sender<I>),
+    Bounded {
+        send: SyncSender<I>,
+        recv: Weak<Mutex<Receiver<I>>>,
+        overflow: OverflowPolicy,
+    },
+}
+
+impl<I> Clone for Outlet<I> {
+    fn clone(&self) -> Outlet<I> {
+        match *self {
+            Outlet::Unbounded(ref send) => Outlet::Unbounded(send.clone()),
+            Outlet::Bounded { ref send, ref recv, overflow } => {
+                Outlet::Bounded {
+                    send: send.clone(),
+                    recv: recv.clone(),
+                    overflow,
+                }
+            }
+        }
+    }
+}
+
+/// What became of an item passed on through an `Outlet`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Delivery {
+    /// Passed on, after dropping this many older items to make room for it.
+    Sent(usize),
+    /// Dropped as the channel was full.
+    Dropped,
+    /// The receiving half hung up or the receiver was closed.
+    HungUp,
+}
+
+/// Channels the receiver threads pass what they received on through.
+struct Sinks<T> {
+    items: Outlet<Received<T>>,
+    errors: Outlet<ParseFailure>,
+    stats: Arc<ReceiverStats>,
+    // Packets are read but discarded while set
+    paused: Arc<AtomicBool>,
+}
+
+impl<T> Clone for Sinks<T> {
+    fn clone(&self) -> Sinks<T> {
+        Sinks {
+            items: self.items.clone(),
+            errors: self.errors.clone(),
+            stats: self.stats.clone(),
+            paused: self.paused.clone(),
+        }
+    }
+}
+
+impl<I> Outlet<I> {
+    /// Channel holding at most `capacity` items, returning the outlet and the shared receiving half.
+    ///
+    /// The outlet only holds on to the receiving half weakly, to drop the oldest item from it.
+    fn bounded(capacity: usize, overflow: OverflowPolicy) -> (Outlet<I>, Arc<Mutex<Receiver<I>>>) {
+        let (send, recv) = mpsc::sync_channel(cmp::max(capacity, 1));
+        let recv = Arc::new(Mutex::new(recv));
+        let send = Outlet::Bounded {
+            send,
+            recv: Arc::downgrade(&recv),
+            overflow,
+        };
+
+        (send, recv)
+    }
+
+    /// Channel holding at most `capacity` items that drops new items while full, returning the outlet
+    /// and the receiving half.
+    pub fn dropping_newest(capacity: usize) -> (Outlet<I>, Receiver<I>) {
+        let (send, recv) = mpsc::sync_channel(cmp::max(capacity, 1));
+        let send = Outlet::Bounded {
+            send,
+            recv: Weak::new(),
+            overflow: OverflowPolicy::DropNewest,
+        };
+
+        (send, recv)
+    }
+
+    /// Pass the item on to the receiving half, applying the overflow policy while it is full.
+    pub fn deliver(&self, item: I, closed: &AtomicBool) -> Delivery {
+        let (send, recv, overflow) = match *self {
+            Outlet::Unbounded(ref send) => {
+                return match send.send(item) {
+                    Ok(()) => Delivery::Sent(0),
+                    Err(_) => Delivery::HungUp,
+                };
+            }
+            Outlet::Bounded { ref send, ref recv, overflow } => (send, recv, overflow),
+        };
+
+        let mut item = item;
+        let mut dropped = 0;
+        loop {
+            item = match send.try_send(item) {
+                Ok(()) => return Delivery::Sent(dropped),
+                Err(TrySendError::Disconnected(_)) => return Delivery::HungUp,
+                Err(TrySendError::Full(item)) => item,
+            };
+
+            match overflow {
+                OverflowPolicy::DropNewest => return Delivery::Dropped,
+                OverflowPolicy::DropOldest => {
+                    let recv = match recv.upgrade() {
+                        Some(recv) => recv,
+                        None => return Delivery::HungUp,
+                    };
+                    if recv.lock().unwrap().try_recv().is_ok() {
+                        dropped += 1;
+                    }
+                }
+                OverflowPolicy::Block => {
+                    if closed.load(Ordering::SeqCst) {
+                        return Delivery::HungUp;
+                    }
+                    thread::sleep(Duration::from_millis(CLOSE_POLL_MS));
+                }
+            }
+        }
+    }
 }
 
 /// Iterator for an `SSDPReceiver`.
@@ -24,6 +548,11 @@ impl<T> SSDPIter<T> {
     fn new(recv: SSDPReceiver<T>) -> SSDPIter<T> {
         SSDPIter { recv: recv }
     }
+
+    /// Next value if one is available right away, never blocks.
+    pub fn try_next(&mut self) -> Option<(T, SocketAddr)> {
+        self.recv.try_recv().ok()
+    }
 }
 
 impl<T> Iterator for SSDPIter<T> {
@@ -34,9 +563,125 @@ impl<T> Iterator for SSDPIter<T> {
     }
 }
 
+/// Iterator over a borrowed `SSDPReceiver`.
+pub struct SSDPRefIter<'a, T: 'a> {
+    recv: &'a SSDPReceiver<T>,
+}
+
+impl<'a, T> Iterator for SSDPRefIter<'a, T> {
+    type Item = (T, SocketAddr);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv.recv().ok()
+    }
+}
+
+/// Iterator over the values a borrowed `SSDPReceiver` already received, never blocks.
+pub struct SSDPTryIter<'a, T: 'a> {
+    recv: &'a SSDPReceiver<T>,
+}
+
+impl<'a, T> Iterator for SSDPTryIter<'a, T> {
+    type Item = (T, SocketAddr);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv.try_recv().ok()
+    }
+}
+
+/// Iterator over a borrowed `SSDPReceiver` that ends at a deadline, see `SSDPReceiver::iter_until`.
+pub struct SSDPUntilIter<'a, T: 'a> {
+    recv: &'a SSDPReceiver<T>,
+    deadline: Instant,
+}
+
+impl<'a, T> Iterator for SSDPUntilIter<'a, T> {
+    type Item = (T, SocketAddr);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let now = Instant::now();
+        if now >= self.deadline {
+            return None;
+        }
+
+        self.recv.recv_timeout(self.deadline - now).ok()
+    }
+}
+
+/// Iterator over a borrowed `SSDPReceiver` that yields the local address each value was received on.
+pub struct SSDPLocalIter<'a, T: 'a> {
+    recv: &'a SSDPReceiver<T>,
+}
+
+impl<'a, T> Iterator for SSDPLocalIter<'a, T> {
+    type Item = (T, SocketAddr, SocketAddr);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv.recv_with_local().ok()
+    }
+}
+
+/// Iterator over a borrowed `SSDPReceiver` that yields where and when each value was received.
+pub struct SSDPMetaIter<'a, T: 'a> {
+    recv: &'a SSDPReceiver<T>,
+}
+
+impl<'a, T> Iterator for SSDPMetaIter<'a, T> {
+    type Item = ReceivedFrom<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv.recv_meta().ok()
+    }
+}
+
 /// A non-blocking SSDP message receiver.
+///
+/// Dropping the receiver signals the receiver threads to exit, use `close()` to
+/// also wait for them to release the sockets.
 pub struct SSDPReceiver<T> {
-    recvr: Receiver<(T, SocketAddr)>,
+    recvr: SharedReceiver<T>,
+    errors: Mutex<Option<ErrorStream>>,
+    stats: Arc<ReceiverStats>,
+    paused: Arc<AtomicBool>,
+    closed: Arc<AtomicBool>,
+    shutdown: SharedShutdown,
+    cleanup: SharedCleanup,
+}
+
+/// Handles needed to stop the receiver threads.
+struct Shutdown {
+    socks: Vec<UdpSocket>,
+    // Receiver threads come back around on their own within the idle timeout, if set
+    idle_timeout: Option<Duration>,
+    // Disconnects once every receiver and watcher thread has exited
+    done: Receiver<()>,
+    done_send: Sender<()>,
+    // Dropping these stops the watcher threads
+    watchers: Vec<Sender<()>>,
+    // Holds a sender for the channel, dropped once every receiver thread has exited
+    spawner: Option<Spawner>,
+    // Every receiver and watcher thread, for reporting the ones that did not exit
+    threads: Vec<JoinHandle<()>>,
+}
+
+/// Close hooks along with handles to the sockets they are run with.
+struct Cleanup {
+    socks: Vec<UdpSocket>,
+    hooks: Vec<CloseHook>,
+}
+
+/// Run the close hooks unless they already ran, logging and swallowing panics.
+fn run_cleanup(cleanup: &Mutex<Option<Cleanup>>) {
+    let Cleanup { socks, hooks } = match cleanup.lock().unwrap().take() {
+        Some(cleanup) => cleanup,
+        None => return,
+    };
+
+    for hook in hooks {
+        if panic::catch_unwind(AssertUnwindSafe(|| hook(&socks))).is_err() {
+            warn!("Receiver shutdown hook panicked");
+        }
+    }
 }
 
 impl<T> SSDPReceiver<T>
@@ -44,111 +689,2392 @@ impl<T> SSDPReceiver<T>
 {
     /// Construct a receiver that receives bytes from a number of UdpSockets and
     /// tries to construct an object T from them. If a duration is provided, the
-    /// channel will be shutdown after the specified duration.
+    /// channel will be shutdown after the specified duration, whether or not any
+    /// packets arrive in the meantime.
     ///
     /// Due to implementation details, none of the UdpSockets should be bound to
     /// the default route, 0.0.0.0, address.
     pub fn new(socks: Vec<UdpSocket>, time: Option<Duration>) -> io::Result<SSDPReceiver<T>> {
-        let (send, recv) = mpsc::channel();
+        SSDPReceiver::spawn(socks, time, None, ReceiverOptions::default())
+    }
+
+    /// Construct a receiver that holds at most `capacity` unread objects T.
+    ///
+    /// Objects that arrive while the receiver is full are handled according to the
+    /// overflow policy, see `dropped` for the number of objects lost to it. A capacity
+    /// of 0 is treated as 1.
+    pub fn with_capacity(socks: Vec<UdpSocket>,
+                         time: Option<Duration>,
+                         capacity: usize,
+                         overflow: OverflowPolicy)
+                         -> io::Result<SSDPReceiver<T>> {
+        SSDPReceiver::spawn(socks, time, None, ReceiverOptions::new().set_capacity(capacity, overflow))
+    }
+
+    /// Construct a receiver that only delivers objects T for which the filter returns true.
+    ///
+    /// The filter is run on the receiver threads, so objects that are filtered out
+    /// are dropped before ever reaching the channel.
+    pub fn with_filter<F>(socks: Vec<UdpSocket>,
+                          time: Option<Duration>,
+                          filter: F)
+                          -> io::Result<SSDPReceiver<T>>
+        where F: Fn(&T) -> bool + Send + Sync + 'static
+    {
+        SSDPReceiver::spawn(socks, time, Some(bool_filter(filter)), ReceiverOptions::default())
+    }
+
+    /// Construct a filtered receiver that parses packets according to the given policy.
+    ///
+    /// Same as `with_filter` except that the deviations from the standard allowed by
+    /// the policy are tolerated when constructing objects T.
+    pub fn with_policy<F>(socks: Vec<UdpSocket>,
+                          time: Option<Duration>,
+                          policy: ParsePolicy,
+                          filter: F)
+                          -> io::Result<SSDPReceiver<T>>
+        where F: Fn(&T) -> bool + Send + Sync + 'static
+    {
+        let options = ReceiverOptions::new().set_parse_policy(policy);
+
+        SSDPReceiver::spawn(socks, time, Some(bool_filter(filter)), options)
+    }
+
+    /// Construct a filtered receiver that reads, parses and holds on to packets according to the options.
+    ///
+    /// Same as `with_filter` except for the options, see `ReceiverOptions`, and that the
+    /// filter gives the reason an object is dropped. Drops are counted by reason in `stats`.
+    pub fn with_options<F>(socks: Vec<UdpSocket>,
+                           time: Option<Duration>,
+                           options: ReceiverOptions,
+                           filter: F)
+                           -> io::Result<SSDPReceiver<T>>
+        where F: Fn(&T) -> Result<(), DropReason> + Send + Sync + 'static
+    {
+        SSDPReceiver::spawn(socks, time, Some(Arc::new(filter)), options)
+    }
+
+    fn spawn(socks: Vec<UdpSocket>,
+             time: Option<Duration>,
+             filter: Option<Filter<T>>,
+             options: ReceiverOptions)
+             -> io::Result<SSDPReceiver<T>> {
+        let (send, recv) = match options.bound {
+            Some((capacity, overflow)) => Outlet::bounded(capacity, overflow),
+            None => {
+                let (send, recv) = mpsc::channel();
+
+                (Outlet::Unbounded(send), Arc::new(Mutex::new(recv)))
+            }
+        };
+        let (error_send, error_recv) = Outlet::bounded(ERROR_STREAM_CAPACITY, OverflowPolicy::DropOldest);
+        let sinks = Sinks {
+            items: send,
+            errors: error_send,
+            stats: Arc::new(ReceiverStats {
+                anomalies: Some(options.anomaly_sources)
+                    .filter(|&sources| sources > 0)
+                    .map(|sources| Mutex::new(AnomalyTracker::new(sources))),
+                ..ReceiverStats::default()
+            }),
+            paused: Arc::new(AtomicBool::new(false)),
+        };
+        let stats = sinks.stats.clone();
+        let paused = sinks.paused.clone();
 
         // Ensure `receive_packets` times out in the event the timeout packet is not received
         for sock in socks.iter() {
             try!(sock.set_read_timeout(time));
         }
 
-        // Spawn Receiver Threads
-        spawn_receivers(socks, send);
+        let wake_socks = socks.iter().map(|sock| sock.try_clone()).collect::<io::Result<Vec<_>>>()?;
+        let cleanup_socks = socks.iter().map(|sock| sock.try_clone()).collect::<io::Result<_>>()?;
+        let closed = Arc::new(AtomicBool::new(false));
+        let cleanup = Arc::new(Mutex::new(Some(Cleanup {
+            socks: cleanup_socks,
+            hooks: Vec::new(),
+        })));
+        let (done_send, done_recv) = mpsc::channel();
+        let deadline = time.map(|time| Instant::now() + time);
+        let shutdown = Arc::new(Mutex::new(None));
 
-        Ok(SSDPReceiver { recvr: recv })
-    }
-}
+        // Spawn Receiver Threads
+        let exits = ExitCounter {
+            running: Arc::new(AtomicUsize::new(0)),
+            cleanup: cleanup.clone(),
+            shutdown: Arc::downgrade(&shutdown),
+            done: done_send.clone(),
+        };
+        let spawn_threads: SpawnFn<T> = match options.backend {
+            ReceiverBackend::Threaded => spawn_receivers,
+            ReceiverBackend::Polled => spawn_polled,
+        };
+        let thread_exits = exits.enter_all(socks.len());
+        let recvs = socks.into_iter().map(|sock| options.packet_receiver(sock)).collect();
+        let spawned = spawn_threads(recvs,
+                                    sinks.clone(),
+                                    filter.clone(),
+                                    options,
+                                    &closed,
+                                    thread_exits,
+                                    deadline);
+        let threads = match spawned {
+            Ok(threads) => threads,
+            Err(err) => {
+                // Stop the threads that did start
+                closed.store(true, Ordering::SeqCst);
+                wake_receivers(&wake_socks);
+                return Err(err);
+            }
+        };
 
-/// Spawn a number of receiver threads that will receive packets, forward the
-/// bytes on to T, and send successfully constructed objects through the sender.
-fn spawn_receivers<T>(socks: Vec<UdpSocket>, sender: Sender<(T, SocketAddr)>)
-    where T: FromRawSSDP + Send + 'static
-{
-    for sock in socks {
-        let pckt_recv = PacketReceiver::new(sock);
-        let sender = sender.clone();
+        let spawn_closed = closed.clone();
+        let spawner: Spawner = Box::new(move |sock: UdpSocket| {
+            sock.set_read_timeout(time)?;
+            spawn_threads(vec![options.packet_receiver(sock)],
+                          sinks.clone(),
+                          filter.clone(),
+                          options,
+                          &spawn_closed,
+                          exits.enter_all(1),
+                          deadline)
+        });
 
-        thread::spawn(move || {
-            receive_packets(pckt_recv, sender);
+        *shutdown.lock().unwrap() = Some(Shutdown {
+            socks: wake_socks,
+            idle_timeout: options.read_timeout,
+            done: done_recv,
+            done_send,
+            watchers: Vec::new(),
+            spawner: Some(spawner),
+            threads,
         });
-    }
-}
 
-impl<T> SSDPReceiver<T> {
-    /// Non-blocking method that attempts to read a value from the receiver.
-    pub fn try_recv(&self) -> Result<(T, SocketAddr), TryRecvError> {
-        self.recvr.try_recv()
+        Ok(SSDPReceiver {
+            recvr: recv,
+            errors: Mutex::new(Some(ErrorStream { recv: error_recv })),
+            stats,
+            paused,
+            closed,
+            shutdown,
+            cleanup,
+        })
     }
+}
 
-    /// Blocking method that reads a value from the receiver until one is available.
-    pub fn recv(&self) -> Result<(T, SocketAddr), RecvError> {
-        self.recvr.recv()
-    }
+/// Counts the running receiver threads, handing each a `ThreadExit` to hold.
+struct ExitCounter {
+    running: Arc<AtomicUsize>,
+    cleanup: SharedCleanup,
+    shutdown: Weak<Mutex<Option<Shutdown>>>,
+    done: Sender<()>,
 }
 
-impl<'a, T> IntoIterator for &'a SSDPReceiver<T> {
-    type Item = (T, SocketAddr);
-    type IntoIter = Iter<'a, (T, SocketAddr)>;
+impl ExitCounter {
+    fn enter_all(&self, count: usize) -> Vec<ThreadExit> {
+        self.running.fetch_add(count, Ordering::SeqCst);
 
-    fn into_iter(self) -> Self::IntoIter {
-        self.recvr.iter()
+        (0..count)
+            .map(|_| {
+                ThreadExit {
+                    running: self.running.clone(),
+                    cleanup: self.cleanup.clone(),
+                    shutdown: self.shutdown.clone(),
+                    _done: self.done.clone(),
+                }
+            })
+            .collect()
     }
 }
 
-impl<'a, T> IntoIterator for &'a mut SSDPReceiver<T> {
-    type Item = (T, SocketAddr);
-    type IntoIter = Iter<'a, (T, SocketAddr)>;
-
-    fn into_iter(self) -> Self::IntoIter {
-        self.recvr.iter()
-    }
+/// Held by each receiver thread until it exits, the last thread to exit runs the close hooks.
+struct ThreadExit {
+    running: Arc<AtomicUsize>,
+    cleanup: SharedCleanup,
+    shutdown: Weak<Mutex<Option<Shutdown>>>,
+    // Disconnects the done channel once every thread has exited
+    _done: Sender<()>,
 }
 
-impl<T> IntoIterator for SSDPReceiver<T> {
-    type Item = (T, SocketAddr);
-    type IntoIter = SSDPIter<T>;
+impl Drop for ThreadExit {
+    fn drop(&mut self) {
+        if self.running.fetch_sub(1, Ordering::SeqCst) != 1 {
+            return;
+        }
 
-    fn into_iter(self) -> Self::IntoIter {
-        SSDPIter::new(self)
+        // Without the spawner the channel disconnects once this thread's sender is gone
+        if let Some(shutdown) = self.shutdown.upgrade() {
+            if let Some(ref mut shutdown) = *shutdown.lock().unwrap() {
+                shutdown.spawner = None;
+            }
+        }
+        run_cleanup(&self.cleanup);
     }
 }
 
-/// Receives bytes and attempts to construct a T which will be sent through the supplied channel.
+/// Spawn a number of receiver threads that will receive packets, forward the
+/// bytes on to T, and send successfully constructed objects through the sinks.
+fn spawn_receivers<T>(recvs: Vec<PacketReceiver>,
+                      sinks: Sinks<T>,
+                      filter: Option<Filter<T>>,
+                      options: ReceiverOptions,
+                      closed: &Arc<AtomicBool>,
+                      exits: Vec<ThreadExit>,
+                      deadline: Option<Instant>)
+                      -> io::Result<Vec<JoinHandle<()>>>
+    where T: FromRawSSDP + Send + 'static
+{
+    let mut threads = Vec::new();
+    for (pckt_recv, exit) in recvs.into_iter().zip(exits) {
+        let sinks = sinks.clone();
+        let filter = filter.clone();
+        let closed = closed.clone();
+
+        let thread = thread::Builder::new().name(format!("ssdp-receiver {}", pckt_recv)).spawn(move || {
+            receive_packets(pckt_recv, sinks, filter, &options.policy, &closed, deadline);
+
+            drop(exit);
+        })?;
+        threads.push(thread);
+    }
+
+    Ok(threads)
+}
+
+/// Spawn a single thread that waits on all of the sockets at once, forwarding the bytes
+/// of each packet on to T and sending successfully constructed objects through the sinks.
+#[cfg(unix)]
+fn spawn_polled<T>(recvs: Vec<PacketReceiver>,
+                   sinks: Sinks<T>,
+                   filter: Option<Filter<T>>,
+                   options: ReceiverOptions,
+                   closed: &Arc<AtomicBool>,
+                   exits: Vec<ThreadExit>,
+                   deadline: Option<Instant>)
+                   -> io::Result<Vec<JoinHandle<()>>>
+    where T: FromRawSSDP + Send + 'static
+{
+    if recvs.is_empty() {
+        return Ok(Vec::new());
+    }
+    let name = format!("ssdp-poller {} sockets", recvs.len());
+    let closed = closed.clone();
+
+    // The thread holds the exit of every socket, so it counts as one receiver per socket
+    let thread = thread::Builder::new().name(name).spawn(move || {
+        poll_packets(recvs, sinks, filter, &options, &closed, deadline);
+
+        drop(exits);
+    })?;
+
+    Ok(vec![thread])
+}
+
+/// Polling is only implemented on unix, fall back to a thread per socket.
+#[cfg(not(unix))]
+fn spawn_polled<T>(recvs: Vec<PacketReceiver>,
+                   sinks: Sinks<T>,
+                   filter: Option<Filter<T>>,
+                   options: ReceiverOptions,
+                   closed: &Arc<AtomicBool>,
+                   exits: Vec<ThreadExit>,
+                   deadline: Option<Instant>)
+                   -> io::Result<Vec<JoinHandle<()>>>
+    where T: FromRawSSDP + Send + 'static
+{
+    spawn_receivers(recvs, sinks, filter, options, closed, exits, deadline)
+}
+
+impl<T> SSDPReceiver<T> {
+    /// Stop receiving, waiting for the receiver threads to exit and close their sockets.
+    ///
+    /// Closing the sockets also leaves any multicast groups they joined. Messages that
+    /// were already received can still be read, after which the receiver reports it is
+    /// disconnected. Gives up waiting after one second, closing again is a no-op.
+    pub fn close(&self) {
+        if let Err(err) = self.close_within(Duration::from_millis(CLOSE_TIMEOUT_MS)) {
+            warn!("{}", err);
+        }
+    }
+
+    /// Stop receiving, waiting up to the timeout for every receiver and watcher thread to exit.
+    ///
+    /// Unlike `close` every thread is joined, and the threads that did not exit in time are
+    /// reported by name in a `TimedOut` error. Panicked threads are reported as well. Like
+    /// `close`, closing again is a no-op.
+    pub fn close_and_join(&self, timeout: Duration) -> io::Result<()> {
+        self.close_within(timeout)
+    }
+
+    fn close_within(&self, timeout: Duration) -> io::Result<()> {
+        self.closed.store(true, Ordering::SeqCst);
+
+        let shutdown = self.shutdown.lock().unwrap().take();
+        let Shutdown { socks, idle_timeout, done, done_send, watchers, spawner, threads } = match shutdown {
+            Some(shutdown) => shutdown,
+            None => return Ok(()),
+        };
+        drop(done_send);
+        drop(watchers);
+        drop(spawner);
+
+        // Threads with an idle timeout notice the close on their own, unless that takes too long
+        if idle_timeout.is_none_or(|idle| idle >= timeout) {
+            wake_receivers(&socks);
+        }
+        let exited = done.recv_timeout(timeout) == Err(RecvTimeoutError::Disconnected);
+
+        // Only runs the hooks if the receiver threads did not get to it first
+        run_cleanup(&self.cleanup);
+
+        if !exited {
+            let running = threads.iter()
+                .filter(|thread| !thread.is_finished())
+                .map(|thread| thread.thread().name().unwrap_or("unnamed").to_owned())
+                .collect::<Vec<_>>();
+            let msg = format!("Receiver threads did not exit within {}ms: {}",
+                              timeout.as_millis(),
+                              running.join(", "));
+
+            return Err(io::Error::new(io::ErrorKind::TimedOut, msg));
+        }
+
+        let panicked = threads.into_iter()
+            .filter_map(|thread| {
+                let name = thread.thread().name().unwrap_or("unnamed").to_owned();
+                thread.join().err().map(|_| name)
+            })
+            .collect::<Vec<_>>();
+        if !panicked.is_empty() {
+            return Err(io::Error::other(format!("Receiver threads panicked: {}", panicked.join(", "))));
+        }
+
+        Ok(())
+    }
+
+    /// Run the closure once the receiver shuts down.
+    ///
+    /// The receiver shuts down once every receiver thread has exited, on timeout or close,
+    /// or when it is dropped, whichever comes first. The closure runs right away if the
+    /// receiver already shut down. Panics in the closure are logged and swallowed.
+    pub fn on_shutdown<F>(&self, f: F)
+        where F: FnOnce() + Send + 'static
+    {
+        if let Some(ref mut cleanup) = *self.cleanup.lock().unwrap() {
+            cleanup.hooks.push(Box::new(move |_: &[UdpSocket]| f()));
+            return;
+        }
+
+        if panic::catch_unwind(AssertUnwindSafe(f)).is_err() {
+            warn!("Receiver shutdown hook panicked");
+        }
+    }
+
+    /// Non-blocking method that attempts to read a value from the receiver.
+    pub fn try_recv(&self) -> Result<(T, SocketAddr), TryRecvError> {
+        self.try_recv_meta().map(|received| (received.msg, received.src))
+    }
+
+    /// Blocking method that reads a value from the receiver until one is available.
+    pub fn recv(&self) -> Result<(T, SocketAddr), RecvError> {
+        self.recv_meta().map(|received| (received.msg, received.src))
+    }
+
+    /// Blocking method that reads a value from the receiver, giving up after the timeout.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<(T, SocketAddr), RecvTimeoutError> {
+        self.recvr.lock().unwrap().recv_timeout(timeout).map(|received| (received.msg, received.src))
+    }
+
+    /// Non-blocking method that attempts to read a value along with the local address it was received on.
+    pub fn try_recv_with_local(&self) -> Result<(T, SocketAddr, SocketAddr), TryRecvError> {
+        self.try_recv_meta().map(|received| (received.msg, received.src, received.local))
+    }
+
+    /// Blocking method that reads a value along with the local address it was received on.
+    ///
+    /// The local address is the address of the socket that received the value. For sockets
+    /// bound to the unspecified address it is the address of the interface the packet
+    /// arrived on instead, on platforms that report it through `IP_PKTINFO` or
+    /// `IPV6_RECVPKTINFO`, and the unspecified address elsewhere.
+    pub fn recv_with_local(&self) -> Result<(T, SocketAddr, SocketAddr), RecvError> {
+        self.recv_meta().map(|received| (received.msg, received.src, received.local))
+    }
+
+    /// Non-blocking method that attempts to read a value along with where and when it was received.
+    ///
+    /// Returns `TryRecvError::Empty` while another thread is blocked reading from the receiver.
+    pub fn try_recv_meta(&self) -> Result<ReceivedFrom<T>, TryRecvError> {
+        match self.recvr.try_lock() {
+            Ok(recvr) => recvr.try_recv(),
+            Err(TryLockError::WouldBlock) => Err(TryRecvError::Empty),
+            Err(TryLockError::Poisoned(err)) => err.into_inner().try_recv(),
+        }
+    }
+
+    /// Blocking method that reads a value along with where and when it was received.
+    pub fn recv_meta(&self) -> Result<ReceivedFrom<T>, RecvError> {
+        self.recvr.lock().unwrap().recv()
+    }
+
+    /// Blocking method that reads a value along with where and when it was received, waiting at most
+    /// the timeout.
+    pub fn recv_timeout_meta(&self, timeout: Duration) -> Result<ReceivedFrom<T>, RecvTimeoutError> {
+        self.recvr.lock().unwrap().recv_timeout(timeout)
+    }
+
+    /// Take the stream of packets that failed to parse, along with their raw bytes and source.
+    ///
+    /// The stream holds the latest 64 failures that were not read yet, see `ErrorStream`.
+    /// Returns `None` if the stream was already taken.
+    pub fn take_error_stream(&self) -> Option<ErrorStream> {
+        self.errors.lock().unwrap().take()
+    }
+
+    /// Number of values dropped because a receiver constructed with `with_capacity` was full.
+    pub fn dropped(&self) -> usize {
+        self.stats.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Stop delivering values while keeping the sockets open and their multicast groups joined.
+    ///
+    /// Packets are still read, so the socket buffers do not fill up, but are discarded
+    /// without being parsed. See `StatsSnapshot::paused_drops` for the number discarded.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Deliver values again after `pause`, packets that arrived in the meantime are lost.
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    /// Whether delivery is paused, see `pause`.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Snapshot of the counters of packets received, parsed and delivered so far.
+    pub fn stats(&self) -> StatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    /// Anomalies of the packets that failed to parse by source, most recently seen first.
+    ///
+    /// Points out the device sending malformed messages. Deviations that the parse policy tolerated
+    /// are recorded under the error they would have failed to parse with, see `Deviation::error`.
+    /// Empty unless enabled through `ReceiverOptions::set_anomaly_sources`.
+    pub fn anomalies(&self) -> Vec<(IpAddr, SourceAnomalies)> {
+        match self.stats.anomalies {
+            Some(ref anomalies) => anomalies.lock().unwrap().report(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Number of packets read from each socket so far, by the local address of the socket.
+    ///
+    /// A socket that is read from far less than the others while packets are sent to all of
+    /// them is being starved. Sockets show up once their thread started reading from them.
+    pub fn reads_per_socket(&self) -> Vec<(SocketAddr, usize)> {
+        self.stats
+            .socket_reads
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(local, reads)| (*local, reads.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    /// Effective receive buffer size of each socket, in bytes.
+    ///
+    /// Kernels clamp the requested size, and Linux doubles it for bookkeeping. Empty once closed.
+    pub fn recv_buffer_sizes(&self) -> io::Result<Vec<usize>> {
+        match *self.shutdown.lock().unwrap() {
+            Some(ref shutdown) => shutdown.socks.iter().map(|sock| sock.recv_buffer_size()).collect(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Read up to `n` values, then close the receiver so its sockets are released right away.
+    ///
+    /// Returns fewer values if the receiver shuts down first. Adapters such as `unique_by`
+    /// have their own `take_first`, which counts the values that made it through the adapter.
+    pub fn take_first(self, n: usize) -> Vec<(T, SocketAddr)> {
+        let taken = (&self).into_iter().take(n).collect();
+        self.close();
+
+        taken
+    }
+
+    /// Read the first value, then close the receiver, see `take_first`.
+    pub fn first(self) -> Option<(T, SocketAddr)> {
+        self.take_first(1).pop()
+    }
+
+    /// Iterate over the values that were already received, without blocking.
+    ///
+    /// Ends as soon as no value is available, so it can be drained again later on.
+    pub fn try_iter(&self) -> SSDPTryIter<'_, T> {
+        SSDPTryIter { recv: self }
+    }
+
+    /// Iterate over the values received until the deadline.
+    ///
+    /// Ends at the deadline even if nothing is received, or earlier if the receiver shuts down.
+    pub fn iter_until(&self, deadline: Instant) -> SSDPUntilIter<'_, T> {
+        SSDPUntilIter {
+            recv: self,
+            deadline,
+        }
+    }
+
+    /// Iterate over the values received for the duration, see `iter_until`.
+    pub fn iter_for(&self, duration: Duration) -> SSDPUntilIter<'_, T> {
+        self.iter_until(Instant::now() + duration)
+    }
+
+    /// Iterate over the values along with the local address each was received on.
+    pub fn iter_with_local(&self) -> SSDPLocalIter<'_, T> {
+        SSDPLocalIter { recv: self }
+    }
+
+    /// Iterate over the values along with where and when each was received.
+    pub fn iter_meta(&self) -> SSDPMetaIter<'_, T> {
+        SSDPMetaIter { recv: self }
+    }
+}
+
+impl<T> Drop for SSDPReceiver<T> {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::SeqCst);
+
+        if let Some(ref mut shutdown) = *self.shutdown.lock().unwrap() {
+            shutdown.watchers.clear();
+            shutdown.spawner = None;
+            run_cleanup(&self.cleanup);
+            wake_receivers(&shutdown.socks);
+        }
+    }
+}
+
+/// Run the closure with the receiving sockets every interval on its own thread until the
+/// receiver is closed or dropped.
 ///
-/// This should almost always be run in it's own thread.
-fn receive_packets<T>(recv: PacketReceiver, send: Sender<(T, SocketAddr)>)
-    where T: FromRawSSDP + Send
+/// Closing the receiver waits for the watcher thread to exit as well, so the sockets
+/// are released by then.
+pub fn spawn_watcher<T, F>(receiver: &SSDPReceiver<T>, interval: Duration, mut f: F) -> io::Result<()>
+    where F: FnMut(&[UdpSocket]) + Send + 'static
 {
-    // TODO: Add logging to this function. Maybe forward sender IP Address along
-    // so that we can do some checks when we parse the http.
-    loop {
-        trace!("Waiting on packet at {}...", recv);
-        let (msg_bytes, addr) = match recv.recv_pckt() {
-            Ok((bytes, addr)) => (bytes, addr),
-            // Unix returns WouldBlock on timeout while Windows returns TimedOut
-            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock ||
-                            err.kind() == io::ErrorKind::TimedOut => {
-                // We have waited for at least the desired timeout (or possibly longer)
-                trace!("Receiver at {} timed out", recv);
-                return;
-            }
-            Err(_) => {
-                continue;
-            }
+    let mut guard = receiver.shutdown.lock().unwrap();
+    let shutdown = match *guard {
+        Some(ref mut shutdown) => shutdown,
+        None => return Ok(()),
+    };
+
+    let socks = shutdown.socks.iter().map(|sock| sock.try_clone()).collect::<io::Result<Vec<_>>>()?;
+    let (stop_send, stop_recv) = mpsc::channel::<()>();
+    let done = shutdown.done_send.clone();
+    shutdown.watchers.push(stop_send);
+
+    let thread = thread::Builder::new().name("ssdp-watcher".to_owned()).spawn(move || {
+        while let Err(RecvTimeoutError::Timeout) = stop_recv.recv_timeout(interval) {
+            f(&socks);
+        }
+
+        drop(done);
+    })?;
+    shutdown.threads.push(thread);
+
+    Ok(())
+}
+
+/// Handle for adding sockets to a receiver from another thread, see `socket_adder`.
+pub struct SocketAdder {
+    shutdown: Weak<Mutex<Option<Shutdown>>>,
+}
+
+impl SocketAdder {
+    /// Start receiving on the socket as well.
+    ///
+    /// Returns false, dropping the socket, if the receiver already shut down.
+    pub fn add(&self, sock: UdpSocket) -> io::Result<bool> {
+        let shutdown = match self.shutdown.upgrade() {
+            Some(shutdown) => shutdown,
+            None => return Ok(false),
+        };
+        let mut guard = shutdown.lock().unwrap();
+        let shutdown = match *guard {
+            Some(ref mut shutdown) => shutdown,
+            None => return Ok(false),
+        };
+
+        let wake_sock = sock.try_clone()?;
+        let threads = match shutdown.spawner {
+            Some(ref mut spawner) => spawner(sock)?,
+            None => return Ok(false),
         };
+        shutdown.socks.push(wake_sock);
+        shutdown.threads.extend(threads);
 
-        trace!("Received packet with {} bytes", msg_bytes.len());
+        Ok(true)
+    }
+}
 
-        // Unwrap Will Cause A Panic If Receiver Hung Up Which Is Desired
-        match T::raw_ssdp(&msg_bytes[..]) {
-            Ok(n) => send.send((n, addr)).unwrap(),
-            Err(_) => {
-                continue;
+/// Handle for adding sockets to the receiver after it was constructed.
+///
+/// Added sockets share the receiver's filter, policy and deadline, and are woken up
+/// along with the other sockets when the receiver is closed or dropped.
+pub fn socket_adder<T>(receiver: &SSDPReceiver<T>) -> SocketAdder {
+    SocketAdder { shutdown: Arc::downgrade(&receiver.shutdown) }
+}
+
+/// Run the closure with the receiving sockets once the receiver shuts down, see `SSDPReceiver::on_shutdown`.
+///
+/// If the receiver already shut down the closure is never run, its sockets are gone.
+pub fn on_close<T, F>(receiver: &SSDPReceiver<T>, f: F)
+    where F: FnOnce(&[UdpSocket]) + Send + 'static
+{
+    if let Some(ref mut cleanup) = *receiver.cleanup.lock().unwrap() {
+        cleanup.hooks.push(Box::new(f));
+    }
+}
+
+/// Unblock the receiver threads reading from the sockets, once per socket.
+///
+/// Sockets are shut down for reading where the platform supports it, otherwise each is sent
+/// a single empty datagram. With `SO_REUSEPORT` the datagram may be delivered to another socket
+/// sharing the port, in which case the receiver thread exits on its next read or idle timeout.
+fn wake_receivers(socks: &[UdpSocket]) {
+    for sock in socks {
+        if net::shutdown_read(sock).is_ok() {
+            continue;
+        }
+
+        let result = sock.local_addr().and_then(|addr| {
+            let wake_ip = match addr.ip() {
+                IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)),
+                ip => ip,
+            };
+            let wake_addr = SocketAddr::new(wake_ip, addr.port());
+            let local_addr = SocketAddr::new(wake_ip, 0);
+
+            UdpSocket::bind(local_addr).and_then(|wake_sock| wake_sock.send_to(&[], wake_addr))
+        });
+
+        if let Err(err) = result {
+            debug!("Failed to wake receiver thread: {}", err);
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a SSDPReceiver<T> {
+    type Item = (T, SocketAddr);
+    type IntoIter = SSDPRefIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        SSDPRefIter { recv: self }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut SSDPReceiver<T> {
+    type Item = (T, SocketAddr);
+    type IntoIter = SSDPRefIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        SSDPRefIter { recv: self }
+    }
+}
+
+impl<T> IntoIterator for SSDPReceiver<T> {
+    type Item = (T, SocketAddr);
+    type IntoIter = SSDPIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        SSDPIter::new(self)
+    }
+}
+
+impl<T> SSDPReceiver<T> {
+    /// Wrap the receiver so that values with the same key as an earlier value are skipped.
+    ///
+    /// Every key is remembered unless a capacity is set with `UniqueReceiver::set_capacity`.
+    pub fn unique_by<K, F>(self, key: F) -> UniqueReceiver<T, K, F>
+        where K: Hash + Eq + Clone,
+              F: FnMut(&T, &SocketAddr) -> K
+    {
+        UniqueReceiver {
+            recv: self,
+            key,
+            seen: HashMap::new(),
+            order: BTreeMap::new(),
+            uses: 0,
+            capacity: None,
+        }
+    }
+}
+
+impl<T: 'static> SSDPReceiver<T> {
+    /// Wrap the receiver so that values are transformed as they are read.
+    pub fn map<U, F>(self, mut f: F) -> MapReceiver<T, U>
+        where F: FnMut(T, SocketAddr) -> U + Send + 'static
+    {
+        self.filter_map(move |msg, src| Some(f(msg, src)))
+    }
+
+    /// Wrap the receiver so that values are transformed as they are read, skipping values
+    /// the closure returns `None` for.
+    pub fn filter_map<U, F>(self, f: F) -> MapReceiver<T, U>
+        where F: FnMut(T, SocketAddr) -> Option<U> + Send + 'static
+    {
+        MapReceiver {
+            recv: self,
+            f: Box::new(f),
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<T: Send + 'static> SSDPReceiver<T> {
+    /// Turn the receiver into a `Stream` of values, for use from async code.
+    ///
+    /// Values are read on a thread of its own, so no particular async runtime is needed.
+    /// The stream ends once the receiver times out or is closed, dropping the stream
+    /// drops the receiver.
+    pub fn into_stream(self) -> SSDPStream<T> {
+        let (send, recv) = futures::channel::mpsc::unbounded();
+
+        thread::spawn(move || feed_stream(&self, &send));
+
+        SSDPStream { recv }
+    }
+}
+
+/// Pass values on to the stream until the receiver shuts down or the stream is dropped.
+#[cfg(feature = "stream")]
+fn feed_stream<T>(recv: &SSDPReceiver<T>, send: &UnboundedSender<(T, SocketAddr)>) {
+    // Waking up regularly lets an idle receiver without timeout notice the stream was dropped
+    while !send.is_closed() {
+        match recv.recv_timeout(Duration::from_millis(FORWARD_POLL_MS)) {
+            Ok(item) => {
+                if send.unbounded_send(item).is_err() {
+                    return;
+                }
             }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// Stream of the values of an `SSDPReceiver`, see `SSDPReceiver::into_stream`.
+#[cfg(feature = "stream")]
+pub struct SSDPStream<T> {
+    recv: UnboundedReceiver<(T, SocketAddr)>,
+}
+
+#[cfg(feature = "stream")]
+impl<T> Stream for SSDPStream<T> {
+    type Item = (T, SocketAddr);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.recv).poll_next(cx)
+    }
+}
+
+/// Receiver whose values are forwarded into a merged receiver, see `Merger`.
+trait MergeSource<U>: Send {
+    fn recv_timeout(&self, timeout: Duration) -> Result<Received<U>, RecvTimeoutError>;
+
+    fn close(&self);
+}
+
+impl<T: Send, U: From<T>> MergeSource<U> for SSDPReceiver<T> {
+    fn recv_timeout(&self, timeout: Duration) -> Result<Received<U>, RecvTimeoutError> {
+        let ReceivedFrom { msg, src, local, received_at, received_at_wall } =
+            self.recvr.lock().unwrap().recv_timeout(timeout)?;
+
+        Ok(ReceivedFrom {
+            msg: U::from(msg),
+            src,
+            local,
+            received_at,
+            received_at_wall,
+        })
+    }
+
+    fn close(&self) {
+        SSDPReceiver::close(self)
+    }
+}
+
+/// Builder for a receiver that delivers the values of a number of receivers.
+///
+/// Values are converted into U, so receivers of different types can be merged, e.g. into a
+/// `ReceivedMessage`. Each receiver is read from on a thread of its own, until it shuts down
+/// or the merged receiver is closed or dropped, which closes the remaining receivers as well.
+///
+/// Error streams are not merged, take them from the receivers before adding them.
+pub struct Merger<U> {
+    sources: Vec<Box<MergeSource<U>>>,
+}
+
+impl<U: Send + 'static> Merger<U> {
+    pub fn new() -> Self {
+        Merger { sources: Vec::new() }
+    }
+
+    /// Deliver the values of the receiver through the merged receiver as well.
+    pub fn add_receiver<T>(mut self, recv: SSDPReceiver<T>) -> Self
+        where T: Send + 'static,
+              U: From<T>
+    {
+        self.sources.push(Box::new(recv));
+        self
+    }
+
+    /// Start forwarding the values of the receivers into the merged receiver.
+    ///
+    /// The merged receiver shuts down once every receiver did. Its statistics only count
+    /// values delivered and values discarded while paused.
+    pub fn build(self) -> io::Result<SSDPReceiver<U>> {
+        let (send, recv) = mpsc::channel();
+        let stats = Arc::new(ReceiverStats::default());
+        let paused = Arc::new(AtomicBool::new(false));
+        let closed = Arc::new(AtomicBool::new(false));
+        let cleanup = Arc::new(Mutex::new(Some(Cleanup {
+            socks: Vec::new(),
+            hooks: Vec::new(),
+        })));
+        let (done_send, done_recv) = mpsc::channel();
+        let shutdown = Arc::new(Mutex::new(None));
+
+        let exits = ExitCounter {
+            running: Arc::new(AtomicUsize::new(0)),
+            cleanup: cleanup.clone(),
+            shutdown: Arc::downgrade(&shutdown),
+            done: done_send.clone(),
         };
+        let thread_exits = exits.enter_all(self.sources.len());
+        let mut threads = Vec::new();
+        for (source, exit) in self.sources.into_iter().zip(thread_exits) {
+            let send = send.clone();
+            let stats = stats.clone();
+            let paused = paused.clone();
+            let forward_closed = closed.clone();
+
+            let spawned = thread::Builder::new().name("ssdp-forwarder".to_owned()).spawn(move || {
+                forward_merged(&*source, &send, &stats, &paused, &forward_closed);
+                source.close();
+
+                drop(exit);
+            });
+            match spawned {
+                Ok(thread) => threads.push(thread),
+                Err(err) => {
+                    // Stop the forwarders that did start
+                    closed.store(true, Ordering::SeqCst);
+                    return Err(err);
+                }
+            }
+        }
+
+        *shutdown.lock().unwrap() = Some(Shutdown {
+            socks: Vec::new(),
+            idle_timeout: None,
+            done: done_recv,
+            done_send,
+            watchers: Vec::new(),
+            spawner: None,
+            threads,
+        });
+
+        Ok(SSDPReceiver {
+            recvr: Arc::new(Mutex::new(recv)),
+            errors: Mutex::new(None),
+            stats,
+            paused,
+            closed,
+            shutdown,
+            cleanup,
+        })
+    }
+}
+
+impl<U: Send + 'static> Default for Merger<U> {
+    fn default() -> Self {
+        Merger::new()
+    }
+}
+
+impl<T: Send + 'static> SSDPReceiver<T> {
+    /// Merge the receivers into one that delivers the values of all of them, see `Merger`.
+    pub fn select(receivers: Vec<SSDPReceiver<T>>) -> io::Result<SSDPReceiver<T>> {
+        receivers.into_iter().fold(Merger::new(), Merger::add_receiver).build()
+    }
+}
+
+/// Pass values of the source on to the merged receiver until either one shuts down.
+fn forward_merged<U>(source: &MergeSource<U>,
+                     send: &Sender<Received<U>>,
+                     stats: &ReceiverStats,
+                     paused: &AtomicBool,
+                     closed: &AtomicBool) {
+    // Waking up regularly lets an idle source notice the merged receiver was closed
+    while !closed.load(Ordering::SeqCst) {
+        match source.recv_timeout(Duration::from_millis(FORWARD_POLL_MS)) {
+            Ok(_) if paused.load(Ordering::SeqCst) => {
+                stats.paused_drops.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(item) => {
+                if send.send(item).is_err() {
+                    return;
+                }
+                stats.delivered.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// Receiver that transforms values as they are read, see `SSDPReceiver::map`.
+///
+/// Values keep the source address they were received from.
+pub struct MapReceiver<T, U> {
+    recv: SSDPReceiver<T>,
+    f: MapFn<T, U>,
+}
+
+impl<T: 'static, U: 'static> MapReceiver<T, U> {
+    /// Transform the values further as they are read.
+    pub fn map<V, F>(self, mut f: F) -> MapReceiver<T, V>
+        where F: FnMut(U, SocketAddr) -> V + Send + 'static
+    {
+        self.filter_map(move |msg, src| Some(f(msg, src)))
+    }
+
+    /// Transform the values further as they are read, skipping values the closure returns `None` for.
+    pub fn filter_map<V, F>(self, mut f: F) -> MapReceiver<T, V>
+        where F: FnMut(U, SocketAddr) -> Option<V> + Send + 'static
+    {
+        let MapReceiver { recv, f: mut first } = self;
+
+        MapReceiver {
+            recv,
+            f: Box::new(move |msg, src| first(msg, src).and_then(|msg| f(msg, src))),
+        }
+    }
+}
+
+impl<T, U> MapReceiver<T, U> {
+    /// Non-blocking method that attempts to read a transformed value.
+    pub fn try_recv(&mut self) -> Result<(U, SocketAddr), TryRecvError> {
+        loop {
+            let (msg, src) = self.recv.try_recv()?;
+            if let Some(msg) = (self.f)(msg, src) {
+                return Ok((msg, src));
+            }
+        }
+    }
+
+    /// Blocking method that reads a transformed value.
+    pub fn recv(&mut self) -> Result<(U, SocketAddr), RecvError> {
+        loop {
+            let (msg, src) = self.recv.recv()?;
+            if let Some(msg) = (self.f)(msg, src) {
+                return Ok((msg, src));
+            }
+        }
+    }
+
+    /// Blocking method that reads a transformed value, giving up after the timeout.
+    ///
+    /// Time spent on values that were skipped counts towards the timeout.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<(U, SocketAddr), RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let (msg, src) = self.recv.recv_timeout(remaining)?;
+            if let Some(msg) = (self.f)(msg, src) {
+                return Ok((msg, src));
+            }
+        }
+    }
+
+    /// Underlying receiver, for closing it or reading its statistics.
+    pub fn get_ref(&self) -> &SSDPReceiver<T> {
+        &self.recv
+    }
+
+    /// Read up to `n` transformed values, then close the underlying receiver.
+    ///
+    /// Skipped values do not count towards `n`, see `SSDPReceiver::take_first`.
+    pub fn take_first(mut self, n: usize) -> Vec<(U, SocketAddr)> {
+        let taken = self.by_ref().take(n).collect();
+        self.recv.close();
+
+        taken
+    }
+}
+
+impl<T, U> Iterator for MapReceiver<T, U> {
+    type Item = (U, SocketAddr);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv().ok()
+    }
+}
+
+/// Receiver that skips values whose key was already seen, see `SSDPReceiver::unique_by`.
+pub struct UniqueReceiver<T, K, F> {
+    recv: SSDPReceiver<T>,
+    key: F,
+    // Key along with when it was last seen, and the keys ordered by when they were last seen
+    // for evicting the least recently seen key
+    seen: HashMap<K, u64>,
+    order: BTreeMap<u64, K>,
+    uses: u64,
+    capacity: Option<usize>,
+}
+
+impl<T, K, F> UniqueReceiver<T, K, F>
+    where K: Hash + Eq + Clone,
+          F: FnMut(&T, &SocketAddr) -> K
+{
+    /// Remember at most `capacity` keys, forgetting the least recently seen key first.
+    ///
+    /// A value whose key was forgotten is delivered again. A capacity of 0 is treated as 1.
+    pub fn set_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(cmp::max(capacity, 1));
+        self
+    }
+
+    /// Non-blocking method that attempts to read a value whose key was not seen before.
+    pub fn try_recv(&mut self) -> Result<(T, SocketAddr), TryRecvError> {
+        loop {
+            let (msg, src) = self.recv.try_recv()?;
+            if self.first_seen(&msg, &src) {
+                return Ok((msg, src));
+            }
+        }
+    }
+
+    /// Blocking method that reads a value whose key was not seen before.
+    pub fn recv(&mut self) -> Result<(T, SocketAddr), RecvError> {
+        loop {
+            let (msg, src) = self.recv.recv()?;
+            if self.first_seen(&msg, &src) {
+                return Ok((msg, src));
+            }
+        }
+    }
+
+    /// Underlying receiver, for closing it or reading its statistics.
+    pub fn get_ref(&self) -> &SSDPReceiver<T> {
+        &self.recv
+    }
+
+    /// Read up to `n` values with distinct keys, then close the underlying receiver.
+    ///
+    /// Duplicates do not count towards `n`, see `SSDPReceiver::take_first`.
+    pub fn take_first(mut self, n: usize) -> Vec<(T, SocketAddr)> {
+        let taken = self.by_ref().take(n).collect();
+        self.recv.close();
+
+        taken
+    }
+
+    /// Record the key of the value, returns true if it was not seen before.
+    fn first_seen(&mut self, msg: &T, src: &SocketAddr) -> bool {
+        let key = (self.key)(msg, src);
+        self.uses += 1;
+
+        if let Some(used) = self.seen.get_mut(&key) {
+            if let Some(key) = self.order.remove(used) {
+                self.order.insert(self.uses, key);
+            }
+            *used = self.uses;
+            return false;
+        }
+        self.order.insert(self.uses, key.clone());
+        self.seen.insert(key, self.uses);
+
+        let capacity = self.capacity.unwrap_or(usize::MAX);
+        while self.seen.len() > capacity {
+            match self.order.pop_first() {
+                Some((_, oldest)) => self.seen.remove(&oldest),
+                None => break,
+            };
+        }
+
+        true
+    }
+}
+
+impl<T, K, F> Iterator for UniqueReceiver<T, K, F>
+    where K: Hash + Eq + Clone,
+          F: FnMut(&T, &SocketAddr) -> K
+{
+    type Item = (T, SocketAddr);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv().ok()
+    }
+}
+
+/// Receives bytes and attempts to construct a T which will be sent through the supplied channel.
+///
+/// Receiving stops once the deadline, if any, has passed. This should almost always be
+/// run in it's own thread.
+fn receive_packets<T>(recv: PacketReceiver,
+                      sinks: Sinks<T>,
+                      filter: Option<Filter<T>>,
+                      policy: &ParsePolicy,
+                      closed: &AtomicBool,
+                      deadline: Option<Instant>)
+    where T: FromRawSSDP + Send
+{
+    // TODO: Add logging to this function. Maybe forward sender IP Address along
+    // so that we can do some checks when we parse the http.
+    let local_addr = match recv.local_addr() {
+        Ok(addr) => addr,
+        Err(err) => {
+            warn!("Failed to get local address of receiver: {}", err);
+            return;
+        }
+    };
+
+    let reads = sinks.stats.socket_reads(local_addr);
+
+    let idle_timeout = recv.idle_timeout();
+    if deadline.is_none() && idle_timeout.is_some() {
+        if let Err(err) = recv.set_read_timeout(idle_timeout) {
+            warn!("Failed to set read timeout on receiver at {}: {}", recv, err);
+            return;
+        }
+    }
+
+    loop {
+        if let Some(deadline) = deadline {
+            let now = Instant::now();
+            if now >= deadline {
+                trace!("Receiver at {} reached its deadline", recv);
+                return;
+            }
+
+            // Shrink the timeout so a steady stream of packets can not extend the deadline
+            let timeout = idle_timeout.map_or(deadline - now, |idle| cmp::min(idle, deadline - now));
+            if let Err(err) = recv.set_read_timeout(Some(timeout)) {
+                warn!("Failed to set read timeout on receiver at {}: {}", recv, err);
+                return;
+            }
+        }
+
+        trace!("Waiting on packet at {}...", recv);
+        let result = recv.recv_pckt();
+
+        if closed.load(Ordering::SeqCst) {
+            trace!("Receiver at {} closed", recv);
+            return;
+        }
+
+        let packet = match result {
+            Ok(packet) => packet,
+            // Unix returns WouldBlock on timeout while Windows returns TimedOut
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock ||
+                            err.kind() == io::ErrorKind::TimedOut => {
+                // Idle timeouts only wake the receiver up, the deadline is checked at the top
+                if idle_timeout.is_some() {
+                    trace!("Receiver at {} idle", recv);
+                    continue;
+                }
+
+                // We have waited for at least the desired timeout (or possibly longer)
+                trace!("Receiver at {} timed out", recv);
+                return;
+            }
+            Err(err) => {
+                sinks.stats.recv_error(&recv, &err);
+                continue;
+            }
+        };
+
+        reads.fetch_add(1, Ordering::Relaxed);
+        if !dispatch_packet(&recv, packet, &sinks, &filter, policy, closed) {
+            return;
+        }
+    }
+}
+
+/// Waits on all of the receivers at once, constructing a T from each packet that arrives and
+/// sending it through the supplied channel.
+///
+/// Same as `receive_packets` except that the receivers are set to non-blocking and read from
+/// once `poll(2)` reports them readable.
+#[cfg(unix)]
+fn poll_packets<T>(recvs: Vec<PacketReceiver>,
+                   sinks: Sinks<T>,
+                   filter: Option<Filter<T>>,
+                   options: &ReceiverOptions,
+                   closed: &AtomicBool,
+                   deadline: Option<Instant>)
+    where T: FromRawSSDP + Send
+{
+    let recvs = recvs.into_iter()
+        .filter_map(|recv| {
+            match recv.socket().set_nonblocking(true).and_then(|()| recv.local_addr()) {
+                Ok(addr) => Some((recv, sinks.stats.socket_reads(addr))),
+                Err(err) => {
+                    warn!("Failed to set up receiver at {} for polling: {}", recv, err);
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    if recvs.is_empty() {
+        return;
+    }
+    let socks = recvs.iter().map(|(recv, _)| recv.socket()).collect::<Vec<_>>();
+    let idle_timeout = recvs[0].0.idle_timeout();
+
+    loop {
+        let timeout = match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    trace!("Polled receivers reached their deadline");
+                    return;
+                }
+
+                Some(idle_timeout.map_or(deadline - now, |idle| cmp::min(idle, deadline - now)))
+            }
+            None => idle_timeout,
+        };
+
+        trace!("Polling {} receivers...", recvs.len());
+        let readable = match net::poll_readable(&socks, timeout) {
+            Ok(readable) => readable,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                warn!("Failed to poll receivers: {}", err);
+                return;
+            }
+        };
+
+        if closed.load(Ordering::SeqCst) {
+            trace!("Polled receivers closed");
+            return;
+        }
+
+        let ready = readable.into_iter()
+            .enumerate()
+            .filter(|&(_, readable)| readable)
+            .map(|(index, _)| index)
+            .collect();
+        let read = |index: usize| recvs[index].0.recv_pckt();
+        let delivering = read_in_turns(ready, options.reads_per_turn, read, |index, result| {
+            let (ref recv, ref reads) = recvs[index];
+            match result {
+                Ok(packet) => {
+                    reads.fetch_add(1, Ordering::Relaxed);
+                    dispatch_packet(recv, packet, &sinks, &filter, &options.policy, closed)
+                }
+                Err(err) => {
+                    sinks.stats.recv_error(recv, &err);
+                    true
+                }
+            }
+        });
+        if !delivering {
+            return;
+        }
+    }
+}
+
+/// Reads from the ready sockets in turns of at most `per_turn` packets each until every one of
+/// them would block, passing each read along with the index of its socket on to `dispatch`.
+///
+/// A socket that fails to read sits out the remaining turns. Returns false as soon as `dispatch` does.
+#[cfg(any(unix, test))]
+fn read_in_turns<P, R, D>(ready: Vec<usize>, per_turn: usize, mut read: R, mut dispatch: D) -> bool
+    where R: FnMut(usize) -> io::Result<P>,
+          D: FnMut(usize, io::Result<P>) -> bool
+{
+    let mut ready = ready;
+    while !ready.is_empty() {
+        let mut still_ready = Vec::with_capacity(ready.len());
+
+        for index in ready {
+            let mut drained = false;
+            for _ in 0..per_turn {
+                let result = read(index);
+                match result {
+                    Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                        drained = true;
+                        break;
+                    }
+                    _ => {}
+                }
+
+                let failed = result.is_err();
+                if !dispatch(index, result) {
+                    return false;
+                }
+                if failed {
+                    drained = true;
+                    break;
+                }
+            }
+
+            if !drained {
+                still_ready.push(index);
+            }
+        }
+
+        ready = still_ready;
+    }
+
+    true
+}
+
+/// Filter dropping the objects the predicate returns false for with `DropReason::Filtered`.
+fn bool_filter<T, F>(predicate: F) -> Filter<T>
+    where F: Fn(&T) -> bool + Send + Sync + 'static
+{
+    Arc::new(move |item| if predicate(item) { Ok(()) } else { Err(DropReason::Filtered) })
+}
+
+/// Constructs a T from the packet and sends it through the supplied channel, unless filtered out.
+///
+/// Returns false once the channel hung up or the receiver was closed.
+fn dispatch_packet<T>(recv: &PacketReceiver,
+                      (msg_bytes, addr, local_addr): (Vec<u8>, SocketAddr, SocketAddr),
+                      sinks: &Sinks<T>,
+                      filter: &Option<Filter<T>>,
+                      policy: &ParsePolicy,
+                      closed: &AtomicBool)
+                      -> bool
+    where T: FromRawSSDP + Send
+{
+    let (received_at, received_at_wall) = (Instant::now(), SystemTime::now());
+
+    trace!("Received packet with {} bytes", msg_bytes.len());
+    sinks.stats.packets.fetch_add(1, Ordering::Relaxed);
+    sinks.stats.bytes.fetch_add(msg_bytes.len(), Ordering::Relaxed);
+
+    if sinks.paused.load(Ordering::SeqCst) {
+        sinks.stats.paused_drops.fetch_add(1, Ordering::Relaxed);
+        return true;
+    }
+
+    match T::raw_ssdp_from_with_policy(&msg_bytes[..], addr, policy) {
+        Ok(n) => {
+            sinks.stats.deviations(addr, n.deviations(), &msg_bytes);
+
+            if let Some(Err(reason)) = filter.as_ref().map(|filter| filter(&n)) {
+                trace!("Filtered out message from {}: {:?}", addr, reason);
+                sinks.stats.filter_drop(reason);
+                return true;
+            }
+
+            // The consumer hanging up is just another way of shutting down
+            let received = ReceivedFrom {
+                msg: n,
+                src: addr,
+                local: local_addr,
+                received_at,
+                received_at_wall,
+            };
+            match sinks.items.deliver(received, closed) {
+                Delivery::Sent(dropped) => {
+                    sinks.stats.delivered.fetch_add(1, Ordering::Relaxed);
+                    sinks.stats.dropped.fetch_add(dropped, Ordering::Relaxed);
+                }
+                Delivery::Dropped => {
+                    sinks.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                Delivery::HungUp => {
+                    trace!("Receiver at {} hung up", recv);
+                    return false;
+                }
+            }
+        }
+        Err(err) => {
+            trace!("Failed to parse message from {}: {}", addr, err);
+            sinks.stats.parse_failure(addr, &err, &msg_bytes);
+            // Nobody reading the error stream is no reason to stop receiving
+            if let Delivery::Sent(dropped) = sinks.errors.deliver((err, msg_bytes, addr), closed) {
+                if dropped > 0 {
+                    trace!("Error stream of receiver at {} dropped {} old failures", recv, dropped);
+                }
+            }
+        }
+    };
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::io;
+    use std::net::{UdpSocket, SocketAddr};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use std::sync::{Arc, Mutex};
+
+    use {SSDPErrorKind, SSDPResult};
+    use message::{Deviation, ParsePolicy, SSDPMessage};
+    use net::packet::{PacketReceiver, DEFAULT_MAX_PCKT_LEN};
+    use super::{DropReason, FromRawSSDP, OverflowPolicy, Outlet, RawResult, ReceiverBackend, ReceiverOptions,
+                SSDPReceiver, Sinks, StatsSnapshot};
+
+    struct RawBytes(Vec<u8>);
+
+    impl FromRawSSDP for RawBytes {
+        fn raw_ssdp(bytes: &[u8]) -> SSDPResult<RawBytes> {
+            Ok(RawBytes(bytes.to_vec()))
+        }
+    }
+
+    /// Every receiver test is run against both backends.
+    const BACKENDS: [ReceiverBackend; 2] = [ReceiverBackend::Threaded, ReceiverBackend::Polled];
+
+    fn receiver<T>(socks: Vec<UdpSocket>, time: Option<Duration>, backend: ReceiverBackend) -> SSDPReceiver<T>
+        where T: FromRawSSDP + Send + 'static
+    {
+        SSDPReceiver::spawn(socks, time, None, ReceiverOptions::new().set_backend(backend)).unwrap()
+    }
+
+    fn loopback_pair() -> (UdpSocket, UdpSocket, SocketAddr) {
+        let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let send_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let recv_addr = recv_sock.local_addr().unwrap();
+
+        (recv_sock, send_sock, recv_addr)
+    }
+
+    #[test]
+    fn positive_close_releases_socket() {
+        for &backend in &BACKENDS {
+            let recv_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let recv_addr = recv_sock.local_addr().unwrap();
+            let recv = receiver::<RawBytes>(vec![recv_sock], None, backend);
+
+            recv.close();
+            recv.close();
+
+            assert!(recv.recv().is_err());
+            UdpSocket::bind(recv_addr).unwrap();
+        }
+    }
+
+    #[test]
+    fn positive_close_stops_timeoutless_receiver() {
+        for &backend in &BACKENDS {
+            let (first_sock, _, _) = loopback_pair();
+            let (second_sock, _, _) = loopback_pair();
+            let recv = receiver::<RawBytes>(vec![first_sock, second_sock], None, backend);
+            let (done_send, done_recv) = mpsc::channel();
+
+            // Only runs once every receiver thread has exited
+            recv.on_shutdown(move || done_send.send(()).unwrap());
+
+            let start = Instant::now();
+            recv.close();
+
+            done_recv.recv_timeout(Duration::from_millis(1000)).unwrap();
+            assert!(start.elapsed() < Duration::from_millis(1000));
+            assert_eq!(recv.iter_with_local().count(), 0);
+        }
+    }
+
+    #[test]
+    fn positive_hung_up_consumer_exits_quietly() {
+        let (recv_sock, send_sock, recv_addr) = loopback_pair();
+        let (send, recv) = mpsc::channel();
+        let (errors, _) = Outlet::bounded(1, OverflowPolicy::DropOldest);
+        drop(recv);
+
+        send_sock.send_to(b"late", recv_addr).unwrap();
+        let deadline = Instant::now() + Duration::from_millis(1000);
+        super::receive_packets::<RawBytes>(PacketReceiver::with_buffer_size(recv_sock, DEFAULT_MAX_PCKT_LEN),
+                                           Sinks {
+                                               items: Outlet::Unbounded(send),
+                                               errors,
+                                               stats: Default::default(),
+                                               paused: Default::default(),
+                                           },
+                                           None,
+                                           &ParsePolicy::default(),
+                                           &AtomicBool::new(false),
+                                           Some(deadline));
+
+        // Returned on the hang up rather than waiting out the deadline
+        assert!(Instant::now() < deadline);
+    }
+
+    #[test]
+    fn positive_read_timeout_keeps_receiving() {
+        for &backend in &BACKENDS {
+            let (recv_sock, send_sock, recv_addr) = loopback_pair();
+            let options = ReceiverOptions::new()
+                .set_backend(backend)
+                .set_read_timeout(Some(Duration::from_millis(50)));
+            let recv = SSDPReceiver::<RawBytes>::with_options(vec![recv_sock], None, options, |_| Ok(()))
+                .unwrap();
+
+            send_sock.send_to(b"before", recv_addr).unwrap();
+            let (bytes, _) = recv.recv_timeout(Duration::from_millis(1000)).unwrap();
+            assert_eq!(bytes.0, b"before");
+
+            // Quiet for several read timeouts
+            thread::sleep(Duration::from_millis(200));
+
+            send_sock.send_to(b"after", recv_addr).unwrap();
+            let (bytes, _) = recv.recv_timeout(Duration::from_millis(1000)).unwrap();
+            assert_eq!(bytes.0, b"after");
+        }
+    }
+
+    #[test]
+    fn positive_read_timeout_notices_close() {
+        let (recv_sock, _, _) = loopback_pair();
+        let (send, _recv) = mpsc::channel();
+        let (errors, _) = Outlet::bounded(1, OverflowPolicy::DropOldest);
+        let sinks = Sinks {
+            items: Outlet::Unbounded(send),
+            errors,
+            stats: Default::default(),
+            paused: Default::default(),
+        };
+        let recv = PacketReceiver::with_buffer_size(recv_sock, DEFAULT_MAX_PCKT_LEN)
+            .with_idle_timeout(Some(Duration::from_millis(50)));
+
+        // Closed without a wake up packet, only the read timeout brings the receiver back around
+        let start = Instant::now();
+        let closed = AtomicBool::new(true);
+        super::receive_packets::<RawBytes>(recv, sinks, None, &ParsePolicy::default(), &closed, None);
+
+        assert!(start.elapsed() < Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn positive_read_timeout_close_without_wake() {
+        for &backend in &BACKENDS {
+            let (recv_sock, _, _) = loopback_pair();
+            let probe_sock = recv_sock.try_clone().unwrap();
+            let options = ReceiverOptions::new()
+                .set_backend(backend)
+                .set_read_timeout(Some(Duration::from_millis(100)));
+            let recv = SSDPReceiver::<RawBytes>::with_options(vec![recv_sock], None, options, |_| Ok(()))
+                .unwrap();
+
+            let start = Instant::now();
+            recv.close_and_join(Duration::from_millis(500)).unwrap();
+            assert!(start.elapsed() < Duration::from_millis(300));
+
+            // Neither shut down nor sent a wake up packet, so reading just times out
+            probe_sock.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+            let err = probe_sock.recv_from(&mut [0u8; 16]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        }
+    }
+
+    /// Receiver of capacity 2 that four packets were sent to.
+    fn overfilled(overflow: OverflowPolicy, backend: ReceiverBackend) -> SSDPReceiver<RawBytes> {
+        let (recv_sock, send_sock, recv_addr) = loopback_pair();
+        let options = ReceiverOptions::new().set_backend(backend).set_capacity(2, overflow);
+        let recv = SSDPReceiver::<RawBytes>::spawn(vec![recv_sock], None, None, options).unwrap();
+
+        for packet in &[b"1", b"2", b"3", b"4"] {
+            send_sock.send_to(*packet, recv_addr).unwrap();
+        }
+        thread::sleep(Duration::from_millis(200));
+
+        recv
+    }
+
+    fn drain(recv: &SSDPReceiver<RawBytes>) -> Vec<Vec<u8>> {
+        let mut received = Vec::new();
+        while let Ok((bytes, _)) = recv.try_recv() {
+            received.push(bytes.0);
+        }
+
+        received
+    }
+
+    #[test]
+    fn positive_capacity_drop_newest() {
+        for &backend in &BACKENDS {
+            let recv = overfilled(OverflowPolicy::DropNewest, backend);
+
+            assert_eq!(drain(&recv), vec![b"1".to_vec(), b"2".to_vec()]);
+            assert_eq!(recv.dropped(), 2);
+        }
+    }
+
+    #[test]
+    fn positive_capacity_drop_oldest() {
+        for &backend in &BACKENDS {
+            let recv = overfilled(OverflowPolicy::DropOldest, backend);
+
+            assert_eq!(drain(&recv), vec![b"3".to_vec(), b"4".to_vec()]);
+            assert_eq!(recv.dropped(), 2);
+        }
+    }
+
+    #[test]
+    fn positive_capacity_block() {
+        for &backend in &BACKENDS {
+            let recv = overfilled(OverflowPolicy::Block, backend);
+
+            let received = (&recv).into_iter().take(4).map(|(bytes, _)| bytes.0).collect::<Vec<_>>();
+
+            assert_eq!(received, vec![b"1".to_vec(), b"2".to_vec(), b"3".to_vec(), b"4".to_vec()]);
+            assert_eq!(recv.dropped(), 0);
+        }
+    }
+
+    /// Bytes that only parse if they do not start with `bad`.
+    struct NotBad(Vec<u8>);
+
+    impl FromRawSSDP for NotBad {
+        fn raw_ssdp(bytes: &[u8]) -> SSDPResult<NotBad> {
+            if bytes.starts_with(b"bad") {
+                Err(SSDPErrorKind::InvalidHttp(bytes.to_vec()).into())
+            } else {
+                Ok(NotBad(bytes.to_vec()))
+            }
+        }
+    }
+
+    #[test]
+    fn positive_error_stream_gets_parse_failures() {
+        for &backend in &BACKENDS {
+            let (recv_sock, send_sock, recv_addr) = loopback_pair();
+            let recv = receiver::<NotBad>(vec![recv_sock], None, backend);
+            let errors = recv.take_error_stream().unwrap();
+
+            send_sock.send_to(b"good", recv_addr).unwrap();
+            send_sock.send_to(b"bad", recv_addr).unwrap();
+
+            let (good, _) = recv.recv().unwrap();
+            assert_eq!(good.0, b"good".to_vec());
+            let (err, bytes, src) = errors.recv_timeout(Duration::from_millis(1000)).unwrap();
+            assert_eq!(err.code(), 1);
+            assert_eq!((bytes, src), (b"bad".to_vec(), send_sock.local_addr().unwrap()));
+            assert!(recv.take_error_stream().is_none());
+        }
+    }
+
+    #[test]
+    fn positive_error_stream_keeps_latest_failures() {
+        for &backend in &BACKENDS {
+            let (recv_sock, send_sock, recv_addr) = loopback_pair();
+            let recv = receiver::<NotBad>(vec![recv_sock], None, backend);
+            let errors = recv.take_error_stream().unwrap();
+            let sent = super::ERROR_STREAM_CAPACITY + 6;
+
+            for n in 0..sent {
+                send_sock.send_to(format!("bad {}", n).as_bytes(), recv_addr).unwrap();
+            }
+            let deadline = Instant::now() + Duration::from_millis(1000);
+            while recv.stats().parse_failures < sent && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(10));
+            }
+
+            let mut kept = Vec::new();
+            while let Ok((_, bytes, _)) = errors.try_recv() {
+                kept.push(bytes);
+            }
+            let latest = (6..sent).map(|n| format!("bad {}", n).into_bytes()).collect::<Vec<_>>();
+            assert_eq!(kept, latest);
+        }
+    }
+
+    #[test]
+    fn positive_stats_count_packets() {
+        for &backend in &BACKENDS {
+            let (recv_sock, send_sock, recv_addr) = loopback_pair();
+            let options = ReceiverOptions::new()
+                .set_backend(backend)
+                .set_capacity(1, OverflowPolicy::DropNewest);
+            let recv = SSDPReceiver::<NotBad>::spawn(vec![recv_sock], None, None, options).unwrap();
+
+            for packet in &[&b"good"[..], b"bad", b"ok", b"bad"] {
+                send_sock.send_to(packet, recv_addr).unwrap();
+            }
+            thread::sleep(Duration::from_millis(200));
+
+            assert_eq!(recv.stats(),
+                       StatsSnapshot {
+                           packets: 4,
+                           bytes: 12,
+                           parse_failures: 2,
+                           delivered: 1,
+                           dropped: 1,
+                           paused_drops: 0,
+                           recv_errors: 0,
+                           filtered: 0,
+                           max_age_out_of_bounds: 0,
+                       });
+        }
+    }
+
+    #[test]
+    fn positive_anomalies_track_parse_failures() {
+        for &backend in &BACKENDS {
+            let (recv_sock, send_sock, recv_addr) = loopback_pair();
+            let options = ReceiverOptions::new().set_backend(backend).set_anomaly_sources(4);
+            let recv = SSDPReceiver::<NotBad>::spawn(vec![recv_sock], None, None, options).unwrap();
+
+            for packet in &[&b"bad first"[..], b"good", b"bad second"] {
+                send_sock.send_to(packet, recv_addr).unwrap();
+            }
+            thread::sleep(Duration::from_millis(200));
+
+            let anomalies = recv.anomalies();
+            assert_eq!(anomalies.len(), 1);
+            assert_eq!(anomalies[0].0, send_sock.local_addr().unwrap().ip());
+
+            let kinds = anomalies[0].1.kinds.values().collect::<Vec<_>>();
+            assert_eq!(kinds.len(), 1);
+            assert_eq!(kinds[0].count, 2);
+            assert_eq!(&kinds[0].sample[..], &b"bad first"[..]);
+        }
+    }
+
+    #[test]
+    fn positive_anomalies_track_deviations() {
+        let (recv_sock, send_sock, recv_addr) = loopback_pair();
+        let options = ReceiverOptions::new()
+            .set_parse_policy(ParsePolicy::new().set_require_host(false))
+            .set_anomaly_sources(4);
+        let recv = SSDPReceiver::<SSDPMessage>::spawn(vec![recv_sock], None, None, options).unwrap();
+
+        let packet = b"NOTIFY * HTTP/1.1\r\nNT: upnp:rootdevice\r\n\r\n";
+        send_sock.send_to(packet, recv_addr).unwrap();
+
+        recv.recv().unwrap();
+
+        let anomalies = recv.anomalies();
+        assert_eq!(anomalies.len(), 1);
+
+        let kind = &anomalies[0].1.kinds[&Deviation::MissingHost.error().code()];
+        assert_eq!(kind.count, 1);
+        assert_eq!(&kind.sample[..], &packet[..]);
+    }
+
+    #[test]
+    fn negative_anomalies_untracked_by_default() {
+        let (recv_sock, send_sock, recv_addr) = loopback_pair();
+        let recv = SSDPReceiver::<NotBad>::spawn(vec![recv_sock], None, None, ReceiverOptions::new())
+            .unwrap();
+
+        send_sock.send_to(b"bad", recv_addr).unwrap();
+        thread::sleep(Duration::from_millis(200));
+
+        assert_eq!(recv.stats().parse_failures, 1);
+        assert!(recv.anomalies().is_empty());
+    }
+
+    #[test]
+    fn positive_reads_per_socket() {
+        for &backend in &BACKENDS {
+            let (first_sock, send_sock, first_addr) = loopback_pair();
+            let (second_sock, _, second_addr) = loopback_pair();
+            let recv = receiver::<RawBytes>(vec![first_sock, second_sock], None, backend);
+
+            for &(packet, addr) in &[(b"1", first_addr), (b"2", first_addr), (b"3", second_addr)] {
+                send_sock.send_to(packet, addr).unwrap();
+            }
+            thread::sleep(Duration::from_millis(200));
+
+            let mut reads = recv.reads_per_socket();
+            reads.sort();
+            let mut expected = vec![(first_addr, 2), (second_addr, 1)];
+            expected.sort();
+            assert_eq!(reads, expected);
+        }
+    }
+
+    type Script = VecDeque<io::Result<(Vec<u8>, SocketAddr)>>;
+
+    /// Queue of `count` packets named after the socket.
+    fn scripted(name: &str, count: usize) -> Script {
+        let src = "127.0.0.1:1900".parse().unwrap();
+
+        (0..count).map(|n| Ok((format!("{} {}", name, n).into_bytes(), src))).collect()
+    }
+
+    /// Read every scripted socket in turns, returning the packets in the order they were dispatched.
+    fn read_scripted(mut socks: Vec<Script>, per_turn: usize) -> Vec<String> {
+        let ready = (0..socks.len()).collect();
+        let mut dispatched = Vec::new();
+
+        let read = |index: usize| {
+            socks[index].pop_front().unwrap_or_else(|| Err(io::ErrorKind::WouldBlock.into()))
+        };
+        let delivering = super::read_in_turns(ready, per_turn, read, |_, result| {
+            dispatched.push(match result {
+                Ok((bytes, _)) => String::from_utf8(bytes).unwrap(),
+                Err(_) => "error".to_owned(),
+            });
+            true
+        });
+
+        assert!(delivering);
+        dispatched
+    }
+
+    #[test]
+    fn positive_read_in_turns_interleaves_trickle() {
+        let dispatched = read_scripted(vec![scripted("flood", 40), scripted("trickle", 2)], 16);
+
+        // The trickle is read after the first turn of the flood rather than after all of it
+        assert_eq!(dispatched.len(), 42);
+        assert_eq!(dispatched.iter().position(|packet| packet.starts_with("trickle")), Some(16));
+        assert_eq!(&dispatched[15..19], ["flood 15", "trickle 0", "trickle 1", "flood 16"]);
+    }
+
+    #[test]
+    fn positive_read_in_turns_alternates_single_reads() {
+        let dispatched = read_scripted(vec![scripted("flood", 3), scripted("trickle", 2)], 1);
+
+        assert_eq!(dispatched, ["flood 0", "trickle 0", "flood 1", "trickle 1", "flood 2"]);
+    }
+
+    #[test]
+    fn negative_read_in_turns_failed_socket_sits_out() {
+        let mut failing = Script::new();
+        failing.push_back(Err(io::ErrorKind::ConnectionReset.into()));
+        failing.push_back(Ok((b"late".to_vec(), "127.0.0.1:1900".parse().unwrap())));
+
+        let dispatched = read_scripted(vec![failing, scripted("flood", 3)], 2);
+
+        assert_eq!(dispatched, ["error", "flood 0", "flood 1", "flood 2"]);
+    }
+
+    #[test]
+    fn positive_receive_timestamps_in_order() {
+        for &backend in &BACKENDS {
+            let (recv_sock, send_sock, recv_addr) = loopback_pair();
+            let recv = receiver::<RawBytes>(vec![recv_sock], None, backend);
+
+            let before = Instant::now();
+            send_sock.send_to(b"first", recv_addr).unwrap();
+            send_sock.send_to(b"second", recv_addr).unwrap();
+            thread::sleep(Duration::from_millis(100));
+            let queued = Instant::now();
+
+            let received = recv.iter_meta().take(2).collect::<Vec<_>>();
+
+            assert_eq!(received[0].msg.0, b"first".to_vec());
+            assert!(before <= received[0].received_at);
+            assert!(received[0].received_at <= received[1].received_at);
+            // Taken when the packets were read, not when they were dequeued
+            assert!(received[1].received_at <= queued);
+        }
+    }
+
+    /// Bytes along with the source address and policy they were parsed with.
+    struct FromSource(Vec<u8>, SocketAddr, ParsePolicy);
+
+    impl FromRawSSDP for FromSource {
+        fn raw_ssdp(_bytes: &[u8]) -> SSDPResult<FromSource> {
+            Err("Source Address Is Required".into())
+        }
+
+        fn raw_ssdp_from_with_policy(bytes: &[u8],
+                                     src: SocketAddr,
+                                     policy: &ParsePolicy)
+                                     -> SSDPResult<FromSource> {
+            Ok(FromSource(bytes.to_vec(), src, *policy))
+        }
+    }
+
+    #[test]
+    fn positive_parsed_with_source_addr() {
+        for &backend in &BACKENDS {
+            let (recv_sock, send_sock, recv_addr) = loopback_pair();
+            let recv = receiver::<RawResult<FromSource>>(vec![recv_sock], None, backend);
+
+            send_sock.send_to(b"hello", recv_addr).unwrap();
+
+            let (parsed, src) = recv.recv().unwrap();
+            let FromSource(bytes, parsed_src, _) = parsed.ok().unwrap();
+            assert_eq!((bytes, parsed_src), (b"hello".to_vec(), src));
+            assert_eq!(src, send_sock.local_addr().unwrap());
+        }
+    }
+
+    #[test]
+    fn positive_parsed_with_source_addr_and_policy() {
+        for &backend in &BACKENDS {
+            let (recv_sock, send_sock, recv_addr) = loopback_pair();
+            let policy = ParsePolicy::new().set_require_host(false);
+            let options = ReceiverOptions::new().set_backend(backend).set_parse_policy(policy);
+            let recv = SSDPReceiver::<RawResult<FromSource>>::spawn(vec![recv_sock], None, None, options)
+                .unwrap();
+
+            send_sock.send_to(b"hello", recv_addr).unwrap();
+
+            let (parsed, src) = recv.recv().unwrap();
+            let FromSource(_, parsed_src, parsed_policy) = parsed.ok().unwrap();
+            assert_eq!((parsed_src, parsed_policy), (src, policy));
+        }
+    }
+
+    #[test]
+    fn positive_try_iter_drains_without_blocking() {
+        for &backend in &BACKENDS {
+            let (recv_sock, send_sock, recv_addr) = loopback_pair();
+            let recv = receiver::<RawBytes>(vec![recv_sock], None, backend);
+
+            send_sock.send_to(b"first", recv_addr).unwrap();
+            send_sock.send_to(b"second", recv_addr).unwrap();
+            thread::sleep(Duration::from_millis(100));
+
+            let start = Instant::now();
+            let received = recv.try_iter().map(|(bytes, _)| bytes.0).collect::<Vec<_>>();
+            assert_eq!(received, vec![b"first".to_vec(), b"second".to_vec()]);
+            assert!(recv.try_iter().next().is_none());
+
+            let mut iter = recv.into_iter();
+            assert!(iter.try_next().is_none());
+            assert!(start.elapsed() < Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn positive_try_iter_while_blocked_in_recv() {
+        for &backend in &BACKENDS {
+            let (recv_sock, _send_sock, _) = loopback_pair();
+            let recv = Arc::new(receiver::<RawBytes>(vec![recv_sock], None, backend));
+
+            let blocked = recv.clone();
+            let handle = thread::spawn(move || blocked.recv_timeout(Duration::from_millis(500)).is_err());
+            thread::sleep(Duration::from_millis(50));
+
+            let start = Instant::now();
+            assert!(recv.try_iter().next().is_none());
+            assert!(recv.try_recv().is_err());
+            assert!(start.elapsed() < Duration::from_millis(100));
+
+            assert!(handle.join().unwrap());
+        }
+    }
+
+    #[test]
+    fn positive_iter_until_ends_at_deadline() {
+        for &backend in &BACKENDS {
+            let (recv_sock, send_sock, recv_addr) = loopback_pair();
+            let recv = receiver::<RawBytes>(vec![recv_sock], None, backend);
+
+            let deadline = Instant::now() + Duration::from_millis(200);
+            assert_eq!(recv.iter_until(deadline).count(), 0);
+            assert!(Instant::now() >= deadline);
+            assert!(Instant::now() < deadline + Duration::from_millis(100));
+
+            thread::spawn(move || {
+                for _ in 0..5 {
+                    send_sock.send_to(b"keep", recv_addr).unwrap();
+                    thread::sleep(Duration::from_millis(20));
+                }
+            });
+            let start = Instant::now();
+            assert_eq!(recv.iter_for(Duration::from_millis(300)).count(), 5);
+            assert!(start.elapsed() >= Duration::from_millis(300));
+            assert!(start.elapsed() < Duration::from_millis(400));
+        }
+    }
+
+    #[test]
+    fn positive_unique_by_source_addr() {
+        let (recv_sock, first_send, recv_addr) = loopback_pair();
+        let (_, second_send, _) = loopback_pair();
+        let recv = SSDPReceiver::<RawBytes>::new(vec![recv_sock], Some(Duration::from_millis(300))).unwrap();
+
+        first_send.send_to(b"first", recv_addr).unwrap();
+        first_send.send_to(b"again", recv_addr).unwrap();
+        second_send.send_to(b"second", recv_addr).unwrap();
+
+        let received = recv.unique_by(|_, src| *src).map(|(bytes, _)| bytes.0).collect::<Vec<_>>();
+
+        assert_eq!(received, vec![b"first".to_vec(), b"second".to_vec()]);
+    }
+
+    #[test]
+    fn positive_unique_by_evicts_least_recently_seen() {
+        let (recv_sock, send_sock, recv_addr) = loopback_pair();
+        let recv = SSDPReceiver::<RawBytes>::new(vec![recv_sock], Some(Duration::from_millis(300))).unwrap();
+
+        for packet in &[&b"a"[..], b"b", b"b", b"a", b"c", b"b"] {
+            send_sock.send_to(packet, recv_addr).unwrap();
+        }
+
+        let received = recv.unique_by(|bytes, _| bytes.0.clone())
+            .set_capacity(2)
+            .map(|(bytes, _)| bytes.0)
+            .collect::<Vec<_>>();
+
+        // Seeing `a` again keeps it over `b`, which is forgotten once `c` arrives
+        assert_eq!(received, vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"b".to_vec()]);
+    }
+
+    #[test]
+    fn positive_filter_map_extracts_locations() {
+        let (recv_sock, send_sock, recv_addr) = loopback_pair();
+        let recv = SSDPReceiver::<RawBytes>::new(vec![recv_sock], Some(Duration::from_millis(300))).unwrap();
+
+        send_sock.send_to(b"LOCATION: http://192.168.0.1/desc.xml", recv_addr).unwrap();
+        send_sock.send_to(b"ST: upnp:rootdevice", recv_addr).unwrap();
+        send_sock.send_to(b"LOCATION: http://192.168.0.2/desc.xml", recv_addr).unwrap();
+
+        let mut locations = recv.map(|bytes, _| String::from_utf8(bytes.0).unwrap())
+            .filter_map(|line, _| line.strip_prefix("LOCATION: ").map(|location| location.to_owned()));
+
+        let (first, src) = locations.recv_timeout(Duration::from_millis(1000)).unwrap();
+        assert_eq!((&first[..], src), ("http://192.168.0.1/desc.xml", send_sock.local_addr().unwrap()));
+        assert_eq!(locations.collect::<Vec<_>>(),
+                   vec![("http://192.168.0.2/desc.xml".to_owned(), send_sock.local_addr().unwrap())]);
+    }
+
+    #[test]
+    #[cfg(feature = "stream")]
+    fn positive_stream_collects_received() {
+        use futures::StreamExt;
+        use futures::executor::block_on;
+
+        let (recv_sock, send_sock, recv_addr) = loopback_pair();
+        let recv = SSDPReceiver::<RawBytes>::new(vec![recv_sock], Some(Duration::from_millis(300))).unwrap();
+        let stream = recv.into_stream();
+
+        send_sock.send_to(b"first", recv_addr).unwrap();
+        send_sock.send_to(b"second", recv_addr).unwrap();
+
+        // Usually polled before the feeding thread passed anything on, so the values wake the task
+        let received = block_on(stream.map(|(bytes, _)| bytes.0).collect::<Vec<_>>());
+
+        assert_eq!(received, vec![b"first".to_vec(), b"second".to_vec()]);
+    }
+
+    #[test]
+    fn positive_first_releases_socket() {
+        for &backend in &BACKENDS {
+            let (recv_sock, send_sock, recv_addr) = loopback_pair();
+            let recv = receiver::<RawBytes>(vec![recv_sock], None, backend);
+
+            send_sock.send_to(b"first", recv_addr).unwrap();
+            send_sock.send_to(b"second", recv_addr).unwrap();
+
+            let (bytes, _) = recv.first().unwrap();
+            assert_eq!(bytes.0, b"first".to_vec());
+            UdpSocket::bind(recv_addr).unwrap();
+        }
+    }
+
+    #[test]
+    fn positive_take_first_counts_after_unique_by() {
+        let (recv_sock, send_sock, recv_addr) = loopback_pair();
+        let recv = SSDPReceiver::<RawBytes>::new(vec![recv_sock], None).unwrap();
+
+        for packet in &[&b"a"[..], b"a", b"b", b"c"] {
+            send_sock.send_to(packet, recv_addr).unwrap();
+        }
+
+        let taken = recv.unique_by(|bytes, _| bytes.0.clone()).take_first(2);
+
+        assert_eq!(taken.into_iter().map(|(bytes, _)| bytes.0).collect::<Vec<_>>(),
+                   vec![b"a".to_vec(), b"b".to_vec()]);
+        UdpSocket::bind(recv_addr).unwrap();
+    }
+
+    #[test]
+    fn positive_pause_discards_packets() {
+        for &backend in &BACKENDS {
+            let (recv_sock, send_sock, recv_addr) = loopback_pair();
+            let recv = receiver::<RawBytes>(vec![recv_sock], None, backend);
+
+            recv.pause();
+            assert!(recv.is_paused());
+            for _ in 0..3 {
+                send_sock.send_to(b"paused", recv_addr).unwrap();
+            }
+            thread::sleep(Duration::from_millis(100));
+            recv.resume();
+            send_sock.send_to(b"resumed", recv_addr).unwrap();
+
+            let (bytes, _) = recv.recv().unwrap();
+            assert_eq!(bytes.0, b"resumed".to_vec());
+            assert!(recv.try_recv().is_err());
+            assert_eq!(recv.stats().paused_drops, 3);
+        }
+    }
+
+    #[test]
+    fn positive_select_merges_and_closes_sources() {
+        let (first_sock, send_sock, first_addr) = loopback_pair();
+        let (second_sock, _, second_addr) = loopback_pair();
+        let first = SSDPReceiver::<RawBytes>::new(vec![first_sock], None).unwrap();
+        let second = SSDPReceiver::<RawBytes>::new(vec![second_sock], None).unwrap();
+        let merged = SSDPReceiver::select(vec![first, second]).unwrap();
+
+        let packets = [(b"1", first_addr), (b"2", second_addr), (b"3", first_addr), (b"4", second_addr)];
+        for &(packet, addr) in &packets {
+            send_sock.send_to(packet, addr).unwrap();
+        }
+
+        let mut received = merged.iter_for(Duration::from_millis(500))
+            .map(|(bytes, _)| bytes.0)
+            .collect::<Vec<_>>();
+        received.sort();
+        assert_eq!(received, vec![b"1".to_vec(), b"2".to_vec(), b"3".to_vec(), b"4".to_vec()]);
+
+        // Closing the merged receiver stops the forwarders, which close the sources
+        merged.close_and_join(Duration::from_millis(1000)).unwrap();
+        UdpSocket::bind(first_addr).unwrap();
+        UdpSocket::bind(second_addr).unwrap();
+    }
+
+    #[test]
+    fn negative_packet_truncated_to_size() {
+        for &backend in &BACKENDS {
+            let (recv_sock, send_sock, recv_addr) = loopback_pair();
+            let options = ReceiverOptions::new().set_backend(backend).set_max_packet_size(4);
+            let recv = SSDPReceiver::<RawBytes>::with_options(vec![recv_sock],
+                                                              Some(Duration::from_millis(500)),
+                                                              options,
+                                                              |_| Ok(()))
+                .unwrap();
+
+            send_sock.send_to(b"123456", recv_addr).unwrap();
+            send_sock.send_to(b"1234", recv_addr).unwrap();
+
+            let received = recv.into_iter().map(|(bytes, _)| bytes.0).collect::<Vec<_>>();
+
+            assert_eq!(received, vec![b"1234".to_vec(), b"1234".to_vec()]);
+        }
+    }
+
+    #[test]
+    fn positive_filter_drops_rejected() {
+        for &backend in &BACKENDS {
+            let (recv_sock, send_sock, recv_addr) = loopback_pair();
+            let filter = |bytes: &RawBytes| match &bytes.0[..] {
+                b"drop" => Err(DropReason::Filtered),
+                b"old" => Err(DropReason::MaxAgeOutOfBounds),
+                _ => Ok(()),
+            };
+            let recv = SSDPReceiver::<RawBytes>::with_options(vec![recv_sock],
+                                                              Some(Duration::from_millis(500)),
+                                                              ReceiverOptions::new().set_backend(backend),
+                                                              filter)
+                .unwrap();
+
+            for packet in &[&b"drop"[..], b"old", b"keep"] {
+                send_sock.send_to(packet, recv_addr).unwrap();
+            }
+
+            let received = (&recv).into_iter().map(|(bytes, _)| bytes.0).collect::<Vec<_>>();
+            let stats = recv.stats();
+
+            assert_eq!(received, vec![b"keep".to_vec()]);
+            assert_eq!((stats.filtered, stats.max_age_out_of_bounds), (1, 1));
+        }
+    }
+
+    #[test]
+    fn positive_deadline_not_extended_by_packets() {
+        for &backend in &BACKENDS {
+            let (recv_sock, send_sock, recv_addr) = loopback_pair();
+            let recv = receiver::<RawBytes>(vec![recv_sock], Some(Duration::from_millis(300)), backend);
+
+            let start = Instant::now();
+            thread::spawn(move || {
+                for _ in 0..40 {
+                    let _ = send_sock.send_to(b"keep", recv_addr);
+                    thread::sleep(Duration::from_millis(50));
+                }
+            });
+
+            let received = recv.into_iter().count();
+
+            assert!(received > 0);
+            assert!(start.elapsed() < Duration::from_millis(1500));
+        }
+    }
+
+    #[test]
+    fn positive_recv_with_local() {
+        for &backend in &BACKENDS {
+            let (first_sock, send_sock, first_addr) = loopback_pair();
+            let second_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let second_addr = second_sock.local_addr().unwrap();
+            let recv = receiver::<RawBytes>(vec![first_sock, second_sock], None, backend);
+
+            send_sock.send_to(b"first", first_addr).unwrap();
+            let (bytes, src, local) = recv.recv_with_local().unwrap();
+            let send_addr = send_sock.local_addr().unwrap();
+            assert_eq!((&bytes.0[..], src, local), (&b"first"[..], send_addr, first_addr));
+
+            send_sock.send_to(b"second", second_addr).unwrap();
+            let (bytes, src, local) = recv.recv_with_local().unwrap();
+            assert_eq!((&bytes.0[..], src, local), (&b"second"[..], send_addr, second_addr));
+        }
+    }
+
+    #[test]
+    fn positive_close_stops_watcher() {
+        for &backend in &BACKENDS {
+            let (recv_sock, _, _) = loopback_pair();
+            let recv = receiver::<RawBytes>(vec![recv_sock], None, backend);
+            let runs = Arc::new(Mutex::new(0));
+
+            let watcher_runs = runs.clone();
+            let interval = Duration::from_millis(10);
+            super::spawn_watcher(&recv, interval, move |_| *watcher_runs.lock().unwrap() += 1).unwrap();
+            thread::sleep(Duration::from_millis(100));
+            recv.close();
+
+            let closed_runs = *runs.lock().unwrap();
+            assert!(closed_runs > 0);
+            thread::sleep(Duration::from_millis(50));
+            assert_eq!(*runs.lock().unwrap(), closed_runs);
+        }
+    }
+
+    #[test]
+    fn positive_close_and_join_releases_socket() {
+        for &backend in &BACKENDS {
+            let (recv_sock, _, recv_addr) = loopback_pair();
+            let recv = receiver::<RawBytes>(vec![recv_sock], None, backend);
+            super::spawn_watcher(&recv, Duration::from_millis(10), |_| ()).unwrap();
+
+            recv.close_and_join(Duration::from_millis(1000)).unwrap();
+
+            UdpSocket::bind(recv_addr).unwrap();
+        }
+    }
+
+    #[test]
+    fn negative_close_and_join_reports_stuck_thread() {
+        let (recv_sock, _, _) = loopback_pair();
+        let recv = SSDPReceiver::<RawBytes>::new(vec![recv_sock], None).unwrap();
+        let stuck = |_: &_| thread::sleep(Duration::from_millis(500));
+        super::spawn_watcher(&recv, Duration::from_millis(1), stuck).unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        let err = recv.close_and_join(Duration::from_millis(50)).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(err.to_string().contains("ssdp-watcher"));
+        assert!(!err.to_string().contains("ssdp-receiver"));
+    }
+
+    #[test]
+    fn positive_close_hooks_run_once() {
+        for &backend in &BACKENDS {
+            let (recv_sock, _, recv_addr) = loopback_pair();
+            let recv = receiver::<RawBytes>(vec![recv_sock], None, backend);
+            let runs = Arc::new(Mutex::new(Vec::new()));
+
+            let hook_runs = runs.clone();
+            super::on_close(&recv, move |socks| {
+                hook_runs.lock().unwrap().extend(socks.iter().map(|sock| sock.local_addr().unwrap()))
+            });
+            recv.close();
+            recv.close();
+            drop(recv);
+
+            assert_eq!(*runs.lock().unwrap(), vec![recv_addr]);
+        }
+    }
+
+    #[test]
+    fn positive_shutdown_hook_runs_once_on_timeout() {
+        for &backend in &BACKENDS {
+            let (recv_sock, _, _) = loopback_pair();
+            let recv = receiver::<RawBytes>(vec![recv_sock], Some(Duration::from_millis(50)), backend);
+            let runs = Arc::new(Mutex::new(0));
+
+            let hook_runs = runs.clone();
+            recv.on_shutdown(move || *hook_runs.lock().unwrap() += 1);
+
+            // Runs once the receiver thread times out, without closing or dropping
+            thread::sleep(Duration::from_millis(300));
+            assert_eq!(*runs.lock().unwrap(), 1);
+
+            recv.close();
+            drop(recv);
+            assert_eq!(*runs.lock().unwrap(), 1);
+        }
+    }
+
+    #[test]
+    fn positive_shutdown_hook_panic_swallowed() {
+        for &backend in &BACKENDS {
+            let (recv_sock, _, _) = loopback_pair();
+            let recv = receiver::<RawBytes>(vec![recv_sock], None, backend);
+            let runs = Arc::new(Mutex::new(0));
+
+            let hook_runs = runs.clone();
+            recv.on_shutdown(|| panic!("hook panicked"));
+            recv.on_shutdown(move || *hook_runs.lock().unwrap() += 1);
+            recv.close();
+
+            assert_eq!(*runs.lock().unwrap(), 1);
+        }
+    }
+
+    #[test]
+    fn positive_shutdown_hook_after_shutdown() {
+        for &backend in &BACKENDS {
+            let (recv_sock, _, _) = loopback_pair();
+            let recv = receiver::<RawBytes>(vec![recv_sock], None, backend);
+            let runs = Arc::new(Mutex::new(0));
+            recv.close();
+
+            let hook_runs = runs.clone();
+            recv.on_shutdown(move || *hook_runs.lock().unwrap() += 1);
+
+            assert_eq!(*runs.lock().unwrap(), 1);
+        }
+    }
+
+    #[test]
+    fn positive_added_socket_received_on() {
+        for &backend in &BACKENDS {
+            let (first_sock, send_sock, first_addr) = loopback_pair();
+            let second_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let second_addr = second_sock.local_addr().unwrap();
+            let recv = receiver::<RawBytes>(vec![first_sock], None, backend);
+
+            assert!(super::socket_adder(&recv).add(second_sock).unwrap());
+
+            send_sock.send_to(b"second", second_addr).unwrap();
+            let (bytes, _, local) = recv.recv_with_local().unwrap();
+            assert_eq!((&bytes.0[..], local), (&b"second"[..], second_addr));
+
+            recv.close_and_join(Duration::from_millis(1000)).unwrap();
+            UdpSocket::bind(first_addr).unwrap();
+            UdpSocket::bind(second_addr).unwrap();
+        }
+    }
+
+    #[test]
+    fn negative_added_socket_after_timeout() {
+        for &backend in &BACKENDS {
+            let (recv_sock, _, _) = loopback_pair();
+            let recv = receiver::<RawBytes>(vec![recv_sock], Some(Duration::from_millis(50)), backend);
+            let adder = super::socket_adder(&recv);
+
+            assert_eq!(recv.iter_with_local().count(), 0);
+            assert!(!adder.add(UdpSocket::bind("127.0.0.1:0").unwrap()).unwrap());
+        }
     }
 }
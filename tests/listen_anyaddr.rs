@@ -0,0 +1,33 @@
+extern crate ssdp;
+
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use ssdp::{FieldMap, IpVersionMode};
+use ssdp::header::{HeaderRef, NT, NTS, USN};
+use ssdp::message::{Config, Listen, Multicast, NotifyListener, NotifyMessage};
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+fn positive_anyaddr_receives_local_multicast() {
+    let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+    let config = Config::new().set_port(port).set_mode(IpVersionMode::V4Only).set_multicast_loop(true);
+    let recv = NotifyListener::listen_anyaddr_with_config(&config).unwrap();
+
+    let usn = USN(FieldMap::uuid("listen-anyaddr"), None);
+    let notify = NotifyMessage::byebye(NT(FieldMap::upnp("rootdevice")), usn.clone());
+
+    // Multicast datagrams can be lost, keep sending until one makes it through
+    for _ in 0..10 {
+        notify.multicast_with_config(&config).unwrap();
+        ::std::thread::sleep(Duration::from_millis(100));
+
+        if let Ok((received, _)) = recv.try_recv() {
+            assert_eq!(received.get::<NTS>(), Some(&NTS::ByeBye));
+            assert_eq!(received.get::<USN>(), Some(&usn));
+            return;
+        }
+    }
+
+    panic!("Multicast Notify Message Was Not Received");
+}
@@ -0,0 +1,39 @@
+extern crate ssdp;
+
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use ssdp::{FieldMap, IpVersionMode};
+use ssdp::header::{HeaderRef, NT, NTS, USN};
+use ssdp::message::{Config, Listen, Multicast, NotifyListener, NotifyMessage};
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+fn positive_loopback_multicast_round_trip() {
+    let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+    let config = Config::new()
+        .set_port(port)
+        .set_mode(IpVersionMode::V4Only)
+        .set_multicast_loop(true)
+        .set_include_loopback(true);
+    let recv = NotifyListener::listen_with_config(&config).unwrap();
+
+    let usn = USN(FieldMap::uuid("loopback"), None);
+    let notify = NotifyMessage::byebye(NT(FieldMap::upnp("rootdevice")), usn.clone());
+
+    // Multicast datagrams can be lost, keep sending until one makes it through
+    for _ in 0..10 {
+        notify.multicast_with_config(&config).unwrap();
+        ::std::thread::sleep(Duration::from_millis(100));
+
+        while let Ok((received, src)) = recv.try_recv() {
+            if src.ip().is_loopback() {
+                assert_eq!(received.get::<NTS>(), Some(&NTS::ByeBye));
+                assert_eq!(received.get::<USN>(), Some(&usn));
+                return;
+            }
+        }
+    }
+
+    panic!("Multicast Notify Message Was Not Received On Loopback");
+}
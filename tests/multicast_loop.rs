@@ -0,0 +1,47 @@
+extern crate ssdp;
+
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use ssdp::{FieldMap, IpVersionMode};
+use ssdp::header::{HeaderRef, NTS, USN};
+use ssdp::message::{Advertiser, Config, Listen, NotifyListener, TargetSet};
+
+const UUID: &str = "2f402f80-da50-11e1-9b23-00178809ea66";
+
+/// Advertise a device with the multicast loop set as given, returning whether a local listener heard it.
+fn advertisement_heard(multicast_loop: bool) -> bool {
+    let port = UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+    let config = Config::new()
+        .set_port(port)
+        .set_mode(IpVersionMode::V4Only)
+        .set_multicast_loop(multicast_loop);
+    let recv = NotifyListener::listen_with_config(&config).unwrap();
+
+    // Alive messages are re-sent every second or so, multicast datagrams can be lost
+    let targets = TargetSet::new(UUID, "schemas-upnp-org:device:Basic:1");
+    let mut advertiser =
+        Advertiser::for_targets(&targets, "http://127.0.0.1/desc.xml", Duration::from_secs(2), &config)
+            .unwrap();
+
+    let heard = recv.iter_for(Duration::from_millis(2500))
+        .any(|(notify, _)| {
+            notify.get::<NTS>() == Some(&NTS::Alive) &&
+            notify.get::<USN>().map(|usn| &usn.0) == Some(&FieldMap::uuid(UUID))
+        });
+
+    advertiser.stop();
+    heard
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+fn positive_multicast_loop_on_reaches_local_listener() {
+    assert!(advertisement_heard(true), "Advertisement Was Not Looped Back To The Local Listener");
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+fn negative_multicast_loop_off_skips_local_listener() {
+    assert!(!advertisement_heard(false), "Advertisement Was Looped Back With The Multicast Loop Off");
+}